@@ -31,7 +31,7 @@ pub(crate) async fn get_collection_by_id(
     ctx: web::Data<AppContext>,
 ) -> Result<HttpResponse> {
     let collection = service::get_collection_by_id(ctx.into_inner(), &collection_id).await?;
-    Ok(HttpResponse::Ok().json(&collection.meta))
+    Ok(HttpResponse::Ok().json(collection))
 }
 
 pub(crate) async fn delete_collection_by_id(
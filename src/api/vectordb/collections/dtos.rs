@@ -1,7 +1,8 @@
 use crate::metadata;
 use crate::models::collection::{
-    CollectionConfig, DenseVectorOptions, SparseVectorOptions, TFIDFOptions,
+    CollectionConfig, CollectionMetadata, DenseVectorOptions, SparseVectorOptions, TFIDFOptions,
 };
+use crate::models::vector_counts::VectorCounts;
 use serde::{Deserialize, Serialize};
 
 #[derive(Deserialize)]
@@ -87,6 +88,13 @@ pub(crate) struct CreateCollectionDtoResponse {
     pub description: Option<String>,
 }
 
+#[derive(Serialize)]
+pub(crate) struct CollectionDetailsDto {
+    #[serde(flatten)]
+    pub meta: CollectionMetadata,
+    pub vector_counts: VectorCounts,
+}
+
 #[derive(Deserialize)]
 pub(crate) struct GetCollectionsDto {}
 
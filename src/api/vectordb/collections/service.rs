@@ -1,10 +1,13 @@
 use std::sync::Arc;
 
-use crate::{app_context::AppContext, models::collection::Collection};
+use crate::{
+    app_context::AppContext,
+    models::{collection::Collection, vector_counts::VectorCounts},
+};
 
 use super::{
     dtos::{
-        CreateCollectionDto, CreateCollectionDtoResponse, GetCollectionsDto,
+        CollectionDetailsDto, CreateCollectionDto, CreateCollectionDtoResponse, GetCollectionsDto,
         GetCollectionsResponseDto, ListCollectionsResponseDto,
     },
     error::CollectionsError,
@@ -32,15 +35,26 @@ pub(crate) async fn get_collections(
     Ok(collections)
 }
 
-/// gets a collection by its id
+/// gets the details of a collection by its id, including the no. of
+/// vectors in its current version
 ///
 /// currently collection_id = collection.name
 pub(crate) async fn get_collection_by_id(
     ctx: Arc<AppContext>,
     collection_id: &str,
-) -> Result<Arc<Collection>, CollectionsError> {
+) -> Result<CollectionDetailsDto, CollectionsError> {
     let collection = repo::get_collection_by_name(ctx, collection_id).await?;
-    Ok(collection)
+    let current_version = *collection.current_version.read().unwrap();
+    // Counts are persisted on commit (and when loading collections
+    // predating them), so they are missing only for collections
+    // without any commits
+    let vector_counts = VectorCounts::load(&collection.lmdb, current_version)
+        .map_err(CollectionsError::WaCustomError)?
+        .unwrap_or_default();
+    Ok(CollectionDetailsDto {
+        meta: collection.meta.clone(),
+        vector_counts,
+    })
 }
 
 /// deletes a collection by its id
@@ -5,7 +5,7 @@ pub(crate) mod controller;
 pub(crate) mod dtos;
 mod error;
 mod repo;
-mod service;
+pub(crate) mod service;
 
 pub(crate) fn indexes_module() -> Scope {
     web::scope("/collections/{collection_id}/indexes")
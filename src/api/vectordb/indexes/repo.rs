@@ -17,10 +17,13 @@ use super::{
     error::IndexesError,
 };
 
+/// Creates the dense index of the named dense vector space of the
+/// collection with the same name, if any, otherwise the default dense
+/// index of the collection
 pub(crate) async fn create_dense_index(
     ctx: Arc<AppContext>,
     collection_name: String,
-    _name: String,
+    name: String,
     distance_metric: DistanceMetric,
     quantization: DenseIndexQuantizationDto,
     index_params: DenseIndexParamsDto,
@@ -31,9 +34,18 @@ pub(crate) async fn create_dense_index(
         .get_collection(&collection_name)
         .ok_or(IndexesError::CollectionNotFound)?;
 
+    let vector_name = collection
+        .meta
+        .dense_vector
+        .get_named_vector(&name)
+        .map(|named_vector| named_vector.name.clone());
+
     // Check if index already exists BEFORE initializing
-    if collection.get_hnsw_index().is_some() {
-        return Err(IndexesError::IndexAlreadyExists("dense".to_string()));
+    if collection.get_dense_index(vector_name.as_deref()).is_some() {
+        return Err(IndexesError::IndexAlreadyExists(match vector_name {
+            Some(vector_name) => format!("dense ({})", vector_name),
+            None => "dense".to_string(),
+        }));
     }
 
     let (quantization_metric, storage_type, range, sample_threshold, is_configured) =
@@ -58,6 +70,7 @@ pub(crate) async fn create_dense_index(
     init_hnsw_index_for_collection(
         ctx,
         collection,
+        vector_name,
         range,
         hnsw_params,
         quantization_metric,
@@ -143,7 +156,13 @@ pub(crate) async fn get_index(
 
     let mut indexes_array = Vec::new();
 
-    if let Some(hnsw) = collection.get_hnsw_index() {
+    let dense_indexes = collection.get_dense_indexes().into_iter().chain(
+        collection
+            .get_multi_vector_indexes()
+            .into_iter()
+            .map(|(_, hnsw)| hnsw),
+    );
+    for hnsw in dense_indexes {
         let distance_metric = *hnsw.distance_metric.read().unwrap();
         let values_range = *hnsw.values_range.read().unwrap();
         let hnsw_params = hnsw.hnsw_params.read().unwrap();
@@ -152,7 +171,13 @@ pub(crate) async fn get_index(
 
         indexes_array.push(serde_json::json!({
             "type": "dense",
-            "name": collection_name,
+            "name": hnsw.vector_name.as_deref().unwrap_or(&collection_name),
+            "vector_name": hnsw.vector_name,
+            "dimension": hnsw.dim,
+            "multi_vector": hnsw
+                .vector_name
+                .as_deref()
+                .is_some_and(|name| collection.meta.dense_vector.is_multi_vector(name)),
             "algorithm": "HNSW",
             "distance_metric": format!("{:?}", distance_metric),
             "quantization": {
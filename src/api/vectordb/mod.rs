@@ -6,3 +6,6 @@ pub(crate) mod transactions;
 
 pub(crate) mod indexes;
 pub(crate) mod versions;
+
+#[cfg(test)]
+pub(crate) mod test_utils;
@@ -1,18 +1,22 @@
 use actix_web::{web, HttpResponse, Result};
+use std::num::{NonZeroU32, NonZeroUsize};
 
 use crate::app_context::AppContext;
+use crate::indexes::hnsw::types::DenseSearchOptions;
 use crate::models::collection_cache::CollectionCacheExt;
+use crate::models::common::WaCustomError;
 use crate::models::types::MetricResult;
 
 use super::dtos::{
-    BatchDenseSearchRequestDto, BatchSearchResponseDto, BatchSearchTFIDFDocumentsDto,
-    BatchSparseSearchRequestDto, DenseSearchRequestDto, FindSimilarTFIDFDocumentDto,
-    HybridSearchRequestDto, SearchResponseDto, SearchResultItemDto, SparseSearchRequestDto,
+    BatchDenseSearchRequestDto, BatchHybridSearchRequestDto, BatchSearchResponseDto,
+    BatchSearchTFIDFDocumentsDto, BatchSparseSearchRequestDto, DenseSearchRequestDto,
+    FindSimilarTFIDFDocumentDto, HybridSearchRequestDto, MultiVectorSearchRequestDto,
+    RecommendRequestDto, SearchByIdRequestDto, SearchResponseDto, SparseSearchRequestDto,
 };
 use super::error::SearchError;
 use crate::api_service::{ann_vector_query, batch_ann_vector_query};
 
-use super::service;
+use super::{repo, service};
 
 pub(crate) async fn dense_search(
     path: web::Path<String>,
@@ -25,23 +29,41 @@ pub(crate) async fn dense_search(
     ctx.update_collection_for_query(&collection_id)
         .map_err(|e| SearchError::InternalServerError(format!("Cache update error: {}", e)))?;
 
+    if body.group_by.is_some() {
+        let response_data =
+            service::grouped_dense_search(ctx.into_inner(), &collection_id, body).await?;
+        return Ok(HttpResponse::Ok().json(response_data));
+    }
+
     let collection = ctx
         .ain_env
         .collections_map
         .get_collection(&collection_id)
         .ok_or_else(|| SearchError::CollectionNotFound(collection_id.clone()))?;
 
-    let hnsw_index = collection.get_hnsw_index().ok_or_else(|| {
-        SearchError::IndexNotFound(format!(
-            "Dense (HNSW) index not found for collection '{}'",
-            collection_id
-        ))
-    })?;
+    let hnsw_index = collection
+        .get_dense_index(body.vector_name.as_deref())
+        .ok_or_else(|| {
+            SearchError::IndexNotFound(format!(
+                "Dense (HNSW) index of vector '{}' not found for collection '{}'",
+                body.vector_name.as_deref().unwrap_or("default"),
+                collection_id
+            ))
+        })?;
 
     let metadata_filter = match body.filter {
         Some(api_filter) => Some(api_filter),
         None => None,
     };
+    let options = DenseSearchOptions {
+        ef_search: body.ef_search.map(NonZeroU32::get),
+        skip_rerank: body.skip_rerank,
+        rerank_oversampling: body.rerank_oversampling.map(NonZeroUsize::get),
+        score_threshold: body.score_threshold,
+        diversity: repo::diversity_options(body.diversity)?,
+        as_of: repo::version_snapshot(&collection, body.result_options.version)?,
+    };
+    let as_of = options.as_of.clone();
 
     let result: Vec<(crate::models::types::VectorId, MetricResult)> = ann_vector_query(
         ctx.into_inner(),
@@ -49,19 +71,26 @@ pub(crate) async fn dense_search(
         hnsw_index.clone(),
         body.query_vector,
         metadata_filter,
+        options,
         body.top_k,
     )
     .await
-    .map_err(|e| SearchError::SearchFailed(format!("ANN query failed: {}", e)))?;
+    .map_err(|e| match e {
+        WaCustomError::MetadataError(e) => SearchError::InvalidFilter(e.to_string()),
+        other => SearchError::SearchFailed(format!("ANN query failed: {}", other)),
+    })?;
 
+    let truncated =
+        repo::is_radius_search_truncated(body.top_k, body.score_threshold, result.len());
     let response_data = SearchResponseDto {
-        results: result
-            .into_iter()
-            .map(|(id, dist)| SearchResultItemDto {
-                id,
-                score: dist.get_value(),
-            })
-            .collect(),
+        results: service::to_search_results(
+            &collection,
+            result.into_iter().map(|(id, dist)| (id, dist.get_value())),
+            &body.result_options.include,
+            body.vector_name.as_deref(),
+            as_of.as_deref(),
+        )?,
+        truncated,
     };
     Ok(HttpResponse::Ok().json(response_data))
 }
@@ -84,17 +113,21 @@ pub(crate) async fn batch_dense_search(
         .get_collection(&collection_id)
         .ok_or_else(|| SearchError::CollectionNotFound(collection_id.clone()))?;
 
-    let hnsw_index = collection.get_hnsw_index().ok_or_else(|| {
-        SearchError::IndexNotFound(format!(
-            "Dense (HNSW) index not found for collection '{}'",
-            collection_id
-        ))
-    })?;
+    let hnsw_index = collection
+        .get_dense_index(body.vector_name.as_deref())
+        .ok_or_else(|| {
+            SearchError::IndexNotFound(format!(
+                "Dense (HNSW) index of vector '{}' not found for collection '{}'",
+                body.vector_name.as_deref().unwrap_or("default"),
+                collection_id
+            ))
+        })?;
 
     let metadata_filter = match body.filter {
         Some(api_filter) => Some(api_filter),
         None => None,
     };
+    let as_of = repo::version_snapshot(&collection, body.result_options.version)?;
 
     let results: Vec<Vec<(crate::models::types::VectorId, MetricResult)>> = batch_ann_vector_query(
         ctx.into_inner(),
@@ -102,23 +135,43 @@ pub(crate) async fn batch_dense_search(
         hnsw_index.clone(),
         body.query_vectors,
         metadata_filter,
+        DenseSearchOptions {
+            ef_search: body.ef_search.map(NonZeroU32::get),
+            skip_rerank: body.skip_rerank,
+            rerank_oversampling: body.rerank_oversampling.map(NonZeroUsize::get),
+            score_threshold: body.score_threshold,
+            diversity: repo::diversity_options(body.diversity)?,
+            as_of: as_of.clone(),
+        },
         body.top_k,
     )
     .await
-    .map_err(|e| SearchError::SearchFailed(format!("Batch ANN query failed: {}", e)))?;
+    .map_err(|e| match e {
+        WaCustomError::MetadataError(e) => SearchError::InvalidFilter(e.to_string()),
+        other => SearchError::SearchFailed(format!("Batch ANN query failed: {}", other)),
+    })?;
 
-    let response_data: BatchSearchResponseDto = results
+    let response_data = results
         .into_iter()
-        .map(|result_list| SearchResponseDto {
-            results: result_list
-                .into_iter()
-                .map(|(id, dist)| SearchResultItemDto {
-                    id,
-                    score: dist.get_value(),
-                })
-                .collect(),
+        .map(|result_list| {
+            Ok(SearchResponseDto {
+                truncated: repo::is_radius_search_truncated(
+                    body.top_k,
+                    body.score_threshold,
+                    result_list.len(),
+                ),
+                results: service::to_search_results(
+                    &collection,
+                    result_list
+                        .into_iter()
+                        .map(|(id, dist)| (id, dist.get_value())),
+                    &body.result_options.include,
+                    body.vector_name.as_deref(),
+                    as_of.as_deref(),
+                )?,
+            })
         })
-        .collect();
+        .collect::<Result<BatchSearchResponseDto, SearchError>>()?;
 
     Ok(HttpResponse::Ok().json(response_data))
 }
@@ -162,6 +215,62 @@ pub(crate) async fn hybrid_search(
     Ok(HttpResponse::Ok().json(results))
 }
 
+// Route: `POST /collections/{collection_id}/search/batch-hybrid`
+pub(crate) async fn batch_hybrid_search(
+    path: web::Path<String>,
+    web::Json(body): web::Json<BatchHybridSearchRequestDto>,
+    ctx: web::Data<AppContext>,
+) -> Result<HttpResponse, SearchError> {
+    let collection_id = path.into_inner();
+    ctx.update_collection_for_query(&collection_id)
+        .map_err(|e| SearchError::InternalServerError(format!("Cache update error: {}", e)))?;
+
+    let results = service::batch_hybrid_search(ctx.into_inner(), &collection_id, body).await?;
+    Ok(HttpResponse::Ok().json(results))
+}
+
+// Route: `POST /collections/{collection_id}/search/recommend`
+pub(crate) async fn recommend(
+    path: web::Path<String>,
+    web::Json(body): web::Json<RecommendRequestDto>,
+    ctx: web::Data<AppContext>,
+) -> Result<HttpResponse, SearchError> {
+    let collection_id = path.into_inner();
+    ctx.update_collection_for_query(&collection_id)
+        .map_err(|e| SearchError::InternalServerError(format!("Cache update error: {}", e)))?;
+
+    let results = service::recommend(ctx.into_inner(), &collection_id, body).await?;
+    Ok(HttpResponse::Ok().json(results))
+}
+
+// Route: `POST /collections/{collection_id}/search/by-id`
+pub(crate) async fn search_by_id(
+    path: web::Path<String>,
+    web::Json(body): web::Json<SearchByIdRequestDto>,
+    ctx: web::Data<AppContext>,
+) -> Result<HttpResponse, SearchError> {
+    let collection_id = path.into_inner();
+    ctx.update_collection_for_query(&collection_id)
+        .map_err(|e| SearchError::InternalServerError(format!("Cache update error: {}", e)))?;
+
+    let results = service::search_by_id(ctx.into_inner(), &collection_id, body).await?;
+    Ok(HttpResponse::Ok().json(results))
+}
+
+// Route: `POST /collections/{collection_id}/search/multi-vector`
+pub(crate) async fn multi_vector_search(
+    path: web::Path<String>,
+    web::Json(body): web::Json<MultiVectorSearchRequestDto>,
+    ctx: web::Data<AppContext>,
+) -> Result<HttpResponse, SearchError> {
+    let collection_id = path.into_inner();
+    ctx.update_collection_for_query(&collection_id)
+        .map_err(|e| SearchError::InternalServerError(format!("Cache update error: {}", e)))?;
+
+    let results = service::multi_vector_search(ctx.into_inner(), &collection_id, body).await?;
+    Ok(HttpResponse::Ok().json(results))
+}
+
 pub(crate) async fn tf_idf_search(
     path: web::Path<String>,
     web::Json(body): web::Json<FindSimilarTFIDFDocumentDto>,
@@ -1,7 +1,12 @@
+use crate::api::vectordb::indexes::dtos::IndexType;
 use crate::indexes::inverted::types::SparsePair;
 use crate::metadata::query_filtering::Filter;
-use crate::models::types::VectorId;
+use crate::metadata::{FieldValue, MetadataFields};
+use crate::models::external_ids::ExternalId;
+use crate::models::fusion::ScoreNormalization;
+use crate::models::versioning::{Hash, VersionRef};
 use serde::{Deserialize, Serialize};
+use std::num::{NonZeroU32, NonZeroUsize};
 
 fn default_top_k() -> usize {
     10
@@ -9,19 +14,117 @@ fn default_top_k() -> usize {
 fn default_fusion_constant_k() -> f32 {
     60.0
 }
+fn default_hybrid_weight() -> f32 {
+    1.0
+}
+fn default_diversity_lambda() -> f32 {
+    0.5
+}
+
+/// Parameters for diversifying the results using Maximal Marginal
+/// Relevance
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub(crate) struct DiversityDto {
+    /// Trade-off between the relevance (1.0) and the diversity (0.0)
+    /// of the results
+    #[serde(default = "default_diversity_lambda")]
+    pub lambda: f32,
+    /// No. of most relevant candidates the results are selected from,
+    /// defaults to 5 times `top_k`
+    pub fetch_k: Option<NonZeroUsize>,
+}
+
+/// Version of the collection to search as of, either by its hash or
+/// by its number
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum VersionDto {
+    Hash(u32),
+    Number(u16),
+}
+
+impl From<VersionDto> for VersionRef {
+    fn from(version: VersionDto) -> Self {
+        match version {
+            VersionDto::Hash(hash) => Self::Hash(Hash::from(hash)),
+            VersionDto::Number(number) => Self::Number(number.into()),
+        }
+    }
+}
 
 #[derive(Deserialize, Debug)]
 pub(crate) struct DenseSearchRequestDto {
+    /// Name of the dense vector space to search, the default one if
+    /// not specified
+    pub vector_name: Option<String>,
     pub query_vector: Vec<f32>,
     pub top_k: Option<usize>,
     pub filter: Option<Filter>,
+    /// Skips rescoring the results using the raw vectors, returning
+    /// the approximate scores computed by the index instead
+    #[serde(default)]
+    pub skip_rerank: bool,
+    /// Overrides the `ef_search` of the index
+    pub ef_search: Option<NonZeroU32>,
+    /// No. of candidates, as a multiple of `top_k`, that are rescored
+    /// using the raw vectors
+    pub rerank_oversampling: Option<NonZeroUsize>,
+    /// Min. similarity (or max. distance, for distance metrics) of the
+    /// results. If `top_k` isn't specified, all the matches are
+    /// returned, up to `RADIUS_SEARCH_MAX_RESULTS`.
+    pub score_threshold: Option<f32>,
+    /// Metadata field to group the results by, returning the best
+    /// `group_size` results for each of `groups` distinct values of it
+    pub group_by: Option<String>,
+    /// Max. no. of results per group, defaults to 1
+    pub group_size: Option<NonZeroUsize>,
+    /// Max. no. of groups, defaults to `top_k`
+    pub groups: Option<NonZeroUsize>,
+    /// Diversifies the results using Maximal Marginal Relevance
+    pub diversity: Option<DiversityDto>,
+    #[serde(flatten)]
+    pub result_options: ResultOptionsDto,
 }
 
 #[derive(Deserialize, Debug)]
 pub(crate) struct BatchDenseSearchRequestDto {
+    /// Name of the dense vector space to search, the default one if
+    /// not specified
+    pub vector_name: Option<String>,
     pub query_vectors: Vec<Vec<f32>>,
     pub top_k: Option<usize>,
     pub filter: Option<Filter>,
+    /// Skips rescoring the results using the raw vectors, returning
+    /// the approximate scores computed by the index instead
+    #[serde(default)]
+    pub skip_rerank: bool,
+    /// Overrides the `ef_search` of the index
+    pub ef_search: Option<NonZeroU32>,
+    /// No. of candidates, as a multiple of `top_k`, that are rescored
+    /// using the raw vectors
+    pub rerank_oversampling: Option<NonZeroUsize>,
+    /// Min. similarity (or max. distance, for distance metrics) of the
+    /// results. If `top_k` isn't specified, all the matches are
+    /// returned, up to `RADIUS_SEARCH_MAX_RESULTS`.
+    pub score_threshold: Option<f32>,
+    /// Diversifies the results using Maximal Marginal Relevance
+    pub diversity: Option<DiversityDto>,
+    #[serde(flatten)]
+    pub result_options: ResultOptionsDto,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct MultiVectorSearchRequestDto {
+    /// Name of the multi-vector space to search
+    pub vector_name: String,
+    pub query_vectors: Vec<Vec<f32>>,
+    pub top_k: Option<usize>,
+    /// No. of nearest vectors retrieved from the index for each of the
+    /// query vectors to gather the candidate documents. Defaults to 4
+    /// times `top_k`.
+    pub candidates_per_query: Option<usize>,
+    #[serde(flatten)]
+    pub result_options: ResultOptionsDto,
 }
 
 #[derive(Deserialize, Debug)]
@@ -29,6 +132,18 @@ pub(crate) struct SparseSearchRequestDto {
     pub query_terms: Vec<SparsePair>,
     pub top_k: Option<usize>,
     pub early_terminate_threshold: Option<f32>,
+    pub filter: Option<Filter>,
+    /// Overrides the server config for rescoring the results using
+    /// the raw sparse vectors
+    pub rerank_sparse_with_raw_values: Option<bool>,
+    /// No. of candidates, as a multiple of `top_k`, that are rescored
+    /// using the raw sparse vectors
+    pub rerank_oversampling: Option<NonZeroUsize>,
+    /// Min. score of the results. If `top_k` isn't specified, all
+    /// the matches are returned, up to `RADIUS_SEARCH_MAX_RESULTS`.
+    pub score_threshold: Option<f32>,
+    #[serde(flatten)]
+    pub result_options: ResultOptionsDto,
 }
 
 #[derive(Deserialize, Debug)]
@@ -36,39 +151,419 @@ pub(crate) struct BatchSparseSearchRequestDto {
     pub query_terms_list: Vec<Vec<SparsePair>>,
     pub top_k: Option<usize>,
     pub early_terminate_threshold: Option<f32>,
+    pub filter: Option<Filter>,
+    /// Overrides the server config for rescoring the results using
+    /// the raw sparse vectors
+    pub rerank_sparse_with_raw_values: Option<bool>,
+    /// No. of candidates, as a multiple of `top_k`, that are rescored
+    /// using the raw sparse vectors
+    pub rerank_oversampling: Option<NonZeroUsize>,
+    /// Min. score of the results. If `top_k` isn't specified, all
+    /// the matches are returned, up to `RADIUS_SEARCH_MAX_RESULTS`.
+    pub score_threshold: Option<f32>,
+    #[serde(flatten)]
+    pub result_options: ResultOptionsDto,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum HybridFusion {
+    /// Reciprocal rank fusion, using `fusion_constant_k`
+    #[default]
+    Rrf,
+    /// Weighted sum of the scores of the components, normalized using
+    /// `normalization`
+    Weighted,
+}
+
+/// Weights of the components of a hybrid search in the fused scores
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub(crate) struct HybridWeightsDto {
+    #[serde(default = "default_hybrid_weight")]
+    pub dense: f32,
+    #[serde(default = "default_hybrid_weight")]
+    pub sparse: f32,
+    #[serde(default = "default_hybrid_weight")]
+    pub text: f32,
+}
+
+impl Default for HybridWeightsDto {
+    fn default() -> Self {
+        Self {
+            dense: default_hybrid_weight(),
+            sparse: default_hybrid_weight(),
+            text: default_hybrid_weight(),
+        }
+    }
 }
 
 #[derive(Deserialize, Debug)]
 pub(crate) struct HybridSearchRequestDto {
-    pub query_vector: Vec<f32>,
-    pub query_terms: Vec<SparsePair>,
+    /// Name of the dense vector space to search, the default one if
+    /// not specified
+    pub vector_name: Option<String>,
+    /// Query of the dense component
+    pub query_vector: Option<Vec<f32>>,
+    /// Query of the sparse component
+    pub query_terms: Option<Vec<SparsePair>>,
+    /// Query of the BM25 text component, searched in the TF-IDF index
+    pub query_text: Option<String>,
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    #[serde(default)]
+    pub fusion: HybridFusion,
+    #[serde(default = "default_fusion_constant_k")]
+    pub fusion_constant_k: f32,
+    #[serde(default)]
+    pub normalization: ScoreNormalization,
+    #[serde(default)]
+    pub weights: HybridWeightsDto,
+    /// Metadata filter applied to all the components of the search
+    pub filter: Option<Filter>,
+    /// Overrides the `ef_search` of the dense index
+    pub ef_search: Option<NonZeroU32>,
+    /// No. of candidates, as a multiple of the no. of results of each
+    /// component, that are rescored using the raw vectors
+    pub rerank_oversampling: Option<NonZeroUsize>,
+    /// Overrides the server config for rescoring the results of the
+    /// sparse component using the raw sparse vectors
+    pub rerank_sparse_with_raw_values: Option<bool>,
+    /// Diversifies the results using Maximal Marginal Relevance
+    pub diversity: Option<DiversityDto>,
+    #[serde(flatten)]
+    pub result_options: ResultOptionsDto,
+}
+
+/// Queries of the components of a hybrid search in a batch
+#[derive(Deserialize, Debug)]
+pub(crate) struct HybridQueryDto {
+    pub query_vector: Option<Vec<f32>>,
+    pub query_terms: Option<Vec<SparsePair>>,
+    pub query_text: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct BatchHybridSearchRequestDto {
+    /// Name of the dense vector space to search, the default one if
+    /// not specified
+    pub vector_name: Option<String>,
+    pub queries: Vec<HybridQueryDto>,
     #[serde(default = "default_top_k")]
     pub top_k: usize,
+    #[serde(default)]
+    pub fusion: HybridFusion,
     #[serde(default = "default_fusion_constant_k")]
     pub fusion_constant_k: f32,
+    #[serde(default)]
+    pub normalization: ScoreNormalization,
+    #[serde(default)]
+    pub weights: HybridWeightsDto,
+    /// Metadata filter applied to all the components of the searches
+    pub filter: Option<Filter>,
+    /// Overrides the `ef_search` of the dense index
+    pub ef_search: Option<NonZeroU32>,
+    /// No. of candidates, as a multiple of the no. of results of each
+    /// component, that are rescored using the raw vectors
+    pub rerank_oversampling: Option<NonZeroUsize>,
+    /// Overrides the server config for rescoring the results of the
+    /// sparse component using the raw sparse vectors
+    pub rerank_sparse_with_raw_values: Option<bool>,
+    /// Diversifies the results using Maximal Marginal Relevance
+    pub diversity: Option<DiversityDto>,
+    #[serde(flatten)]
+    pub result_options: ResultOptionsDto,
+}
+
+/// Example for recommendations, either a stored vector or a raw one
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub(crate) enum RecommendExampleDto {
+    Vector(Vec<f32>),
+    Id(ExternalId),
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RecommendStrategy {
+    /// Searches using a single query built by averaging the examples,
+    /// see `average_vector_query`
+    #[default]
+    AverageVector,
+    /// Scores the candidates by their similarity to the most similar
+    /// positive and negative examples, see `best_score`
+    BestScore,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct RecommendRequestDto {
+    /// Name of the dense vector space to search, the default one if
+    /// not specified
+    pub vector_name: Option<String>,
+    pub positive: Vec<RecommendExampleDto>,
+    #[serde(default)]
+    pub negative: Vec<RecommendExampleDto>,
+    #[serde(default)]
+    pub strategy: RecommendStrategy,
+    pub top_k: Option<usize>,
+    pub filter: Option<Filter>,
+    #[serde(flatten)]
+    pub result_options: ResultOptionsDto,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct SearchByIdRequestDto {
+    /// Id of the stored vector to search the neighbors of
+    pub id: ExternalId,
+    /// Index to be searched using the stored representation of the
+    /// vector in it. If not specified, the first index having the
+    /// vector is searched, in the order dense, sparse and TF-IDF.
+    pub index_type: Option<IndexType>,
+    /// Name of the dense vector space to search, the default one if
+    /// not specified
+    pub vector_name: Option<String>,
+    pub top_k: Option<usize>,
+    pub filter: Option<Filter>,
+    #[serde(flatten)]
+    pub result_options: ResultOptionsDto,
+}
+
+/// Options common to all the search requests, on the results and the
+/// version of the collection searched
+#[derive(Deserialize, Debug, Default)]
+pub(crate) struct ResultOptionsDto {
+    /// Searches the collection as of the given version, instead of
+    /// the current one. Only supported by dense, sparse, hybrid and
+    /// TF-IDF searches.
+    pub version: Option<VersionDto>,
+    /// Stored data of the matching vectors to be returned along with
+    /// the results
+    #[serde(default)]
+    pub include: Vec<IncludeField>,
+}
+
+/// Stored data of a vector that can be included in the search results
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum IncludeField {
+    Metadata,
+    DenseValues,
+    SparseValues,
+    Text,
 }
 
 #[derive(Serialize, Debug, Clone)]
 pub(crate) struct SearchResultItemDto {
-    pub id: VectorId,
+    pub id: ExternalId,
     pub score: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<MetadataFields>,
+    /// Dense values of the vector in the searched dense vector space,
+    /// or the default one for other kinds of searches
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dense_values: Option<Vec<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sparse_values: Option<Vec<SparsePair>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// Scores of the vector in the components of a hybrid search, as
+    /// returned by each of them before fusion
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub component_scores: Option<HybridComponentScoresDto>,
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub(crate) struct HybridComponentScoresDto {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dense: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sparse: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<f32>,
 }
 
 #[derive(Serialize, Debug)]
 pub(crate) struct SearchResponseDto {
     pub results: Vec<SearchResultItemDto>,
+    /// Whether the results of a radius search have been capped at
+    /// `RADIUS_SEARCH_MAX_RESULTS`, in which case more vectors may
+    /// match the score threshold
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
 }
 
 pub(crate) type BatchSearchResponseDto = Vec<SearchResponseDto>;
 
+/// Results of a search grouped by the value of a metadata field
+#[derive(Serialize, Debug)]
+pub(crate) struct SearchGroupDto {
+    pub value: FieldValue,
+    pub results: Vec<SearchResultItemDto>,
+}
+
+#[derive(Serialize, Debug)]
+pub(crate) struct GroupedSearchResponseDto {
+    pub groups: Vec<SearchGroupDto>,
+}
+
 #[derive(Deserialize, Debug)]
 pub(crate) struct FindSimilarTFIDFDocumentDto {
     pub query: String,
     pub top_k: Option<usize>,
+    pub filter: Option<Filter>,
+    #[serde(flatten)]
+    pub result_options: ResultOptionsDto,
 }
 
 #[derive(Deserialize, Debug)]
 pub(crate) struct BatchSearchTFIDFDocumentsDto {
     pub queries: Vec<String>,
     pub top_k: Option<usize>,
+    pub filter: Option<Filter>,
+    #[serde(flatten)]
+    pub result_options: ResultOptionsDto,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_de_dense_search_tuning_params() {
+        let input = r#"{"query_vector": [0.1, 0.2], "top_k": 5}"#;
+        let req: DenseSearchRequestDto = serde_json::from_str(input).unwrap();
+        assert!(!req.skip_rerank);
+        assert!(req.ef_search.is_none());
+        assert!(req.rerank_oversampling.is_none());
+        assert!(req.group_by.is_none());
+        assert!(req.diversity.is_none());
+
+        let input = r#"{"query_vector": [0.1, 0.2], "ef_search": 256, "rerank_oversampling": 2}"#;
+        let req: DenseSearchRequestDto = serde_json::from_str(input).unwrap();
+        assert_eq!(256, req.ef_search.unwrap().get());
+        assert_eq!(2, req.rerank_oversampling.unwrap().get());
+
+        let input = r#"{"query_vector": [0.1, 0.2], "diversity": {"fetch_k": 20}}"#;
+        let req: DenseSearchRequestDto = serde_json::from_str(input).unwrap();
+        let diversity = req.diversity.unwrap();
+        assert_eq!(0.5, diversity.lambda);
+        assert_eq!(20, diversity.fetch_k.unwrap().get());
+
+        // Zero values are rejected
+        let input = r#"{"query_vector": [0.1, 0.2], "ef_search": 0}"#;
+        assert!(serde_json::from_str::<DenseSearchRequestDto>(input).is_err());
+        let input = r#"{"query_vector": [0.1, 0.2], "rerank_oversampling": 0}"#;
+        assert!(serde_json::from_str::<DenseSearchRequestDto>(input).is_err());
+    }
+
+    #[test]
+    fn test_de_recommend_request() {
+        let input = r#"{"positive": [42, "doc-1", [0.1, 0.2]], "strategy": "best_score"}"#;
+        let req: RecommendRequestDto = serde_json::from_str(input).unwrap();
+        assert!(matches!(
+            req.positive[0],
+            RecommendExampleDto::Id(ExternalId::Int(42))
+        ));
+        assert!(matches!(
+            &req.positive[1],
+            RecommendExampleDto::Id(ExternalId::Str(id)) if id == "doc-1"
+        ));
+        assert!(matches!(
+            &req.positive[2],
+            RecommendExampleDto::Vector(vector) if vector == &[0.1, 0.2]
+        ));
+        assert!(req.negative.is_empty());
+        assert_eq!(RecommendStrategy::BestScore, req.strategy);
+
+        let input = r#"{"positive": [1], "negative": [2]}"#;
+        let req: RecommendRequestDto = serde_json::from_str(input).unwrap();
+        assert_eq!(RecommendStrategy::AverageVector, req.strategy);
+    }
+
+    #[test]
+    fn test_include_fields() {
+        let input = r#"{"query_terms": [], "include": ["metadata", "sparse_values", "text"]}"#;
+        let req: SparseSearchRequestDto = serde_json::from_str(input).unwrap();
+        assert_eq!(
+            vec![
+                IncludeField::Metadata,
+                IncludeField::SparseValues,
+                IncludeField::Text
+            ],
+            req.result_options.include
+        );
+
+        let input = r#"{"query": "foo"}"#;
+        let req: FindSimilarTFIDFDocumentDto = serde_json::from_str(input).unwrap();
+        assert!(req.result_options.include.is_empty());
+        assert!(req.result_options.version.is_none());
+
+        let input = r#"{"query_vector": [0.1], "version": {"number": 3}, "include": ["metadata"]}"#;
+        let req: DenseSearchRequestDto = serde_json::from_str(input).unwrap();
+        assert!(matches!(
+            req.result_options.version,
+            Some(VersionDto::Number(3))
+        ));
+        assert_eq!(vec![IncludeField::Metadata], req.result_options.include);
+
+        let input = r#"{"query_vector": [0.1], "include": ["vectors"]}"#;
+        assert!(serde_json::from_str::<DenseSearchRequestDto>(input).is_err());
+
+        // Fields that aren't included are omitted from the results
+        let item = SearchResultItemDto {
+            id: ExternalId::Int(1),
+            score: 0.5,
+            metadata: None,
+            dense_values: Some(vec![0.5]),
+            sparse_values: None,
+            text: None,
+            component_scores: None,
+        };
+        assert_eq!(
+            r#"{"id":1,"score":0.5,"dense_values":[0.5]}"#,
+            serde_json::to_string(&item).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_de_hybrid_request() {
+        let input = r#"{"query_vector": [0.1, 0.2], "query_terms": [[1, 0.5]]}"#;
+        let req: HybridSearchRequestDto = serde_json::from_str(input).unwrap();
+        assert!(req.query_text.is_none());
+        assert_eq!(HybridFusion::Rrf, req.fusion);
+        assert_eq!(60.0, req.fusion_constant_k);
+        assert_eq!(1.0, req.weights.text);
+
+        let input = r#"{
+            "query_text": "foo bar",
+            "query_vector": [0.1, 0.2],
+            "fusion": "weighted",
+            "normalization": "z_score",
+            "weights": {"dense": 0.3}
+        }"#;
+        let req: HybridSearchRequestDto = serde_json::from_str(input).unwrap();
+        assert!(req.query_terms.is_none());
+        assert_eq!(HybridFusion::Weighted, req.fusion);
+        assert_eq!(ScoreNormalization::ZScore, req.normalization);
+        assert_eq!(0.3, req.weights.dense);
+        assert_eq!(1.0, req.weights.sparse);
+    }
+
+    #[test]
+    fn test_de_batch_hybrid_request() {
+        let input = r#"{
+            "queries": [
+                {"query_vector": [0.1, 0.2], "query_text": "foo"},
+                {"query_terms": [[1, 0.5]]}
+            ],
+            "top_k": 5,
+            "fusion": "weighted"
+        }"#;
+        let req: BatchHybridSearchRequestDto = serde_json::from_str(input).unwrap();
+        assert_eq!(2, req.queries.len());
+        assert!(req.queries[0].query_terms.is_none());
+        assert_eq!(Some("foo"), req.queries[0].query_text.as_deref());
+        assert!(req.queries[1].query_vector.is_none());
+        assert_eq!(5, req.top_k);
+        assert_eq!(ScoreNormalization::MinMax, req.normalization);
+    }
 }
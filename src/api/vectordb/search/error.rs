@@ -9,6 +9,7 @@ use crate::models::common::WaCustomError;
 #[derive(Debug)]
 pub(crate) enum SearchError {
     CollectionNotFound(String),
+    VectorNotFound(String),
     IndexNotFound(String),
     SearchFailed(String),
     InvalidFilter(String),
@@ -22,6 +23,7 @@ impl Display for SearchError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             SearchError::CollectionNotFound(name) => write!(f, "Collection '{}' not found", name),
+            SearchError::VectorNotFound(id) => write!(f, "Vector '{}' not found", id),
             SearchError::IndexNotFound(msg) => write!(f, "Required index not found: {}", msg),
             SearchError::SearchFailed(msg) => write!(f, "Search operation failed: {}", msg),
             SearchError::InvalidFilter(msg) => write!(f, "Invalid metadata filter: {}", msg),
@@ -48,6 +50,7 @@ impl ResponseError for SearchError {
     fn status_code(&self) -> StatusCode {
         match self {
             SearchError::CollectionNotFound(_) => StatusCode::NOT_FOUND,
+            SearchError::VectorNotFound(_) => StatusCode::NOT_FOUND,
             SearchError::IndexNotFound(_) => StatusCode::BAD_REQUEST,
             SearchError::SearchFailed(_) => StatusCode::INTERNAL_SERVER_ERROR,
             SearchError::InvalidFilter(_) => StatusCode::BAD_REQUEST,
@@ -1,7 +1,8 @@
 use actix_web::{web, Scope};
 use controller::{
-    batch_dense_search, batch_sparse_search, batch_tf_idf_search, dense_search, hybrid_search,
-    sparse_search, tf_idf_search,
+    batch_dense_search, batch_hybrid_search, batch_sparse_search, batch_tf_idf_search,
+    dense_search, hybrid_search, multi_vector_search, recommend, search_by_id, sparse_search,
+    tf_idf_search,
 };
 
 mod controller;
@@ -19,4 +20,8 @@ pub(crate) fn search_module() -> Scope {
         .route("/tf-idf", web::post().to(tf_idf_search))
         .route("/batch-tf-idf", web::post().to(batch_tf_idf_search))
         .route("/hybrid", web::post().to(hybrid_search))
+        .route("/batch-hybrid", web::post().to(batch_hybrid_search))
+        .route("/multi-vector", web::post().to(multi_vector_search))
+        .route("/by-id", web::post().to(search_by_id))
+        .route("/recommend", web::post().to(recommend))
 }
@@ -1,29 +1,71 @@
-use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
-use std::collections::HashMap;
+use rayon::iter::{
+    IndexedParallelIterator, IntoParallelIterator, IntoParallelRefIterator, ParallelIterator,
+};
+use std::collections::{HashMap, HashSet};
+use std::num::{NonZeroU32, NonZeroUsize};
 use std::sync::Arc;
 
 use super::dtos;
 use super::error::SearchError;
+use crate::api::vectordb::indexes::dtos::IndexType;
+use crate::api::vectordb::vectors::repo::{
+    get_live_dense_embedding, get_live_document, get_live_sparse_embedding,
+};
+use crate::indexes::hnsw::HNSWIndex;
 use crate::indexes::tf_idf::TFIDFIndex;
 use crate::metadata::query_filtering::Filter;
+use crate::metadata::FieldValue;
 use crate::{
-    api_service::{ann_vector_query, batch_ann_vector_query},
+    api_service::{
+        ann_vector_query, batch_ann_vector_query, best_score_ann_query, grouped_ann_vector_query,
+        multi_vector_ann_query,
+    },
     app_context::AppContext,
     config_loader::Config,
     distance::dotproduct::DotProductDistance,
-    indexes::{inverted::types::SparsePair, inverted::InvertedIndex, tf_idf::process_text},
+    indexes::{
+        hnsw::types::{DenseSearchOptions, DiversityOptions},
+        inverted::types::SparsePair,
+        inverted::InvertedIndex,
+        tf_idf::process_text,
+        BEST_SCORE_MAX_POSITIVES, RADIUS_SEARCH_MAX_RESULTS,
+    },
     models::{
+        collection::Collection,
         common::WaCustomError,
+        diversity::mmr_select,
+        fusion::{fuse, normalize_scores, FusionMethod, ScoreNormalization},
+        recommend::average_vector_query,
         sparse_ann_query::{SparseAnnQueryBasic, SparseAnnResult},
-        types::{MetricResult, SparseVector, VectorId},
+        tombstones::Tombstones,
+        types::{DistanceMetric, MetricResult, SparseVector, VectorId},
+        versioning::{VersionRef, VersionSnapshot},
     },
 };
 
+/// Per-request overrides of the parameters for searching the sparse
+/// index, which otherwise default to the server config
+#[derive(Debug, Clone, Default)]
+pub struct SparseSearchOptions {
+    /// Overrides `search.early_terminate_threshold`
+    pub early_terminate_threshold: Option<f32>,
+    /// Overrides `rerank_sparse_with_raw_values`
+    pub rerank_with_raw_values: Option<bool>,
+    /// Overrides `sparse_raw_values_reranking_factor`
+    pub rerank_oversampling: Option<usize>,
+    /// Min. score of the results, applied to the final (i.e. reranked,
+    /// if enabled) scores
+    pub score_threshold: Option<f32>,
+    /// Searches the index as of the snapshot's version
+    pub as_of: Option<Arc<VersionSnapshot>>,
+}
+
 #[allow(dead_code)]
 pub(crate) async fn dense_search(
     ctx: Arc<AppContext>,
     collection_id: &str,
     request: dtos::DenseSearchRequestDto,
+    as_of: Option<Arc<VersionSnapshot>>,
 ) -> Result<Vec<(VectorId, MetricResult)>, WaCustomError> {
     let collection = ctx
         .ain_env
@@ -31,14 +73,19 @@ pub(crate) async fn dense_search(
         .get_collection(collection_id)
         .ok_or_else(|| WaCustomError::NotFound(format!("collection '{}'", collection_id)))?;
 
-    let hnsw_index = collection.get_hnsw_index().ok_or_else(|| {
-        WaCustomError::NotFound(format!(
-            "Dense index not found for collection '{}'",
-            collection_id
-        ))
-    })?;
+    let hnsw_index = collection
+        .get_dense_index(request.vector_name.as_deref())
+        .ok_or_else(|| {
+            WaCustomError::NotFound(format!(
+                "Dense index of vector '{}' not found for collection '{}'",
+                request.vector_name.as_deref().unwrap_or("default"),
+                collection_id
+            ))
+        })?;
 
     let metadata_filter: Option<Filter> = request.filter;
+    let diversity =
+        diversity_options(request.diversity).map_err(|_| WaCustomError::InvalidParams)?;
 
     ann_vector_query(
         ctx,
@@ -46,16 +93,77 @@ pub(crate) async fn dense_search(
         hnsw_index.clone(),
         request.query_vector,
         metadata_filter,
+        DenseSearchOptions {
+            ef_search: request.ef_search.map(NonZeroU32::get),
+            skip_rerank: request.skip_rerank,
+            rerank_oversampling: request.rerank_oversampling.map(NonZeroUsize::get),
+            score_threshold: request.score_threshold,
+            diversity,
+            as_of,
+        },
         request.top_k,
     )
     .await
 }
 
+/// Searches the dense index for the best `group_size` results for each
+/// of `groups` distinct values of the metadata field `group_by` of the
+/// request, see `grouped_ann_vector_query`
+pub(crate) async fn grouped_dense_search(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    request: dtos::DenseSearchRequestDto,
+    as_of: Option<Arc<VersionSnapshot>>,
+) -> Result<Vec<(FieldValue, Vec<(VectorId, MetricResult)>)>, WaCustomError> {
+    let collection = ctx
+        .ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or_else(|| WaCustomError::NotFound(format!("collection '{}'", collection_id)))?;
+
+    let hnsw_index = collection
+        .get_dense_index(request.vector_name.as_deref())
+        .ok_or_else(|| {
+            WaCustomError::NotFound(format!(
+                "Dense index of vector '{}' not found for collection '{}'",
+                request.vector_name.as_deref().unwrap_or("default"),
+                collection_id
+            ))
+        })?;
+
+    let group_by = request.group_by.ok_or(WaCustomError::InvalidParams)?;
+    let groups = request
+        .groups
+        .map_or(request.top_k.unwrap_or(10), NonZeroUsize::get);
+    let group_size = request.group_size.map_or(1, NonZeroUsize::get);
+
+    grouped_ann_vector_query(
+        ctx,
+        &collection,
+        hnsw_index.clone(),
+        request.query_vector,
+        request.filter,
+        DenseSearchOptions {
+            ef_search: request.ef_search.map(NonZeroU32::get),
+            skip_rerank: request.skip_rerank,
+            rerank_oversampling: request.rerank_oversampling.map(NonZeroUsize::get),
+            score_threshold: request.score_threshold,
+            diversity: None,
+            as_of,
+        },
+        &group_by,
+        groups,
+        group_size,
+    )
+    .await
+}
+
 #[allow(dead_code)]
 pub(crate) async fn batch_dense_search(
     ctx: Arc<AppContext>,
     collection_id: &str,
     request: dtos::BatchDenseSearchRequestDto,
+    as_of: Option<Arc<VersionSnapshot>>,
 ) -> Result<Vec<Vec<(VectorId, MetricResult)>>, WaCustomError> {
     let collection = ctx
         .ain_env
@@ -63,14 +171,19 @@ pub(crate) async fn batch_dense_search(
         .get_collection(collection_id)
         .ok_or_else(|| WaCustomError::NotFound(format!("collection '{}'", collection_id)))?;
 
-    let hnsw_index = collection.get_hnsw_index().ok_or_else(|| {
-        WaCustomError::NotFound(format!(
-            "Dense index not found for collection '{}'",
-            collection_id
-        ))
-    })?;
+    let hnsw_index = collection
+        .get_dense_index(request.vector_name.as_deref())
+        .ok_or_else(|| {
+            WaCustomError::NotFound(format!(
+                "Dense index of vector '{}' not found for collection '{}'",
+                request.vector_name.as_deref().unwrap_or("default"),
+                collection_id
+            ))
+        })?;
 
     let metadata_filter: Option<Filter> = request.filter;
+    let diversity =
+        diversity_options(request.diversity).map_err(|_| WaCustomError::InvalidParams)?;
 
     batch_ann_vector_query(
         ctx,
@@ -78,15 +191,89 @@ pub(crate) async fn batch_dense_search(
         hnsw_index.clone(),
         request.query_vectors,
         metadata_filter,
+        DenseSearchOptions {
+            ef_search: request.ef_search.map(NonZeroU32::get),
+            skip_rerank: request.skip_rerank,
+            rerank_oversampling: request.rerank_oversampling.map(NonZeroUsize::get),
+            score_threshold: request.score_threshold,
+            diversity,
+            as_of,
+        },
         request.top_k,
     )
     .await
 }
 
+pub(crate) async fn multi_vector_search(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    request: dtos::MultiVectorSearchRequestDto,
+) -> Result<Vec<(VectorId, f32)>, SearchError> {
+    let collection = ctx
+        .ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or_else(|| SearchError::CollectionNotFound(collection_id.to_string()))?;
+
+    if !collection
+        .meta
+        .dense_vector
+        .is_multi_vector(&request.vector_name)
+    {
+        return Err(SearchError::InvalidInput(format!(
+            "Vector '{}' is not a multi-vector space",
+            request.vector_name
+        )));
+    }
+    let hnsw_index = collection
+        .get_named_hnsw_index(&request.vector_name)
+        .ok_or_else(|| {
+            SearchError::IndexNotFound(format!(
+                "Dense index of vector '{}' not found for collection '{}'",
+                request.vector_name, collection_id
+            ))
+        })?;
+
+    if request.query_vectors.is_empty() {
+        return Err(SearchError::InvalidInput(
+            "At least one query vector is required".to_string(),
+        ));
+    }
+    if let Some(query) = request
+        .query_vectors
+        .iter()
+        .find(|query| query.len() != hnsw_index.dim)
+    {
+        return Err(SearchError::InvalidInput(format!(
+            "Query vector has {} dimensions, expected {}",
+            query.len(),
+            hnsw_index.dim
+        )));
+    }
+
+    let top_k = request.top_k.unwrap_or(10);
+    let candidates_per_query = request
+        .candidates_per_query
+        .unwrap_or(top_k.saturating_mul(4));
+
+    multi_vector_ann_query(
+        ctx.clone(),
+        &collection,
+        hnsw_index,
+        &request.vector_name,
+        request.query_vectors,
+        candidates_per_query,
+        Some(top_k),
+    )
+    .await
+    .map_err(|e| SearchError::SearchFailed(format!("Multi-vector query failed: {}", e)))
+}
+
 pub(crate) async fn sparse_search(
     ctx: Arc<AppContext>,
     collection_id: &str,
     request: dtos::SparseSearchRequestDto,
+    as_of: Option<Arc<VersionSnapshot>>,
 ) -> Result<Vec<(VectorId, MetricResult)>, WaCustomError> {
     let collection = ctx
         .ain_env
@@ -101,16 +288,22 @@ pub(crate) async fn sparse_search(
         ))
     })?;
 
-    let threshold = request
-        .early_terminate_threshold
-        .unwrap_or(ctx.config.search.early_terminate_threshold);
+    let options = SparseSearchOptions {
+        early_terminate_threshold: request.early_terminate_threshold,
+        rerank_with_raw_values: request.rerank_sparse_with_raw_values,
+        rerank_oversampling: request.rerank_oversampling.map(NonZeroUsize::get),
+        score_threshold: request.score_threshold,
+        as_of,
+    };
     // Directly call the logic for regular sparse
     sparse_ann_vector_query_logic(
         &ctx.config,
         inverted_index.clone(),
+        &collection,
         &request.query_terms,
         request.top_k,
-        threshold,
+        &options,
+        request.filter.as_ref(),
     )
 }
 
@@ -118,6 +311,7 @@ pub(crate) async fn batch_sparse_search(
     ctx: Arc<AppContext>,
     collection_id: &str,
     request: dtos::BatchSparseSearchRequestDto,
+    as_of: Option<Arc<VersionSnapshot>>,
 ) -> Result<Vec<Vec<(VectorId, MetricResult)>>, WaCustomError> {
     let collection = ctx
         .ain_env
@@ -132,26 +326,58 @@ pub(crate) async fn batch_sparse_search(
         ))
     })?;
 
-    let threshold = request
-        .early_terminate_threshold
-        .unwrap_or(ctx.config.search.early_terminate_threshold);
+    let options = SparseSearchOptions {
+        early_terminate_threshold: request.early_terminate_threshold,
+        rerank_with_raw_values: request.rerank_sparse_with_raw_values,
+        rerank_oversampling: request.rerank_oversampling.map(NonZeroUsize::get),
+        score_threshold: request.score_threshold,
+        as_of,
+    };
     // Directly call the logic for regular sparse batch
     batch_sparse_ann_vector_query_logic(
         &ctx.config,
         inverted_index.clone(),
+        &collection,
         &request.query_terms_list,
         request.top_k,
-        threshold,
+        &options,
+        request.filter.as_ref(),
     )
 }
 
+/// Returns true if the results of a radius search (i.e. one with a
+/// score threshold but without `top_k`) have been capped at
+/// `RADIUS_SEARCH_MAX_RESULTS`
+pub(crate) fn is_radius_search_truncated(
+    top_k: Option<usize>,
+    score_threshold: Option<f32>,
+    num_results: usize,
+) -> bool {
+    top_k.is_none() && score_threshold.is_some() && num_results >= RADIUS_SEARCH_MAX_RESULTS
+}
+
 pub fn sparse_ann_vector_query_logic(
     config: &Config,
     inverted_index: Arc<InvertedIndex>,
+    collection: &Collection,
     query: &[SparsePair],
     top_k: Option<usize>,
-    early_terminate_threshold: f32,
+    options: &SparseSearchOptions,
+    metadata_filter: Option<&Filter>,
 ) -> Result<Vec<(VectorId, MetricResult)>, WaCustomError> {
+    let early_terminate_threshold = options
+        .early_terminate_threshold
+        .unwrap_or(config.search.early_terminate_threshold);
+    let rerank_with_raw_values = options
+        .rerank_with_raw_values
+        .unwrap_or(config.rerank_sparse_with_raw_values);
+    let as_of = options.as_of.as_deref();
+    // Radius searches i.e. ones with a score threshold but without
+    // `top_k` are capped
+    let top_k = match (top_k, options.score_threshold) {
+        (None, Some(_)) => Some(RADIUS_SEARCH_MAX_RESULTS),
+        _ => top_k,
+    };
     let sparse_vec = SparseVector {
         vector_id: u32::MAX,
         entries: query.iter().map(|pair| (pair.0, pair.1)).collect(),
@@ -162,18 +388,36 @@ pub fn sparse_ann_vector_query_logic(
         inverted_index.root.root.quantization_bits,
         *inverted_index.values_upper_bound.read().unwrap(),
         early_terminate_threshold,
-        if config.rerank_sparse_with_raw_values {
-            config.sparse_raw_values_reranking_factor
+        if rerank_with_raw_values {
+            options
+                .rerank_oversampling
+                .unwrap_or(config.sparse_raw_values_reranking_factor)
         } else {
             1
         },
         top_k,
+        &collection.tombstones,
+        as_of,
+        |vector_id| {
+            metadata_filter.is_none_or(|filter| {
+                collection
+                    .vector_metadata
+                    .matches_as_of(&VectorId(vector_id as u64), filter, as_of)
+            })
+        },
     )?;
 
-    if config.rerank_sparse_with_raw_values {
-        finalize_sparse_ann_results(inverted_index, intermediate_results, query, top_k)
+    let mut results = if rerank_with_raw_values {
+        finalize_sparse_ann_results(
+            inverted_index,
+            &collection.tombstones,
+            intermediate_results,
+            query,
+            top_k,
+            as_of,
+        )?
     } else {
-        Ok(intermediate_results
+        let mut results: Vec<_> = intermediate_results
             .into_iter()
             .map(|result| {
                 (
@@ -181,17 +425,25 @@ pub fn sparse_ann_vector_query_logic(
                     MetricResult::DotProductDistance(DotProductDistance(result.similarity as f32)),
                 )
             })
-            .collect())
+            .collect();
+        results.sort_unstable_by(|(_, a), (_, b)| b.cmp(a));
+        results
+    };
+    if let Some(threshold) = options.score_threshold {
+        results.retain(|(_, score)| score.meets_threshold(threshold));
     }
+    Ok(results)
 }
 
 // Synchronous batch helper
 fn batch_sparse_ann_vector_query_logic(
     config: &Config,
     inverted_index: Arc<InvertedIndex>,
+    collection: &Collection,
     queries: &[Vec<SparsePair>],
     top_k: Option<usize>,
-    early_terminate_threshold: f32,
+    options: &SparseSearchOptions,
+    metadata_filter: Option<&Filter>,
 ) -> Result<Vec<Vec<(VectorId, MetricResult)>>, WaCustomError> {
     queries
         .par_iter()
@@ -199,9 +451,11 @@ fn batch_sparse_ann_vector_query_logic(
             sparse_ann_vector_query_logic(
                 config,
                 inverted_index.clone(),
+                collection,
                 query,
                 top_k,
-                early_terminate_threshold,
+                options,
+                metadata_filter,
             )
         })
         .collect()
@@ -210,18 +464,28 @@ fn batch_sparse_ann_vector_query_logic(
 // Change return type back to MetricResult
 fn finalize_sparse_ann_results(
     inverted_index: Arc<InvertedIndex>,
+    tombstones: &Tombstones,
     intermediate_results: Vec<SparseAnnResult>,
     query: &[SparsePair],
     k: Option<usize>,
+    as_of: Option<&VersionSnapshot>,
 ) -> Result<Vec<(VectorId, MetricResult)>, WaCustomError> {
     // <-- Return MetricResult
-    let mut results = Vec::with_capacity(k.unwrap_or(intermediate_results.len()));
+    let mut results = Vec::with_capacity(k.map_or(intermediate_results.len(), |k| {
+        k.min(intermediate_results.len())
+    }));
 
     for result in intermediate_results {
         let vector_u64_id = result.vector_id as u64;
         let vector_id_obj = VectorId(vector_u64_id);
 
-        match inverted_index.vec_raw_map.get_latest(vector_u64_id) {
+        // The raw vector the vector had as of the snapshot, if any
+        let raw_sparse_embedding = inverted_index
+            .vec_raw_map
+            .get_versioned(vector_u64_id)
+            .and_then(|item| tombstones.visible_item(item, as_of))
+            .map(|item| &item.value);
+        match raw_sparse_embedding {
             Some(raw_sparse_embedding_ref) => {
                 let sparse_pairs = raw_sparse_embedding_ref.raw_vec.clone();
                 let map: std::collections::HashMap<u32, f32> =
@@ -261,108 +525,890 @@ fn finalize_sparse_ann_results(
     Ok(results)
 }
 
+/// Searches the dense, sparse and text (BM25) components with the
+/// queries provided for them, fusing their results
+///
+/// Returns the fused score of every result along with its scores in
+/// the individual components.
 pub(crate) async fn hybrid_search(
     ctx: Arc<AppContext>,
     collection_id: &str,
     request: dtos::HybridSearchRequestDto,
-) -> Result<Vec<(VectorId, f32)>, SearchError> {
+    as_of: Option<Arc<VersionSnapshot>>,
+) -> Result<Vec<(VectorId, f32, dtos::HybridComponentScoresDto)>, SearchError> {
     let collection = ctx
         .ain_env
         .collections_map
         .get_collection(collection_id)
         .ok_or_else(|| WaCustomError::NotFound(format!("Collection '{}'", collection_id)))?;
 
-    let hnsw_index = collection.get_hnsw_index().ok_or_else(|| {
-        SearchError::IndexNotFound("Dense index required for hybrid search.".to_string())
-    })?;
+    if request.query_vector.is_none()
+        && request.query_terms.is_none()
+        && request.query_text.is_none()
+    {
+        return Err(SearchError::InvalidInput(
+            "At least one of query_vector, query_terms and query_text is required for hybrid search"
+                .to_string(),
+        ));
+    }
+    let method = hybrid_fusion_method(
+        request.fusion,
+        request.fusion_constant_k,
+        request.normalization,
+        &request.weights,
+    )?;
+
+    let diversity = diversity_options(request.diversity)?;
+    // With diversity, the results are selected from `fetch_k` fused
+    // results
+    let num_fused = diversity.map_or(request.top_k, |diversity| diversity.fetch_k(request.top_k));
+
+    let component_k = hybrid_component_k(&ctx.config, num_fused);
+    let sparse_options = SparseSearchOptions {
+        early_terminate_threshold: None,
+        rerank_with_raw_values: request.rerank_sparse_with_raw_values,
+        rerank_oversampling: request.rerank_oversampling.map(NonZeroUsize::get),
+        score_threshold: None,
+        as_of: as_of.clone(),
+    };
+
+    let sparse_results = match &request.query_terms {
+        Some(query_terms) => hybrid_sparse_component(
+            &ctx.config,
+            &collection,
+            query_terms,
+            component_k,
+            &sparse_options,
+            request.filter.as_ref(),
+        )?,
+        None => Vec::new(),
+    };
+
+    let text_results = match &request.query_text {
+        Some(query_text) => hybrid_text_component(
+            &collection,
+            query_text,
+            component_k,
+            request.filter.as_ref(),
+            as_of.as_deref(),
+        )?,
+        None => Vec::new(),
+    };
+
+    let dense_results: Vec<(VectorId, MetricResult)> = match request.query_vector {
+        None => Vec::new(),
+        Some(query_vector) => {
+            let hnsw_index = get_hybrid_dense_index(&collection, request.vector_name.as_deref())?;
+            ann_vector_query(
+                ctx.clone(),
+                &collection,
+                hnsw_index,
+                query_vector,
+                request.filter,
+                DenseSearchOptions {
+                    ef_search: request.ef_search.map(NonZeroU32::get),
+                    skip_rerank: false,
+                    rerank_oversampling: request.rerank_oversampling.map(NonZeroUsize::get),
+                    score_threshold: None,
+                    diversity: None,
+                    as_of: as_of.clone(),
+                },
+                Some(component_k),
+            )
+            .await
+            .map_err(|e| {
+                SearchError::SearchFailed(format!("Hybrid: Dense component failed: {}", e))
+            })?
+        }
+    };
+
+    let results = fuse_hybrid_results(
+        &dense_results,
+        &sparse_results,
+        text_results,
+        &request.weights,
+        method,
+        num_fused,
+    );
+    match diversity {
+        Some(diversity) => diversify_hybrid_results(
+            &collection,
+            request.vector_name.as_deref(),
+            results,
+            &diversity,
+            request.top_k,
+            as_of.as_deref(),
+        ),
+        None => Ok(results),
+    }
+}
+
+/// Returns the no. of candidates to retrieve from every component of a
+/// hybrid search fusing `num_fused` results
+///
+/// Every component retrieves more candidates than requested, so that
+/// results ranked lower by one of them can still make it to the top
+/// after fusion. As `num_fused` comes from the request, they are
+/// capped at `max_ef_search`, the widest dense search.
+fn hybrid_component_k(config: &Config, num_fused: usize) -> usize {
+    num_fused
+        .saturating_mul(3)
+        .min(config.search.max_ef_search as usize)
+}
+
+/// Runs the hybrid queries of the batch, searching each component for
+/// all the queries in parallel
+pub(crate) async fn batch_hybrid_search(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    request: dtos::BatchHybridSearchRequestDto,
+    as_of: Option<Arc<VersionSnapshot>>,
+) -> Result<Vec<Vec<(VectorId, f32, dtos::HybridComponentScoresDto)>>, SearchError> {
+    let collection = ctx
+        .ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or_else(|| WaCustomError::NotFound(format!("Collection '{}'", collection_id)))?;
+
+    if let Some(position) = request.queries.iter().position(|query| {
+        query.query_vector.is_none() && query.query_terms.is_none() && query.query_text.is_none()
+    }) {
+        return Err(SearchError::InvalidInput(format!(
+            "At least one of query_vector, query_terms and query_text is required for hybrid search (query {})",
+            position
+        )));
+    }
+    let method = hybrid_fusion_method(
+        request.fusion,
+        request.fusion_constant_k,
+        request.normalization,
+        &request.weights,
+    )?;
+
+    let diversity = diversity_options(request.diversity)?;
+    let num_fused = diversity.map_or(request.top_k, |diversity| diversity.fetch_k(request.top_k));
+    let component_k = hybrid_component_k(&ctx.config, num_fused);
+    let sparse_options = SparseSearchOptions {
+        early_terminate_threshold: None,
+        rerank_with_raw_values: request.rerank_sparse_with_raw_values,
+        rerank_oversampling: request.rerank_oversampling.map(NonZeroUsize::get),
+        score_threshold: None,
+        as_of: as_of.clone(),
+    };
+
+    let sparse_and_text_results = request
+        .queries
+        .par_iter()
+        .map(|query| {
+            let sparse_results = match &query.query_terms {
+                Some(query_terms) => hybrid_sparse_component(
+                    &ctx.config,
+                    &collection,
+                    query_terms,
+                    component_k,
+                    &sparse_options,
+                    request.filter.as_ref(),
+                )?,
+                None => Vec::new(),
+            };
+            let text_results = match &query.query_text {
+                Some(query_text) => hybrid_text_component(
+                    &collection,
+                    query_text,
+                    component_k,
+                    request.filter.as_ref(),
+                    as_of.as_deref(),
+                )?,
+                None => Vec::new(),
+            };
+            Ok((sparse_results, text_results))
+        })
+        .collect::<Result<Vec<_>, SearchError>>()?;
+
+    // The dense component is searched in a single batch for the queries
+    // having a query vector
+    let (dense_positions, dense_queries): (Vec<usize>, Vec<Vec<f32>>) = request
+        .queries
+        .iter()
+        .enumerate()
+        .filter_map(|(position, query)| Some((position, query.query_vector.clone()?)))
+        .unzip();
+    let mut dense_results = vec![Vec::new(); request.queries.len()];
+    if !dense_queries.is_empty() {
+        let hnsw_index = get_hybrid_dense_index(&collection, request.vector_name.as_deref())?;
+        let results = batch_ann_vector_query(
+            ctx.clone(),
+            &collection,
+            hnsw_index,
+            dense_queries,
+            request.filter.clone(),
+            DenseSearchOptions {
+                ef_search: request.ef_search.map(NonZeroU32::get),
+                skip_rerank: false,
+                rerank_oversampling: request.rerank_oversampling.map(NonZeroUsize::get),
+                score_threshold: None,
+                diversity: None,
+                as_of: as_of.clone(),
+            },
+            Some(component_k),
+        )
+        .await
+        .map_err(|e| SearchError::SearchFailed(format!("Hybrid: Dense component failed: {}", e)))?;
+        for (position, results) in dense_positions.into_iter().zip(results) {
+            dense_results[position] = results;
+        }
+    }
+
+    sparse_and_text_results
+        .into_par_iter()
+        .zip(dense_results)
+        .map(|((sparse_results, text_results), dense_results)| {
+            let results = fuse_hybrid_results(
+                &dense_results,
+                &sparse_results,
+                text_results,
+                &request.weights,
+                method,
+                num_fused,
+            );
+            match &diversity {
+                Some(diversity) => diversify_hybrid_results(
+                    &collection,
+                    request.vector_name.as_deref(),
+                    results,
+                    diversity,
+                    request.top_k,
+                    as_of.as_deref(),
+                ),
+                None => Ok(results),
+            }
+        })
+        .collect()
+}
+
+/// Validates the diversity parameters of a search request
+pub(crate) fn diversity_options(
+    diversity: Option<dtos::DiversityDto>,
+) -> Result<Option<DiversityOptions>, SearchError> {
+    let Some(diversity) = diversity else {
+        return Ok(None);
+    };
+    if !(0.0..=1.0).contains(&diversity.lambda) {
+        return Err(SearchError::InvalidInput(format!(
+            "Diversity lambda must be between 0 and 1, got {}",
+            diversity.lambda
+        )));
+    }
+    Ok(Some(DiversityOptions {
+        lambda: diversity.lambda,
+        fetch_k: diversity.fetch_k.map(NonZeroUsize::get),
+    }))
+}
+
+/// Resolves the version of the collection a search request is to be
+/// run as of, if any
+pub(crate) fn version_snapshot(
+    collection: &Collection,
+    version: Option<dtos::VersionDto>,
+) -> Result<Option<Arc<VersionSnapshot>>, SearchError> {
+    let Some(version) = version else {
+        return Ok(None);
+    };
+    let version = VersionRef::from(version);
+    match collection.version_snapshot(version)? {
+        Some(snapshot) => Ok(Some(Arc::new(snapshot))),
+        None => Err(SearchError::InvalidInput(format!(
+            "No committed version {}",
+            version
+        ))),
+    }
+}
+
+/// Selects `k` of the fused results of a hybrid search using Maximal
+/// Marginal Relevance over their raw dense embeddings, see `mmr_select`
+///
+/// The relevance of the results is their min-max normalized fused
+/// score. Results without a live dense embedding in the vector space
+/// (as of the snapshot `as_of`, if any) aren't considered similar to
+/// any other result.
+fn diversify_hybrid_results(
+    collection: &Collection,
+    vector_name: Option<&str>,
+    results: Vec<(VectorId, f32, dtos::HybridComponentScoresDto)>,
+    diversity: &DiversityOptions,
+    k: usize,
+    as_of: Option<&VersionSnapshot>,
+) -> Result<Vec<(VectorId, f32, dtos::HybridComponentScoresDto)>, SearchError> {
+    let hnsw_index = get_hybrid_dense_index(collection, vector_name)?;
+    let embeddings = results
+        .iter()
+        .map(|(id, _, _)| {
+            get_live_dense_embedding(collection, &hnsw_index, id, as_of)
+                .map_err(|e| SearchError::SearchFailed(e.to_string()))
+        })
+        .collect::<Result<Vec<_>, SearchError>>()?;
+    let vectors: Vec<&[f32]> = embeddings
+        .iter()
+        .map(|embedding| {
+            embedding
+                .as_ref()
+                .map_or(&[][..], |embedding| embedding.raw_vec.as_slice())
+        })
+        .collect();
+    let scores: Vec<f32> = results.iter().map(|(_, score, _)| *score).collect();
+    let relevance = normalize_scores(&scores, ScoreNormalization::MinMax);
+
+    let selected = mmr_select(&relevance, &vectors, diversity.lambda, k);
+    let mut results: Vec<_> = results.into_iter().map(Some).collect();
+    Ok(selected
+        .into_iter()
+        .filter_map(|position| results[position].take())
+        .collect())
+}
+
+fn get_hybrid_dense_index(
+    collection: &Collection,
+    vector_name: Option<&str>,
+) -> Result<Arc<HNSWIndex>, SearchError> {
+    collection.get_dense_index(vector_name).ok_or_else(|| {
+        SearchError::IndexNotFound(format!(
+            "Dense index of vector '{}' required for hybrid search.",
+            vector_name.unwrap_or("default")
+        ))
+    })
+}
 
-    // Perform Search on *Available* Sparse Index (Synchronous Call)
-    let sparse_results: Vec<(VectorId, MetricResult)> = if let Some(inverted_index) =
-        collection.get_inverted_index()
+/// Validates the fusion parameters of a hybrid search, returning the
+/// fusion method to be used
+fn hybrid_fusion_method(
+    fusion: dtos::HybridFusion,
+    constant_k: f32,
+    normalization: ScoreNormalization,
+    weights: &dtos::HybridWeightsDto,
+) -> Result<FusionMethod, SearchError> {
+    if [weights.dense, weights.sparse, weights.text]
+        .iter()
+        .any(|weight| !weight.is_finite() || *weight < 0.0)
     {
-        let sparse_k = request.top_k * 3;
-        let threshold = ctx.config.search.early_terminate_threshold;
-        // Call synchronous helper
+        return Err(SearchError::InvalidInput(
+            "Hybrid search weights must be non-negative".to_string(),
+        ));
+    }
+    Ok(match fusion {
+        dtos::HybridFusion::Rrf => {
+            if constant_k < 0.0 {
+                log::warn!("RRF fusion_constant_k ({}) is non-positive.", constant_k);
+            }
+            FusionMethod::ReciprocalRank { constant_k }
+        }
+        dtos::HybridFusion::Weighted => FusionMethod::Weighted(normalization),
+    })
+}
+
+/// Searches the sparse component of a hybrid search, falling back to
+/// the TF-IDF index if the collection has no sparse index
+fn hybrid_sparse_component(
+    config: &Config,
+    collection: &Collection,
+    query_terms: &[SparsePair],
+    k: usize,
+    options: &SparseSearchOptions,
+    metadata_filter: Option<&Filter>,
+) -> Result<Vec<(VectorId, MetricResult)>, SearchError> {
+    if let Some(inverted_index) = collection.get_inverted_index() {
         sparse_ann_vector_query_logic(
-            &ctx.config,
-            inverted_index.clone(),
-            &request.query_terms,
-            Some(sparse_k),
-            threshold,
+            config,
+            inverted_index,
+            collection,
+            query_terms,
+            Some(k),
+            options,
+            metadata_filter,
         )
         .map_err(|e| {
             SearchError::SearchFailed(format!("Hybrid: Sparse component (regular) failed: {}", e))
-        })?
+        })
     } else if let Some(idf_index) = collection.get_tf_idf_index() {
         log::debug!(
             "Using IDF index for hybrid sparse component in collection '{}'",
-            collection_id
+            collection.meta.name
         );
-        let sparse_k = request.top_k * 3;
         let query_sparse_vector = SparseVector {
             vector_id: u32::MAX,
-            entries: request.query_terms.iter().map(|p| (p.0, p.1)).collect(),
+            entries: query_terms.iter().map(|p| (p.0, p.1)).collect(),
         };
+        let corpus = options
+            .as_of
+            .as_deref()
+            .map(|as_of| idf_index.corpus_snapshot(&collection.tombstones, as_of));
         // Call synchronous search_bm25
         SparseAnnQueryBasic::new(query_sparse_vector)
-            .search_bm25(&idf_index.root, Some(sparse_k))
+            .search_bm25(&idf_index.root, Some(k), corpus.as_ref(), |document_id| {
+                is_tf_idf_document_match(
+                    &idf_index,
+                    collection,
+                    document_id,
+                    metadata_filter,
+                    options.as_of.as_deref(),
+                )
+            })
             .map(|idf_results| {
+                // Map internal document ids back to external vector ids,
+                // same as `tf_idf_ann_vector_query`
                 idf_results
                     .into_iter()
-                    .map(|res| {
-                        (
-                            VectorId(res.document_id as u64),
-                            MetricResult::DotProductDistance(DotProductDistance(res.score)),
-                        )
+                    .filter_map(|res| {
+                        idf_index
+                            .vec_raw_map
+                            .get_latest(res.document_id as u64)
+                            .map(|(ext_id, _)| {
+                                (
+                                    ext_id.clone(),
+                                    MetricResult::DotProductDistance(DotProductDistance(res.score)),
+                                )
+                            })
                     })
                     .collect()
             })
             .map_err(|e| {
                 SearchError::SearchFailed(format!("Hybrid: Sparse component (IDF) failed: {}", e))
-            })?
+            })
     } else {
-        return Err(SearchError::IndexNotFound(
+        Err(SearchError::IndexNotFound(
             "Sparse index (regular or IDF) required for hybrid search.".to_string(),
-        ));
-    };
+        ))
+    }
+}
 
-    let dense_k = request.top_k * 3;
-    let dense_results = ann_vector_query(
-        ctx.clone(),
-        &collection,
-        hnsw_index.clone(),
-        request.query_vector,
-        None, // Pass None for filter
-        Some(dense_k),
+/// Searches the text (BM25) component of a hybrid search in the TF-IDF
+/// index
+fn hybrid_text_component(
+    collection: &Collection,
+    query_text: &str,
+    k: usize,
+    metadata_filter: Option<&Filter>,
+    as_of: Option<&VersionSnapshot>,
+) -> Result<Vec<(VectorId, f32)>, SearchError> {
+    let tf_idf_index = collection.get_tf_idf_index().ok_or_else(|| {
+        SearchError::IndexNotFound(
+            "TF-IDF index required for hybrid search with query_text.".to_string(),
+        )
+    })?;
+    tf_idf_ann_vector_query(
+        tf_idf_index,
+        collection,
+        query_text,
+        Some(k),
+        metadata_filter,
+        as_of,
     )
-    .await
-    .map_err(|e| SearchError::SearchFailed(format!("Hybrid: Dense component failed: {}", e)))?;
+    .map_err(|e| SearchError::SearchFailed(format!("Hybrid: Text component failed: {}", e)))
+}
+
+/// Fuses the results of the components of a hybrid search, returning
+/// the top `k` results along with their scores in the components
+fn fuse_hybrid_results(
+    dense_results: &[(VectorId, MetricResult)],
+    sparse_results: &[(VectorId, MetricResult)],
+    text_results: Vec<(VectorId, f32)>,
+    weights: &dtos::HybridWeightsDto,
+    method: FusionMethod,
+    k: usize,
+) -> Vec<(VectorId, f32, dtos::HybridComponentScoresDto)> {
+    // Scores are fused such that higher is better, irrespective of
+    // whether the dense metric is a distance
+    let to_fused_scores = |results: &[(VectorId, MetricResult)]| -> Vec<(VectorId, f32)> {
+        results
+            .iter()
+            .map(|(id, metric)| (id.clone(), metric.similarity()))
+            .collect()
+    };
+    let dense_scores = to_fused_scores(dense_results);
+    let sparse_scores = to_fused_scores(sparse_results);
+    let mut final_results = fuse(
+        &[
+            (&dense_scores, weights.dense),
+            (&sparse_scores, weights.sparse),
+            (&text_results, weights.text),
+        ],
+        method,
+    );
+    final_results.truncate(k);
+
+    let to_score_map = |results: &[(VectorId, MetricResult)]| -> HashMap<VectorId, f32> {
+        results
+            .iter()
+            .map(|(id, metric)| (id.clone(), metric.get_value()))
+            .collect()
+    };
+    let dense_score_map = to_score_map(dense_results);
+    let sparse_score_map = to_score_map(sparse_results);
+    let text_score_map: HashMap<VectorId, f32> = text_results.into_iter().collect();
+
+    final_results
+        .into_iter()
+        .map(|(id, score)| {
+            let component_scores = dtos::HybridComponentScoresDto {
+                dense: dense_score_map.get(&id).copied(),
+                sparse: sparse_score_map.get(&id).copied(),
+                text: text_score_map.get(&id).copied(),
+            };
+            (id, score, component_scores)
+        })
+        .collect()
+}
+
+/// Recommends vectors based on the positive and negative examples,
+/// using the given strategy
+///
+/// The stored vectors used as examples are excluded from the results.
+pub(crate) async fn recommend(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    request: dtos::RecommendRequestDto,
+) -> Result<Vec<(VectorId, f32)>, SearchError> {
+    let collection = ctx
+        .ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or_else(|| SearchError::CollectionNotFound(collection_id.to_string()))?;
+
+    let hnsw_index = collection
+        .get_dense_index(request.vector_name.as_deref())
+        .ok_or_else(|| {
+            SearchError::IndexNotFound(format!(
+                "Dense index of vector '{}' not found for collection '{}'",
+                request.vector_name.as_deref().unwrap_or("default"),
+                collection_id
+            ))
+        })?;
 
-    let mut final_scores: HashMap<VectorId, f32> = HashMap::new();
-    let constant_k = request.fusion_constant_k;
-    if constant_k < 0.0 {
-        log::warn!("RRF fusion_constant_k ({}) is non-positive.", constant_k);
+    if request.positive.is_empty() {
+        return Err(SearchError::InvalidInput(
+            "At least one positive example is required".to_string(),
+        ));
+    }
+    let distance_metric = *hnsw_index.distance_metric.read().unwrap();
+    if request.strategy == dtos::RecommendStrategy::BestScore
+        && matches!(distance_metric, DistanceMetric::Hamming)
+    {
+        return Err(SearchError::InvalidInput(
+            "Best score strategy is not supported for hamming distance".to_string(),
+        ));
     }
-    for (rank, (vector_id, _score)) in dense_results.iter().enumerate() {
-        let score = 1.0 / (rank as f32 + constant_k + f32::EPSILON);
-        final_scores.insert(vector_id.clone(), score);
+    if request.strategy == dtos::RecommendStrategy::BestScore
+        && request.positive.len() > BEST_SCORE_MAX_POSITIVES
+    {
+        return Err(SearchError::InvalidInput(format!(
+            "Best score strategy supports at most {} positive examples",
+            BEST_SCORE_MAX_POSITIVES
+        )));
     }
-    for (rank, (vector_id, _score)) in sparse_results.iter().enumerate() {
-        let score = 1.0 / (rank as f32 + constant_k + f32::EPSILON);
-        *final_scores.entry(vector_id.clone()).or_insert(0.0) += score;
+
+    let mut example_ids = HashSet::new();
+    let positives =
+        resolve_recommend_examples(&collection, &hnsw_index, request.positive, &mut example_ids)?;
+    let negatives =
+        resolve_recommend_examples(&collection, &hnsw_index, request.negative, &mut example_ids)?;
+
+    let top_k = request.top_k.unwrap_or(10);
+    // The stored examples may be among the results, hence as many more
+    // results are requested
+    let k = top_k.saturating_add(example_ids.len());
+
+    let results = match request.strategy {
+        dtos::RecommendStrategy::AverageVector => ann_vector_query(
+            ctx.clone(),
+            &collection,
+            hnsw_index,
+            average_vector_query(&positives, &negatives),
+            request.filter,
+            DenseSearchOptions::default(),
+            Some(k),
+        )
+        .await?
+        .into_iter()
+        .map(|(id, score)| (id, score.get_value()))
+        .collect(),
+        dtos::RecommendStrategy::BestScore => {
+            best_score_ann_query(
+                ctx.clone(),
+                &collection,
+                hnsw_index,
+                positives,
+                negatives,
+                request.filter,
+                k,
+            )
+            .await?
+        }
+    };
+
+    let mut results: Vec<_> = results
+        .into_iter()
+        .filter(|(id, _)| !example_ids.contains(id))
+        .collect();
+    results.truncate(top_k);
+    Ok(results)
+}
+
+/// Returns the raw vectors of the recommendation examples, loading
+/// the stored ones from the dense index
+///
+/// Ids of the stored examples are added to `example_ids`.
+fn resolve_recommend_examples(
+    collection: &Collection,
+    hnsw_index: &HNSWIndex,
+    examples: Vec<dtos::RecommendExampleDto>,
+    example_ids: &mut HashSet<VectorId>,
+) -> Result<Vec<Vec<f32>>, SearchError> {
+    examples
+        .into_iter()
+        .map(|example| {
+            let vector = match example {
+                dtos::RecommendExampleDto::Vector(vector) => vector,
+                dtos::RecommendExampleDto::Id(id) => {
+                    let vector_not_found = || SearchError::VectorNotFound(id.to_string());
+                    let vector_id = collection
+                        .get_internal_id(&id)?
+                        .ok_or_else(vector_not_found)?;
+                    let embedding =
+                        get_live_dense_embedding(collection, hnsw_index, &vector_id, None)
+                            .map_err(|e| SearchError::SearchFailed(e.to_string()))?
+                            .ok_or_else(vector_not_found)?;
+                    example_ids.insert(vector_id);
+                    embedding.raw_vec.as_ref().clone()
+                }
+            };
+            if vector.len() != hnsw_index.dim {
+                return Err(SearchError::InvalidInput(format!(
+                    "Example vector has {} dimensions, expected {}",
+                    vector.len(),
+                    hnsw_index.dim
+                )));
+            }
+            Ok(vector)
+        })
+        .collect()
+}
+
+/// Searches the neighbors of a stored vector, using its representation
+/// in the dense, sparse or TF-IDF index
+///
+/// The vector itself is excluded from the results.
+pub(crate) async fn search_by_id(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    request: dtos::SearchByIdRequestDto,
+) -> Result<Vec<(VectorId, f32)>, SearchError> {
+    let collection = ctx
+        .ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or_else(|| SearchError::CollectionNotFound(collection_id.to_string()))?;
+
+    let vector_not_found = || SearchError::VectorNotFound(request.id.to_string());
+    let vector_id = collection
+        .get_internal_id(&request.id)?
+        .ok_or_else(vector_not_found)?;
+
+    let top_k = request.top_k.unwrap_or(10);
+    // One more result is requested as the vector itself is expected to
+    // be the nearest one
+    let k = top_k.saturating_add(1);
+    let filter = request.filter.as_ref();
+    let dense_index = collection.get_dense_index(request.vector_name.as_deref());
+
+    let results = match request.index_type {
+        Some(IndexType::Dense) => {
+            let hnsw_index = dense_index.ok_or_else(|| {
+                SearchError::IndexNotFound(format!(
+                    "Dense index of vector '{}' not found for collection '{}'",
+                    request.vector_name.as_deref().unwrap_or("default"),
+                    collection_id
+                ))
+            })?;
+            dense_search_by_id(ctx.clone(), &collection, hnsw_index, &vector_id, filter, k).await?
+        }
+        Some(IndexType::Sparse) => {
+            let inverted_index = collection.get_inverted_index().ok_or_else(|| {
+                SearchError::IndexNotFound(format!(
+                    "Sparse index not found for collection '{}'",
+                    collection_id
+                ))
+            })?;
+            sparse_search_by_id(
+                &ctx.config,
+                &collection,
+                inverted_index,
+                &vector_id,
+                filter,
+                k,
+            )?
+        }
+        Some(IndexType::TfIdf) => {
+            let tf_idf_index = collection.get_tf_idf_index().ok_or_else(|| {
+                SearchError::IndexNotFound(format!(
+                    "Sparse IDF index not found for collection '{}'",
+                    collection_id
+                ))
+            })?;
+            tf_idf_search_by_id(&collection, tf_idf_index, &vector_id, filter, k)?
+        }
+        None => {
+            let mut results = None;
+            if let Some(hnsw_index) = dense_index {
+                results =
+                    dense_search_by_id(ctx.clone(), &collection, hnsw_index, &vector_id, filter, k)
+                        .await?;
+            }
+            if let (None, Some(inverted_index)) = (&results, collection.get_inverted_index()) {
+                results = sparse_search_by_id(
+                    &ctx.config,
+                    &collection,
+                    inverted_index,
+                    &vector_id,
+                    filter,
+                    k,
+                )?;
+            }
+            if let (None, Some(tf_idf_index)) = (&results, collection.get_tf_idf_index()) {
+                results = tf_idf_search_by_id(&collection, tf_idf_index, &vector_id, filter, k)?;
+            }
+            results
+        }
     }
+    .ok_or_else(vector_not_found)?;
+
+    let mut results: Vec<_> = results
+        .into_iter()
+        .filter(|(id, _)| *id != vector_id)
+        .collect();
+    results.truncate(top_k);
+    Ok(results)
+}
 
-    let mut final_results: Vec<(VectorId, f32)> = final_scores.into_iter().collect();
-    final_results.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
-    final_results.truncate(request.top_k);
+/// Searches the dense index using the stored embedding of the vector,
+/// returns None if the vector isn't live in the index
+async fn dense_search_by_id(
+    ctx: Arc<AppContext>,
+    collection: &Collection,
+    hnsw_index: Arc<HNSWIndex>,
+    vector_id: &VectorId,
+    metadata_filter: Option<&Filter>,
+    k: usize,
+) -> Result<Option<Vec<(VectorId, f32)>>, SearchError> {
+    let Some(embedding) = get_live_dense_embedding(collection, &hnsw_index, vector_id, None)
+        .map_err(|e| SearchError::SearchFailed(e.to_string()))?
+    else {
+        return Ok(None);
+    };
+    let results = ann_vector_query(
+        ctx,
+        collection,
+        hnsw_index,
+        embedding.raw_vec.as_ref().clone(),
+        metadata_filter.cloned(),
+        DenseSearchOptions::default(),
+        Some(k),
+    )
+    .await?;
+    Ok(Some(
+        results
+            .into_iter()
+            .map(|(id, score)| (id, score.get_value()))
+            .collect(),
+    ))
+}
+
+/// Searches the sparse index using the stored sparse embedding of the
+/// vector, returns None if the vector isn't live in the index
+fn sparse_search_by_id(
+    config: &Config,
+    collection: &Collection,
+    inverted_index: Arc<InvertedIndex>,
+    vector_id: &VectorId,
+    metadata_filter: Option<&Filter>,
+    k: usize,
+) -> Result<Option<Vec<(VectorId, f32)>>, SearchError> {
+    let Some(embedding) = get_live_sparse_embedding(collection, &inverted_index, vector_id, None)
+    else {
+        return Ok(None);
+    };
+    let query = embedding.raw_vec.clone();
+    let results = sparse_ann_vector_query_logic(
+        config,
+        inverted_index.clone(),
+        collection,
+        &query,
+        Some(k),
+        &SparseSearchOptions::default(),
+        metadata_filter,
+    )?;
+    Ok(Some(
+        results
+            .into_iter()
+            .map(|(id, score)| (id, score.get_value()))
+            .collect(),
+    ))
+}
+
+/// Searches the TF-IDF index using the stored raw text of the vector,
+/// returns None if the vector isn't live in the index
+fn tf_idf_search_by_id(
+    collection: &Collection,
+    tf_idf_index: Arc<TFIDFIndex>,
+    vector_id: &VectorId,
+    metadata_filter: Option<&Filter>,
+    k: usize,
+) -> Result<Option<Vec<(VectorId, f32)>>, SearchError> {
+    let Some((_, text)) = get_live_document(collection, &tf_idf_index, vector_id, None) else {
+        return Ok(None);
+    };
+    let Some(text) = text.clone() else {
+        return Err(SearchError::InvalidInput(
+            "Raw text of the vector is not stored, hence it can't be searched by id".to_string(),
+        ));
+    };
+    let results = tf_idf_ann_vector_query(
+        tf_idf_index.clone(),
+        collection,
+        &text,
+        Some(k),
+        metadata_filter,
+        None,
+    )?;
+    Ok(Some(results))
+}
 
-    Ok(final_results)
+/// Returns true if the document is live and matches the metadata
+/// filter (if any), as of the snapshot `as_of` (if any)
+fn is_tf_idf_document_match(
+    tf_idf_index: &TFIDFIndex,
+    collection: &Collection,
+    document_id: u32,
+    metadata_filter: Option<&Filter>,
+    as_of: Option<&VersionSnapshot>,
+) -> bool {
+    if !tf_idf_index.is_document_live(document_id, &collection.tombstones, as_of) {
+        return false;
+    }
+    let Some(filter) = metadata_filter else {
+        return true;
+    };
+    tf_idf_index
+        .vec_raw_map
+        .get_latest(document_id as u64)
+        .is_some_and(|(id, _)| collection.vector_metadata.matches_as_of(id, filter, as_of))
 }
 
 pub fn tf_idf_ann_vector_query(
     tf_idf_index: Arc<TFIDFIndex>,
+    collection: &Collection,
     query: &str,
     top_k: Option<usize>,
+    metadata_filter: Option<&Filter>,
+    as_of: Option<&VersionSnapshot>,
 ) -> Result<Vec<(VectorId, f32)>, WaCustomError> {
     // Return f32 directly
     let entries = process_text(
@@ -377,7 +1423,21 @@ pub fn tf_idf_ann_vector_query(
         entries,
     };
 
-    let results = SparseAnnQueryBasic::new(sparse_vec).search_bm25(&tf_idf_index.root, top_k)?;
+    let corpus = as_of.map(|as_of| tf_idf_index.corpus_snapshot(&collection.tombstones, as_of));
+    let results = SparseAnnQueryBasic::new(sparse_vec).search_bm25(
+        &tf_idf_index.root,
+        top_k,
+        corpus.as_ref(),
+        |document_id| {
+            is_tf_idf_document_match(
+                &tf_idf_index,
+                collection,
+                document_id,
+                metadata_filter,
+                as_of,
+            )
+        },
+    )?;
 
     // Map internal document ID back to external VectorId using vec_raw_map
     Ok(results
@@ -394,12 +1454,24 @@ pub fn tf_idf_ann_vector_query(
 
 fn batch_tf_idf_ann_vector_query(
     tf_idf_index: Arc<TFIDFIndex>,
+    collection: &Collection,
     queries: &[String],
     top_k: Option<usize>,
+    metadata_filter: Option<&Filter>,
+    as_of: Option<&VersionSnapshot>,
 ) -> Result<Vec<Vec<(VectorId, f32)>>, WaCustomError> {
     queries
         .par_iter() // Use parallel iterator
-        .map(|query| tf_idf_ann_vector_query(tf_idf_index.clone(), query, top_k))
+        .map(|query| {
+            tf_idf_ann_vector_query(
+                tf_idf_index.clone(),
+                collection,
+                query,
+                top_k,
+                metadata_filter,
+                as_of,
+            )
+        })
         .collect() // Collect results
 }
 
@@ -407,6 +1479,7 @@ pub(crate) async fn tf_idf_search(
     ctx: Arc<AppContext>,
     collection_id: &str,
     request: dtos::FindSimilarTFIDFDocumentDto,
+    as_of: Option<&VersionSnapshot>,
 ) -> Result<Vec<(VectorId, f32)>, WaCustomError> {
     let collection = ctx
         .ain_env
@@ -423,13 +1496,21 @@ pub(crate) async fn tf_idf_search(
     })?;
 
     // Call the helper directly
-    tf_idf_ann_vector_query(tf_idf_index, &request.query, request.top_k)
+    tf_idf_ann_vector_query(
+        tf_idf_index,
+        &collection,
+        &request.query,
+        request.top_k,
+        request.filter.as_ref(),
+        as_of,
+    )
 }
 
 pub(crate) async fn batch_tf_idf_search(
     ctx: Arc<AppContext>,
     collection_id: &str,
     request: dtos::BatchSearchTFIDFDocumentsDto,
+    as_of: Option<&VersionSnapshot>,
 ) -> Result<Vec<Vec<(VectorId, f32)>>, WaCustomError> {
     let collection = ctx
         .ain_env
@@ -446,5 +1527,655 @@ pub(crate) async fn batch_tf_idf_search(
     })?;
 
     // Call the helper directly
-    batch_tf_idf_ann_vector_query(tf_idf_index, &request.queries, request.top_k)
+    batch_tf_idf_ann_vector_query(
+        tf_idf_index,
+        &collection,
+        &request.queries,
+        request.top_k,
+        request.filter.as_ref(),
+        as_of,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use lmdb::{Cursor, Transaction, WriteFlags};
+    use serde_json::{json, Value};
+
+    use crate::{
+        api::vectordb::test_utils::{delete, test_context, upsert, TestCollection},
+        macros::key,
+        metadata::{self, FieldValue, MetadataFields},
+        models::{
+            collection::NamedDenseVectorOptions, external_ids::ExternalId,
+            vector_metadata::VectorMetadata,
+        },
+    };
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_hybrid_search_with_tf_idf_index() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            tf_idf: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        // Internal document ids are assigned from 0, hence they don't
+        // coincide with the vector ids
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 100, "text": "quick fox" },
+                { "id": 200, "text": "lazy dog" },
+            ]),
+        )
+        .await;
+
+        let query_terms: Vec<_> = process_text("quick", 40, 1.0, 1.2, 0.75)
+            .into_iter()
+            .map(|(term_hash, weight)| json!([term_hash, weight]))
+            .collect();
+        let request =
+            serde_json::from_value(json!({ "query_terms": query_terms, "top_k": 10 })).unwrap();
+        let ids: Vec<_> = hybrid_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(id, _, _)| id.0)
+            .collect();
+        assert_eq!(vec![100], ids);
+
+        // The no. of candidates of the components doesn't overflow
+        let request = serde_json::from_value(json!({
+            "query_text": "quick",
+            "top_k": usize::MAX,
+        }))
+        .unwrap();
+        let ids: Vec<_> = hybrid_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(id, _, _)| id.0)
+            .collect();
+        assert_eq!(vec![100], ids);
+    }
+
+    #[actix_web::test]
+    async fn test_batch_hybrid_search() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            tf_idf: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 100, "text": "quick fox" },
+                { "id": 200, "text": "lazy dog" },
+            ]),
+        )
+        .await;
+
+        // Results are returned per query, in the order of the queries,
+        // and the no. of candidates of the components doesn't overflow
+        let request = serde_json::from_value(json!({
+            "queries": [{ "query_text": "dog" }, { "query_text": "quick" }],
+            "top_k": usize::MAX,
+        }))
+        .unwrap();
+        let ids: Vec<Vec<_>> = batch_hybrid_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|results| results.into_iter().map(|(id, _, _)| id.0).collect())
+            .collect();
+        assert_eq!(vec![vec![200], vec![100]], ids);
+    }
+
+    #[actix_web::test]
+    async fn test_sparse_search_filters_legacy_vectors() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            sparse: true,
+            metadata_schema: Some(json!({
+                "fields": [{ "name": "tag", "values": ["a", "b"] }],
+                "supported_conditions": [],
+            })),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                {
+                    "id": 1,
+                    "dense_values": [0.1, 0.2, 0.3, 0.4],
+                    "sparse_indices": [1],
+                    "sparse_values": [0.5],
+                    "metadata": { "tag": "a" },
+                },
+                {
+                    "id": 2,
+                    "dense_values": [0.4, 0.3, 0.2, 0.1],
+                    "sparse_indices": [1],
+                    "sparse_values": [0.7],
+                    "metadata": { "tag": "b" },
+                },
+            ]),
+        )
+        .await;
+        let collection = ctx
+            .ain_env
+            .collections_map
+            .get_collection(&collection_id)
+            .unwrap();
+
+        // Older releases only indexed the metadata with the dense
+        // embeddings, and persisted the metadata updated afterwards
+        // without its version
+        let lmdb = &collection.lmdb;
+        let mut txn = lmdb.env.begin_rw_txn().unwrap();
+        let keys: Vec<_> = txn
+            .open_ro_cursor(*lmdb.db)
+            .unwrap()
+            .iter_from(key!(f:VectorId(0)))
+            .map(|(k, _)| k.to_vec())
+            .take_while(|k| k[0] == 11)
+            .collect();
+        for k in keys {
+            txn.del(*lmdb.db, &k, None).unwrap();
+        }
+        txn.del(*lmdb.db, &key!(m:vector_metadata_recorded), None)
+            .unwrap();
+        let fields: MetadataFields =
+            HashMap::from([("tag".to_string(), FieldValue::String("a".to_string()))]);
+        let fields = serde_cbor::to_vec(&fields).unwrap();
+        txn.put(*lmdb.db, &key!(f:VectorId(2)), &fields, WriteFlags::empty())
+            .unwrap();
+        txn.commit().unwrap();
+
+        let search = |tag: &str| {
+            let request = serde_json::from_value(json!({
+                "query_terms": [[1, 1.0]],
+                "top_k": 10,
+                "filter": { "Is": { "field_name": "tag", "field_value": tag, "operator": "Equal" } },
+            }))
+            .unwrap();
+            let (ctx, collection_id) = (ctx.clone(), collection_id.clone());
+            async move {
+                let mut ids: Vec<_> = sparse_search(ctx, &collection_id, request, None)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|(id, _)| id.0)
+                    .collect();
+                ids.sort();
+                ids
+            }
+        };
+        assert!(search("a").await.is_empty());
+
+        VectorMetadata::backfill(&collection).unwrap();
+        assert_eq!(vec![1, 2], search("a").await);
+        assert!(search("b").await.is_empty());
+    }
+
+    #[actix_web::test]
+    async fn test_dense_search_ef_search_override() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        let vectors: Vec<_> = (1..=40)
+            .map(|id| {
+                let x = id as f32 / 40.0;
+                json!({ "id": id, "dense_values": [x, 1.0 - x, x * x, 0.5] })
+            })
+            .collect();
+        upsert(&ctx, &collection_id, json!(vectors)).await;
+
+        let search = |ef_search: u32| {
+            let request = serde_json::from_value(json!({
+                "query_vector": [0.5, 0.5, 0.25, 0.5],
+                "top_k": 20,
+                "ef_search": ef_search,
+            }))
+            .unwrap();
+            let (ctx, collection_id) = (ctx.clone(), collection_id.clone());
+            async move {
+                dense_search(ctx, &collection_id, request, None)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|(id, _)| id.0)
+                    .collect::<Vec<_>>()
+            }
+        };
+        // The 20 vectors nearest to the query, by cosine similarity
+        let mut expected: Vec<_> = (1..=40u64)
+            .map(|id| {
+                let x = id as f32 / 40.0;
+                let values = [x, 1.0 - x, x * x, 0.5];
+                let query = [0.5, 0.5, 0.25, 0.5];
+                let dot: f32 = values.iter().zip(query).map(|(a, b)| a * b).sum();
+                let norm = |v: &[f32]| v.iter().map(|a| a * a).sum::<f32>().sqrt();
+                (id, dot / (norm(&values) * norm(&query)))
+            })
+            .collect();
+        expected.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        let expected: Vec<_> = expected.into_iter().take(20).map(|(id, _)| id).collect();
+
+        // The no. of candidates retrieved from the index depends on the
+        // `ef_search` of the request, instead of the index's one
+        assert!(search(4).await.len() < 20);
+        assert_eq!(expected, search(64).await);
+        // Overrides above the configured max. are capped
+        assert_eq!(expected, search(u32::MAX).await);
+    }
+
+    #[actix_web::test]
+    async fn test_dense_search_with_diversity() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "dense_values": [1.0, 0.0, 0.0, 0.0] },
+                { "id": 2, "dense_values": [0.99, 0.01, 0.0, 0.0] },
+                { "id": 3, "dense_values": [0.7, 0.7, 0.0, 0.0] },
+            ]),
+        )
+        .await;
+
+        let search = |top_k: usize| {
+            let request = serde_json::from_value(json!({
+                "query_vector": [1.0, 0.0, 0.0, 0.0],
+                "top_k": top_k,
+                "diversity": { "lambda": 0.1 },
+            }))
+            .unwrap();
+            let (ctx, collection_id) = (ctx.clone(), collection_id.clone());
+            async move {
+                let results = dense_search(ctx, &collection_id, request, None)
+                    .await
+                    .unwrap();
+                results.into_iter().map(|(id, _)| id.0).collect::<Vec<_>>()
+            }
+        };
+        // The near duplicate of the most relevant vector is passed over
+        assert_eq!(vec![1, 3], search(2).await);
+        // The no. of candidates doesn't overflow
+        assert_eq!(vec![1, 3, 2], search(usize::MAX).await);
+    }
+
+    #[actix_web::test]
+    async fn test_grouped_dense_search_with_filter() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        // The vectors nearest to the query are all in the same group
+        let vectors: Vec<_> = (1..=40)
+            .map(|id| {
+                let x = id as f32 / 100.0;
+                let (dense_values, tag) = if id <= 30 {
+                    (json!([1.0, x, 0.0, 0.0]), "a")
+                } else {
+                    (json!([0.1, 1.0, x, 0.0]), ["b", "c", "d"][id % 3])
+                };
+                json!({ "id": id, "dense_values": dense_values, "metadata": { "tag": tag } })
+            })
+            .collect();
+        upsert(&ctx, &collection_id, json!(vectors)).await;
+
+        // The candidates filtered out don't stop the search from
+        // widening until the groups are filled
+        let request = serde_json::from_value(json!({
+            "query_vector": [1.0, 0.0, 0.0, 0.0],
+            "ef_search": 2,
+            "group_by": "tag",
+            "groups": 3,
+            "group_size": 2,
+            "filter": { "Is": { "field_name": "tag", "field_value": "a", "operator": "NotEqual" } },
+        }))
+        .unwrap();
+        let mut grouped = grouped_dense_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap();
+        grouped.sort_by_key(|(value, _)| format!("{:?}", value));
+        let tags: Vec<_> = grouped.iter().map(|(value, _)| value.clone()).collect();
+        assert_eq!(
+            ["b", "c", "d"]
+                .map(|tag| FieldValue::String(tag.to_string()))
+                .to_vec(),
+            tags
+        );
+        for (tag, hits) in grouped {
+            assert_eq!(2, hits.len());
+            // Hits are those of the group, most similar first
+            assert!(hits.iter().all(|(id, _)| {
+                id.0 > 30
+                    && FieldValue::String(["b", "c", "d"][id.0 as usize % 3].to_string()) == tag
+            }));
+            assert!(hits[0].1.get_value() >= hits[1].1.get_value());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_radius_search_truncation() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            sparse: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        let vectors: Vec<_> = (1..=RADIUS_SEARCH_MAX_RESULTS + 1)
+            .map(|id| json!({ "id": id, "sparse_indices": [1], "sparse_values": [0.5] }))
+            .collect();
+        upsert(&ctx, &collection_id, json!(vectors)).await;
+
+        let search = |top_k: Option<usize>| {
+            let request = serde_json::from_value(json!({
+                "query_terms": [[1, 1.0]],
+                "top_k": top_k,
+                "score_threshold": 0.1,
+            }))
+            .unwrap();
+            let (ctx, collection_id) = (ctx.clone(), collection_id.clone());
+            async move {
+                let results = sparse_search(ctx, &collection_id, request, None)
+                    .await
+                    .unwrap();
+                // Every vector is returned at most once
+                let ids: HashSet<_> = results.iter().map(|(id, _)| id.0).collect();
+                assert_eq!(ids.len(), results.len());
+                results.len()
+            }
+        };
+        let num_results = search(None).await;
+        assert_eq!(RADIUS_SEARCH_MAX_RESULTS, num_results);
+        assert!(is_radius_search_truncated(None, Some(0.1), num_results));
+
+        let num_results = search(Some(5)).await;
+        assert_eq!(5, num_results);
+        assert!(!is_radius_search_truncated(Some(5), Some(0.1), num_results));
+    }
+
+    #[actix_web::test]
+    async fn test_dense_search_filter_on_unknown_field() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            metadata_schema: Some(json!({
+                "fields": [
+                    { "name": "tag", "values": ["a", "b"] },
+                    { "name": "lang", "values": ["en", "de"] },
+                ],
+                "supported_conditions": [],
+            })),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                {
+                    "id": 1,
+                    "dense_values": [0.1, 0.2, 0.3, 0.4],
+                    "metadata": { "tag": "a", "lang": "en" },
+                },
+                {
+                    "id": 2,
+                    "dense_values": [0.4, 0.3, 0.2, 0.1],
+                    "metadata": { "tag": "a", "lang": "de" },
+                },
+            ]),
+        )
+        .await;
+        let search = |filter: Value| {
+            let request = serde_json::from_value(json!({
+                "query_vector": [0.1, 0.2, 0.3, 0.4],
+                "top_k": 10,
+                "filter": filter,
+            }))
+            .unwrap();
+            let (ctx, collection_id) = (ctx.clone(), collection_id.clone());
+            async move { dense_search(ctx, &collection_id, request, None).await }
+        };
+
+        let result = search(json!({
+            "Is": { "field_name": "year", "field_value": 2020, "operator": "Equal" },
+        }))
+        .await;
+        assert!(matches!(
+            result,
+            Err(WaCustomError::MetadataError(metadata::Error::InvalidField(
+                _
+            )))
+        ));
+
+        // Conjunctions not covered by an `And` condition of the schema
+        // are applied to the results instead
+        let results = search(json!({
+            "And": [
+                { "field_name": "tag", "field_value": "a", "operator": "Equal" },
+                { "field_name": "lang", "field_value": "de", "operator": "Equal" },
+            ],
+        }))
+        .await
+        .unwrap();
+        let ids: Vec<_> = results.into_iter().map(|(id, _)| id.0).collect();
+        assert_eq!(vec![2], ids);
+    }
+
+    #[actix_web::test]
+    async fn test_recommend_excludes_examples() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "dense_values": [1.0, 0.1, 0.0, 0.0] },
+                { "id": 2, "dense_values": [0.9, 0.2, 0.1, 0.0] },
+                { "id": 3, "dense_values": [0.1, 0.1, 1.0, 0.1] },
+                { "id": 4, "dense_values": [0.0, 0.0, 0.1, 1.0] },
+            ]),
+        )
+        .await;
+
+        for strategy in ["average_vector", "best_score"] {
+            let request = serde_json::from_value(json!({
+                "positive": [1],
+                "negative": [4],
+                "strategy": strategy,
+                "top_k": usize::MAX,
+            }))
+            .unwrap();
+            let ids: Vec<_> = recommend(ctx.clone(), &collection_id, request)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(id, _)| id.0)
+                .collect();
+            assert_eq!(vec![2, 3], ids, "{}", strategy);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_search_by_id_excludes_source_vector() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "dense_values": [0.1, 0.2, 0.3, 0.4] },
+                { "id": 2, "dense_values": [0.1, 0.2, 0.3, 0.5] },
+                { "id": 3, "dense_values": [0.4, 0.3, 0.2, 0.1] },
+            ]),
+        )
+        .await;
+
+        for top_k in [2, usize::MAX] {
+            let request = serde_json::from_value(json!({ "id": 1, "top_k": top_k })).unwrap();
+            let ids: Vec<_> = search_by_id(ctx.clone(), &collection_id, request)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(id, _)| id.0)
+                .collect();
+            assert_eq!(vec![2, 3], ids);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_search_by_id_index_fallthrough() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            sparse: true,
+            tf_idf: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "dense_values": [0.1, 0.2, 0.3, 0.4] },
+                { "id": 2, "dense_values": [0.4, 0.3, 0.2, 0.1] },
+                { "id": 11, "sparse_indices": [1, 2], "sparse_values": [0.5, 0.5] },
+                { "id": 12, "sparse_indices": [1], "sparse_values": [0.7] },
+                { "id": 21, "text": "quick brown fox" },
+                { "id": 22, "text": "quick fox" },
+                { "id": 23, "text": "lazy dog" },
+            ]),
+        )
+        .await;
+
+        // Without an index type, the first index having the vector is
+        // searched, in the order dense, sparse and TF-IDF
+        let search = |id: u64, top_k: usize| {
+            let request = serde_json::from_value(json!({ "id": id, "top_k": top_k })).unwrap();
+            let (ctx, collection_id) = (ctx.clone(), collection_id.clone());
+            async move {
+                let results = search_by_id(ctx, &collection_id, request).await.unwrap();
+                results.into_iter().map(|(id, _)| id.0).collect::<Vec<_>>()
+            }
+        };
+
+        for top_k in [10, usize::MAX] {
+            assert_eq!(vec![2], search(1, top_k).await);
+            assert_eq!(vec![12], search(11, top_k).await);
+            assert_eq!(vec![22], search(21, top_k).await);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_search_by_id_tf_idf_without_text() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            tf_idf: true,
+            store_raw_text: false,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "text": "quick fox" },
+                { "id": 2, "text": "quick dog" },
+            ]),
+        )
+        .await;
+
+        let request = serde_json::from_value(json!({ "id": 1 })).unwrap();
+        let result = search_by_id(ctx.clone(), &collection_id, request).await;
+        assert!(matches!(result, Err(SearchError::InvalidInput(_))));
+    }
+
+    #[actix_web::test]
+    async fn test_multi_vector_search_skips_deleted_documents() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            named_vectors: vec![NamedDenseVectorOptions {
+                name: "tokens".to_string(),
+                dimension: 2,
+                multi_vector: true,
+            }],
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "multi_dense_values": { "tokens": [[1.0, 0.0]] } },
+                { "id": 2, "multi_dense_values": { "tokens": [[0.9, 0.1]] } },
+                { "id": 3, "multi_dense_values": { "tokens": [[0.7, 0.3]] } },
+                { "id": 4, "multi_dense_values": { "tokens": [[0.0, 1.0]] } },
+            ]),
+        )
+        .await;
+        delete(&ctx, &collection_id, ExternalId::Int(1)).await;
+
+        // The deleted document's vector is the nearest one, yet it
+        // doesn't take up any of the candidates
+        let request = serde_json::from_value(json!({
+            "vector_name": "tokens",
+            "query_vectors": [[1.0, 0.0]],
+            "top_k": 2,
+            "candidates_per_query": 2,
+        }))
+        .unwrap();
+        let ids: Vec<_> = multi_vector_search(ctx.clone(), &collection_id, request)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id.0)
+            .collect();
+        assert_eq!(vec![2, 3], ids);
+    }
 }
@@ -1,11 +1,20 @@
+use crate::api::vectordb::vectors::repo::{
+    get_live_dense_embedding, get_live_document, get_live_sparse_embedding,
+};
 use crate::app_context::AppContext;
+use crate::models::collection::Collection;
 use crate::models::common::WaCustomError;
+use crate::models::types::VectorId;
+use crate::models::versioning::VersionSnapshot;
 use std::sync::Arc;
 
 use super::dtos::{
-    BatchDenseSearchRequestDto, BatchSearchResponseDto, BatchSearchTFIDFDocumentsDto,
-    BatchSparseSearchRequestDto, DenseSearchRequestDto, FindSimilarTFIDFDocumentDto,
-    HybridSearchRequestDto, SearchResponseDto, SearchResultItemDto, SparseSearchRequestDto,
+    BatchDenseSearchRequestDto, BatchHybridSearchRequestDto, BatchSearchResponseDto,
+    BatchSearchTFIDFDocumentsDto, BatchSparseSearchRequestDto, DenseSearchRequestDto,
+    FindSimilarTFIDFDocumentDto, GroupedSearchResponseDto, HybridComponentScoresDto,
+    HybridSearchRequestDto, IncludeField, MultiVectorSearchRequestDto, RecommendRequestDto,
+    ResultOptionsDto, SearchByIdRequestDto, SearchGroupDto, SearchResponseDto, SearchResultItemDto,
+    SparseSearchRequestDto,
 };
 use super::error::SearchError;
 use super::repo;
@@ -16,21 +25,78 @@ pub(crate) async fn dense_search(
     collection_id: &str,
     request: DenseSearchRequestDto,
 ) -> Result<SearchResponseDto, SearchError> {
-    let results = repo::dense_search(ctx, collection_id, request)
+    let collection = get_collection(&ctx, collection_id)?;
+    let as_of = repo::version_snapshot(&collection, request.result_options.version)?;
+    let include = request.result_options.include.clone();
+    let (top_k, score_threshold) = (request.top_k, request.score_threshold);
+    let vector_name = request.vector_name.clone();
+    // Validated up front so that an invalid lambda is reported as such
+    repo::diversity_options(request.diversity)?;
+    let results = repo::dense_search(ctx.clone(), collection_id, request, as_of.clone())
         .await
         .map_err(|e| match e {
             WaCustomError::NotFound(msg) => SearchError::IndexNotFound(msg),
+            WaCustomError::MetadataError(e) => SearchError::InvalidFilter(e.to_string()),
             other => SearchError::SearchFailed(format!("Repo dense search failed: {}", other)),
         })?;
 
+    let truncated = repo::is_radius_search_truncated(top_k, score_threshold, results.len());
     Ok(SearchResponseDto {
-        results: results
+        truncated,
+        results: to_search_results(
+            &collection,
+            results
+                .into_iter()
+                .map(|(id, metric)| (id, metric.get_value())),
+            &include,
+            vector_name.as_deref(),
+            as_of.as_deref(),
+        )?,
+    })
+}
+
+pub(crate) async fn grouped_dense_search(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    request: DenseSearchRequestDto,
+) -> Result<GroupedSearchResponseDto, SearchError> {
+    if request.diversity.is_some() {
+        return Err(SearchError::InvalidInput(
+            "Diversity can't be combined with group_by".to_string(),
+        ));
+    }
+    let collection = get_collection(&ctx, collection_id)?;
+    let as_of = repo::version_snapshot(&collection, request.result_options.version)?;
+    let include = request.result_options.include.clone();
+    let vector_name = request.vector_name.clone();
+    let grouped = repo::grouped_dense_search(ctx.clone(), collection_id, request, as_of.clone())
+        .await
+        .map_err(|e| match e {
+            WaCustomError::NotFound(msg) => SearchError::IndexNotFound(msg),
+            WaCustomError::MetadataError(e) => SearchError::InvalidFilter(e.to_string()),
+            other => {
+                SearchError::SearchFailed(format!("Repo grouped dense search failed: {}", other))
+            }
+        })?;
+
+    Ok(GroupedSearchResponseDto {
+        groups: grouped
             .into_iter()
-            .map(|(id, metric)| SearchResultItemDto {
-                id,
-                score: metric.get_value(),
+            .map(|(value, results)| {
+                Ok(SearchGroupDto {
+                    value,
+                    results: to_search_results(
+                        &collection,
+                        results
+                            .into_iter()
+                            .map(|(id, metric)| (id, metric.get_value())),
+                        &include,
+                        vector_name.as_deref(),
+                        as_of.as_deref(),
+                    )?,
+                })
             })
-            .collect(),
+            .collect::<Result<_, SearchError>>()?,
     })
 }
 
@@ -40,27 +106,39 @@ pub(crate) async fn batch_dense_search(
     collection_id: &str,
     request: BatchDenseSearchRequestDto,
 ) -> Result<BatchSearchResponseDto, SearchError> {
-    let results_list = repo::batch_dense_search(ctx, collection_id, request)
+    let collection = get_collection(&ctx, collection_id)?;
+    let as_of = repo::version_snapshot(&collection, request.result_options.version)?;
+    let include = request.result_options.include.clone();
+    let (top_k, score_threshold) = (request.top_k, request.score_threshold);
+    let vector_name = request.vector_name.clone();
+    repo::diversity_options(request.diversity)?;
+    let results_list = repo::batch_dense_search(ctx.clone(), collection_id, request, as_of.clone())
         .await
         .map_err(|e| match e {
             WaCustomError::NotFound(msg) => SearchError::IndexNotFound(msg),
+            WaCustomError::MetadataError(e) => SearchError::InvalidFilter(e.to_string()),
             other => {
                 SearchError::SearchFailed(format!("Repo batch dense search failed: {}", other))
             }
         })?;
 
-    Ok(results_list
+    results_list
         .into_iter()
-        .map(|results| SearchResponseDto {
-            results: results
-                .into_iter()
-                .map(|(id, metric)| SearchResultItemDto {
-                    id,
-                    score: metric.get_value(),
-                })
-                .collect(),
+        .map(|results| {
+            Ok(SearchResponseDto {
+                truncated: repo::is_radius_search_truncated(top_k, score_threshold, results.len()),
+                results: to_search_results(
+                    &collection,
+                    results
+                        .into_iter()
+                        .map(|(id, metric)| (id, metric.get_value())),
+                    &include,
+                    vector_name.as_deref(),
+                    as_of.as_deref(),
+                )?,
+            })
         })
-        .collect())
+        .collect()
 }
 
 pub(crate) async fn sparse_search(
@@ -68,7 +146,11 @@ pub(crate) async fn sparse_search(
     collection_id: &str,
     request: SparseSearchRequestDto,
 ) -> Result<SearchResponseDto, SearchError> {
-    let results = repo::sparse_search(ctx, collection_id, request)
+    let collection = get_collection(&ctx, collection_id)?;
+    let as_of = repo::version_snapshot(&collection, request.result_options.version)?;
+    let include = request.result_options.include.clone();
+    let (top_k, score_threshold) = (request.top_k, request.score_threshold);
+    let results = repo::sparse_search(ctx.clone(), collection_id, request, as_of.clone())
         .await
         .map_err(|e| match e {
             // Map specific WaCustomError variants if needed
@@ -76,14 +158,18 @@ pub(crate) async fn sparse_search(
             other => SearchError::SearchFailed(format!("Repo sparse search failed: {}", other)),
         })?;
 
+    let truncated = repo::is_radius_search_truncated(top_k, score_threshold, results.len());
     Ok(SearchResponseDto {
-        results: results
-            .into_iter()
-            .map(|(id, metric)| SearchResultItemDto {
-                id,
-                score: metric.get_value(),
-            })
-            .collect(),
+        truncated,
+        results: to_search_results(
+            &collection,
+            results
+                .into_iter()
+                .map(|(id, metric)| (id, metric.get_value())),
+            &include,
+            None,
+            as_of.as_deref(),
+        )?,
     })
 }
 
@@ -92,27 +178,37 @@ pub(crate) async fn batch_sparse_search(
     collection_id: &str,
     request: BatchSparseSearchRequestDto,
 ) -> Result<BatchSearchResponseDto, SearchError> {
-    let results_list = repo::batch_sparse_search(ctx, collection_id, request)
-        .await
-        .map_err(|e| match e {
-            WaCustomError::NotFound(msg) => SearchError::IndexNotFound(msg),
-            other => {
-                SearchError::SearchFailed(format!("Repo batch sparse search failed: {}", other))
-            }
-        })?;
+    let collection = get_collection(&ctx, collection_id)?;
+    let as_of = repo::version_snapshot(&collection, request.result_options.version)?;
+    let include = request.result_options.include.clone();
+    let (top_k, score_threshold) = (request.top_k, request.score_threshold);
+    let results_list =
+        repo::batch_sparse_search(ctx.clone(), collection_id, request, as_of.clone())
+            .await
+            .map_err(|e| match e {
+                WaCustomError::NotFound(msg) => SearchError::IndexNotFound(msg),
+                other => {
+                    SearchError::SearchFailed(format!("Repo batch sparse search failed: {}", other))
+                }
+            })?;
 
-    Ok(results_list
+    results_list
         .into_iter()
-        .map(|results| SearchResponseDto {
-            results: results
-                .into_iter()
-                .map(|(id, metric)| SearchResultItemDto {
-                    id,
-                    score: metric.get_value(),
-                })
-                .collect(),
+        .map(|results| {
+            Ok(SearchResponseDto {
+                truncated: repo::is_radius_search_truncated(top_k, score_threshold, results.len()),
+                results: to_search_results(
+                    &collection,
+                    results
+                        .into_iter()
+                        .map(|(id, metric)| (id, metric.get_value())),
+                    &include,
+                    None,
+                    as_of.as_deref(),
+                )?,
+            })
         })
-        .collect())
+        .collect()
 }
 
 pub(crate) async fn hybrid_search(
@@ -120,13 +216,100 @@ pub(crate) async fn hybrid_search(
     collection_id: &str,
     request: HybridSearchRequestDto,
 ) -> Result<SearchResponseDto, SearchError> {
-    let results = repo::hybrid_search(ctx, collection_id, request).await?;
+    let collection = get_collection(&ctx, collection_id)?;
+    let as_of = repo::version_snapshot(&collection, request.result_options.version)?;
+    let include = request.result_options.include.clone();
+    let vector_name = request.vector_name.clone();
+    let results = repo::hybrid_search(ctx.clone(), collection_id, request, as_of.clone()).await?;
 
     Ok(SearchResponseDto {
-        results: results
-            .into_iter()
-            .map(|(id, score)| SearchResultItemDto { id, score })
-            .collect(),
+        truncated: false,
+        results: to_hybrid_search_results(
+            &collection,
+            results,
+            &include,
+            vector_name.as_deref(),
+            as_of.as_deref(),
+        )?,
+    })
+}
+
+pub(crate) async fn batch_hybrid_search(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    request: BatchHybridSearchRequestDto,
+) -> Result<BatchSearchResponseDto, SearchError> {
+    let collection = get_collection(&ctx, collection_id)?;
+    let as_of = repo::version_snapshot(&collection, request.result_options.version)?;
+    let include = request.result_options.include.clone();
+    let vector_name = request.vector_name.clone();
+    let results_list =
+        repo::batch_hybrid_search(ctx.clone(), collection_id, request, as_of.clone()).await?;
+
+    results_list
+        .into_iter()
+        .map(|results| {
+            Ok(SearchResponseDto {
+                truncated: false,
+                results: to_hybrid_search_results(
+                    &collection,
+                    results,
+                    &include,
+                    vector_name.as_deref(),
+                    as_of.as_deref(),
+                )?,
+            })
+        })
+        .collect()
+}
+
+pub(crate) async fn recommend(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    request: RecommendRequestDto,
+) -> Result<SearchResponseDto, SearchError> {
+    let collection = get_collection(&ctx, collection_id)?;
+    reject_version(&request.result_options, "recommendations")?;
+    let include = request.result_options.include.clone();
+    let vector_name = request.vector_name.clone();
+    let results = repo::recommend(ctx.clone(), collection_id, request).await?;
+
+    Ok(SearchResponseDto {
+        truncated: false,
+        results: to_search_results(&collection, results, &include, vector_name.as_deref(), None)?,
+    })
+}
+
+pub(crate) async fn search_by_id(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    request: SearchByIdRequestDto,
+) -> Result<SearchResponseDto, SearchError> {
+    let collection = get_collection(&ctx, collection_id)?;
+    reject_version(&request.result_options, "searches by id")?;
+    let include = request.result_options.include.clone();
+    let vector_name = request.vector_name.clone();
+    let results = repo::search_by_id(ctx.clone(), collection_id, request).await?;
+
+    Ok(SearchResponseDto {
+        truncated: false,
+        results: to_search_results(&collection, results, &include, vector_name.as_deref(), None)?,
+    })
+}
+
+pub(crate) async fn multi_vector_search(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    request: MultiVectorSearchRequestDto,
+) -> Result<SearchResponseDto, SearchError> {
+    let collection = get_collection(&ctx, collection_id)?;
+    reject_version(&request.result_options, "multi-vector searches")?;
+    let include = request.result_options.include.clone();
+    let results = repo::multi_vector_search(ctx.clone(), collection_id, request).await?;
+
+    Ok(SearchResponseDto {
+        truncated: false,
+        results: to_search_results(&collection, results, &include, None, None)?,
     })
 }
 
@@ -135,7 +318,10 @@ pub(crate) async fn tf_idf_search(
     collection_id: &str,
     request: FindSimilarTFIDFDocumentDto,
 ) -> Result<SearchResponseDto, SearchError> {
-    let results = repo::tf_idf_search(ctx, collection_id, request)
+    let collection = get_collection(&ctx, collection_id)?;
+    let as_of = repo::version_snapshot(&collection, request.result_options.version)?;
+    let include = request.result_options.include.clone();
+    let results = repo::tf_idf_search(ctx.clone(), collection_id, request, as_of.as_deref())
         .await
         .map_err(|e| match e {
             // Basic error mapping
@@ -144,13 +330,8 @@ pub(crate) async fn tf_idf_search(
         })?;
 
     Ok(SearchResponseDto {
-        results: results
-            .into_iter()
-            .map(|(id, score)| SearchResultItemDto {
-                id,
-                score, // Use f32 score directly
-            })
-            .collect(),
+        truncated: false,
+        results: to_search_results(&collection, results, &include, None, as_of.as_deref())?,
     })
 }
 
@@ -159,26 +340,159 @@ pub(crate) async fn batch_tf_idf_search(
     collection_id: &str,
     request: BatchSearchTFIDFDocumentsDto,
 ) -> Result<BatchSearchResponseDto, SearchError> {
-    let results_list = repo::batch_tf_idf_search(ctx, collection_id, request)
-        .await
-        .map_err(|e| match e {
-            // Basic error mapping
-            WaCustomError::NotFound(msg) => SearchError::IndexNotFound(msg),
-            other => {
-                SearchError::SearchFailed(format!("Repo batch sparse IDF search failed: {}", other))
-            }
-        })?;
+    let collection = get_collection(&ctx, collection_id)?;
+    let as_of = repo::version_snapshot(&collection, request.result_options.version)?;
+    let include = request.result_options.include.clone();
+    let results_list =
+        repo::batch_tf_idf_search(ctx.clone(), collection_id, request, as_of.as_deref())
+            .await
+            .map_err(|e| match e {
+                // Basic error mapping
+                WaCustomError::NotFound(msg) => SearchError::IndexNotFound(msg),
+                other => SearchError::SearchFailed(format!(
+                    "Repo batch sparse IDF search failed: {}",
+                    other
+                )),
+            })?;
 
-    Ok(results_list
+    results_list
         .into_iter()
-        .map(|results| SearchResponseDto {
-            results: results
-                .into_iter()
-                .map(|(id, score)| SearchResultItemDto {
-                    id,
-                    score, // Use f32 score directly
-                })
-                .collect(),
+        .map(|results| {
+            Ok(SearchResponseDto {
+                truncated: false,
+                results: to_search_results(&collection, results, &include, None, as_of.as_deref())?,
+            })
         })
-        .collect())
+        .collect()
+}
+
+fn get_collection(ctx: &AppContext, collection_id: &str) -> Result<Arc<Collection>, SearchError> {
+    ctx.ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or_else(|| SearchError::CollectionNotFound(collection_id.to_string()))
+}
+
+/// Converts the results of a hybrid search to the response items,
+/// along with the scores of the vectors in the components of the search
+fn to_hybrid_search_results(
+    collection: &Collection,
+    results: Vec<(VectorId, f32, HybridComponentScoresDto)>,
+    include: &[IncludeField],
+    vector_name: Option<&str>,
+    as_of: Option<&VersionSnapshot>,
+) -> Result<Vec<SearchResultItemDto>, SearchError> {
+    let (results, component_scores): (Vec<_>, Vec<_>) = results
+        .into_iter()
+        .map(|(id, score, component_scores)| ((id, score), component_scores))
+        .unzip();
+    let mut results = to_search_results(collection, results, include, vector_name, as_of)?;
+    for (result, component_scores) in results.iter_mut().zip(component_scores) {
+        result.component_scores = Some(component_scores);
+    }
+    Ok(results)
+}
+
+/// Converts the search results to the response items, replacing the
+/// internal vector ids with the ids provided by the user
+///
+/// The stored data of the vectors requested by `include` is resolved
+/// from the indexes of the collection, with the dense values taken from
+/// the dense vector space `vector_name` (the default one if `None`, as
+/// for searches other than those of a single dense vector space). For
+/// searches run as of a version `as_of`, the data is as of the version
+/// too.
+pub(crate) fn to_search_results(
+    collection: &Collection,
+    results: impl IntoIterator<Item = (VectorId, f32)>,
+    include: &[IncludeField],
+    vector_name: Option<&str>,
+    as_of: Option<&VersionSnapshot>,
+) -> Result<Vec<SearchResultItemDto>, SearchError> {
+    let include_metadata = include.contains(&IncludeField::Metadata);
+    let include_dense_values = include.contains(&IncludeField::DenseValues);
+    // Indexes are only looked up if any of their data is requested
+    let hnsw_index = (include_metadata || include_dense_values)
+        .then(|| collection.get_dense_index(vector_name))
+        .flatten();
+    let inverted_index = include
+        .contains(&IncludeField::SparseValues)
+        .then(|| collection.get_inverted_index())
+        .flatten();
+    let tf_idf_index = include
+        .contains(&IncludeField::Text)
+        .then(|| collection.get_tf_idf_index())
+        .flatten();
+
+    results
+        .into_iter()
+        .map(|(id, score)| {
+            let mut metadata = None;
+            let mut metadata_found = false;
+            if include_metadata {
+                if let Some(fields) = collection
+                    .vector_metadata
+                    .get_as_of(&id, as_of)
+                    .map_err(SearchError::WaCustom)?
+                {
+                    metadata = (!fields.is_empty()).then_some(fields);
+                    metadata_found = true;
+                }
+            }
+
+            // The raw dense embedding is also loaded for the metadata of
+            // vectors inserted before the metadata of all vectors was
+            // recorded separately
+            let mut dense_values = None;
+            if let Some(hnsw_index) = hnsw_index
+                .as_ref()
+                .filter(|_| include_dense_values || (include_metadata && !metadata_found))
+            {
+                if let Some(embedding) =
+                    get_live_dense_embedding(collection, hnsw_index, &id, as_of)
+                        .map_err(|e| SearchError::SearchFailed(e.to_string()))?
+                {
+                    if include_dense_values {
+                        dense_values = Some(embedding.raw_vec.as_ref().clone());
+                    }
+                    if include_metadata && !metadata_found {
+                        metadata = embedding.raw_metadata;
+                    }
+                }
+            }
+
+            let sparse_values = inverted_index.as_ref().and_then(|inverted_index| {
+                get_live_sparse_embedding(collection, inverted_index, &id, as_of)
+                    .map(|embedding| embedding.raw_vec.as_ref().clone())
+            });
+            let text = tf_idf_index.as_ref().and_then(|tf_idf_index| {
+                get_live_document(collection, tf_idf_index, &id, as_of)
+                    .and_then(|(_, text)| text.clone())
+            });
+
+            Ok(SearchResultItemDto {
+                id: collection
+                    .get_external_id(&id)
+                    .map_err(SearchError::WaCustom)?,
+                score,
+                metadata,
+                dense_values,
+                sparse_values,
+                text,
+                component_scores: None,
+            })
+        })
+        .collect()
+}
+
+/// Rejects the version of the search requests that always search the
+/// current version, rather than ignoring it
+fn reject_version(options: &ResultOptionsDto, search: &str) -> Result<(), SearchError> {
+    if options.version.is_some() {
+        return Err(SearchError::InvalidInput(format!(
+            "version isn't supported by {}",
+            search
+        )));
+    }
+    Ok(())
 }
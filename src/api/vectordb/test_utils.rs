@@ -0,0 +1,214 @@
+//! Helpers for the tests of the vectordb services, which run against a
+//! real app context backed by a temporary data directory
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc, OnceLock,
+};
+
+use serde_json::{json, Value};
+
+use crate::{
+    api::vectordb::{collections, indexes, transactions},
+    app_context::AppContext,
+    args::CosdataArgs,
+    config_loader::Config,
+    models::{
+        collection::NamedDenseVectorOptions, external_ids::ExternalId, types::DistanceMetric,
+        versioning::Hash,
+    },
+};
+
+/// Returns the app context shared by all the tests of the process
+///
+/// The lmdb environment of the app lives at a fixed path under the
+/// data directory, hence a single context is created (in a temporary
+/// `COSDATA_HOME`) and the tests isolate themselves by using their
+/// own collections, see [`TestCollection::create`].
+pub(crate) fn test_context() -> Arc<AppContext> {
+    static CONTEXT: OnceLock<Arc<AppContext>> = OnceLock::new();
+
+    CONTEXT
+        .get_or_init(|| {
+            let home = tempfile::tempdir().unwrap().into_path();
+            std::env::set_var("COSDATA_HOME", &home);
+            let config: Config = toml::from_str(include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/config.toml"
+            )))
+            .unwrap();
+            let args = CosdataArgs {
+                admin_key: "admin".to_string(),
+                skip_confirmation: true,
+                confirmed: true,
+            };
+            Arc::new(AppContext::new(config, args).unwrap())
+        })
+        .clone()
+}
+
+/// Options for a test collection along with its indexes
+pub(crate) struct TestCollection {
+    /// Dimension of the dense vectors, if the collection has a dense
+    /// index
+    pub dense_dimension: Option<usize>,
+    /// Named dense vector spaces, each of which gets a dense index of
+    /// its own
+    pub named_vectors: Vec<NamedDenseVectorOptions>,
+    pub distance_metric: DistanceMetric,
+    pub sparse: bool,
+    pub tf_idf: bool,
+    pub store_raw_text: bool,
+    pub metadata_schema: Option<Value>,
+}
+
+impl Default for TestCollection {
+    fn default() -> Self {
+        Self {
+            dense_dimension: None,
+            named_vectors: Vec::new(),
+            distance_metric: DistanceMetric::Cosine,
+            sparse: false,
+            tf_idf: false,
+            store_raw_text: true,
+            metadata_schema: None,
+        }
+    }
+}
+
+impl TestCollection {
+    /// Creates the collection and its indexes under a unique name,
+    /// which is returned
+    pub(crate) async fn create(self, ctx: &Arc<AppContext>) -> String {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("test_{}", NEXT_ID.fetch_add(1, Ordering::Relaxed));
+
+        let create_collection_dto = serde_json::from_value(json!({
+            "name": name,
+            "description": null,
+            "dense_vector": {
+                "enabled": self.dense_dimension.is_some(),
+                "dimension": self.dense_dimension.unwrap_or(0),
+                "named_vectors": self.named_vectors,
+            },
+            "sparse_vector": { "enabled": self.sparse },
+            "tf_idf_options": { "enabled": self.tf_idf },
+            "metadata_schema": self.metadata_schema,
+            "config": { "max_vectors": null, "replication_factor": null },
+        }))
+        .unwrap();
+        collections::service::create_collection(ctx.clone(), create_collection_dto)
+            .await
+            .unwrap();
+
+        // The dense index of a named vector space has the name of the
+        // vector space
+        let dense_index_names = self
+            .dense_dimension
+            .map(|_| "dense".to_string())
+            .into_iter()
+            .chain(self.named_vectors.into_iter().map(|options| options.name));
+        for index_name in dense_index_names {
+            let create_index_dto = serde_json::from_value(json!({
+                "name": index_name,
+                "distance_metric_type": self.distance_metric,
+                "quantization": {
+                    "type": "scalar",
+                    "properties": { "data_type": "f32", "range": { "min": -1.0, "max": 1.0 } },
+                },
+                "index": { "type": "hnsw", "properties": {} },
+            }))
+            .unwrap();
+            indexes::service::create_dense_index(name.clone(), create_index_dto, ctx.clone())
+                .await
+                .unwrap();
+        }
+        if self.sparse {
+            let create_index_dto = serde_json::from_value(json!({
+                "name": "sparse",
+                "quantization": 64,
+                "sample_threshold": 1000,
+            }))
+            .unwrap();
+            indexes::service::create_sparse_index(name.clone(), create_index_dto, ctx.clone())
+                .await
+                .unwrap();
+        }
+        if self.tf_idf {
+            let create_index_dto = serde_json::from_value(json!({
+                "name": "tf_idf",
+                "sample_threshold": 1000,
+                "store_raw_text": self.store_raw_text,
+                "k1": 1.2,
+                "b": 0.75,
+            }))
+            .unwrap();
+            indexes::service::create_tf_idf_index(name.clone(), create_index_dto, ctx.clone())
+                .await
+                .unwrap();
+        }
+
+        name
+    }
+}
+
+/// Starts a transaction on the collection and returns its id
+pub(crate) async fn begin_transaction(ctx: &Arc<AppContext>, collection_id: &str) -> Hash {
+    transactions::service::create_transaction(ctx.clone(), collection_id)
+        .await
+        .unwrap()
+        .transaction_id
+        .parse::<u32>()
+        .map(Hash::from)
+        .unwrap()
+}
+
+/// Runs `f` in a new transaction on the collection, which is committed
+/// if `f` succeeds and aborted otherwise
+pub(crate) async fn in_transaction<F, Fut>(
+    ctx: &Arc<AppContext>,
+    collection_id: &str,
+    f: F,
+) -> Result<(), transactions::error::TransactionError>
+where
+    F: FnOnce(Hash) -> Fut,
+    Fut: std::future::Future<Output = Result<(), transactions::error::TransactionError>>,
+{
+    let transaction_id = begin_transaction(ctx, collection_id).await;
+    match f(transaction_id).await {
+        Ok(()) => {
+            transactions::service::commit_transaction(ctx.clone(), collection_id, transaction_id)
+                .await
+        }
+        Err(err) => {
+            transactions::service::abort_transaction(ctx.clone(), collection_id, transaction_id)
+                .await?;
+            Err(err)
+        }
+    }
+}
+
+/// Upserts the vectors, given as their JSON representation in the API,
+/// in a transaction of their own
+pub(crate) async fn upsert(ctx: &Arc<AppContext>, collection_id: &str, vectors: Value) {
+    let vectors: Vec<_> = serde_json::from_value(vectors).unwrap();
+    in_transaction(ctx, collection_id, |transaction_id| {
+        transactions::service::upsert_vectors(ctx.clone(), collection_id, transaction_id, vectors)
+    })
+    .await
+    .unwrap();
+}
+
+/// Deletes the vector in a transaction of its own
+pub(crate) async fn delete(ctx: &Arc<AppContext>, collection_id: &str, vector_id: ExternalId) {
+    in_transaction(ctx, collection_id, |transaction_id| {
+        transactions::service::delete_vector_by_id(
+            ctx.clone(),
+            collection_id,
+            transaction_id,
+            vector_id,
+        )
+    })
+    .await
+    .unwrap();
+}
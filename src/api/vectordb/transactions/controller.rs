@@ -3,6 +3,7 @@ use actix_web::{web, HttpResponse};
 use crate::api::vectordb::vectors::dtos::CreateVectorDto;
 use crate::app_context::AppContext;
 use crate::models::collection_cache::CollectionCacheExt;
+use crate::models::external_ids::ExternalId;
 
 use super::{dtos::UpsertDto, error::TransactionError, service};
 
@@ -65,7 +66,7 @@ pub(crate) async fn abort_transaction(
 }
 
 pub(crate) async fn delete_vector_by_id(
-    path: web::Path<(String, u32, u32)>,
+    path: web::Path<(String, u32, String)>,
     ctx: web::Data<AppContext>,
 ) -> Result<HttpResponse, TransactionError> {
     let (collection_id, transaction_id, vector_id) = path.into_inner();
@@ -77,7 +78,7 @@ pub(crate) async fn delete_vector_by_id(
         ctx.into_inner(),
         &collection_id,
         transaction_id.into(),
-        vector_id,
+        ExternalId::from(vector_id),
     )
     .await?;
     Ok(HttpResponse::NoContent().finish())
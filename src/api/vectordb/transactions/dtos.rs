@@ -1,4 +1,6 @@
 use crate::api::vectordb::vectors::dtos::CreateVectorDto;
+use crate::metadata::MetadataFields;
+use crate::models::external_ids::ExternalId;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
@@ -12,3 +14,14 @@ pub(crate) struct CreateTransactionResponseDto {
 pub struct UpsertDto {
     pub vectors: Vec<CreateVectorDto>,
 }
+
+#[derive(Deserialize)]
+pub(crate) struct VectorMetadataUpdateDto {
+    pub id: ExternalId,
+    pub metadata: MetadataFields,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct UpdateMetadataDto {
+    pub vectors: Vec<VectorMetadataUpdateDto>,
+}
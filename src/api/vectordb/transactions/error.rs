@@ -17,6 +17,7 @@ pub(crate) enum TransactionError {
     FailedToCommitTransaction(String),
     FailedToCreateVector(String),
     FailedToDeleteVector(String),
+    FailedToUpdateVector(String),
     NotImplemented,
 }
 
@@ -43,6 +44,9 @@ impl Display for TransactionError {
             Self::FailedToDeleteVector(msg) => {
                 write!(f, "Failed to delete vector in transaction due to: {}", msg)
             }
+            Self::FailedToUpdateVector(msg) => {
+                write!(f, "Failed to update vector in transaction due to: {}", msg)
+            }
         }
     }
 }
@@ -65,6 +69,7 @@ impl ResponseError for TransactionError {
             Self::NotImplemented => StatusCode::BAD_REQUEST,
             Self::FailedToCreateVector(_) => StatusCode::BAD_REQUEST,
             Self::FailedToDeleteVector(_) => StatusCode::BAD_REQUEST,
+            Self::FailedToUpdateVector(_) => StatusCode::BAD_REQUEST,
         }
     }
 }
@@ -1,9 +1,9 @@
 mod controller;
 mod delete;
 mod dtos;
-mod error;
+pub(crate) mod error;
 mod repo;
-mod service;
+pub(crate) mod service;
 mod update;
 
 use actix_web::{web, Scope};
@@ -2,9 +2,15 @@ use std::sync::Arc;
 
 use self::vectors::dtos::CreateVectorDto;
 
-use super::{dtos::CreateTransactionResponseDto, error::TransactionError};
+use super::{
+    dtos::{CreateTransactionResponseDto, VectorMetadataUpdateDto},
+    error::TransactionError,
+};
 use crate::models::collection_transaction::CollectionTransaction;
+use crate::models::external_ids::ExternalId;
 use crate::models::meta_persist::update_current_version;
+use crate::models::tombstones::Tombstone;
+use crate::models::vector_ids::{PresenceChange, VectorPresence};
 use crate::models::versioning::Hash;
 use crate::{api::vectordb::vectors, app_context::AppContext};
 use chrono::Utc;
@@ -65,7 +71,7 @@ pub(crate) async fn commit_transaction(
     let version_number = current_open_transaction.version_number;
 
     current_open_transaction
-        .pre_commit(&collection, &ctx.config)
+        .pre_commit(&collection, *current_version_guard, &ctx.config)
         .map_err(|err| TransactionError::FailedToCommitTransaction(err.to_string()))?;
 
     *current_version_guard = current_transaction_id;
@@ -137,19 +143,66 @@ pub(crate) async fn abort_transaction(
     }
 
     current_open_transaction
-        .pre_commit(&collection, &ctx.config)
+        .abort(&collection, &ctx.config)
         .map_err(|err| TransactionError::FailedToCommitTransaction(err.to_string()))?;
 
     Ok(())
 }
 
 pub(crate) async fn delete_vector_by_id(
-    _ctx: Arc<AppContext>,
-    _collection_id: &str,
-    _transaction_id: Hash,
-    _vector_id: u32,
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    transaction_id: Hash,
+    vector_id: ExternalId,
 ) -> Result<(), TransactionError> {
-    unimplemented!();
+    let collection = ctx
+        .ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or(TransactionError::CollectionNotFound)?;
+
+    // The guard is taken before checking that the vector exists, so
+    // that the transaction can't be committed or aborted in between
+    let current_open_transaction_guard = collection.current_open_transaction.read().unwrap();
+    let Some(current_open_transaction) = &*current_open_transaction_guard else {
+        return Err(TransactionError::NotFound);
+    };
+
+    if current_open_transaction.id != transaction_id {
+        return Err(TransactionError::FailedToDeleteVector(
+            "This is not the currently open transaction!".into(),
+        ));
+    }
+
+    let not_found =
+        || TransactionError::FailedToDeleteVector(format!("Vector {} not found", vector_id));
+    let internal_id = collection
+        .get_internal_id(&vector_id)
+        .map_err(|e| TransactionError::FailedToDeleteVector(e.to_string()))?
+        .ok_or_else(not_found)?;
+    // Vectors inserted or deleted earlier in the transaction exist as
+    // the transaction left them
+    let exists = match current_open_transaction.get_presence_change(&internal_id) {
+        Some(change) if change.replaces || !change.presence.is_empty() => {
+            !change.presence.is_empty()
+        }
+        _ => vectors::repo::vector_exists(&collection, &internal_id)
+            .map_err(|e| TransactionError::FailedToDeleteVector(e.to_string()))?,
+    };
+    if !exists {
+        return Err(not_found());
+    }
+
+    current_open_transaction.add_tombstone(internal_id.clone(), Tombstone::Deleted);
+    current_open_transaction.record_presence_change(
+        internal_id,
+        PresenceChange {
+            replaces: true,
+            presence: VectorPresence::default(),
+        },
+    );
+
+    Ok(())
 }
 
 pub(crate) async fn upsert_vectors(
@@ -186,3 +239,579 @@ pub(crate) async fn upsert_vectors(
 
     Ok(())
 }
+
+pub(crate) async fn update_vectors_metadata(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    transaction_id: Hash,
+    updates: Vec<VectorMetadataUpdateDto>,
+) -> Result<(), TransactionError> {
+    let collection = ctx
+        .ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or(TransactionError::CollectionNotFound)?;
+
+    let current_open_transaction_guard = collection.current_open_transaction.read().unwrap();
+    let Some(current_open_transaction) = &*current_open_transaction_guard else {
+        return Err(TransactionError::NotFound);
+    };
+
+    if current_open_transaction.id != transaction_id {
+        return Err(TransactionError::FailedToUpdateVector(
+            "This is not the currently open transaction!".into(),
+        ));
+    }
+
+    vectors::repo::update_vectors_metadata_in_transaction(
+        ctx,
+        &collection,
+        current_open_transaction,
+        updates
+            .into_iter()
+            .map(|update| (update.id, update.metadata))
+            .collect(),
+    )
+    .await
+    .map_err(|e| TransactionError::FailedToUpdateVector(e.to_string()))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use crate::{
+        api::vectordb::{
+            collections, search,
+            test_utils::{begin_transaction, test_context, upsert, TestCollection},
+        },
+        metadata::FieldValue,
+        models::{
+            vector_counts::VectorCounts,
+            versioning::{VersionRef, VersionSnapshot},
+        },
+    };
+
+    use super::*;
+
+    async fn exists(ctx: &Arc<AppContext>, collection_id: &str, id: u64) -> bool {
+        vectors::repo::check_vector_existence(ctx.clone(), collection_id, &ExternalId::Int(id))
+            .await
+            .unwrap()
+    }
+
+    async fn get_metadata(
+        ctx: &Arc<AppContext>,
+        collection_id: &str,
+        id: u64,
+    ) -> Option<crate::metadata::MetadataFields> {
+        vectors::repo::get_vector_by_id(ctx.clone(), collection_id, ExternalId::Int(id), None)
+            .await
+            .unwrap()
+            .metadata
+    }
+
+    #[actix_web::test]
+    async fn test_delete_takes_effect_on_commit() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "dense_values": [0.1, 0.2, 0.3, 0.4] },
+                { "id": 2, "dense_values": [0.4, 0.3, 0.2, 0.1] },
+            ]),
+        )
+        .await;
+
+        let transaction_id = begin_transaction(&ctx, &collection_id).await;
+        delete_vector_by_id(
+            ctx.clone(),
+            &collection_id,
+            transaction_id,
+            ExternalId::Int(1),
+        )
+        .await
+        .unwrap();
+        let search = || async {
+            let request = serde_json::from_value(json!({
+                "query_vector": [0.1, 0.2, 0.3, 0.4],
+                "top_k": 10,
+            }))
+            .unwrap();
+            search::repo::dense_search(ctx.clone(), &collection_id, request, None)
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(id, _)| id.0)
+                .collect::<Vec<_>>()
+        };
+        // Not visible before the transaction is committed
+        assert!(exists(&ctx, &collection_id, 1).await);
+        assert_eq!(vec![1, 2], search().await);
+
+        commit_transaction(ctx.clone(), &collection_id, transaction_id)
+            .await
+            .unwrap();
+        assert!(!exists(&ctx, &collection_id, 1).await);
+        assert!(exists(&ctx, &collection_id, 2).await);
+        assert_eq!(vec![2], search().await);
+    }
+
+    #[actix_web::test]
+    async fn test_delete_vector_upserted_in_transaction() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([{ "id": 1, "dense_values": [0.1, 0.2, 0.3, 0.4] }]),
+        )
+        .await;
+
+        let transaction_id = begin_transaction(&ctx, &collection_id).await;
+        let vectors = serde_json::from_value(json!([
+            { "id": 2, "dense_values": [0.4, 0.3, 0.2, 0.1] },
+            { "id": 3, "dense_values": [0.2, 0.2, 0.2, 0.2] },
+        ]))
+        .unwrap();
+        upsert_vectors(ctx.clone(), &collection_id, transaction_id, vectors)
+            .await
+            .unwrap();
+        let delete = |id| delete_vector_by_id(ctx.clone(), &collection_id, transaction_id, id);
+        // Vectors created in the transaction can be deleted in it, and
+        // vectors deleted in it can't be deleted again
+        delete(ExternalId::Int(2)).await.unwrap();
+        delete(ExternalId::Int(1)).await.unwrap();
+        assert!(delete(ExternalId::Int(1)).await.is_err());
+        assert!(delete(ExternalId::Int(2)).await.is_err());
+        assert!(delete(ExternalId::Int(4)).await.is_err());
+        commit_transaction(ctx.clone(), &collection_id, transaction_id)
+            .await
+            .unwrap();
+
+        assert!(!exists(&ctx, &collection_id, 1).await);
+        assert!(!exists(&ctx, &collection_id, 2).await);
+        assert!(exists(&ctx, &collection_id, 3).await);
+        let request = serde_json::from_value(json!({
+            "query_vector": [0.4, 0.3, 0.2, 0.1],
+            "top_k": 10,
+        }))
+        .unwrap();
+        let ids: Vec<_> = search::repo::dense_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| id.0)
+            .collect();
+        assert_eq!(vec![3], ids);
+    }
+
+    #[actix_web::test]
+    async fn test_create_replaces_live_vector() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            sparse: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                {
+                    "id": 1,
+                    "dense_values": [0.1, 0.2, 0.3, 0.4],
+                    "sparse_indices": [1],
+                    "sparse_values": [0.5],
+                },
+                { "id": 2, "dense_values": [0.4, 0.3, 0.2, 0.1] },
+            ]),
+        )
+        .await;
+
+        let transaction_id = begin_transaction(&ctx, &collection_id).await;
+        let vector =
+            serde_json::from_value(json!({ "id": 1, "dense_values": [0.4, 0.3, 0.2, 0.2] }))
+                .unwrap();
+        create_vector_in_transaction(ctx.clone(), &collection_id, transaction_id, vector)
+            .await
+            .unwrap();
+        commit_transaction(ctx.clone(), &collection_id, transaction_id)
+            .await
+            .unwrap();
+
+        // Only the new dense values are searched, and the sparse values
+        // of the replaced vector are gone
+        let request = serde_json::from_value(json!({
+            "query_vector": [0.1, 0.2, 0.3, 0.4],
+            "top_k": 10,
+        }))
+        .unwrap();
+        let results = search::repo::dense_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap();
+        let ids: Vec<_> = results.iter().map(|(id, _)| id.0).collect();
+        assert_eq!(vec![1, 2], ids);
+        // Scored by the new values, the old ones being identical to the
+        // query
+        assert!((results[0].1.get_value() - 0.763).abs() < 1e-3);
+        let request = serde_json::from_value(json!({
+            "query_terms": [[1, 1.0]],
+            "top_k": 10,
+        }))
+        .unwrap();
+        let results = search::repo::sparse_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap();
+        assert!(results.is_empty());
+        let vector =
+            vectors::repo::get_vector_by_id(ctx.clone(), &collection_id, ExternalId::Int(1), None)
+                .await
+                .unwrap();
+        assert_eq!(Some(vec![0.4, 0.3, 0.2, 0.2]), vector.dense_values);
+        assert!(vector.sparse_values.is_none());
+    }
+
+    #[actix_web::test]
+    async fn test_abort_discards_changes() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "dense_values": [0.1, 0.2, 0.3, 0.4], "metadata": { "tag": "a" } },
+                { "id": 2, "dense_values": [0.4, 0.3, 0.2, 0.1] },
+            ]),
+        )
+        .await;
+
+        let transaction_id = begin_transaction(&ctx, &collection_id).await;
+        delete_vector_by_id(
+            ctx.clone(),
+            &collection_id,
+            transaction_id,
+            ExternalId::Int(2),
+        )
+        .await
+        .unwrap();
+        let vectors = serde_json::from_value(json!([
+            { "id": 1, "dense_values": [0.5, 0.5, 0.5, 0.5], "metadata": { "tag": "b" } },
+            { "id": 3, "dense_values": [0.2, 0.2, 0.2, 0.2] },
+        ]))
+        .unwrap();
+        upsert_vectors(ctx.clone(), &collection_id, transaction_id, vectors)
+            .await
+            .unwrap();
+        // The new vector isn't visible before the transaction is
+        // committed
+        assert!(!exists(&ctx, &collection_id, 3).await);
+        abort_transaction(ctx.clone(), &collection_id, transaction_id)
+            .await
+            .unwrap();
+
+        assert!(exists(&ctx, &collection_id, 1).await);
+        assert!(exists(&ctx, &collection_id, 2).await);
+        assert!(!exists(&ctx, &collection_id, 3).await);
+        let vector =
+            vectors::repo::get_vector_by_id(ctx.clone(), &collection_id, ExternalId::Int(1), None)
+                .await
+                .unwrap();
+        assert_eq!(Some(vec![0.1, 0.2, 0.3, 0.4]), vector.dense_values);
+        assert_eq!(
+            Some(&FieldValue::String("a".to_string())),
+            vector
+                .metadata
+                .as_ref()
+                .and_then(|fields| fields.get("tag"))
+        );
+
+        // The changes of the next transaction are unaffected
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([{ "id": 3, "dense_values": [0.2, 0.2, 0.2, 0.2] }]),
+        )
+        .await;
+        assert!(exists(&ctx, &collection_id, 3).await);
+    }
+
+    #[actix_web::test]
+    async fn test_uncommitted_vectors_are_not_searched() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            tf_idf: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([{ "id": 1, "dense_values": [0.1, 0.2, 0.3, 0.4], "text": "quick fox" }]),
+        )
+        .await;
+
+        let search = || async {
+            let request = serde_json::from_value(json!({
+                "query_vector": [0.1, 0.2, 0.3, 0.4],
+                "top_k": 10,
+            }))
+            .unwrap();
+            let dense_ids: Vec<_> =
+                search::repo::dense_search(ctx.clone(), &collection_id, request, None)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|(id, _)| id.0)
+                    .collect();
+            let request =
+                serde_json::from_value(json!({ "query": "quick fox", "top_k": 10 })).unwrap();
+            let tf_idf_ids: Vec<_> =
+                search::repo::tf_idf_search(ctx.clone(), &collection_id, request, None)
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|(id, _)| id.0)
+                    .collect();
+            (dense_ids, tf_idf_ids)
+        };
+
+        let transaction_id = begin_transaction(&ctx, &collection_id).await;
+        let vectors = serde_json::from_value(json!([
+            { "id": 2, "dense_values": [0.1, 0.2, 0.3, 0.4], "text": "quick fox" },
+        ]))
+        .unwrap();
+        upsert_vectors(ctx.clone(), &collection_id, transaction_id, vectors)
+            .await
+            .unwrap();
+        assert_eq!((vec![1], vec![1]), search().await);
+
+        abort_transaction(ctx.clone(), &collection_id, transaction_id)
+            .await
+            .unwrap();
+        assert_eq!((vec![1], vec![1]), search().await);
+    }
+
+    #[actix_web::test]
+    async fn test_metadata_update_takes_effect_on_commit() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([{ "id": 1, "dense_values": [0.1, 0.2, 0.3, 0.4], "metadata": { "tag": "a" } }]),
+        )
+        .await;
+
+        let transaction_id = begin_transaction(&ctx, &collection_id).await;
+        let updates =
+            serde_json::from_value(json!([{ "id": 1, "metadata": { "tag": "b" } }])).unwrap();
+        update_vectors_metadata(ctx.clone(), &collection_id, transaction_id, updates)
+            .await
+            .unwrap();
+        let tag = |metadata: Option<crate::metadata::MetadataFields>| {
+            metadata.and_then(|fields| fields.get("tag").cloned())
+        };
+        assert_eq!(
+            Some(FieldValue::String("a".to_string())),
+            tag(get_metadata(&ctx, &collection_id, 1).await)
+        );
+
+        commit_transaction(ctx.clone(), &collection_id, transaction_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            Some(FieldValue::String("b".to_string())),
+            tag(get_metadata(&ctx, &collection_id, 1).await)
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_vector_counts() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            sparse: true,
+            tf_idf: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        let counts = |ctx: Arc<AppContext>, collection_id: String| async move {
+            collections::service::get_collection_by_id(ctx, &collection_id)
+                .await
+                .unwrap()
+                .vector_counts
+        };
+        assert_eq!(
+            VectorCounts::default(),
+            counts(ctx.clone(), collection_id.clone()).await
+        );
+
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "dense_values": [0.1, 0.2, 0.3, 0.4], "text": "quick fox" },
+                { "id": 2, "sparse_indices": [1], "sparse_values": [0.5] },
+                { "id": 3, "dense_values": [0.4, 0.3, 0.2, 0.1] },
+            ]),
+        )
+        .await;
+        let expected = VectorCounts {
+            total: 3,
+            dense: 2,
+            sparse: 1,
+            tf_idf: 1,
+        };
+        assert_eq!(expected, counts(ctx.clone(), collection_id.clone()).await);
+
+        // Aborted transactions don't change the counts
+        let transaction_id = begin_transaction(&ctx, &collection_id).await;
+        delete_vector_by_id(
+            ctx.clone(),
+            &collection_id,
+            transaction_id,
+            ExternalId::Int(1),
+        )
+        .await
+        .unwrap();
+        abort_transaction(ctx.clone(), &collection_id, transaction_id)
+            .await
+            .unwrap();
+        assert_eq!(expected, counts(ctx.clone(), collection_id.clone()).await);
+
+        // Replacing a vector with other representations moves it
+        // between the indexes, whether it's upserted or created
+        let transaction_id = begin_transaction(&ctx, &collection_id).await;
+        delete_vector_by_id(
+            ctx.clone(),
+            &collection_id,
+            transaction_id,
+            ExternalId::Int(3),
+        )
+        .await
+        .unwrap();
+        let vectors = serde_json::from_value(json!([
+            { "id": 1, "sparse_indices": [2], "sparse_values": [0.5] },
+            { "id": 4, "text": "lazy dog" },
+        ]))
+        .unwrap();
+        upsert_vectors(ctx.clone(), &collection_id, transaction_id, vectors)
+            .await
+            .unwrap();
+        let vector = serde_json::from_value(json!({ "id": 2, "text": "brown" })).unwrap();
+        create_vector_in_transaction(ctx.clone(), &collection_id, transaction_id, vector)
+            .await
+            .unwrap();
+        commit_transaction(ctx.clone(), &collection_id, transaction_id)
+            .await
+            .unwrap();
+        assert_eq!(
+            VectorCounts {
+                total: 3,
+                dense: 0,
+                sparse: 1,
+                tf_idf: 2,
+            },
+            counts(ctx.clone(), collection_id.clone()).await
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_search_as_of_version() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            tf_idf: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        let collection = ctx
+            .ain_env
+            .collections_map
+            .get_collection(&collection_id)
+            .unwrap();
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([{ "id": 1, "dense_values": [-0.4, 0.3, -0.2, 0.1], "text": "quick fox" }]),
+        )
+        .await;
+        let version = *collection.current_version.read().unwrap();
+        let as_of = || {
+            collection
+                .version_snapshot(VersionRef::Hash(version))
+                .unwrap()
+                .map(Arc::new)
+        };
+        let (ctx, collection_id) = (&ctx, &collection_id);
+        let search = |as_of: Option<Arc<VersionSnapshot>>| async move {
+            let request = serde_json::from_value(json!({
+                "query_vector": [0.1, 0.2, 0.3, 0.4],
+                "top_k": 1,
+                "ef_search": 2,
+            }))
+            .unwrap();
+            let dense_ids: Vec<_> =
+                search::repo::dense_search(ctx.clone(), collection_id, request, as_of.clone())
+                    .await
+                    .unwrap()
+                    .into_iter()
+                    .map(|(id, _)| id.0)
+                    .collect();
+            let request = serde_json::from_value(json!({ "query": "quick", "top_k": 1 })).unwrap();
+            let tf_idf_results =
+                search::repo::tf_idf_search(ctx.clone(), collection_id, request, as_of.as_deref())
+                    .await
+                    .unwrap();
+            (dense_ids, tf_idf_results)
+        };
+        let (_, tf_idf_results) = search(None).await;
+
+        // Vectors inserted later, closer to the query and containing
+        // the same terms, neither crowd out the vector nor change its
+        // score as of the version
+        let vectors: Vec<_> = (2..=40)
+            .map(|id| {
+                json!({
+                    "id": id,
+                    "dense_values": [0.1, 0.2, 0.3, 0.4 + id as f32 / 1000.0],
+                    "text": "quick fox",
+                })
+            })
+            .collect();
+        upsert(ctx, collection_id, Value::Array(vectors)).await;
+        assert_eq!((vec![1], tf_idf_results), search(as_of()).await);
+        assert_ne!(vec![1], search(None).await.0);
+    }
+}
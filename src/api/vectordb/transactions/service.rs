@@ -1,11 +1,16 @@
 use std::sync::Arc;
 
 use crate::{
-    api::vectordb::vectors::dtos::CreateVectorDto, app_context::AppContext,
-    models::versioning::Hash,
+    api::vectordb::vectors::dtos::CreateVectorDto,
+    app_context::AppContext,
+    models::{external_ids::ExternalId, versioning::Hash},
 };
 
-use super::{dtos::CreateTransactionResponseDto, error::TransactionError, repo};
+use super::{
+    dtos::{CreateTransactionResponseDto, VectorMetadataUpdateDto},
+    error::TransactionError,
+    repo,
+};
 
 pub(crate) async fn create_transaction(
     ctx: Arc<AppContext>,
@@ -43,7 +48,7 @@ pub(crate) async fn delete_vector_by_id(
     ctx: Arc<AppContext>,
     collection_id: &str,
     transaction_id: Hash,
-    vector_id: u32,
+    vector_id: ExternalId,
 ) -> Result<(), TransactionError> {
     repo::delete_vector_by_id(ctx, collection_id, transaction_id, vector_id).await
 }
@@ -56,3 +61,12 @@ pub(crate) async fn upsert_vectors(
 ) -> Result<(), TransactionError> {
     repo::upsert_vectors(ctx, collection_id, transaction_id, vectors).await
 }
+
+pub(crate) async fn update_vectors_metadata(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    transaction_id: Hash,
+    updates: Vec<VectorMetadataUpdateDto>,
+) -> Result<(), TransactionError> {
+    repo::update_vectors_metadata(ctx, collection_id, transaction_id, updates).await
+}
@@ -1,12 +1,29 @@
 use crate::app_context::AppContext;
+use crate::models::collection_cache::CollectionCacheExt;
 use actix_web::{web, HttpResponse};
 
+use super::{dtos::UpdateMetadataDto, error::TransactionError, service};
+
 // Route: `/vectordb/{database_name}/transactions/{transaction_id}/update`
+//
+// Updates only the metadata fields of existing vectors, reusing their
+// stored embeddings
 pub(crate) async fn update(
-    path_data: web::Path<(String, String)>,
-    _ctx: web::Data<AppContext>,
-) -> HttpResponse {
-    let (_database_name, _transaction_id) = path_data.into_inner();
+    path_data: web::Path<(String, u32)>,
+    ctx: web::Data<AppContext>,
+    web::Json(update_dto): web::Json<UpdateMetadataDto>,
+) -> Result<HttpResponse, TransactionError> {
+    let (database_name, transaction_id) = path_data.into_inner();
+
+    ctx.update_collection_for_transaction(&database_name)
+        .map_err(|e| TransactionError::FailedToUpdateVector(format!("Cache error: {}", e)))?;
 
-    todo!()
+    service::update_vectors_metadata(
+        ctx.into_inner(),
+        &database_name,
+        transaction_id.into(),
+        update_dto.vectors,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().finish())
 }
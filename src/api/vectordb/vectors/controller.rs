@@ -1,15 +1,24 @@
 use actix_web::{web, HttpResponse, Result};
 
-use super::{error::VectorsError, service};
+use super::{
+    dtos::{GetVectorQuery, ListCursor, ListVectorsParams, ListVectorsQuery},
+    error::VectorsError,
+    service,
+};
 
 use crate::models::collection_cache::CollectionCacheExt;
 use crate::{
     app_context::AppContext,
-    models::{common::WaCustomError, types::VectorId},
+    models::{
+        common::WaCustomError,
+        external_ids::ExternalId,
+        versioning::{Hash, VersionRef},
+    },
 };
 
 pub(crate) async fn get_vector_by_id(
-    path: web::Path<(String, u64)>,
+    path: web::Path<(String, String)>,
+    web::Query(query): web::Query<GetVectorQuery>,
     ctx: web::Data<AppContext>,
 ) -> Result<HttpResponse> {
     let (collection_id, vector_id) = path.into_inner();
@@ -17,24 +26,44 @@ pub(crate) async fn get_vector_by_id(
     ctx.update_collection_for_query(&collection_id)
         .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Cache error: {}", e)))?;
 
-    let vector =
-        service::get_vector_by_id(ctx.into_inner(), &collection_id, VectorId(vector_id)).await?;
+    let version = match (query.version_hash, query.version_number) {
+        (Some(_), Some(_)) => {
+            return Err(VectorsError::InvalidParams(
+                "Only one of version_hash and version_number can be specified".to_string(),
+            )
+            .into())
+        }
+        (Some(hash), None) => Some(VersionRef::Hash(Hash::from(hash))),
+        (None, Some(number)) => Some(VersionRef::Number(number.into())),
+        (None, None) => None,
+    };
+    let vector = service::get_vector_by_id(
+        ctx.into_inner(),
+        &collection_id,
+        ExternalId::from(vector_id),
+        version,
+    )
+    .await?;
     Ok(HttpResponse::Ok().json(vector))
 }
 
 pub(crate) async fn check_vector_existence(
-    path: web::Path<(String, u64)>,
+    path: web::Path<(String, String)>,
     ctx: web::Data<AppContext>,
 ) -> Result<HttpResponse, VectorsError> {
-    let (collection_id, vector_id_u64) = path.into_inner();
+    let (collection_id, vector_id) = path.into_inner();
 
     ctx.update_collection_for_query(&collection_id)
         .map_err(|e| {
             VectorsError::WaCustom(WaCustomError::DatabaseError(format!("Cache error: {}", e)))
         })?;
 
-    let exists =
-        service::check_vector_existence(ctx.into_inner(), &collection_id, vector_id_u64).await?;
+    let exists = service::check_vector_existence(
+        ctx.into_inner(),
+        &collection_id,
+        &ExternalId::from(vector_id),
+    )
+    .await?;
 
     if exists {
         // Return 200 OK for HEAD if resource exists
@@ -46,17 +75,74 @@ pub(crate) async fn check_vector_existence(
 }
 
 pub(crate) async fn fetch_vector_neighbors(
-    path: web::Path<(String, u64)>,
+    path: web::Path<(String, String)>,
     ctx: web::Data<AppContext>,
 ) -> Result<HttpResponse, VectorsError> {
-    let (collection_id, vector_id_u64) = path.into_inner();
+    let (collection_id, vector_id) = path.into_inner();
     ctx.update_collection_for_query(&collection_id)
         .map_err(|e| {
             VectorsError::WaCustom(WaCustomError::DatabaseError(format!("Cache error: {}", e)))
         })?;
 
-    let neighbors =
-        service::fetch_vector_neighbors(ctx.into_inner(), &collection_id, VectorId(vector_id_u64))
-            .await?;
+    let neighbors = service::fetch_vector_neighbors(
+        ctx.into_inner(),
+        &collection_id,
+        ExternalId::from(vector_id),
+    )
+    .await?;
     Ok(HttpResponse::Ok().json(neighbors))
 }
+
+const DEFAULT_LIST_LIMIT: usize = 100;
+const MAX_LIST_LIMIT: usize = 1000;
+// Max. no. of ids scanned per page, so that the requests with filters
+// matching few vectors are bounded too
+const MAX_LIST_SCANNED: usize = 10_000;
+
+pub(crate) async fn list_vectors(
+    collection_id: web::Path<String>,
+    web::Query(query): web::Query<ListVectorsQuery>,
+    ctx: web::Data<AppContext>,
+) -> Result<HttpResponse, VectorsError> {
+    let collection_id = collection_id.into_inner();
+    ctx.update_collection_for_query(&collection_id)
+        .map_err(|e| {
+            VectorsError::WaCustom(WaCustomError::DatabaseError(format!("Cache error: {}", e)))
+        })?;
+
+    let cursor = query
+        .cursor
+        .filter(|cursor| !cursor.is_empty())
+        .map(|cursor| {
+            ListCursor::decode(&cursor)
+                .ok_or_else(|| VectorsError::InvalidParams(format!("Invalid cursor: {}", cursor)))
+        })
+        .transpose()?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if limit == 0 || limit > MAX_LIST_LIMIT {
+        return Err(VectorsError::InvalidParams(format!(
+            "limit must be between 1 and {}",
+            MAX_LIST_LIMIT
+        )));
+    }
+    let filter = query
+        .filter
+        .map(|filter| {
+            serde_json::from_str(&filter)
+                .map_err(|e| VectorsError::InvalidParams(format!("Invalid filter: {}", e)))
+        })
+        .transpose()?;
+
+    let page = service::list_vectors(
+        ctx.into_inner(),
+        &collection_id,
+        ListVectorsParams {
+            cursor,
+            limit,
+            filter,
+            max_scanned: MAX_LIST_SCANNED,
+        },
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(page))
+}
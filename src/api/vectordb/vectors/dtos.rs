@@ -1,18 +1,25 @@
-use std::fmt;
+use std::{collections::BTreeMap, fmt};
 
-use crate::metadata::MetadataFields;
+use crate::metadata::{Filter, MetadataFields};
 
 use serde::{
     de::{self, MapAccess, Visitor},
     Deserialize, Deserializer, Serialize,
 };
 
-use crate::{indexes::inverted::types::SparsePair, models::types::VectorId};
+use crate::{
+    indexes::inverted::types::SparsePair,
+    models::{external_ids::ExternalId, types::VectorId},
+};
 
 #[derive(Serialize)]
 pub(crate) struct CreateVectorDto {
-    pub id: VectorId,
+    pub id: ExternalId,
     pub dense_values: Option<Vec<f32>>,
+    /// Dense values of the named dense vector spaces, keyed by name
+    pub named_dense_values: Option<BTreeMap<String, Vec<f32>>>,
+    /// Dense values of the multi-vector spaces, keyed by name
+    pub multi_dense_values: Option<BTreeMap<String, Vec<Vec<f32>>>>,
     pub metadata: Option<MetadataFields>,
     pub sparse_values: Option<Vec<SparsePair>>,
     pub text: Option<String>,
@@ -41,6 +48,8 @@ impl<'de> Deserialize<'de> for CreateVectorDto {
             {
                 let mut id = None;
                 let mut dense_values = None;
+                let mut named_dense_values = None;
+                let mut multi_dense_values = None;
                 let mut metadata = None;
                 let mut sparse_values_raw: Option<(Vec<u32>, Vec<f32>)> = None;
                 let mut text = None;
@@ -59,6 +68,18 @@ impl<'de> Deserialize<'de> for CreateVectorDto {
                             }
                             dense_values = Some(map.next_value()?);
                         }
+                        "named_dense_values" => {
+                            if named_dense_values.is_some() {
+                                return Err(de::Error::duplicate_field("named_dense_values"));
+                            }
+                            named_dense_values = map.next_value()?;
+                        }
+                        "multi_dense_values" => {
+                            if multi_dense_values.is_some() {
+                                return Err(de::Error::duplicate_field("multi_dense_values"));
+                            }
+                            multi_dense_values = map.next_value()?;
+                        }
                         "metadata" => {
                             if metadata.is_some() {
                                 return Err(de::Error::duplicate_field("metadata"));
@@ -93,6 +114,8 @@ impl<'de> Deserialize<'de> for CreateVectorDto {
                                 &[
                                     "id",
                                     "dense_values",
+                                    "named_dense_values",
+                                    "multi_dense_values",
                                     "metadata",
                                     "sparse_values",
                                     "sparse_indices",
@@ -126,6 +149,8 @@ impl<'de> Deserialize<'de> for CreateVectorDto {
                 Ok(CreateVectorDto {
                     id,
                     dense_values,
+                    named_dense_values,
+                    multi_dense_values,
                     metadata,
                     sparse_values,
                     text,
@@ -139,6 +164,69 @@ impl<'de> Deserialize<'de> for CreateVectorDto {
 
 #[derive(Serialize)]
 pub(crate) struct SimilarVector {
-    pub id: VectorId,
+    pub id: ExternalId,
     pub score: f32,
 }
+
+#[derive(Serialize)]
+pub(crate) struct LevelNeighborsDto {
+    pub level: u8,
+    pub neighbors: Vec<SimilarVector>,
+}
+
+/// Version of the collection to read the vector as of, either by its
+/// hash or its number. Defaults to the current version.
+#[derive(Deserialize)]
+pub(crate) struct GetVectorQuery {
+    pub version_hash: Option<u32>,
+    pub version_number: Option<u16>,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ListVectorsQuery {
+    pub cursor: Option<String>,
+    pub limit: Option<usize>,
+    /// JSON encoded metadata `Filter`
+    pub filter: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ListVectorsResponseDto {
+    pub vectors: Vec<CreateVectorDto>,
+    /// Opaque cursor to be passed to fetch the next page, absent
+    /// when there are no more vectors. Note that with a filter, a page
+    /// may hold fewer vectors than the limit (even none) and still be
+    /// followed by more pages.
+    pub next_cursor: Option<String>,
+}
+
+/// Position of a page when listing the vectors of a collection
+///
+/// Vectors are listed in ascending order of their ids, so the cursor
+/// is just the id of the last vector examined for the page. It's encoded as a hex
+/// string so that clients treat it as opaque.
+pub(crate) struct ListCursor(pub VectorId);
+
+impl ListCursor {
+    pub fn encode(&self) -> String {
+        format!("{:016x}", self.0 .0)
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        if cursor.len() != 16 {
+            return None;
+        }
+        u64::from_str_radix(cursor, 16)
+            .ok()
+            .map(|id| Self(VectorId(id)))
+    }
+}
+
+pub(crate) struct ListVectorsParams {
+    pub cursor: Option<ListCursor>,
+    pub limit: usize,
+    pub filter: Option<Filter>,
+    /// Max. no. of ids scanned for the page, after which the page is
+    /// returned as is along with the cursor to resume scanning from
+    pub max_scanned: usize,
+}
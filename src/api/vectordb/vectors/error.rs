@@ -10,8 +10,10 @@ use crate::models::common::WaCustomError;
 #[derive(Debug)]
 pub(crate) enum VectorsError {
     NotFound,
+    CollectionNotFound,
     FailedToGetAppEnv,
     IndexNotFound,
+    InvalidParams(String),
     FailedToCreateVector(String),
     FailedToUpdateVector(String),
     FailedToFindSimilarVectors(String),
@@ -26,8 +28,10 @@ impl Display for VectorsError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::NotFound => write!(f, "Vector Not Found!"),
+            Self::CollectionNotFound => write!(f, "Collection not found"),
             Self::FailedToGetAppEnv => write!(f, "Failed to get App Env!"),
             Self::IndexNotFound => write!(f, "Index not found"),
+            Self::InvalidParams(msg) => write!(f, "Invalid request parameters: {}", msg),
             Self::FailedToCreateVector(msg) => {
                 write!(f, "Failed to create vector due to: {}", msg)
             }
@@ -63,8 +67,10 @@ impl ResponseError for VectorsError {
     fn status_code(&self) -> StatusCode {
         match self {
             Self::NotFound => StatusCode::BAD_REQUEST,
+            Self::CollectionNotFound => StatusCode::NOT_FOUND,
             Self::FailedToGetAppEnv => StatusCode::INTERNAL_SERVER_ERROR,
             Self::IndexNotFound => StatusCode::NOT_FOUND,
+            Self::InvalidParams(_) => StatusCode::BAD_REQUEST,
             Self::FailedToCreateVector(_) => StatusCode::BAD_REQUEST,
             Self::NotImplemented => StatusCode::BAD_REQUEST,
             Self::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
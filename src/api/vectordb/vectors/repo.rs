@@ -1,19 +1,37 @@
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::{atomic::Ordering, Arc};
 
 use crate::{
     indexes::{
-        hnsw::DenseInputEmbedding, inverted::SparseInputEmbedding, tf_idf::TFIDFInputEmbedding,
+        hnsw::{types::RawDenseVectorEmbedding, DenseInputEmbedding, HNSWIndex},
+        inverted::{types::RawSparseVectorEmbedding, InvertedIndex, SparseInputEmbedding},
+        tf_idf::{TFIDFIndex, TFIDFInputEmbedding},
         IndexOps,
     },
+    metadata::MetadataFields,
     models::{
-        collection::Collection, collection_transaction::CollectionTransaction, types::VectorId,
+        collection::Collection,
+        collection_transaction::CollectionTransaction,
+        external_ids::{ExternalId, INTERNAL_IDS_END},
+        prob_lazy_load::lazy_item::ProbLazyItem,
+        tombstones::Tombstone,
+        types::VectorId,
+        vector_ids::{PresenceChange, VectorIds, VectorPresence},
+        versioning::{VersionRef, VersionSnapshot},
+    },
+    vector_store::{
+        find_node_by_id, get_dense_embedding_by_id, get_dense_embedding_version,
+        get_multi_vector_document_embeddings,
     },
 };
 
 use crate::app_context::AppContext;
 
 use super::{
-    dtos::{CreateVectorDto, SimilarVector},
+    dtos::{
+        CreateVectorDto, LevelNeighborsDto, ListCursor, ListVectorsParams, ListVectorsResponseDto,
+        SimilarVector,
+    },
     error::VectorsError,
 };
 
@@ -23,23 +41,67 @@ pub(crate) async fn create_vector_in_transaction(
     transaction: &CollectionTransaction,
     create_vector_dto: CreateVectorDto,
 ) -> Result<(), VectorsError> {
+    let id = assign_internal_ids(collection, [&create_vector_dto.id])?
+        .pop()
+        .unwrap();
+    if transaction
+        .record_upserted_ids(std::slice::from_ref(&id))
+        .is_some()
+    {
+        return Err(VectorsError::InvalidParams(format!(
+            "Vector {} has already been upserted in this transaction",
+            create_vector_dto.id
+        )));
+    }
+    // Same as an upsert, the vector replaces the existing one (if any)
+    retire_earlier_data(collection, transaction, &id)?;
+    let presence_change = PresenceChange {
+        replaces: true,
+        presence: get_presence(&create_vector_dto),
+    };
+    transaction.record_presence_change(id.clone(), presence_change);
+    record_vector_metadata(
+        collection,
+        transaction,
+        &id,
+        create_vector_dto.metadata.as_ref(),
+    )?;
+    for (vector_name, vectors) in create_vector_dto.multi_dense_values.unwrap_or_default() {
+        index_multi_vector_documents(
+            &ctx,
+            collection,
+            transaction,
+            vector_name,
+            vec![(id.clone(), vectors)],
+        )?;
+    }
+    for (vector_name, values) in create_vector_dto.named_dense_values.unwrap_or_default() {
+        index_dense_embeddings(
+            &ctx,
+            collection,
+            transaction,
+            Some(&vector_name),
+            vec![DenseInputEmbedding(
+                id.clone(),
+                values,
+                create_vector_dto.metadata.clone(),
+                false,
+            )],
+        )?;
+    }
     if let Some(values) = create_vector_dto.dense_values {
-        let Some(hnsw_index) = collection.get_hnsw_index() else {
-            return Err(VectorsError::IndexNotFound);
-        };
-        hnsw_index
-            .run_upload(
-                &collection,
-                vec![DenseInputEmbedding(
-                    create_vector_dto.id.clone(),
-                    values,
-                    create_vector_dto.metadata,
-                    false,
-                )],
-                transaction,
-                &ctx.config,
-            )
-            .map_err(VectorsError::WaCustom)?;
+        index_dense_embeddings(
+            &ctx,
+            collection,
+            transaction,
+            None,
+            vec![DenseInputEmbedding(
+                id.clone(),
+                values,
+                create_vector_dto.metadata,
+                false,
+            )],
+        )?;
     }
     if let Some(values) = create_vector_dto.sparse_values {
         let Some(inverted_index) = collection.get_inverted_index() else {
@@ -47,8 +109,8 @@ pub(crate) async fn create_vector_in_transaction(
         };
         inverted_index
             .run_upload(
-                &collection,
-                vec![SparseInputEmbedding(create_vector_dto.id.clone(), values)],
+                collection,
+                vec![SparseInputEmbedding(id.clone(), values)],
                 transaction,
                 &ctx.config,
             )
@@ -60,8 +122,8 @@ pub(crate) async fn create_vector_in_transaction(
         };
         tf_idf_index
             .run_upload(
-                &collection,
-                vec![TFIDFInputEmbedding(create_vector_dto.id, text)],
+                collection,
+                vec![TFIDFInputEmbedding(id, text)],
                 transaction,
                 &ctx.config,
             )
@@ -71,12 +133,462 @@ pub(crate) async fn create_vector_in_transaction(
     Ok(())
 }
 
+/// Indexes the embeddings in the default dense index (`vector_name`
+/// = `None`) or in the named dense index
+fn index_dense_embeddings(
+    ctx: &AppContext,
+    collection: &Collection,
+    transaction: &CollectionTransaction,
+    vector_name: Option<&str>,
+    embeddings: Vec<DenseInputEmbedding>,
+) -> Result<(), VectorsError> {
+    let Some(hnsw_index) = collection.get_dense_index(vector_name) else {
+        return Err(VectorsError::IndexNotFound);
+    };
+    let vector_name = vector_name.unwrap_or("default");
+    if collection.meta.dense_vector.is_multi_vector(vector_name) {
+        return Err(VectorsError::InvalidParams(format!(
+            "Vector '{}' is a multi-vector space, its values must be provided in multi_dense_values",
+            vector_name
+        )));
+    }
+    upload_dense_embeddings(
+        ctx,
+        collection,
+        transaction,
+        &hnsw_index,
+        vector_name,
+        embeddings,
+    )
+}
+
+/// Indexes the vectors of the documents in the multi-vector space,
+/// each of them with an id of its own
+fn index_multi_vector_documents(
+    ctx: &AppContext,
+    collection: &Collection,
+    transaction: &CollectionTransaction,
+    vector_name: String,
+    documents: Vec<(VectorId, Vec<Vec<f32>>)>,
+) -> Result<(), VectorsError> {
+    let Some(hnsw_index) = collection.get_named_hnsw_index(&vector_name) else {
+        return Err(VectorsError::IndexNotFound);
+    };
+    if !collection.meta.dense_vector.is_multi_vector(&vector_name) {
+        return Err(VectorsError::InvalidParams(format!(
+            "Vector '{}' is not a multi-vector space",
+            vector_name
+        )));
+    }
+
+    let mut embeddings = Vec::new();
+    for (document_id, vectors) in documents {
+        if vectors.is_empty() {
+            return Err(VectorsError::InvalidParams(format!(
+                "No vectors provided for multi-vector space '{}'",
+                vector_name
+            )));
+        }
+        let vector_ids = collection
+            .multi_vectors
+            .assign_vector_ids(&collection.lmdb, &document_id, vectors.len())
+            .map_err(VectorsError::WaCustom)?;
+        embeddings.extend(vector_ids.iter().zip(vectors).map(|(vector_id, values)| {
+            DenseInputEmbedding(vector_id.clone(), values, None, false)
+        }));
+        transaction.add_multi_vector_document(vector_name.clone(), document_id, vector_ids);
+    }
+
+    upload_dense_embeddings(
+        ctx,
+        collection,
+        transaction,
+        &hnsw_index,
+        &vector_name,
+        embeddings,
+    )
+}
+
+fn upload_dense_embeddings(
+    ctx: &AppContext,
+    collection: &Collection,
+    transaction: &CollectionTransaction,
+    hnsw_index: &HNSWIndex,
+    vector_name: &str,
+    embeddings: Vec<DenseInputEmbedding>,
+) -> Result<(), VectorsError> {
+    if transaction
+        .get_dense_index_transaction(hnsw_index.vector_name.as_deref())
+        .is_none()
+    {
+        return Err(VectorsError::InvalidParams(format!(
+            "Dense index of vector '{}' was created after the transaction was started",
+            vector_name
+        )));
+    }
+    if let Some(embedding) = embeddings
+        .iter()
+        .find(|embedding| embedding.1.len() != hnsw_index.dim)
+    {
+        return Err(VectorsError::InvalidParams(format!(
+            "Dense values of vector '{}' have {} dimensions, expected {}",
+            vector_name,
+            embedding.1.len(),
+            hnsw_index.dim
+        )));
+    }
+    hnsw_index
+        .run_upload(collection, embeddings, transaction, &ctx.config)
+        .map_err(VectorsError::WaCustom)
+}
+
+/// Returns the internal ids to insert the vectors with the user
+/// provided ids with
+fn assign_internal_ids<'a>(
+    collection: &Collection,
+    ids: impl IntoIterator<Item = &'a ExternalId> + Clone,
+) -> Result<Vec<VectorId>, VectorsError> {
+    for id in ids.clone() {
+        if id.is_out_of_range() {
+            return Err(VectorsError::InvalidParams(format!(
+                "Vector id {} exceeds the maximum vector id {}",
+                id,
+                INTERNAL_IDS_END - 1
+            )));
+        }
+        if let ExternalId::Int(_) = id {
+            if collection
+                .get_internal_id(id)
+                .map_err(VectorsError::WaCustom)?
+                .is_none()
+            {
+                return Err(VectorsError::InvalidParams(format!(
+                    "Vector id {} has been assigned to a string id",
+                    id
+                )));
+            }
+        }
+    }
+    collection
+        .get_or_assign_internal_ids(ids)
+        .map_err(VectorsError::WaCustom)
+}
+
+/// Returns the internal id of an existing vector with the user
+/// provided id
+fn get_internal_id(collection: &Collection, id: &ExternalId) -> Result<VectorId, VectorsError> {
+    collection
+        .get_internal_id(id)
+        .map_err(VectorsError::WaCustom)?
+        .ok_or(VectorsError::NotFound)
+}
+
+/// Records the metadata of the vector, irrespective of the indexes
+/// it's inserted in, so that metadata filters can be applied to the
+/// results of all of them
+///
+/// Vectors without metadata are only recorded if they had metadata
+/// before, so that it doesn't outlive the vector being replaced.
+fn record_vector_metadata(
+    collection: &Collection,
+    transaction: &CollectionTransaction,
+    id: &VectorId,
+    metadata: Option<&MetadataFields>,
+) -> Result<(), VectorsError> {
+    let fields = match metadata {
+        Some(fields) => fields.clone(),
+        None if collection
+            .vector_metadata
+            .get(id)
+            .map_err(VectorsError::WaCustom)?
+            .is_some() =>
+        {
+            MetadataFields::new()
+        }
+        None => return Ok(()),
+    };
+    transaction.add_vector_metadata(id.clone(), fields);
+    Ok(())
+}
+
+/// Returns the indexes in which the vector is to be inserted
+fn get_presence(create_vector_dto: &CreateVectorDto) -> VectorPresence {
+    let mut presence = VectorPresence::default();
+    if create_vector_dto.dense_values.is_some() {
+        presence |= VectorPresence::DENSE;
+    }
+    if create_vector_dto.sparse_values.is_some() {
+        presence |= VectorPresence::SPARSE;
+    }
+    if create_vector_dto.text.is_some() {
+        presence |= VectorPresence::TF_IDF;
+    }
+    let has_named_values = create_vector_dto
+        .named_dense_values
+        .as_ref()
+        .is_some_and(|values| !values.is_empty())
+        || create_vector_dto
+            .multi_dense_values
+            .as_ref()
+            .is_some_and(|values| !values.is_empty());
+    if has_named_values {
+        presence |= VectorPresence::OTHER;
+    }
+    presence
+}
+
+/// Retires the data indexed for an existing (or deleted) vector in
+/// earlier versions, so that only the data inserted for it in the
+/// transaction is live
+fn retire_earlier_data(
+    collection: &Collection,
+    transaction: &CollectionTransaction,
+    id: &VectorId,
+) -> Result<(), VectorsError> {
+    if collection.tombstones.get(id).is_some() || vector_exists(collection, id)? {
+        transaction.add_tombstone(id.clone(), Tombstone::Replaced(transaction.id));
+    }
+    Ok(())
+}
+
+/// Returns the raw embedding of the vector in the dense index if it's
+/// live, as of the snapshot `as_of` (if any)
+///
+/// Only the latest raw embedding of every vector is retained, hence
+/// vectors whose dense values were replaced after the snapshot's
+/// version have none as of the snapshot.
+pub(crate) fn get_live_dense_embedding(
+    collection: &Collection,
+    hnsw_index: &HNSWIndex,
+    vector_id: &VectorId,
+    as_of: Option<&VersionSnapshot>,
+) -> Result<Option<RawDenseVectorEmbedding>, VectorsError> {
+    let Some(version) = get_dense_embedding_version(collection, hnsw_index, vector_id)
+        .map_err(VectorsError::WaCustom)?
+    else {
+        return Ok(None);
+    };
+    if !collection
+        .tombstones
+        .is_live_as_of(vector_id, version, as_of)
+    {
+        return Ok(None);
+    }
+    get_dense_embedding_by_id(collection, hnsw_index, vector_id)
+        .map(Some)
+        .map_err(VectorsError::WaCustom)
+}
+
+/// Returns the raw sparse embedding of the vector if it's live, as of
+/// the snapshot `as_of` (if any)
+pub(crate) fn get_live_sparse_embedding<'a>(
+    collection: &Collection,
+    inverted_index: &'a InvertedIndex,
+    vector_id: &VectorId,
+    as_of: Option<&VersionSnapshot>,
+) -> Option<&'a RawSparseVectorEmbedding> {
+    let versioned = inverted_index.vec_raw_map.get_versioned(vector_id.0)?;
+    let item = collection.tombstones.visible_item(versioned, as_of)?;
+    collection
+        .tombstones
+        .is_live_as_of(vector_id, item.version, as_of)
+        .then_some(&item.value)
+}
+
+/// Returns the internal document id and the stored raw text (if
+/// any) of the vector's TF-IDF document if it's live, as of the
+/// snapshot `as_of` (if any)
+pub(crate) fn get_live_document<'a>(
+    collection: &Collection,
+    tf_idf_index: &'a TFIDFIndex,
+    vector_id: &VectorId,
+    as_of: Option<&VersionSnapshot>,
+) -> Option<(u32, &'a Option<String>)> {
+    let (document_id, (_, text)) =
+        tf_idf_index.get_document(vector_id, &collection.tombstones, as_of)?;
+    tf_idf_index
+        .is_document_live(document_id, &collection.tombstones, as_of)
+        .then_some((document_id, text))
+}
+
+/// Checks if any of the indexes of the collection has live data for
+/// the vector, without loading the raw dense embedding
+pub(crate) fn vector_exists(
+    collection: &Collection,
+    vector_id: &VectorId,
+) -> Result<bool, VectorsError> {
+    if collection.tombstones.is_deleted(vector_id) {
+        return Ok(false);
+    }
+
+    // Dense embeddings are looked up by their offset key in LMDB, so
+    // the raw vector is never read from disk
+    for hnsw_index in collection.get_dense_indexes() {
+        let version = get_dense_embedding_version(collection, &hnsw_index, vector_id)
+            .map_err(VectorsError::WaCustom)?;
+        if version.is_some_and(|version| collection.tombstones.is_live(vector_id, version)) {
+            return Ok(true);
+        }
+    }
+
+    for (vector_name, _) in collection.get_multi_vector_indexes() {
+        let document = collection
+            .multi_vectors
+            .get_document(&collection.lmdb, &vector_name, vector_id)
+            .map_err(VectorsError::WaCustom)?;
+        if document
+            .is_some_and(|document| collection.tombstones.is_live(vector_id, document.version))
+        {
+            return Ok(true);
+        }
+    }
+
+    if let Some(inverted_index) = collection.get_inverted_index() {
+        if get_live_sparse_embedding(collection, &inverted_index, vector_id, None).is_some() {
+            return Ok(true);
+        }
+    }
+
+    if let Some(tf_idf_index) = collection.get_tf_idf_index() {
+        if get_live_document(collection, &tf_idf_index, vector_id, None).is_some() {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Returns the data of the vector, as of the given version of the
+/// collection if any
 pub(crate) async fn get_vector_by_id(
-    _ctx: Arc<AppContext>,
-    _collection_id: &str,
-    _vector_id: VectorId,
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    vector_id: ExternalId,
+    version: Option<VersionRef>,
 ) -> Result<CreateVectorDto, VectorsError> {
-    unimplemented!()
+    let collection = ctx
+        .ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or(VectorsError::CollectionNotFound)?;
+
+    let as_of = version
+        .map(|version| {
+            collection
+                .version_snapshot(version)
+                .map_err(VectorsError::WaCustom)?
+                .ok_or_else(|| {
+                    VectorsError::InvalidParams(format!("No committed version {}", version))
+                })
+        })
+        .transpose()?;
+    let vector_id = get_internal_id(&collection, &vector_id)?;
+    get_live_vector(&collection, vector_id, as_of.as_ref())
+}
+
+/// Returns the live data of the vector from all the indexes of the
+/// collection, as of the snapshot `as_of` (if any)
+///
+/// As only the latest raw dense embeddings are retained, dense values
+/// replaced after the snapshot's version are missing in the returned
+/// data, see `get_live_dense_embedding`.
+fn get_live_vector(
+    collection: &Collection,
+    vector_id: VectorId,
+    as_of: Option<&VersionSnapshot>,
+) -> Result<CreateVectorDto, VectorsError> {
+    if matches!(
+        collection.tombstones.get_as_of(&vector_id, as_of),
+        Some(Tombstone::Deleted)
+    ) {
+        return Err(VectorsError::NotFound);
+    }
+
+    let mut found = false;
+
+    let mut dense_values = None;
+    let mut named_dense_values = BTreeMap::new();
+    let mut metadata = None;
+    // The same metadata is indexed with the vector in all the dense
+    // indexes, so it's taken from the first one having the vector. It's
+    // only used for vectors inserted before the metadata of all vectors
+    // was recorded separately, see `record_vector_metadata`.
+    for hnsw_index in collection.get_dense_indexes() {
+        let Some(embedding) = get_live_dense_embedding(collection, &hnsw_index, &vector_id, as_of)?
+        else {
+            continue;
+        };
+        found = true;
+        let values = embedding.raw_vec.as_ref().clone();
+        match &hnsw_index.vector_name {
+            Some(vector_name) => {
+                named_dense_values.insert(vector_name.clone(), values);
+            }
+            None => dense_values = Some(values),
+        }
+        if metadata.is_none() {
+            metadata = embedding.raw_metadata;
+        }
+    }
+
+    let mut multi_dense_values = BTreeMap::new();
+    for (vector_name, hnsw_index) in collection.get_multi_vector_indexes() {
+        let Some(embeddings) = get_multi_vector_document_embeddings(
+            collection,
+            &hnsw_index,
+            &vector_name,
+            &vector_id,
+            as_of,
+        )
+        .map_err(VectorsError::WaCustom)?
+        else {
+            continue;
+        };
+        found = true;
+        multi_dense_values.insert(
+            vector_name,
+            embeddings
+                .into_iter()
+                .map(|embedding| embedding.raw_vec.as_ref().clone())
+                .collect(),
+        );
+    }
+
+    let sparse_values = collection.get_inverted_index().and_then(|inverted_index| {
+        get_live_sparse_embedding(collection, &inverted_index, &vector_id, as_of)
+            .map(|embedding| embedding.raw_vec.as_ref().clone())
+    });
+    found |= sparse_values.is_some();
+
+    let document = collection.get_tf_idf_index().and_then(|tf_idf_index| {
+        get_live_document(collection, &tf_idf_index, &vector_id, as_of)
+            .map(|(_, text)| text.clone())
+    });
+    found |= document.is_some();
+    let text = document.flatten();
+
+    if !found {
+        return Err(VectorsError::NotFound);
+    }
+    if let Some(fields) = collection
+        .vector_metadata
+        .get_as_of(&vector_id, as_of)
+        .map_err(VectorsError::WaCustom)?
+    {
+        metadata = (!fields.is_empty()).then_some(fields);
+    }
+
+    Ok(CreateVectorDto {
+        id: collection
+            .get_external_id(&vector_id)
+            .map_err(VectorsError::WaCustom)?,
+        dense_values,
+        named_dense_values: (!named_dense_values.is_empty()).then_some(named_dense_values),
+        multi_dense_values: (!multi_dense_values.is_empty()).then_some(multi_dense_values),
+        metadata,
+        sparse_values,
+        text,
+    })
 }
 
 pub(crate) async fn upsert_vectors_in_transaction(
@@ -85,37 +597,106 @@ pub(crate) async fn upsert_vectors_in_transaction(
     transaction: &CollectionTransaction,
     vectors: Vec<CreateVectorDto>,
 ) -> Result<(), VectorsError> {
+    // The data indexed for a vector can't be retired within the
+    // version it was indexed in, so only the last of the vectors with
+    // the same id is upserted, and a vector can't be upserted again
+    // later in the same transaction.
+    let mut seen = HashSet::new();
+    let mut vectors: Vec<_> = vectors
+        .into_iter()
+        .rev()
+        .filter(|dto| seen.insert(dto.id.clone()))
+        .collect();
+    vectors.reverse();
+    let ids = assign_internal_ids(collection, vectors.iter().map(|dto| &dto.id))?;
+    if let Some(id) = transaction.record_upserted_ids(&ids) {
+        let id = collection
+            .get_external_id(&id)
+            .map_err(VectorsError::WaCustom)?;
+        return Err(VectorsError::InvalidParams(format!(
+            "Vector {} has already been upserted in this transaction",
+            id
+        )));
+    }
+
+    for id in &ids {
+        retire_earlier_data(collection, transaction, id)?;
+    }
+
+    for (id, dto) in ids.iter().zip(&vectors) {
+        record_vector_metadata(collection, transaction, id, dto.metadata.as_ref())?;
+    }
+
+    let mut named_dense_vecs: BTreeMap<String, Vec<DenseInputEmbedding>> = BTreeMap::new();
+    let mut multi_vector_documents: BTreeMap<String, Vec<_>> = BTreeMap::new();
     let (dense_vec, sparse_vec, tf_idf_vec): (Vec<_>, Vec<_>, Vec<_>) =
-        vectors
-            .into_iter()
-            .fold((Vec::new(), Vec::new(), Vec::new()), |mut acc, dto| {
+        ids.into_iter().zip(vectors).fold(
+            (Vec::new(), Vec::new(), Vec::new()),
+            |mut acc, (id, dto)| {
+                let presence_change = PresenceChange {
+                    replaces: true,
+                    presence: get_presence(&dto),
+                };
+                transaction.record_presence_change(id.clone(), presence_change);
                 let CreateVectorDto {
-                    id,
+                    id: _,
                     dense_values,
+                    named_dense_values,
+                    multi_dense_values,
                     metadata,
                     sparse_values,
                     text,
                 } = dto;
-
+                // As the upsert replaces the vector, all the provided
+                // representations need to be indexed
+                for (vector_name, vectors) in multi_dense_values.unwrap_or_default() {
+                    multi_vector_documents
+                        .entry(vector_name)
+                        .or_default()
+                        .push((id.clone(), vectors));
+                }
+                for (vector_name, values) in named_dense_values.unwrap_or_default() {
+                    named_dense_vecs
+                        .entry(vector_name)
+                        .or_default()
+                        .push(DenseInputEmbedding(
+                            id.clone(),
+                            values,
+                            metadata.clone(),
+                            false,
+                        ));
+                }
                 if let Some(values) = dense_values {
-                    acc.0.push(DenseInputEmbedding(id, values, metadata, false));
-                } else if let Some(values) = sparse_values {
-                    acc.1.push(SparseInputEmbedding(id, values));
-                } else if let Some(text) = text {
+                    acc.0
+                        .push(DenseInputEmbedding(id.clone(), values, metadata, false));
+                }
+                if let Some(values) = sparse_values {
+                    acc.1.push(SparseInputEmbedding(id.clone(), values));
+                }
+                if let Some(text) = text {
                     acc.2.push(TFIDFInputEmbedding(id, text));
                 }
 
                 acc
-            });
+            },
+        );
 
     if !dense_vec.is_empty() {
-        let Some(hnsw_index) = collection.get_hnsw_index() else {
-            return Err(VectorsError::IndexNotFound);
-        };
+        index_dense_embeddings(&ctx, collection, transaction, None, dense_vec)?;
+    }
 
-        hnsw_index
-            .run_upload(collection, dense_vec, transaction, &ctx.config)
-            .map_err(VectorsError::WaCustom)?;
+    for (vector_name, documents) in multi_vector_documents {
+        index_multi_vector_documents(&ctx, collection, transaction, vector_name, documents)?;
+    }
+
+    for (vector_name, embeddings) in named_dense_vecs {
+        index_dense_embeddings(
+            &ctx,
+            collection,
+            transaction,
+            Some(&vector_name),
+            embeddings,
+        )?;
     }
 
     if !sparse_vec.is_empty() {
@@ -141,18 +722,1124 @@ pub(crate) async fn upsert_vectors_in_transaction(
     Ok(())
 }
 
+/// Updates the metadata fields of existing vectors without requiring
+/// the embeddings to be uploaded again
+///
+/// The given fields are merged into the vector's current metadata
+/// (including the changes made earlier in the transaction), which is
+/// recorded in the transaction. The embeddings are left as
+/// is, except when fields of the collection's metadata schema change
+/// for vectors in the dense indexes, where metadata is part of the
+/// HNSW nodes (through the metadata replicas). Those vectors are
+/// re-indexed in the transaction, see `reindex_vectors`.
+pub(crate) async fn update_vectors_metadata_in_transaction(
+    ctx: Arc<AppContext>,
+    collection: &Collection,
+    transaction: &CollectionTransaction,
+    updates: Vec<(ExternalId, MetadataFields)>,
+) -> Result<(), VectorsError> {
+    let mut reindexed = Vec::new();
+
+    for (vector_id, fields) in updates {
+        let vector_id = get_internal_id(collection, &vector_id)?;
+        if !vector_exists(collection, &vector_id)? {
+            return Err(VectorsError::NotFound);
+        }
+        let mut metadata = match transaction.get_vector_metadata(&vector_id) {
+            Some(fields) => fields,
+            None => get_live_metadata(collection, &vector_id)?,
+        };
+        let is_encoded_field_changed =
+            collection
+                .meta
+                .metadata_schema
+                .as_ref()
+                .is_some_and(|schema| {
+                    fields.iter().any(|(name, value)| {
+                        schema.get_field(name).is_ok() && metadata.get(name) != Some(value)
+                    })
+                });
+        metadata.extend(fields);
+        if is_encoded_field_changed {
+            reindexed.push((vector_id.clone(), metadata.clone()));
+        }
+        transaction.add_vector_metadata(vector_id, metadata);
+    }
+
+    if reindexed.is_empty() {
+        return Ok(());
+    }
+    reindex_vectors(&ctx, collection, transaction, reindexed)
+}
+
+/// Returns the current metadata of the vector
+///
+/// Vectors inserted before the metadata of all vectors was recorded
+/// separately (see `record_vector_metadata`) only have the metadata
+/// indexed along with their dense embeddings.
+fn get_live_metadata(
+    collection: &Collection,
+    vector_id: &VectorId,
+) -> Result<MetadataFields, VectorsError> {
+    if let Some(fields) = collection
+        .vector_metadata
+        .get(vector_id)
+        .map_err(VectorsError::WaCustom)?
+    {
+        return Ok(fields);
+    }
+    for hnsw_index in collection.get_dense_indexes() {
+        if let Some(embedding) = get_live_dense_embedding(collection, &hnsw_index, vector_id, None)?
+        {
+            return Ok(embedding.raw_metadata.unwrap_or_default());
+        }
+    }
+    Ok(MetadataFields::new())
+}
+
+/// Re-indexes the dense embeddings of the vectors with their updated
+/// metadata
+///
+/// The data indexed for the vectors in earlier versions is retired, so
+/// the rest of their data is recorded again in the transaction too:
+/// sparse vectors are re-inserted from their raw values, whereas the
+/// TF-IDF documents and the multi-vector documents are only recorded
+/// again in the version, without being re-indexed.
+fn reindex_vectors(
+    ctx: &AppContext,
+    collection: &Collection,
+    transaction: &CollectionTransaction,
+    vectors: Vec<(VectorId, MetadataFields)>,
+) -> Result<(), VectorsError> {
+    // Vectors that aren't in any of the dense indexes needn't be
+    // re-indexed
+    let mut dense_vecs: BTreeMap<Option<String>, Vec<DenseInputEmbedding>> = BTreeMap::new();
+    let mut ids = Vec::new();
+    for (id, metadata) in vectors {
+        let mut is_dense = false;
+        for hnsw_index in collection.get_dense_indexes() {
+            let Some(embedding) = get_live_dense_embedding(collection, &hnsw_index, &id, None)?
+            else {
+                continue;
+            };
+            dense_vecs
+                .entry(hnsw_index.vector_name.clone())
+                .or_default()
+                .push(DenseInputEmbedding(
+                    id.clone(),
+                    embedding.raw_vec.as_ref().clone(),
+                    Some(metadata.clone()),
+                    false,
+                ));
+            is_dense = true;
+        }
+        if is_dense {
+            ids.push(id);
+        }
+    }
+    if ids.is_empty() {
+        return Ok(());
+    }
+    if let Some(id) = transaction.record_upserted_ids(&ids) {
+        let id = collection
+            .get_external_id(&id)
+            .map_err(VectorsError::WaCustom)?;
+        return Err(VectorsError::InvalidParams(format!(
+            "Vector {} has already been upserted in this transaction",
+            id
+        )));
+    }
+
+    for (vector_name, embeddings) in dense_vecs {
+        index_dense_embeddings(
+            ctx,
+            collection,
+            transaction,
+            vector_name.as_deref(),
+            embeddings,
+        )?;
+    }
+
+    let mut sparse_vec = Vec::new();
+    for id in &ids {
+        for (vector_name, _) in collection.get_multi_vector_indexes() {
+            let document = collection
+                .multi_vectors
+                .get_document(&collection.lmdb, &vector_name, id)
+                .map_err(VectorsError::WaCustom)?;
+            if let Some(document) =
+                document.filter(|document| collection.tombstones.is_live(id, document.version))
+            {
+                transaction.add_multi_vector_document(vector_name, id.clone(), document.vector_ids);
+            }
+        }
+        if let Some(inverted_index) = collection.get_inverted_index() {
+            if let Some(embedding) =
+                get_live_sparse_embedding(collection, &inverted_index, id, None)
+            {
+                sparse_vec.push(SparseInputEmbedding(
+                    id.clone(),
+                    embedding.raw_vec.as_ref().clone(),
+                ));
+            }
+        }
+        if let Some(tf_idf_index) = collection.get_tf_idf_index() {
+            tf_idf_index.reinsert_document(transaction.id, id, &collection.tombstones);
+        }
+        transaction.add_tombstone(id.clone(), Tombstone::Replaced(transaction.id));
+    }
+
+    if !sparse_vec.is_empty() {
+        let Some(inverted_index) = collection.get_inverted_index() else {
+            return Err(VectorsError::IndexNotFound);
+        };
+        inverted_index
+            .run_upload(collection, sparse_vec, transaction, &ctx.config)
+            .map_err(VectorsError::WaCustom)?;
+    }
+
+    Ok(())
+}
+
 pub(crate) async fn check_vector_existence(
-    _ctx: Arc<AppContext>,
-    _collection_id: &str,
-    _vector_id: u64,
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    vector_id: &ExternalId,
 ) -> Result<bool, VectorsError> {
-    unimplemented!()
+    let collection = ctx
+        .ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or(VectorsError::CollectionNotFound)?;
+    match collection
+        .get_internal_id(vector_id)
+        .map_err(VectorsError::WaCustom)?
+    {
+        Some(vector_id) => vector_exists(&collection, &vector_id),
+        None => Ok(false),
+    }
 }
 
 pub(crate) async fn fetch_vector_neighbors(
-    _ctx: Arc<AppContext>,
-    _collection_id: &str,
-    _vector_id: VectorId,
-) -> Result<Vec<SimilarVector>, VectorsError> {
-    unimplemented!()
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    vector_id: ExternalId,
+) -> Result<Vec<LevelNeighborsDto>, VectorsError> {
+    let collection = ctx
+        .ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or(VectorsError::CollectionNotFound)?;
+    let vector_id = get_internal_id(&collection, &vector_id)?;
+    let hnsw_index = collection
+        .get_hnsw_index()
+        .ok_or(VectorsError::IndexNotFound)?;
+
+    let version = get_dense_embedding_version(&collection, &hnsw_index, &vector_id)
+        .map_err(VectorsError::WaCustom)?;
+    if !version.is_some_and(|version| collection.tombstones.is_live(&vector_id, version)) {
+        return Err(VectorsError::NotFound);
+    }
+
+    let Some(mut lazy_item) =
+        find_node_by_id(&ctx.config, &collection, hnsw_index.clone(), &vector_id)
+            .map_err(VectorsError::WaCustom)?
+    else {
+        return Err(VectorsError::FailedToFindSimilarVectors(format!(
+            "node for vector {} not reachable in the index",
+            vector_id
+        )));
+    };
+
+    let cache = &hnsw_index.cache;
+    let mut levels = Vec::new();
+    // Walk up from the level 0 node through the parent nodes, which
+    // are the same vector's nodes in the higher levels.
+    loop {
+        let node = unsafe { &*lazy_item }
+            .try_get_data(cache)
+            .map_err(|e| VectorsError::WaCustom(e.into()))?;
+
+        let mut neighbors = Vec::new();
+        for neighbor in node.get_neighbors_raw().iter() {
+            let Some((_, neighbor_node, score)) =
+                (unsafe { neighbor.load(Ordering::SeqCst).as_ref() })
+            else {
+                continue;
+            };
+            let neighbor_node = unsafe { &**neighbor_node }
+                .try_get_data(cache)
+                .map_err(|e| VectorsError::WaCustom(e.into()))?;
+            let (orig_id, _) = neighbor_node.get_ids();
+            // Skip the root node
+            if orig_id.0 == u64::MAX {
+                continue;
+            }
+            neighbors.push(SimilarVector {
+                id: collection
+                    .get_external_id(&orig_id)
+                    .map_err(VectorsError::WaCustom)?,
+                score: score.get_value(),
+            });
+        }
+        neighbors.sort_unstable_by(|a, b| b.score.total_cmp(&a.score));
+
+        levels.push(LevelNeighborsDto {
+            level: node.hnsw_level.0,
+            neighbors,
+        });
+
+        let parent = node.get_parent();
+        if parent.is_null() {
+            break;
+        }
+        let (latest, _) = ProbLazyItem::get_latest_version(parent, cache)
+            .map_err(|e| VectorsError::WaCustom(e.into()))?;
+        lazy_item = latest;
+    }
+
+    Ok(levels)
+}
+
+/// Lists the live vectors of the collection in ascending order of
+/// their ids, starting after the cursor
+///
+/// The recorded ids of the collection (see [`VectorIds`]) are scanned
+/// from the cursor, in chunks, until `limit` live vectors matching the
+/// filter (if any) are found or `max_scanned` ids have been scanned.
+/// The filter is checked against the recorded metadata of the vectors
+/// (see [`crate::models::vector_metadata::VectorMetadata`]), so that only the matching vectors are
+/// loaded. Vectors that are written in the open transaction aren't
+/// listed until it's committed.
+pub(crate) async fn list_vectors(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    params: ListVectorsParams,
+) -> Result<ListVectorsResponseDto, VectorsError> {
+    let collection = ctx
+        .ain_env
+        .collections_map
+        .get_collection(collection_id)
+        .ok_or(VectorsError::CollectionNotFound)?;
+
+    let mut vectors = Vec::with_capacity(params.limit);
+    let mut last_id = params.cursor.map(|ListCursor(id)| id);
+    let mut num_scanned = 0;
+    let mut has_more = false;
+    'scan: loop {
+        // One more than needed, to know whether there's a next page
+        let ids = VectorIds::get_range(&collection.lmdb, last_id.as_ref(), params.limit + 1)
+            .map_err(VectorsError::WaCustom)?;
+        if ids.is_empty() {
+            break;
+        }
+        for id in ids {
+            if vectors.len() == params.limit || num_scanned == params.max_scanned {
+                has_more = true;
+                break 'scan;
+            }
+            num_scanned += 1;
+            last_id = Some(id.clone());
+            if let Some(filter) = &params.filter {
+                let fields = collection
+                    .vector_metadata
+                    .get(&id)
+                    .map_err(VectorsError::WaCustom)?;
+                if !filter.matches(fields.as_ref()) {
+                    continue;
+                }
+            }
+            let vector = match get_live_vector(&collection, id, None) {
+                Ok(vector) => vector,
+                Err(VectorsError::NotFound) => continue,
+                Err(e) => return Err(e),
+            };
+            vectors.push(vector);
+        }
+    }
+
+    let next_cursor = match last_id {
+        Some(last_id) if has_more => Some(ListCursor(last_id).encode()),
+        _ => None,
+    };
+
+    Ok(ListVectorsResponseDto {
+        vectors,
+        next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::api::vectordb::{
+        search,
+        test_utils::{
+            begin_transaction, delete, in_transaction, test_context, upsert, TestCollection,
+        },
+        transactions,
+    };
+
+    use crate::metadata::FieldValue;
+    use crate::models::collection::NamedDenseVectorOptions;
+
+    use super::*;
+
+    #[actix_web::test]
+    async fn test_check_vector_existence() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            tf_idf: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "dense_values": [0.1, 0.2, 0.3, 0.4] },
+                { "id": "doc", "text": "the quick brown fox" },
+                { "id": 3, "dense_values": [0.4, 0.3, 0.2, 0.1], "text": "lazy dog" },
+            ]),
+        )
+        .await;
+        delete(&ctx, &collection_id, ExternalId::Int(3)).await;
+
+        let exists = |id: ExternalId| {
+            let ctx = ctx.clone();
+            let collection_id = collection_id.clone();
+            async move {
+                check_vector_existence(ctx, &collection_id, &id)
+                    .await
+                    .unwrap()
+            }
+        };
+        assert!(exists(ExternalId::Int(1)).await);
+        assert!(exists(ExternalId::Str("doc".to_string())).await);
+        // Deleted
+        assert!(!exists(ExternalId::Int(3)).await);
+        // Missing
+        assert!(!exists(ExternalId::Int(4)).await);
+        assert!(!exists(ExternalId::Str("missing".to_string())).await);
+    }
+
+    #[actix_web::test]
+    async fn test_check_vector_existence_after_replace() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            tf_idf: true,
+            store_raw_text: false,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(&ctx, &collection_id, json!([{ "id": 1, "text": "first" }])).await;
+        upsert(&ctx, &collection_id, json!([{ "id": 1, "text": "second" }])).await;
+
+        assert!(
+            check_vector_existence(ctx.clone(), &collection_id, &ExternalId::Int(1))
+                .await
+                .unwrap()
+        );
+
+        delete(&ctx, &collection_id, ExternalId::Int(1)).await;
+        assert!(
+            !check_vector_existence(ctx, &collection_id, &ExternalId::Int(1))
+                .await
+                .unwrap()
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_search_upserted_vector() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        let values = |round: usize| {
+            let x = round as f32 / 10.0;
+            vec![1.0 - x, x, x * x, 0.5]
+        };
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "dense_values": values(0) },
+                { "id": 2, "dense_values": [0.0, 0.5, 0.5, 1.0] },
+            ]),
+        )
+        .await;
+
+        // The nodes of the earlier values of the vector, which are still
+        // traversed, don't hide its latest node
+        for round in 1..=8 {
+            upsert(
+                &ctx,
+                &collection_id,
+                json!([{ "id": 1, "dense_values": values(round) }]),
+            )
+            .await;
+            let request = serde_json::from_value(json!({
+                "query_vector": values(round),
+                "top_k": 1,
+            }))
+            .unwrap();
+            let results = search::repo::dense_search(ctx.clone(), &collection_id, request, None)
+                .await
+                .unwrap();
+            assert_eq!(1, results.len());
+            assert_eq!(1, results[0].0 .0);
+            assert!((results[0].1.get_value() - 1.0).abs() < 1e-3);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_upsert_same_id_in_transaction() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            sparse: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "dense_values": [0.1, 0.2, 0.3, 0.4], "sparse_indices": [1], "sparse_values": [0.5] },
+                { "id": 2, "dense_values": [0.4, 0.3, 0.2, 0.1], "sparse_indices": [2], "sparse_values": [0.5] },
+                { "id": 1, "dense_values": [0.5, 0.5, 0.5, 0.5], "sparse_indices": [1], "sparse_values": [0.7] },
+            ]),
+        )
+        .await;
+
+        // Only the last of the vectors with the same id is upserted
+        let vector = get_vector_by_id(ctx.clone(), &collection_id, ExternalId::Int(1), None)
+            .await
+            .unwrap();
+        assert_eq!(Some(vec![0.5, 0.5, 0.5, 0.5]), vector.dense_values);
+        assert_eq!(
+            Some(vec![crate::indexes::inverted::types::SparsePair(1, 0.7)]),
+            vector.sparse_values
+        );
+        let request = serde_json::from_value(json!({
+            "query_vector": [0.5, 0.5, 0.5, 0.5],
+            "top_k": 10,
+        }))
+        .unwrap();
+        let results = search::repo::dense_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap();
+        let ids: Vec<_> = results.iter().map(|(id, _)| id.0).collect();
+        assert_eq!(vec![1, 2], ids);
+        let request = serde_json::from_value(json!({
+            "query_terms": [[1, 1.0]],
+            "top_k": 10,
+        }))
+        .unwrap();
+        let results = search::repo::sparse_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap();
+        assert_eq!(1, results.len());
+        assert_eq!(1, results[0].0 .0);
+
+        // Upserting it again in the same transaction is rejected
+        let result = in_transaction(&ctx, &collection_id, |transaction_id| {
+            let ctx = ctx.clone();
+            let collection_id = collection_id.clone();
+            async move {
+                for values in [[0.1, 0.1, 0.1, 0.1], [0.2, 0.2, 0.2, 0.2]] {
+                    let vectors =
+                        serde_json::from_value(json!([{ "id": 1, "dense_values": values }]))
+                            .unwrap();
+                    transactions::service::upsert_vectors(
+                        ctx.clone(),
+                        &collection_id,
+                        transaction_id,
+                        vectors,
+                    )
+                    .await?;
+                }
+                Ok(())
+            }
+        })
+        .await;
+        assert!(result.is_err());
+        let vector = get_vector_by_id(ctx.clone(), &collection_id, ExternalId::Int(1), None)
+            .await
+            .unwrap();
+        assert_eq!(Some(vec![0.5, 0.5, 0.5, 0.5]), vector.dense_values);
+    }
+
+    #[actix_web::test]
+    async fn test_string_ids() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            sparse: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": "a", "dense_values": [0.1, 0.2, 0.3, 0.4], "sparse_indices": [1], "sparse_values": [0.5] },
+                { "id": "b", "dense_values": [0.4, 0.3, 0.2, 0.1], "sparse_indices": [2], "sparse_values": [0.5] },
+            ]),
+        )
+        .await;
+
+        // The ids assigned to string ids survive the dense and sparse
+        // indexes
+        let collection = ctx
+            .ain_env
+            .collections_map
+            .get_collection(&collection_id)
+            .unwrap();
+        let request = serde_json::from_value(json!({
+            "query_vector": [0.1, 0.2, 0.3, 0.4],
+            "top_k": 10,
+        }))
+        .unwrap();
+        let ids: Vec<_> = search::repo::dense_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|(id, _)| collection.get_external_id(&id).unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                ExternalId::Str("a".to_string()),
+                ExternalId::Str("b".to_string())
+            ],
+            ids
+        );
+        for (term, id) in [(1, "a"), (2, "b")] {
+            let request = serde_json::from_value(json!({
+                "query_terms": [[term, 1.0]],
+                "top_k": 10,
+            }))
+            .unwrap();
+            let results = search::repo::sparse_search(ctx.clone(), &collection_id, request, None)
+                .await
+                .unwrap();
+            assert_eq!(1, results.len());
+            assert_eq!(
+                ExternalId::Str(id.to_string()),
+                collection.get_external_id(&results[0].0).unwrap()
+            );
+        }
+
+        // String ids are assigned counting down from the top of the
+        // u32 range, below the ids of the root node and the query, and
+        // skipping the ids already used by numeric ids
+        let top = u32::MAX as u64 - 2;
+        assert_eq!(
+            Some(VectorId(top)),
+            collection
+                .get_internal_id(&ExternalId::Str("a".to_string()))
+                .unwrap()
+        );
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": top - 2, "sparse_indices": [3], "sparse_values": [0.5] },
+                { "id": 1u64 << 31, "sparse_indices": [3], "sparse_values": [0.5] },
+            ]),
+        )
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([{ "id": "c", "sparse_indices": [3], "sparse_values": [0.5] }]),
+        )
+        .await;
+        assert_eq!(
+            Some(VectorId(top - 3)),
+            collection
+                .get_internal_id(&ExternalId::Str("c".to_string()))
+                .unwrap()
+        );
+        for id in [top - 2, 1 << 31] {
+            let vector = get_vector_by_id(ctx.clone(), &collection_id, ExternalId::Int(id), None)
+                .await
+                .unwrap();
+            assert_eq!(ExternalId::Int(id), vector.id);
+        }
+
+        // Numeric ids assigned to string ids or beyond the internal ids
+        // are rejected
+        for id in [top, INTERNAL_IDS_END] {
+            let vectors = serde_json::from_value(json!([
+                { "id": id, "sparse_indices": [1], "sparse_values": [0.5] },
+            ]))
+            .unwrap();
+            let result = in_transaction(&ctx, &collection_id, |transaction_id| {
+                transactions::service::upsert_vectors(
+                    ctx.clone(),
+                    &collection_id,
+                    transaction_id,
+                    vectors,
+                )
+            })
+            .await;
+            assert!(result.is_err());
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_list_vectors() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            tf_idf: true,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": "doc", "text": "the quick brown fox" },
+                { "id": 5, "dense_values": [0.1, 0.2, 0.3, 0.4] },
+                { "id": 1, "dense_values": [0.4, 0.3, 0.2, 0.1] },
+                { "id": 3, "text": "lazy dog" },
+                { "id": 2, "dense_values": [0.2, 0.2, 0.2, 0.2], "text": "jumps over" },
+            ]),
+        )
+        .await;
+        delete(&ctx, &collection_id, ExternalId::Int(3)).await;
+        // Not listed until committed
+        let transaction_id = begin_transaction(&ctx, &collection_id).await;
+        let vectors =
+            serde_json::from_value(json!([{ "id": 4, "dense_values": [0.3, 0.3, 0.3, 0.3] }]))
+                .unwrap();
+        transactions::service::upsert_vectors(ctx.clone(), &collection_id, transaction_id, vectors)
+            .await
+            .unwrap();
+
+        let mut pages = Vec::new();
+        let mut cursor = None;
+        loop {
+            let params = ListVectorsParams {
+                cursor: cursor.as_deref().and_then(ListCursor::decode),
+                limit: 2,
+                filter: None,
+                max_scanned: usize::MAX,
+            };
+            let page = list_vectors(ctx.clone(), &collection_id, params)
+                .await
+                .unwrap();
+            pages.push(
+                page.vectors
+                    .into_iter()
+                    .map(|vector| vector.id.to_string())
+                    .collect::<Vec<_>>(),
+            );
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(vec![vec!["1", "2"], vec!["5", "doc"]], pages);
+
+        transactions::service::abort_transaction(ctx.clone(), &collection_id, transaction_id)
+            .await
+            .unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_list_vectors_with_filter() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            metadata_schema: Some(json!({
+                "fields": [{ "name": "tag", "values": ["a", "b"] }],
+                "supported_conditions": [],
+            })),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        let vectors: Vec<_> = ["a", "b", "a", "a", "b", "b"]
+            .into_iter()
+            .enumerate()
+            .map(|(i, tag)| {
+                json!({
+                    "id": i + 1,
+                    "dense_values": [0.1, 0.2, 0.3, 0.4],
+                    "metadata": { "tag": tag },
+                })
+            })
+            .collect();
+        upsert(&ctx, &collection_id, json!(vectors)).await;
+        delete(&ctx, &collection_id, ExternalId::Int(5)).await;
+
+        let list_pages = |max_scanned: usize| {
+            let (ctx, collection_id) = (ctx.clone(), collection_id.clone());
+            async move {
+                let mut pages = Vec::new();
+                let mut cursor = None;
+                loop {
+                    let params = ListVectorsParams {
+                        cursor: cursor.as_deref().and_then(ListCursor::decode),
+                        limit: 2,
+                        filter: Some(
+                            serde_json::from_value(json!({
+                                "Is": { "field_name": "tag", "field_value": "b", "operator": "Equal" },
+                            }))
+                            .unwrap(),
+                        ),
+                        max_scanned,
+                    };
+                    let page = list_vectors(ctx.clone(), &collection_id, params)
+                        .await
+                        .unwrap();
+                    pages.push(
+                        page.vectors
+                            .into_iter()
+                            .map(|vector| vector.id.to_string())
+                            .collect::<Vec<_>>(),
+                    );
+                    cursor = page.next_cursor;
+                    if cursor.is_none() {
+                        break pages;
+                    }
+                }
+            }
+        };
+
+        let expected: Vec<Vec<&str>> = vec![vec!["2", "6"]];
+        assert_eq!(expected, list_pages(usize::MAX).await);
+        // Pages are cut short once the ids scanned reach the cap, even
+        // without any matching vector
+        let expected: Vec<Vec<&str>> = vec![vec!["2"], vec![], vec!["6"]];
+        assert_eq!(expected, list_pages(2).await);
+    }
+
+    async fn update_metadata(
+        ctx: &Arc<AppContext>,
+        collection_id: &str,
+        updates: serde_json::Value,
+    ) {
+        let updates = serde_json::from_value(updates).unwrap();
+        in_transaction(ctx, collection_id, |transaction_id| {
+            transactions::service::update_vectors_metadata(
+                ctx.clone(),
+                collection_id,
+                transaction_id,
+                updates,
+            )
+        })
+        .await
+        .unwrap();
+    }
+
+    #[actix_web::test]
+    async fn test_update_metadata() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            sparse: true,
+            tf_idf: true,
+            store_raw_text: false,
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([{
+                "id": 1,
+                "dense_values": [0.1, 0.2, 0.3, 0.4],
+                "sparse_indices": [1],
+                "sparse_values": [0.5],
+                "text": "quick fox",
+                "metadata": { "tag": "a", "lang": "en" },
+            }]),
+        )
+        .await;
+
+        update_metadata(
+            &ctx,
+            &collection_id,
+            json!([{ "id": 1, "metadata": { "tag": "b" } }]),
+        )
+        .await;
+
+        let vector = get_vector_by_id(ctx.clone(), &collection_id, ExternalId::Int(1), None)
+            .await
+            .unwrap();
+        assert_eq!(Some(vec![0.1, 0.2, 0.3, 0.4]), vector.dense_values);
+        assert!(vector.sparse_values.is_some());
+        let metadata = vector.metadata.unwrap();
+        assert_eq!(
+            Some(&FieldValue::String("b".to_string())),
+            metadata.get("tag")
+        );
+        assert_eq!(
+            Some(&FieldValue::String("en".to_string())),
+            metadata.get("lang")
+        );
+        // Without a metadata schema, the vector isn't re-indexed
+        let collection = ctx
+            .ain_env
+            .collections_map
+            .get_collection(&collection_id)
+            .unwrap();
+        assert_eq!(None, collection.tombstones.get(&VectorId(1)));
+
+        let request = serde_json::from_value(json!({
+            "query": "quick fox",
+            "top_k": 10,
+            "filter": { "Is": { "field_name": "tag", "field_value": "b", "operator": "Equal" } },
+        }))
+        .unwrap();
+        let results = search::repo::tf_idf_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![VectorId(1)],
+            results.into_iter().map(|(id, _)| id).collect::<Vec<_>>()
+        );
+        let request = serde_json::from_value(json!({
+            "query_vector": [0.1, 0.2, 0.3, 0.4],
+            "top_k": 10,
+            "filter": { "Is": { "field_name": "tag", "field_value": "b", "operator": "Equal" } },
+        }))
+        .unwrap();
+        let results = search::repo::dense_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            vec![VectorId(1)],
+            results.into_iter().map(|(id, _)| id).collect::<Vec<_>>()
+        );
+
+        // Missing vectors can't be updated
+        let updates =
+            serde_json::from_value(json!([{ "id": 2, "metadata": { "tag": "b" } }])).unwrap();
+        let result = in_transaction(&ctx, &collection_id, |transaction_id| {
+            transactions::service::update_vectors_metadata(
+                ctx.clone(),
+                &collection_id,
+                transaction_id,
+                updates,
+            )
+        })
+        .await;
+        assert!(result.is_err());
+    }
+
+    #[actix_web::test]
+    async fn test_update_metadata_of_schema_fields() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            sparse: true,
+            tf_idf: true,
+            store_raw_text: false,
+            metadata_schema: Some(json!({
+                "fields": [{ "name": "tag", "values": ["a", "b"] }],
+                "supported_conditions": [],
+            })),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        let dense_values = |id: u64| {
+            let x = id as f32 / 10.0;
+            vec![x, 1.0 - x, x * x, 0.5]
+        };
+        let vectors: Vec<_> = (1..=10)
+            .map(|id| {
+                json!({
+                    "id": id,
+                    "dense_values": dense_values(id),
+                    "sparse_indices": [1, id],
+                    "sparse_values": [0.5, 0.5],
+                    "text": "quick fox",
+                    "metadata": { "tag": "a" },
+                })
+            })
+            .collect();
+        upsert(&ctx, &collection_id, json!(vectors)).await;
+        let collection = ctx
+            .ain_env
+            .collections_map
+            .get_collection(&collection_id)
+            .unwrap();
+        let hnsw_index = collection.get_hnsw_index().unwrap();
+        let dense_version = |id| {
+            get_dense_embedding_version(&collection, &hnsw_index, &VectorId(id))
+                .unwrap()
+                .unwrap()
+        };
+        let versions = [dense_version(1), dense_version(2)];
+
+        update_metadata(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "metadata": { "tag": "b" } },
+                { "id": 2, "metadata": { "lang": "en" } },
+            ]),
+        )
+        .await;
+
+        // Only the vector whose schema fields have changed is re-indexed
+        // in the dense index, with its updated metadata
+        assert_ne!(versions[0], dense_version(1));
+        assert_eq!(versions[1], dense_version(2));
+        let embedding = get_dense_embedding_by_id(&collection, &hnsw_index, &VectorId(1)).unwrap();
+        assert_eq!(
+            Some(&FieldValue::String("b".to_string())),
+            embedding
+                .raw_metadata
+                .as_ref()
+                .and_then(|fields| fields.get("tag"))
+        );
+
+        // The rest of the vector's data is still live
+        let vector = get_vector_by_id(ctx.clone(), &collection_id, ExternalId::Int(1), None)
+            .await
+            .unwrap();
+        assert_eq!(Some(dense_values(1)), vector.dense_values);
+        assert!(vector.sparse_values.is_some());
+        // Every vector is found once, none of them twice
+        let sorted_ids = |results: Vec<(VectorId, f32)>| {
+            let mut ids: Vec<_> = results.into_iter().map(|(id, _)| id.0).collect();
+            ids.sort_unstable();
+            ids
+        };
+        let request = serde_json::from_value(json!({ "query": "quick fox", "top_k": 20 })).unwrap();
+        let results = search::repo::tf_idf_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap();
+        assert_eq!((1..=10).collect::<Vec<_>>(), sorted_ids(results));
+        let request =
+            serde_json::from_value(json!({ "query_terms": [[1, 1.0]], "top_k": 20 })).unwrap();
+        let results = search::repo::sparse_search(ctx.clone(), &collection_id, request, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            (1..=10).collect::<Vec<_>>(),
+            sorted_ids(
+                results
+                    .into_iter()
+                    .map(|(id, score)| (id, score.get_value()))
+                    .collect()
+            )
+        );
+    }
+
+    #[actix_web::test]
+    async fn test_fetch_vector_neighbors() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            dense_dimension: Some(4),
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        let vectors: Vec<_> = (1..=20)
+            .map(|id| {
+                let x = id as f32 / 20.0;
+                json!({ "id": id, "dense_values": [x, 1.0 - x, x * x, 0.5] })
+            })
+            .collect();
+        upsert(&ctx, &collection_id, json!(vectors)).await;
+        delete(&ctx, &collection_id, ExternalId::Int(20)).await;
+
+        for id in 1..20 {
+            let levels = fetch_vector_neighbors(ctx.clone(), &collection_id, ExternalId::Int(id))
+                .await
+                .unwrap();
+            // Levels are listed from the bottom up
+            let level_numbers: Vec<_> = levels.iter().map(|level| level.level).collect();
+            assert_eq!((0..levels.len() as u8).collect::<Vec<_>>(), level_numbers);
+            let level_0 = &levels[0];
+            assert!(!level_0.neighbors.is_empty());
+            // The node found is the vector's own
+            assert!(level_0
+                .neighbors
+                .iter()
+                .all(|neighbor| neighbor.id != ExternalId::Int(id)));
+            for level in &levels {
+                assert!(level
+                    .neighbors
+                    .windows(2)
+                    .all(|pair| pair[0].score >= pair[1].score));
+            }
+        }
+        assert!(matches!(
+            fetch_vector_neighbors(ctx.clone(), &collection_id, ExternalId::Int(20)).await,
+            Err(VectorsError::NotFound)
+        ));
+    }
+
+    #[actix_web::test]
+    async fn test_upsert_multi_vector_documents() {
+        let ctx = test_context();
+        let collection_id = TestCollection {
+            named_vectors: vec![NamedDenseVectorOptions {
+                name: "tokens".to_string(),
+                dimension: 2,
+                multi_vector: true,
+            }],
+            ..Default::default()
+        }
+        .create(&ctx)
+        .await;
+        upsert(
+            &ctx,
+            &collection_id,
+            json!([
+                { "id": 1, "multi_dense_values": { "tokens": [[1.0, 0.0], [0.0, 1.0]] } },
+                { "id": 2, "multi_dense_values": { "tokens": [[0.6, 0.8]] } },
+            ]),
+        )
+        .await;
+
+        let collection = ctx
+            .ain_env
+            .collections_map
+            .get_collection(&collection_id)
+            .unwrap();
+        let hnsw_index = collection.get_named_hnsw_index("tokens").unwrap();
+        let results = crate::api_service::multi_vector_ann_query(
+            ctx.clone(),
+            &collection,
+            hnsw_index,
+            "tokens",
+            vec![vec![1.0, 0.0], vec![0.0, 1.0]],
+            10,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let ids: Vec<_> = results.iter().map(|(id, _)| id.clone()).collect();
+        assert_eq!(
+            vec![
+                get_internal_id(&collection, &ExternalId::Int(1)).unwrap(),
+                get_internal_id(&collection, &ExternalId::Int(2)).unwrap(),
+            ],
+            ids
+        );
+        assert!((results[0].1 - 2.0).abs() < 1e-3);
+        assert!((results[1].1 - 1.4).abs() < 1e-3);
+    }
 }
@@ -1,9 +1,12 @@
 use std::sync::Arc;
 
-use crate::{app_context::AppContext, models::types::VectorId};
+use crate::{
+    app_context::AppContext,
+    models::{external_ids::ExternalId, versioning::VersionRef},
+};
 
 use super::{
-    dtos::{CreateVectorDto, SimilarVector},
+    dtos::{CreateVectorDto, LevelNeighborsDto, ListVectorsParams, ListVectorsResponseDto},
     error::VectorsError,
     repo,
 };
@@ -11,15 +14,16 @@ use super::{
 pub(crate) async fn get_vector_by_id(
     ctx: Arc<AppContext>,
     collection_id: &str,
-    vector_id: VectorId,
+    vector_id: ExternalId,
+    version: Option<VersionRef>,
 ) -> Result<CreateVectorDto, VectorsError> {
-    repo::get_vector_by_id(ctx, collection_id, vector_id).await
+    repo::get_vector_by_id(ctx, collection_id, vector_id, version).await
 }
 
 pub(crate) async fn check_vector_existence(
     ctx: Arc<AppContext>,
     collection_id: &str,
-    vector_id: u64,
+    vector_id: &ExternalId,
 ) -> Result<bool, VectorsError> {
     repo::check_vector_existence(ctx, collection_id, vector_id).await
 }
@@ -27,7 +31,15 @@ pub(crate) async fn check_vector_existence(
 pub(crate) async fn fetch_vector_neighbors(
     ctx: Arc<AppContext>,
     collection_id: &str,
-    vector_id: VectorId,
-) -> Result<Vec<SimilarVector>, VectorsError> {
+    vector_id: ExternalId,
+) -> Result<Vec<LevelNeighborsDto>, VectorsError> {
     repo::fetch_vector_neighbors(ctx, collection_id, vector_id).await
 }
+
+pub(crate) async fn list_vectors(
+    ctx: Arc<AppContext>,
+    collection_id: &str,
+    params: ListVectorsParams,
+) -> Result<ListVectorsResponseDto, VectorsError> {
+    repo::list_vectors(ctx, collection_id, params).await
+}
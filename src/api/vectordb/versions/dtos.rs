@@ -1,4 +1,4 @@
-use crate::models::versioning::Hash;
+use crate::models::{vector_counts::VectorCounts, versioning::Hash};
 use serde::Serialize;
 
 #[derive(Serialize)]
@@ -7,6 +7,9 @@ pub struct VersionMetadata {
     pub version_number: u16,
     pub timestamp: u32,
     pub vector_count: u64,
+    /// Counts per index type, absent for versions committed before
+    /// the counts were being tracked
+    pub vector_counts: Option<VectorCounts>,
 }
 
 #[derive(Serialize)]
@@ -21,4 +24,5 @@ pub struct CurrentVersionResponse {
     pub version_number: u16,
     pub timestamp: u64,
     pub vector_count: u64,
+    pub vector_counts: Option<VectorCounts>,
 }
@@ -7,7 +7,7 @@ use crate::{
     app_context::AppContext,
     models::{
         common::WaCustomError, meta_persist::retrieve_current_version, types::MetaDb,
-        versioning::VersionControl,
+        vector_counts::VectorCounts, versioning::VersionControl,
     },
 };
 
@@ -26,13 +26,18 @@ pub(crate) async fn list_versions(
         retrieve_current_version(&lmdb).map_err(|e| VersionError::DatabaseError(e.to_string()))?;
     let mut versions = versions
         .into_iter()
-        .map(|(hash, version_hash)| VersionMetadata {
-            hash,
-            version_number: *version_hash.version,
-            timestamp: version_hash.timestamp.0,
-            vector_count: 0,
+        .map(|(hash, version_hash)| {
+            let vector_counts = VectorCounts::load(&lmdb, hash)
+                .map_err(|e| VersionError::DatabaseError(e.to_string()))?;
+            Ok(VersionMetadata {
+                hash,
+                version_number: *version_hash.version,
+                timestamp: version_hash.timestamp.0,
+                vector_count: vector_counts.map_or(0, |counts| counts.total),
+                vector_counts,
+            })
         })
-        .collect::<Vec<VersionMetadata>>();
+        .collect::<Result<Vec<VersionMetadata>, VersionError>>()?;
     versions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
     Ok(VersionListResponse {
         versions,
@@ -53,17 +58,19 @@ pub(crate) async fn get_current_version(
         .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
     let current_hash =
         retrieve_current_version(&lmdb).map_err(|e| VersionError::DatabaseError(e.to_string()))?;
-    let current_version = versions
+    let (hash, version_hash) = versions
         .into_iter()
         .find(|(hash, _)| *hash == current_hash)
-        .map(|(hash, version_hash)| CurrentVersionResponse {
-            hash,
-            version_number: *version_hash.version,
-            timestamp: version_hash.timestamp.0 as u64,
-            vector_count: 0,
-        })
         .ok_or(VersionError::InvalidVersionHash)?;
-    Ok(current_version)
+    let vector_counts =
+        VectorCounts::load(&lmdb, hash).map_err(|e| VersionError::DatabaseError(e.to_string()))?;
+    Ok(CurrentVersionResponse {
+        hash,
+        version_number: *version_hash.version,
+        timestamp: version_hash.timestamp.0 as u64,
+        vector_count: vector_counts.map_or(0, |counts| counts.total),
+        vector_counts,
+    })
 }
 
 #[allow(unused)]
@@ -1,9 +1,12 @@
 use crate::app_context::AppContext;
-use crate::indexes::hnsw::types::{HNSWHyperParams, QuantizedDenseVectorEmbedding};
-use crate::indexes::hnsw::{DenseInputEmbedding, HNSWIndex};
+use crate::config_loader::Config;
+use crate::indexes::hnsw::types::{
+    DenseSearchOptions, HNSWHyperParams, QuantizedDenseVectorEmbedding,
+};
+use crate::indexes::hnsw::{dense_index_path, DenseInputEmbedding, HNSWIndex};
 use crate::indexes::inverted::InvertedIndex;
 use crate::indexes::tf_idf::TFIDFIndex;
-use crate::indexes::IndexOps;
+use crate::indexes::{IndexOps, GROUP_SEARCH_MAX_CANDIDATES, RADIUS_SEARCH_MAX_RESULTS};
 use crate::metadata::query_filtering::filter_encoded_dimensions;
 use crate::metadata::{self, pseudo_level_probs};
 use crate::models::buffered_io::BufferManagerFactory;
@@ -11,22 +14,30 @@ use crate::models::cache_loader::HNSWIndexCache;
 use crate::models::collection::Collection;
 use crate::models::collection_transaction::CollectionTransaction;
 use crate::models::common::*;
+use crate::models::grouping::{are_groups_filled, group_results};
 use crate::models::meta_persist::{store_values_range, update_current_version};
+use crate::models::multi_vectors::max_sim_score;
 use crate::models::prob_node::ProbNode;
+use crate::models::recommend::best_score;
 use crate::models::types::*;
 use crate::models::versioning::Hash;
 use crate::quantization::{Quantization, StorageType};
 use crate::vector_store::*;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rayon::iter::{IntoParallelIterator, IntoParallelRefIterator, ParallelIterator};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::Path;
 use std::sync::{Arc, RwLock};
 
 /// creates a dense index for a collection
+///
+/// `vector_name` selects one of the named dense vector spaces of the
+/// collection, otherwise the default dense index is created.
 #[allow(clippy::too_many_arguments)]
 pub async fn init_hnsw_index_for_collection(
     ctx: Arc<AppContext>,
     collection: Arc<Collection>,
+    vector_name: Option<String>,
     values_range: Option<(f32, f32)>,
     hnsw_params: HNSWHyperParams,
     quantization_metric: QuantizationMetric,
@@ -36,8 +47,18 @@ pub async fn init_hnsw_index_for_collection(
     is_configured: bool,
 ) -> Result<Arc<HNSWIndex>, WaCustomError> {
     let collection_name = &collection.meta.name;
+    let dimension = collection
+        .meta
+        .dense_vector
+        .get_dimension(vector_name.as_deref())
+        .ok_or_else(|| {
+            WaCustomError::NotFound(format!(
+                "dense vector '{}' not found in collection",
+                vector_name.as_deref().unwrap_or_default()
+            ))
+        })?;
     let collection_path: Arc<Path> = collection.get_path();
-    let index_path = collection_path.join("dense_hnsw");
+    let index_path = dense_index_path(&collection_path, vector_name.as_deref());
     // ensuring that the index has a separate directory created inside the collection directory
     fs::create_dir_all(&index_path).map_err(|e| WaCustomError::FsError(e.to_string()))?;
 
@@ -86,7 +107,7 @@ pub async fn init_hnsw_index_for_collection(
         distance_metric.clone(),
     );
     if let Some(values_range) = values_range {
-        store_values_range(&lmdb, values_range).map_err(|e| {
+        store_values_range(&lmdb, vector_name.as_deref(), values_range).map_err(|e| {
             WaCustomError::DatabaseError(format!("Failed to store values range to LMDB: {}", e))
         })?;
     }
@@ -95,7 +116,7 @@ pub async fn init_hnsw_index_for_collection(
     let root = create_root_node(
         &quantization_metric,
         storage_type,
-        collection.meta.dense_vector.dimension,
+        dimension,
         &cache.prop_file,
         *collection.current_version.read().unwrap(),
         &index_manager,
@@ -139,7 +160,7 @@ pub async fn init_hnsw_index_for_collection(
     let hnsw_index = Arc::new(HNSWIndex::new(
         root,
         lp,
-        collection.meta.dense_vector.dimension,
+        dimension,
         quantization_metric,
         distance_metric,
         storage_type,
@@ -149,6 +170,7 @@ pub async fn init_hnsw_index_for_collection(
         values_range,
         sample_threshold,
         is_configured,
+        vector_name,
     ));
 
     ctx.ain_env
@@ -159,8 +181,7 @@ pub async fn init_hnsw_index_for_collection(
     // nodes to ensure that the query vectors with metadata dimensions
     // are reachable from the root node.
     if collection.meta.metadata_schema.is_some() {
-        let num_dims = collection.meta.dense_vector.dimension;
-        let pseudo_vals: Vec<f32> = vec![1.0; num_dims];
+        let pseudo_vals: Vec<f32> = vec![1.0; dimension];
         // The pseudo vector's id will be equal to the max number that
         // can be represented with 56 bits. This is because of how we
         // are calculating the combined id for nodes having metadata
@@ -171,7 +192,8 @@ pub async fn init_hnsw_index_for_collection(
         let transaction = CollectionTransaction::new(collection.clone())?;
         hnsw_index.run_upload(&collection, vec![pseudo_vec], &transaction, &ctx.config)?;
         let (id, version_number) = (transaction.id, transaction.version_number);
-        transaction.pre_commit(&collection, &ctx.config)?;
+        let base_version = *collection.current_version.read().unwrap();
+        transaction.pre_commit(&collection, base_version, &ctx.config)?;
         *collection.current_version.write().unwrap() = id;
         collection
             .vcs
@@ -251,12 +273,76 @@ pub async fn init_tf_idf_index_for_collection(
     Ok(index)
 }
 
+/// Compiles the metadata filter to the query filter dimensions used
+/// for searching the dense index
+///
+/// Filters whose shape can't be expressed using the metadata
+/// dimensions of the collection (e.g. ones expanding to too many
+/// conjunctions, see `filter_encoded_dimensions`) are returned as is,
+/// so that they get applied to the search results instead, and so are
+/// the filters of collections without a metadata schema. Filters on
+/// fields that aren't part of the schema are invalid.
+fn compile_metadata_filter(
+    collection: &Collection,
+    metadata_filter: Option<metadata::Filter>,
+) -> Result<
+    (
+        Option<Vec<metadata::QueryFilterDimensions>>,
+        Option<metadata::Filter>,
+    ),
+    WaCustomError,
+> {
+    let Some(filter) = metadata_filter else {
+        return Ok((None, None));
+    };
+    let Some(metadata_schema) = collection.meta.metadata_schema.as_ref() else {
+        return Ok((None, Some(filter)));
+    };
+    match filter_encoded_dimensions(metadata_schema, &filter) {
+        Ok(dims) => Ok((Some(dims), None)),
+        Err(metadata::Error::UnsupportedFilter(_)) => Ok((None, Some(filter))),
+        Err(e) => Err(WaCustomError::MetadataError(e)),
+    }
+}
+
+/// Returns the hyperparams of the index to be used for searching, with
+/// the overrides of the request applied
+///
+/// For radius searches, `ef_search` is raised to the max. no. of
+/// results, so that the traversal can find as many matches. The
+/// `ef_search` override is capped at the configured `max_ef_search`.
+fn search_params(
+    config: &Config,
+    hnsw_index: &HNSWIndex,
+    options: &DenseSearchOptions,
+    k: Option<usize>,
+) -> HNSWHyperParams {
+    let mut hnsw_params = hnsw_index.hnsw_params.read().unwrap().clone();
+    if let Some(ef_search) = options.ef_search {
+        hnsw_params.ef_search = ef_search.min(config.search.max_ef_search);
+    }
+    if options.score_threshold.is_some() && k.is_none() {
+        hnsw_params.ef_search = hnsw_params.ef_search.max(RADIUS_SEARCH_MAX_RESULTS as u32);
+    }
+    hnsw_params
+}
+
+/// Returns the max. no. of results to be returned, which is capped for
+/// radius searches i.e. ones with a score threshold but without `k`
+fn results_limit(options: &DenseSearchOptions, k: Option<usize>) -> Option<usize> {
+    match (k, options.score_threshold) {
+        (None, Some(_)) => Some(RADIUS_SEARCH_MAX_RESULTS),
+        _ => k,
+    }
+}
+
 pub async fn ann_vector_query(
     ctx: Arc<AppContext>,
     collection: &Collection,
     hnsw_index: Arc<HNSWIndex>,
     query: Vec<f32>,
     metadata_filter: Option<metadata::Filter>,
+    options: DenseSearchOptions,
     k: Option<usize>,
 ) -> Result<Vec<(VectorId, MetricResult)>, WaCustomError> {
     let vec_hash = VectorId(u64::MAX - 1);
@@ -271,39 +357,322 @@ pub async fn ann_vector_query(
         hash_vec: vec_hash.clone(),
     };
 
-    let hnsw_params_guard = hnsw_index.hnsw_params.read().unwrap();
+    let hnsw_params = search_params(&ctx.config, &hnsw_index, &options, k);
 
-    let query_filter_dims = metadata_filter.map(|filter| {
-        let metadata_schema = collection.meta.metadata_schema.as_ref().unwrap();
-        filter_encoded_dimensions(metadata_schema, &filter).unwrap()
-    });
+    let (query_filter_dims, post_filter) = compile_metadata_filter(collection, metadata_filter)?;
 
-    let results = ann_search(
+    let results = visible_ann_search(
         &ctx.config,
+        collection,
         hnsw_index.clone(),
         vec_emb,
         query_filter_dims.as_ref(),
-        hnsw_index.get_root_vec(),
-        HNSWLevel(hnsw_params_guard.num_layers),
-        &hnsw_params_guard,
+        &hnsw_params,
+        options.as_of.as_deref(),
+        k,
+    )?;
+    let output = finalize_ann_results(
+        collection,
+        &hnsw_index,
+        results,
+        &query,
+        post_filter.as_ref(),
+        &options,
+        results_limit(&options, k),
     )?;
-    drop(hnsw_params_guard);
-    let output = finalize_ann_results(collection, &hnsw_index, results, &query, k)?;
     Ok(output)
 }
 
+/// Searches the documents of a multi-vector space, scoring them by late
+/// interaction (MaxSim) with the query vectors
+///
+/// Candidates are the documents owning the `candidates_per_query`
+/// nearest vectors of each query vector in the HNSW index, leaving out
+/// the vectors that have been deleted or replaced (see
+/// `visible_ann_search`). They are then scored exactly using the raw
+/// embeddings of all their vectors.
+pub async fn multi_vector_ann_query(
+    ctx: Arc<AppContext>,
+    collection: &Collection,
+    hnsw_index: Arc<HNSWIndex>,
+    vector_name: &str,
+    queries: Vec<Vec<f32>>,
+    candidates_per_query: usize,
+    k: Option<usize>,
+) -> Result<Vec<(VectorId, f32)>, WaCustomError> {
+    let mut hnsw_params = search_params(
+        &ctx.config,
+        &hnsw_index,
+        &DenseSearchOptions::default(),
+        Some(candidates_per_query),
+    );
+    let max_ef_search = ctx.config.search.max_ef_search;
+    hnsw_params.ef_search = hnsw_params
+        .ef_search
+        .max(candidates_per_query.min(max_ef_search as usize) as u32);
+    let candidate_document_ids = queries
+        .par_iter()
+        .map(|query| {
+            let vector_list = hnsw_index.quantization_metric.read().unwrap().quantize(
+                query,
+                *hnsw_index.storage_type.read().unwrap(),
+                *hnsw_index.values_range.read().unwrap(),
+            )?;
+            let vec_emb = QuantizedDenseVectorEmbedding {
+                quantized_vec: Arc::new(vector_list),
+                hash_vec: VectorId(u64::MAX - 1),
+            };
+
+            let mut results = visible_ann_search(
+                &ctx.config,
+                collection,
+                hnsw_index.clone(),
+                vec_emb,
+                None,
+                &hnsw_params,
+                None,
+                Some(candidates_per_query),
+            )?;
+            results.sort_unstable_by(|(_, a), (_, b)| b.cmp(a));
+
+            // The nodes that aren't vectors of a document (i.e. the root
+            // and the pseudo nodes) have no mapping, and are skipped so
+            // that they don't take up any of the candidates
+            let mut vector_ids = HashSet::new();
+            let mut document_ids = Vec::new();
+            for (lazy_item, _) in results {
+                if vector_ids.len() >= candidates_per_query {
+                    break;
+                }
+                let node = unsafe { &*lazy_item }.try_get_data(&hnsw_index.cache)?;
+                let vector_id = &node.prop_value.id;
+                if vector_ids.contains(vector_id) {
+                    continue;
+                }
+                if let Some(document_id) = collection
+                    .multi_vectors
+                    .get_document_id(&collection.lmdb, vector_id)?
+                {
+                    vector_ids.insert(vector_id.clone());
+                    document_ids.push(document_id);
+                }
+            }
+            Ok(document_ids)
+        })
+        .collect::<Result<Vec<_>, WaCustomError>>()?;
+    let document_ids: HashSet<_> = candidate_document_ids.into_iter().flatten().collect();
+
+    let distance_metric = *hnsw_index.distance_metric.read().unwrap();
+    let mut results = Vec::with_capacity(document_ids.len());
+    for document_id in document_ids {
+        // Stale candidates i.e. documents that have been deleted or
+        // replaced since are skipped
+        let Some(embeddings) = get_multi_vector_document_embeddings(
+            collection,
+            &hnsw_index,
+            vector_name,
+            &document_id,
+            None,
+        )?
+        else {
+            continue;
+        };
+        let vectors: Vec<_> = embeddings
+            .into_iter()
+            .map(|embedding| embedding.raw_vec)
+            .collect();
+        if let Some(score) = max_sim_score(distance_metric, &queries, &vectors) {
+            results.push((document_id, score));
+        }
+    }
+    results.sort_unstable_by(|(_, a), (_, b)| b.total_cmp(a));
+    if let Some(k) = k {
+        results.truncate(k);
+    }
+    Ok(results)
+}
+
+/// Searches the dense index for the best `group_size` results for each
+/// of `groups` distinct values of the metadata field `group_by`, see
+/// `group_results`
+///
+/// The index is searched with an `ef_search` of at least `groups *
+/// group_size`. While the groups aren't filled, `ef_search` is doubled
+/// and the candidates found by the wider search are added to the ones
+/// found so far, i.e. only the new candidates are rescored, filtered
+/// and looked up for their group. This goes on until the wider search
+/// doesn't find any new candidates (irrespective of whether they are
+/// filtered out), `ef_search` reaches the configured `max_ef_search`
+/// or `GROUP_SEARCH_MAX_CANDIDATES` candidates have been considered.
+#[allow(clippy::too_many_arguments)]
+pub async fn grouped_ann_vector_query(
+    ctx: Arc<AppContext>,
+    collection: &Collection,
+    hnsw_index: Arc<HNSWIndex>,
+    query: Vec<f32>,
+    metadata_filter: Option<metadata::Filter>,
+    options: DenseSearchOptions,
+    group_by: &str,
+    groups: usize,
+    group_size: usize,
+) -> Result<Vec<(metadata::FieldValue, Vec<(VectorId, MetricResult)>)>, WaCustomError> {
+    let vec_hash = VectorId(u64::MAX - 1);
+    let vector_list = hnsw_index.quantization_metric.read().unwrap().quantize(
+        &query,
+        *hnsw_index.storage_type.read().unwrap(),
+        *hnsw_index.values_range.read().unwrap(),
+    )?;
+
+    let vec_emb = QuantizedDenseVectorEmbedding {
+        quantized_vec: Arc::new(vector_list.clone()),
+        hash_vec: vec_hash.clone(),
+    };
+
+    let (query_filter_dims, post_filter) = compile_metadata_filter(collection, metadata_filter)?;
+    let as_of = options.as_of.as_deref();
+    let max_ef_search = ctx.config.search.max_ef_search;
+    let num_hits = groups
+        .saturating_mul(group_size)
+        .min(GROUP_SEARCH_MAX_CANDIDATES);
+    let mut hnsw_params = search_params(&ctx.config, &hnsw_index, &options, None);
+    hnsw_params.ef_search = hnsw_params
+        .ef_search
+        .max((num_hits as u32).min(max_ef_search));
+
+    let mut considered = HashSet::new();
+    let mut candidates = Vec::new();
+    let mut group_keys = HashMap::new();
+    loop {
+        let results = visible_ann_search(
+            &ctx.config,
+            collection,
+            hnsw_index.clone(),
+            vec_emb.clone(),
+            query_filter_dims.as_ref(),
+            &hnsw_params,
+            as_of,
+            None,
+        )?;
+        let mut new_ids = HashSet::new();
+        let mut new_results = Vec::new();
+        for (node, score) in results {
+            let (orig_id, _) = unsafe { &*node }.try_get_data(&hnsw_index.cache)?.get_ids();
+            if !considered.contains(&orig_id) {
+                new_ids.insert(orig_id);
+                new_results.push((node, score));
+            }
+        }
+        let num_new = new_ids.len();
+        considered.extend(new_ids);
+
+        let scored = finalize_ann_results(
+            collection,
+            &hnsw_index,
+            new_results,
+            &query,
+            post_filter.as_ref(),
+            &options,
+            None,
+        )?;
+        for (id, _) in &scored {
+            let group_key = collection
+                .vector_metadata
+                .get_as_of(id, as_of)?
+                .and_then(|mut fields| fields.remove(group_by));
+            group_keys.insert(id.clone(), group_key);
+        }
+        candidates.extend(scored);
+        candidates.sort_unstable_by(|(_, a), (_, b)| b.cmp(a));
+
+        let grouped = group_results(
+            candidates.iter().cloned(),
+            |id| group_keys.get(id).cloned().flatten(),
+            groups,
+            group_size,
+        );
+        if num_new == 0
+            || hnsw_params.ef_search >= max_ef_search
+            || considered.len() >= GROUP_SEARCH_MAX_CANDIDATES
+            || are_groups_filled(&grouped, groups, group_size)
+        {
+            return Ok(grouped);
+        }
+        hnsw_params.ef_search = hnsw_params.ef_search.saturating_mul(2).min(max_ef_search);
+    }
+}
+
+/// Recommends the vectors most similar to the positive examples and
+/// least similar to the negative ones, using the "best score" strategy
+///
+/// Candidates are the `k` nearest vectors of each positive example
+/// (that match the metadata filter, if any). They are then scored
+/// against all the examples using their raw embeddings, see
+/// `best_score`, and returned along with the values of their scores.
+///
+/// The negative examples only take part in the scoring, not in the
+/// retrieval of the candidates. So if most of the neighbors of the
+/// positive examples are closer to a negative example, fewer than `k`
+/// of the results may be closer to a positive one, even if there are
+/// such vectors further away from the positive examples.
+///
+/// Candidates can't be scored for metrics that aren't defined on raw
+/// embeddings (i.e. hamming), and the no. of positive examples is
+/// limited to `BEST_SCORE_MAX_POSITIVES`, both of which are to be
+/// checked by the caller.
+pub async fn best_score_ann_query(
+    ctx: Arc<AppContext>,
+    collection: &Collection,
+    hnsw_index: Arc<HNSWIndex>,
+    positives: Vec<Vec<f32>>,
+    negatives: Vec<Vec<f32>>,
+    metadata_filter: Option<metadata::Filter>,
+    k: usize,
+) -> Result<Vec<(VectorId, f32)>, WaCustomError> {
+    let mut candidate_ids = HashSet::new();
+    for positive in &positives {
+        // Candidates are rescored below, hence not reranked here
+        let results = ann_vector_query(
+            ctx.clone(),
+            collection,
+            hnsw_index.clone(),
+            positive.clone(),
+            metadata_filter.clone(),
+            DenseSearchOptions {
+                skip_rerank: true,
+                ..Default::default()
+            },
+            Some(k),
+        )
+        .await?;
+        candidate_ids.extend(results.into_iter().map(|(id, _)| id));
+    }
+
+    let distance_metric = *hnsw_index.distance_metric.read().unwrap();
+    let mut results = Vec::with_capacity(candidate_ids.len());
+    for id in candidate_ids {
+        let raw = get_dense_embedding_by_id(collection, &hnsw_index, &id)?;
+        if let Some(score) = best_score(distance_metric, &positives, &negatives, &raw.raw_vec) {
+            results.push((id, score));
+        }
+    }
+    results.sort_unstable_by(|(_, a), (_, b)| b.total_cmp(a));
+    results.truncate(k);
+    Ok(results
+        .into_iter()
+        .map(|(id, score)| (id, score.value()))
+        .collect())
+}
+
 pub async fn batch_ann_vector_query(
     ctx: Arc<AppContext>,
     collection: &Collection,
     hnsw_index: Arc<HNSWIndex>,
     queries: Vec<Vec<f32>>,
     metadata_filter: Option<metadata::Filter>,
+    options: DenseSearchOptions,
     k: Option<usize>,
 ) -> Result<Vec<Vec<(VectorId, MetricResult)>>, WaCustomError> {
-    let query_filter_dims = metadata_filter.map(|filter| {
-        let metadata_schema = collection.meta.metadata_schema.as_ref().unwrap();
-        filter_encoded_dimensions(metadata_schema, &filter).unwrap()
-    });
+    let (query_filter_dims, post_filter) = compile_metadata_filter(collection, metadata_filter)?;
 
     queries
         .into_par_iter()
@@ -320,17 +689,26 @@ pub async fn batch_ann_vector_query(
                 hash_vec: vec_hash.clone(),
             };
 
-            let hnsw_params = hnsw_index.hnsw_params.read().unwrap();
-            let results = ann_search(
+            let hnsw_params = search_params(&ctx.config, &hnsw_index, &options, k);
+            let results = visible_ann_search(
                 &ctx.config,
+                collection,
                 hnsw_index.clone(),
                 vec_emb,
                 query_filter_dims.as_ref(),
-                hnsw_index.get_root_vec(),
-                HNSWLevel(hnsw_params.num_layers),
                 &hnsw_params,
+                options.as_of.as_deref(),
+                k,
+            )?;
+            let output = finalize_ann_results(
+                collection,
+                &hnsw_index,
+                results,
+                &query,
+                post_filter.as_ref(),
+                &options,
+                results_limit(&options, k),
             )?;
-            let output = finalize_ann_results(collection, &hnsw_index, results, &query, k)?;
             Ok::<_, WaCustomError>(output)
         })
         .collect()
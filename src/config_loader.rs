@@ -180,6 +180,15 @@ pub struct Indexing {
 pub struct Search {
     pub shortlist_size: usize,
     pub early_terminate_threshold: f32,
+    // Max. `ef_search` of dense searches, which caps the `ef_search`
+    // overrides of the requests as well as the widening of searches
+    // whose results aren't visible
+    #[serde(default = "default_max_ef_search")]
+    pub max_ef_search: u32,
+}
+
+fn default_max_ef_search() -> u32 {
+    4096
 }
 
 pub fn load_config() -> Result<Config, WaCustomError> {
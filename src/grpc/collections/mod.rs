@@ -36,6 +36,7 @@ impl CollectionsService for CollectionsServiceImpl {
         let dense_vector = DenseVectorOptions {
             dimension: req.dense_vector.as_ref().map_or(0, |d| d.dimension as usize),
             enabled: req.dense_vector.as_ref().is_some_and(|d| d.enabled),
+            named_vectors: Vec::new(),
         };
 
         let sparse_vector = SparseVectorOptions {
@@ -68,6 +68,7 @@ impl VectorsService for VectorsServiceImpl {
                     // @TODO: Support for metadata filtering to be
                     // added for grpc endpoints
                     None,
+                    Default::default(),
                     dense.top_k.map(|top_k| top_k as usize)
                 ).await.map_err(|e| match e {
                     WaCustomError::NotFound(msg) => Status::not_found(msg),
@@ -96,7 +97,7 @@ impl VectorsService for VectorsServiceImpl {
 
                 let query: Vec<_> = sparse.values.into_iter().map(|pair| SparsePair(pair.index, pair.value)).collect();
 
-                let results = crate::api::vectordb::search::repo::sparse_ann_vector_query_logic(&self.context.config, inverted_index, &query, sparse.top_k.map(|top_k| top_k as usize), sparse.early_terminate_threshold.unwrap_or(self.context.config.search.early_terminate_threshold)).map_err(Status::from)?;
+                let results = crate::api::vectordb::search::repo::sparse_ann_vector_query_logic(&self.context.config, inverted_index, &collection, &query, sparse.top_k.map(|top_k| top_k as usize), &crate::api::vectordb::search::repo::SparseSearchOptions { early_terminate_threshold: sparse.early_terminate_threshold, ..Default::default() }, None).map_err(Status::from)?;
 
                 Ok(Response::new(FindSimilarVectorsResponse {
                     results: Some(super::proto::SearchResults {
@@ -114,7 +115,7 @@ impl VectorsService for VectorsServiceImpl {
 
                 let inverted_index = collection.get_tf_idf_index().ok_or_else(|| Status::failed_precondition("Sparse index not initialized"))?;
 
-                let results = crate::api::vectordb::search::repo::tf_idf_ann_vector_query(inverted_index, &idf.query, idf.top_k.map(|top_k| top_k as usize)).map_err(Status::from)?;
+                let results = crate::api::vectordb::search::repo::tf_idf_ann_vector_query(inverted_index, &collection, &idf.query, idf.top_k.map(|top_k| top_k as usize), None, None).map_err(Status::from)?;
 
                 Ok(Response::new(FindSimilarVectorsResponse {
                     results: Some(super::proto::SearchResults {
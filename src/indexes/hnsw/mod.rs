@@ -1,14 +1,18 @@
 pub(crate) mod types;
 
-use std::sync::{
-    atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
-    Arc, RwLock,
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
 };
 
 use types::HNSWHyperParams;
 
 use crate::{
     config_loader::Config,
+    macros::key,
     metadata::MetadataFields,
     models::{
         buffered_io::BufferManagerFactory,
@@ -63,6 +67,9 @@ pub struct HNSWIndex {
     pub sampling_data: SamplingData,
     pub vectors_collected: AtomicUsize,
     pub sample_threshold: usize,
+    // Name of the vector space for the named dense indexes, `None`
+    // for the default dense index of the collection
+    pub vector_name: Option<String>,
 }
 
 #[derive(Default)]
@@ -98,6 +105,7 @@ impl HNSWIndex {
         values_range: (f32, f32),
         sample_threshold: usize,
         is_configured: bool,
+        vector_name: Option<String>,
     ) -> Self {
         Self {
             root_vec: AtomicPtr::new(root_vec),
@@ -115,6 +123,7 @@ impl HNSWIndex {
             sampling_data: SamplingData::default(),
             vectors_collected: AtomicUsize::new(0),
             sample_threshold,
+            vector_name,
         }
     }
 
@@ -126,6 +135,42 @@ impl HNSWIndex {
     pub fn root_vec_offset(&self) -> FileIndex {
         unsafe { &*self.get_root_vec() }.get_file_index()
     }
+
+    /// Returns the LMDB key under which the offset of the raw
+    /// embedding of the vector is stored for this index
+    pub fn embedding_key(&self, vector_id: &VectorId) -> Vec<u8> {
+        dense_embedding_key(self.vector_name.as_deref(), vector_id)
+    }
+}
+
+/// Returns the LMDB key under which the offset of the raw embedding of
+/// the vector is stored for the default dense index (`vector_name` =
+/// `None`) or for the named dense index
+pub fn dense_embedding_key(vector_name: Option<&str>, vector_id: &VectorId) -> Vec<u8> {
+    match vector_name {
+        Some(vector_name) => key!(n:HNSWIndex::get_key_for_name(vector_name), vector_id),
+        None => key!(e:vector_id),
+    }
+}
+
+/// Returns the name under which the data of a dense index is persisted
+///
+/// The default dense index keeps using the collection name, so that
+/// existing collections load as before.
+pub fn dense_index_data_name(collection_name: &str, vector_name: Option<&str>) -> String {
+    match vector_name {
+        Some(vector_name) => format!("{}/{}", collection_name, vector_name),
+        None => collection_name.to_string(),
+    }
+}
+
+/// Returns the directory in which the files of a dense index are
+/// stored
+pub fn dense_index_path(collection_path: &Path, vector_name: Option<&str>) -> PathBuf {
+    match vector_name {
+        Some(vector_name) => collection_path.join("dense_hnsw_named").join(vector_name),
+        None => collection_path.join("dense_hnsw"),
+    }
 }
 
 impl IndexOps for HNSWIndex {
@@ -253,7 +298,7 @@ impl IndexOps for HNSWIndex {
         let range = (range_start, range_end);
         *self.values_range.write().unwrap() = range;
         self.is_configured.store(true, Ordering::Release);
-        store_values_range(lmdb, range)?;
+        store_values_range(lmdb, self.vector_name.as_deref(), range)?;
         Ok(())
     }
 
@@ -3,7 +3,10 @@ use std::sync::Arc;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    config_loader::Config, metadata::MetadataFields, models::types::VectorId, storage::Storage,
+    config_loader::Config,
+    metadata::MetadataFields,
+    models::{types::VectorId, versioning::VersionSnapshot},
+    storage::Storage,
 };
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +32,53 @@ impl HNSWHyperParams {
     }
 }
 
+/// Default no. of candidates, as a multiple of `k`, that are rescored
+/// using the raw vectors
+pub const DEFAULT_RERANK_OVERSAMPLING: usize = 5;
+
+/// Per-request overrides of the parameters for searching the index
+#[derive(Debug, Clone, Default)]
+pub struct DenseSearchOptions {
+    /// Overrides `HNSWHyperParams::ef_search`
+    pub ef_search: Option<u32>,
+    /// Skips rescoring the results using the raw vectors
+    pub skip_rerank: bool,
+    /// No. of candidates, as a multiple of `k`, that are rescored
+    /// using the raw vectors. Defaults to
+    /// [`DEFAULT_RERANK_OVERSAMPLING`].
+    pub rerank_oversampling: Option<usize>,
+    /// Min. similarity (or max. distance, for distance metrics) of the
+    /// results, see `MetricResult::meets_threshold`
+    pub score_threshold: Option<f32>,
+    /// Diversifies the results using Maximal Marginal Relevance
+    pub diversity: Option<DiversityOptions>,
+    /// Searches the index as of the snapshot's version, excluding the
+    /// vectors indexed after it and including the ones deleted or
+    /// replaced since
+    pub as_of: Option<Arc<VersionSnapshot>>,
+}
+
+/// Parameters for diversifying the results using Maximal Marginal
+/// Relevance, see `mmr_select`
+#[derive(Debug, Clone, Copy)]
+pub struct DiversityOptions {
+    /// Trade-off between the relevance (1.0) and the diversity (0.0)
+    /// of the results
+    pub lambda: f32,
+    /// No. of most relevant candidates the results are selected from.
+    /// Defaults to `k` times [`DEFAULT_RERANK_OVERSAMPLING`].
+    pub fetch_k: Option<usize>,
+}
+
+impl DiversityOptions {
+    /// No. of candidates to select the `k` results from
+    pub fn fetch_k(&self, k: usize) -> usize {
+        self.fetch_k
+            .unwrap_or(k.saturating_mul(DEFAULT_RERANK_OVERSAMPLING))
+            .max(k)
+    }
+}
+
 // Quantized vector embedding
 #[derive(Debug, Clone, PartialEq)]
 pub struct QuantizedDenseVectorEmbedding {
@@ -15,6 +15,18 @@ pub(crate) mod hnsw;
 pub(crate) mod inverted;
 pub(crate) mod tf_idf;
 
+/// Max. no. of results returned by radius searches i.e. searches with a
+/// score threshold but without `top_k`
+pub const RADIUS_SEARCH_MAX_RESULTS: usize = 1000;
+
+/// Max. no. of candidates retrieved from the index by group-by
+/// searches, while trying to fill the requested groups
+pub const GROUP_SEARCH_MAX_CANDIDATES: usize = 10_000;
+
+/// Max. no. of positive examples of "best score" recommendations, as
+/// the dense index is searched for the candidates of each of them
+pub const BEST_SCORE_MAX_POSITIVES: usize = 32;
+
 pub trait IndexOps {
     type InputEmbedding;
     type Data: serde::Serialize + serde::de::DeserializeOwned;
@@ -2,7 +2,7 @@ use rayon::iter::{IntoParallelIterator, ParallelIterator};
 
 use std::{
     hash::Hasher,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering},
         RwLock,
@@ -21,10 +21,12 @@ use crate::{
         collection_transaction::CollectionTransaction,
         common::WaCustomError,
         meta_persist::{store_average_document_length, store_highest_internal_id},
+        sparse_ann_query::CorpusSnapshot,
         tf_idf_index::TFIDFIndexRoot,
+        tombstones::Tombstones,
         tree_map::TreeMap,
         types::{MetaDb, VectorId},
-        versioning::Hash,
+        versioning::{Hash, VersionSnapshot},
     },
 };
 
@@ -55,6 +57,10 @@ pub struct TFIDFIndex {
     pub sample_threshold: usize,
     pub vec_raw_manager: BufferManagerFactory<u8>,
     pub vec_raw_map: TreeMap<(VectorId, Option<String>)>,
+    pub document_ids_manager: BufferManagerFactory<u8>,
+    /// Internal document ids of the documents indexed for every vector
+    /// id, keyed by the vector id, with a version per (re-)insertion
+    pub document_ids: TreeMap<u32>,
     pub document_id_counter: AtomicU32,
     pub store_raw_text: bool,
     pub k1: f32,
@@ -74,6 +80,7 @@ impl TFIDFIndex {
         k1: f32,
         b: f32,
     ) -> Result<Self, BufIoError> {
+        let document_ids_manager = Self::document_ids_manager(&root_path);
         let root = TFIDFIndexRoot::new(root_path, data_file_parts)?;
 
         Ok(Self {
@@ -86,6 +93,8 @@ impl TFIDFIndex {
             sample_threshold,
             vec_raw_manager,
             vec_raw_map: TreeMap::new(),
+            document_ids_manager,
+            document_ids: TreeMap::new(),
             document_id_counter: AtomicU32::new(0),
             store_raw_text,
             k1,
@@ -111,6 +120,7 @@ impl TFIDFIndex {
             self.root.insert(term_hash, tf, document_id, version)?;
         }
 
+        self.document_ids.insert(version, ext_id.0, document_id);
         self.vec_raw_map.insert(
             version,
             document_id as u64,
@@ -119,6 +129,119 @@ impl TFIDFIndex {
 
         Ok(())
     }
+
+    /// Finds the most recently indexed document for the external id
+    /// `ext_id` and returns its internal document id along with the
+    /// entry from `vec_raw_map`. Documents indexed in versions that
+    /// haven't been committed are skipped, and so are the ones indexed
+    /// after the snapshot's version with `as_of`.
+    pub fn get_document(
+        &self,
+        ext_id: &VectorId,
+        tombstones: &Tombstones,
+        as_of: Option<&VersionSnapshot>,
+    ) -> Option<(u32, &(VectorId, Option<String>))> {
+        let item = self.document_ids.get_versioned(ext_id.0)?;
+        let document_id = tombstones.visible_item(item, as_of)?.value;
+        self.vec_raw_map
+            .get_latest(document_id as u64)
+            .map(|doc| (document_id, doc))
+    }
+
+    /// Records the live document of the vector `ext_id` (if any) again
+    /// in `version`, without re-indexing it, for it to remain live when
+    /// the vector's other data is re-indexed in that version
+    pub fn reinsert_document(&self, version: Hash, ext_id: &VectorId, tombstones: &Tombstones) {
+        let Some((document_id, document)) = self.get_document(ext_id, tombstones, None) else {
+            return;
+        };
+        if !self.is_document_live(document_id, tombstones, None) {
+            return;
+        }
+        let document = document.clone();
+        self.document_ids.insert(version, ext_id.0, document_id);
+        self.vec_raw_map
+            .insert(version, document_id as u64, document);
+    }
+
+    pub fn document_ids_manager(root_path: &Path) -> BufferManagerFactory<u8> {
+        BufferManagerFactory::new(
+            root_path.into(),
+            |root, part: &u8| root.join(format!("{}.doc_ids", part)),
+            8192,
+        )
+    }
+
+    /// Loads the document ids of the vectors, rebuilding them from
+    /// `vec_raw_map` for indexes persisted before they were recorded
+    pub fn load_document_ids(
+        root_path: &Path,
+        document_ids_manager: &BufferManagerFactory<u8>,
+        vec_raw_map: &TreeMap<(VectorId, Option<String>)>,
+        data_file_parts: u8,
+    ) -> Result<TreeMap<u32>, BufIoError> {
+        if root_path.join("0.doc_ids").exists() {
+            return TreeMap::deserialize(document_ids_manager, data_file_parts);
+        }
+        let document_ids = TreeMap::new();
+        for document_id in vec_raw_map.keys() {
+            if let Some(item) = vec_raw_map.get_versioned(document_id) {
+                document_ids.insert(item.version, item.value.0 .0, document_id as u32);
+            }
+        }
+        Ok(document_ids)
+    }
+}
+
+impl TFIDFIndex {
+    /// Returns true if the document with internal id `document_id`
+    /// hasn't been deleted or replaced, as of the snapshot `as_of` (if
+    /// any)
+    ///
+    /// A document is live as long as the version it was last recorded
+    /// in is, see [`Self::reinsert_document`].
+    pub fn is_document_live(
+        &self,
+        document_id: u32,
+        tombstones: &Tombstones,
+        as_of: Option<&VersionSnapshot>,
+    ) -> bool {
+        if tombstones.is_all_live() && as_of.is_none() {
+            return true;
+        }
+        self.vec_raw_map
+            .get_versioned(document_id as u64)
+            .and_then(|versioned| tombstones.visible_item(versioned, as_of))
+            .is_some_and(|item| tombstones.is_live_as_of(&item.value.0, item.version, as_of))
+    }
+
+    /// Returns the corpus of the index as of the snapshot, i.e. the
+    /// documents indexed in the committed versions of the snapshot
+    ///
+    /// Like the stats of the whole index, the corpus includes the
+    /// documents deleted or replaced (as of the snapshot's version).
+    pub fn corpus_snapshot<'a>(
+        &'a self,
+        tombstones: &'a Tombstones,
+        as_of: &'a VersionSnapshot,
+    ) -> CorpusSnapshot<'a> {
+        let is_indexed = move |document_id: u32| {
+            self.vec_raw_map
+                .get_versioned(document_id as u64)
+                .and_then(|versioned| tombstones.visible_item(versioned, Some(as_of)))
+                .is_some()
+        };
+        let documents_count = self
+            .vec_raw_map
+            .keys()
+            .into_iter()
+            .filter(|document_id| is_indexed(*document_id as u32))
+            .count() as u32;
+        CorpusSnapshot {
+            documents_count,
+            is_indexed: Box::new(is_indexed),
+        }
+    }
 }
 
 impl IndexOps for TFIDFIndex {
@@ -193,6 +316,8 @@ impl IndexOps for TFIDFIndex {
         )?;
         self.vec_raw_map
             .serialize(&self.vec_raw_manager, self.root.data_file_parts)?;
+        self.document_ids
+            .serialize(&self.document_ids_manager, self.root.data_file_parts)?;
         self.root.serialize()?;
         self.root.cache.flush_all()?;
         Ok(())
@@ -18,6 +18,99 @@ macro_rules! key {
         key.extend_from_slice(&$branch_id.to_le_bytes());
         key
     }};
+    // tombstone of a vector recorded in a version, big endian version
+    // number so that the keys of a vector are ordered by version
+    (t:$vector_id:expr, $version_number:expr) => {{
+        let mut prefixed_key = Vec::with_capacity(11); // prefix = 1 byte, id = 8 bytes, version number = 2 bytes
+        prefixed_key.push(4);
+        prefixed_key.extend_from_slice(&$vector_id.0.to_le_bytes());
+        prefixed_key.extend_from_slice(&$version_number.to_be_bytes());
+        prefixed_key
+    }};
+    // latest tombstone of a vector, as persisted by older releases
+    (t:$vector_id:expr) => {{
+        let mut prefixed_key = Vec::with_capacity(9); // prefix = 1 byte, id = 8 bytes
+        prefixed_key.push(4);
+        prefixed_key.extend_from_slice(&$vector_id.0.to_le_bytes());
+        prefixed_key
+    }};
+    // string id -> internal vector id
+    (x:$string_id:expr) => {{
+        let key = $string_id.as_bytes();
+        let mut prefixed_key = Vec::with_capacity(1 + key.len()); // prefix = 1 byte
+        prefixed_key.push(5);
+        prefixed_key.extend_from_slice(key);
+        prefixed_key
+    }};
+    // internal vector id -> string id
+    (r:$vector_id:expr) => {{
+        let mut prefixed_key = Vec::with_capacity(9); // prefix = 1 byte, id = 8 bytes
+        prefixed_key.push(6);
+        prefixed_key.extend_from_slice(&$vector_id.0.to_le_bytes());
+        prefixed_key
+    }};
+    // vector counts of a version
+    (c:$version_id:expr) => {{
+        let mut key = Vec::with_capacity(5); // prefix = 1 byte, Hash = 4 byte
+        key.push(7);
+        key.extend_from_slice(&$version_id.to_le_bytes());
+        key
+    }};
+    // embedding offset in a named dense index, keyed by the hash of
+    // the vector name
+    (n:$vector_key:expr, $embedding_id:expr) => {{
+        let mut prefixed_key = Vec::with_capacity(17); // prefix = 1 byte, key = 8 bytes, id = 8 bytes
+        prefixed_key.push(8);
+        prefixed_key.extend_from_slice(&$vector_key.to_le_bytes());
+        prefixed_key.extend_from_slice(&$embedding_id.0.to_le_bytes());
+        prefixed_key
+    }};
+    // id of a vector of a multi-vector document -> document id
+    (y:$vector_id:expr) => {{
+        let mut prefixed_key = Vec::with_capacity(9); // prefix = 1 byte, id = 8 bytes
+        prefixed_key.push(9);
+        prefixed_key.extend_from_slice(&$vector_id.0.to_le_bytes());
+        prefixed_key
+    }};
+    // multi-vector document in a named dense index, keyed by the hash
+    // of the vector name
+    (z:$vector_key:expr, $document_id:expr) => {{
+        let mut prefixed_key = Vec::with_capacity(17); // prefix = 1 byte, key = 8 bytes, id = 8 bytes
+        prefixed_key.push(10);
+        prefixed_key.extend_from_slice(&$vector_key.to_le_bytes());
+        prefixed_key.extend_from_slice(&$document_id.0.to_le_bytes());
+        prefixed_key
+    }};
+    // metadata fields of a vector recorded in a version, big endian
+    // version number so that the keys of a vector are ordered by version
+    (f:$vector_id:expr, $version_number:expr) => {{
+        let mut prefixed_key = Vec::with_capacity(11); // prefix = 1 byte, id = 8 bytes, version number = 2 bytes
+        prefixed_key.push(11);
+        prefixed_key.extend_from_slice(&$vector_id.0.to_le_bytes());
+        prefixed_key.extend_from_slice(&$version_number.to_be_bytes());
+        prefixed_key
+    }};
+    // latest metadata fields of a vector, as persisted by older releases
+    (f:$vector_id:expr) => {{
+        let mut prefixed_key = Vec::with_capacity(9); // prefix = 1 byte, id = 8 bytes
+        prefixed_key.push(11);
+        prefixed_key.extend_from_slice(&$vector_id.0.to_le_bytes());
+        prefixed_key
+    }};
+    // version of a transaction that hasn't been committed (yet)
+    (u:$version_id:expr) => {{
+        let mut key = Vec::with_capacity(5); // prefix = 1 byte, Hash = 4 byte
+        key.push(12);
+        key.extend_from_slice(&$version_id.to_le_bytes());
+        key
+    }};
+    // id of a vector, big endian so that the keys are ordered by id
+    (l:$vector_id:expr) => {{
+        let mut prefixed_key = Vec::with_capacity(9); // prefix = 1 byte, id = 8 bytes
+        prefixed_key.push(13);
+        prefixed_key.extend_from_slice(&$vector_id.0.to_be_bytes());
+        prefixed_key
+    }};
     // misc/metadata
     (m:$name:ident) => {{
         let key = stringify!($name).as_bytes();
@@ -10,7 +10,7 @@ pub mod de;
 pub mod query_filtering;
 pub mod schema;
 
-pub use query_filtering::{Filter, Operator, Predicate, QueryFilterDimensions};
+pub use query_filtering::{Filter, Operator, Predicate, PredicateValue, QueryFilterDimensions};
 pub use schema::MetadataSchema;
 
 use crate::models::common::generate_level_probs;
@@ -1,27 +1,348 @@
 use std::collections::HashMap;
 
-use super::{decimal_to_binary_vec, schema::MetadataSchema, Error, FieldName, FieldValue};
+use super::{
+    decimal_to_binary_vec,
+    schema::{MetadataSchema, SupportedCondition},
+    Error, FieldName, FieldValue, MetadataFields,
+};
 use serde::Deserialize;
 
 #[derive(Clone, Debug, PartialEq, Deserialize)]
 pub enum Operator {
     Equal,
     NotEqual,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    In,
+    NotIn,
 }
 
-#[derive(Clone, Debug, Deserialize)]
+impl Operator {
+    /// Returns the operator that matches exactly the values this one
+    /// doesn't match
+    ///
+    /// Returns `None` for the comparison operators, as they don't
+    /// match vectors that don't have the field, and hence neither
+    /// would the opposite comparison.
+    pub fn negated(&self) -> Option<Self> {
+        match self {
+            Self::Equal => Some(Self::NotEqual),
+            Self::NotEqual => Some(Self::Equal),
+            Self::In => Some(Self::NotIn),
+            Self::NotIn => Some(Self::In),
+            Self::Gt | Self::Gte | Self::Lt | Self::Lte => None,
+        }
+    }
+
+    fn is_comparison(&self) -> bool {
+        matches!(self, Self::Gt | Self::Gte | Self::Lt | Self::Lte)
+    }
+
+    fn compare(&self, value: i32, other: i32) -> bool {
+        match self {
+            Self::Gt => value > other,
+            Self::Gte => value >= other,
+            Self::Lt => value < other,
+            Self::Lte => value <= other,
+            _ => false,
+        }
+    }
+}
+
+/// Value(s) a field is compared against in a predicate i.e. a list
+/// of values for the `In` and `NotIn` operators, a single value
+/// otherwise
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum PredicateValue {
+    Single(FieldValue),
+    Multiple(Vec<FieldValue>),
+}
+
+impl From<FieldValue> for PredicateValue {
+    fn from(value: FieldValue) -> Self {
+        Self::Single(value)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "RawPredicate")]
 pub struct Predicate {
     pub field_name: FieldName,
-    pub field_value: FieldValue,
+    pub field_value: PredicateValue,
     pub operator: Operator,
 }
 
-// @NOTE: Nested And/Or not supported for now
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Deserialize)]
+struct RawPredicate {
+    field_name: FieldName,
+    field_value: PredicateValue,
+    operator: Operator,
+}
+
+impl TryFrom<RawPredicate> for Predicate {
+    type Error = Error;
+
+    fn try_from(raw: RawPredicate) -> Result<Self, Error> {
+        Self::new(raw.field_name, raw.field_value, raw.operator)
+    }
+}
+
+/// Metadata filter, which can be an arbitrarily nested tree of `And`,
+/// `Or` and `Not` nodes with predicates as leaves
+///
+/// For convenience (and backward compatibility), the operands of
+/// `And`, `Or` and `Not` may also be plain predicates in the JSON
+/// representation i.e. `{"And": [<predicate>, {"Not": <predicate>}]}`
+/// is equivalent to `{"And": [{"Is": <predicate>}, {"Not": {"Is":
+/// <predicate>}}]}`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(from = "RawFilter")]
 pub enum Filter {
     Is(Predicate),
-    And(Vec<Predicate>),
-    Or(Vec<Predicate>),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+#[derive(Deserialize)]
+enum RawFilter {
+    Is(Predicate),
+    And(Vec<FilterOperand>),
+    Or(Vec<FilterOperand>),
+    Not(Box<FilterOperand>),
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FilterOperand {
+    Predicate(Predicate),
+    Filter(Filter),
+}
+
+impl From<FilterOperand> for Filter {
+    fn from(operand: FilterOperand) -> Self {
+        match operand {
+            FilterOperand::Predicate(pred) => Self::Is(pred),
+            FilterOperand::Filter(filter) => filter,
+        }
+    }
+}
+
+impl From<RawFilter> for Filter {
+    fn from(raw: RawFilter) -> Self {
+        match raw {
+            RawFilter::Is(pred) => Self::Is(pred),
+            RawFilter::And(operands) => Self::And(operands.into_iter().map(Self::from).collect()),
+            RawFilter::Or(operands) => Self::Or(operands.into_iter().map(Self::from).collect()),
+            RawFilter::Not(operand) => Self::Not(Box::new(Self::from(*operand))),
+        }
+    }
+}
+
+/// Max no. of conjunctions a filter may expand to when converted to
+/// disjunctive normal form. Every conjunction results in a separate
+/// traversal of the HNSW index, so filters expanding to more
+/// conjunctions are applied to the search results instead.
+const MAX_CONJUNCTIONS: usize = 32;
+
+impl Predicate {
+    /// Constructor for Predicate
+    ///
+    /// Also checks that the value is valid for the operator i.e. a
+    /// list of values for `In` and `NotIn`, an int value for the
+    /// comparison operators and a single value otherwise.
+    pub fn new(
+        field_name: FieldName,
+        field_value: PredicateValue,
+        operator: Operator,
+    ) -> Result<Self, Error> {
+        let valid = match (&operator, &field_value) {
+            (Operator::In | Operator::NotIn, PredicateValue::Multiple(_)) => true,
+            (op, PredicateValue::Single(FieldValue::Int(_))) if op.is_comparison() => true,
+            (Operator::Equal | Operator::NotEqual, PredicateValue::Single(_)) => true,
+            _ => false,
+        };
+        if !valid {
+            return Err(Error::InvalidFieldValue(format!(
+                "Invalid value {:?} for operator {:?} on field {}",
+                field_value, operator, field_name
+            )));
+        }
+        Ok(Self {
+            field_name,
+            field_value,
+            operator,
+        })
+    }
+
+    /// Evaluates the predicate against the raw metadata fields of a
+    /// vector. A field that's not present is considered to be not
+    /// equal to any value and to fail all comparisons.
+    pub fn matches(&self, fields: Option<&MetadataFields>) -> bool {
+        let value = fields.and_then(|fields| fields.get(&self.field_name));
+        let is_in = |values: &[FieldValue]| value.is_some_and(|v| values.contains(v));
+        match (&self.operator, &self.field_value) {
+            (Operator::Equal, PredicateValue::Single(other)) => value == Some(other),
+            (Operator::NotEqual, PredicateValue::Single(other)) => value != Some(other),
+            (Operator::In, PredicateValue::Multiple(values)) => is_in(values),
+            (Operator::NotIn, PredicateValue::Multiple(values)) => !is_in(values),
+            (op, PredicateValue::Single(FieldValue::Int(other))) => match value {
+                Some(FieldValue::Int(value)) => op.compare(*value, *other),
+                _ => false,
+            },
+            // Invalid combinations of operator and value, see
+            // `Predicate::new`
+            _ => false,
+        }
+    }
+
+    pub fn negated(&self) -> Option<Self> {
+        Some(Self {
+            field_name: self.field_name.clone(),
+            field_value: self.field_value.clone(),
+            operator: self.operator.negated()?,
+        })
+    }
+
+    /// Expands the predicate into an equivalent filter made up of
+    /// `Equal` and `NotEqual` predicates only, which are the ones that
+    /// can be encoded as query filter dimensions
+    ///
+    /// The comparison operators are expanded to the values of the field
+    /// in the schema that satisfy the comparison.
+    fn expand(&self, schema: &MetadataSchema) -> Result<Filter, Error> {
+        let field = schema
+            .fields
+            .iter()
+            .find(|field| field.name == self.field_name)
+            .ok_or_else(|| {
+                Error::InvalidField(format!(
+                    "field '{}' is not part of the metadata schema",
+                    self.field_name
+                ))
+            })?;
+        let equal_to = |value: &FieldValue, operator: Operator| {
+            Filter::Is(Self {
+                field_name: self.field_name.clone(),
+                field_value: value.clone().into(),
+                operator,
+            })
+        };
+        match (&self.operator, &self.field_value) {
+            (Operator::In, PredicateValue::Multiple(values)) => Ok(Filter::Or(
+                values
+                    .iter()
+                    .map(|value| equal_to(value, Operator::Equal))
+                    .collect(),
+            )),
+            (Operator::NotIn, PredicateValue::Multiple(values)) => Ok(Filter::And(
+                values
+                    .iter()
+                    .map(|value| equal_to(value, Operator::NotEqual))
+                    .collect(),
+            )),
+            (op, PredicateValue::Single(FieldValue::Int(other))) if op.is_comparison() => {
+                // Values are sorted by their ids, which for int fields
+                // is the same as the numeric order
+                let mut values = field.value_index.iter().collect::<Vec<_>>();
+                values.sort_by_key(|(_, id)| **id);
+                let mut filters = vec![];
+                for (value, _) in values {
+                    match value {
+                        FieldValue::Int(v) if op.compare(*v, *other) => {
+                            filters.push(equal_to(value, Operator::Equal));
+                        }
+                        FieldValue::Int(_) => {}
+                        _ => {
+                            return Err(Error::InvalidFieldValue(format!(
+                                "Operator {:?} is only supported for int fields, field {} is not",
+                                op, self.field_name
+                            )))
+                        }
+                    }
+                }
+                Ok(Filter::Or(filters))
+            }
+            _ => Ok(Filter::Is(self.clone())),
+        }
+    }
+}
+
+impl Filter {
+    /// Returns an equivalent filter with all predicates expanded to
+    /// `Equal` and `NotEqual` predicates, see `Predicate::expand`
+    fn expand(&self, schema: &MetadataSchema) -> Result<Filter, Error> {
+        let expand_all = |filters: &[Filter]| {
+            filters
+                .iter()
+                .map(|filter| filter.expand(schema))
+                .collect::<Result<Vec<_>, _>>()
+        };
+        Ok(match self {
+            Filter::Is(pred) => pred.expand(schema)?,
+            Filter::And(filters) => Filter::And(expand_all(filters)?),
+            Filter::Or(filters) => Filter::Or(expand_all(filters)?),
+            Filter::Not(filter) => Filter::Not(Box::new(filter.expand(schema)?)),
+        })
+    }
+
+    /// Evaluates the filter against the raw metadata fields of a
+    /// vector
+    ///
+    /// Unlike the filter encoded dimensions used for searching, this
+    /// doesn't depend on the metadata schema, so it can be used where
+    /// vectors are read directly from storage.
+    pub fn matches(&self, fields: Option<&MetadataFields>) -> bool {
+        match self {
+            Filter::Is(pred) => pred.matches(fields),
+            Filter::And(filters) => filters.iter().all(|filter| filter.matches(fields)),
+            Filter::Or(filters) => filters.iter().any(|filter| filter.matches(fields)),
+            Filter::Not(filter) => !filter.matches(fields),
+        }
+    }
+
+    /// Converts the filter to disjunctive normal form i.e. an `Or` of
+    /// `And`s of predicates, with the negations pushed down to the
+    /// predicates (De Morgan's laws)
+    ///
+    /// An empty conjunction matches all vectors whereas an empty
+    /// result matches none. Returns `None` if the result would have
+    /// more than `max_conjunctions` conjunctions, or if a comparison
+    /// predicate is negated (see `Operator::negated`).
+    fn to_dnf(&self, negate: bool, max_conjunctions: usize) -> Option<Vec<Vec<Predicate>>> {
+        match (self, negate) {
+            (Filter::Is(pred), false) => Some(vec![vec![pred.clone()]]),
+            (Filter::Is(pred), true) => Some(vec![vec![pred.negated()?]]),
+            (Filter::Not(filter), _) => filter.to_dnf(!negate, max_conjunctions),
+            (Filter::And(filters), false) | (Filter::Or(filters), true) => {
+                let mut result = vec![vec![]];
+                for filter in filters {
+                    let dnf = filter.to_dnf(negate, max_conjunctions)?;
+                    if result.len() * dnf.len() > max_conjunctions {
+                        return None;
+                    }
+                    result = result
+                        .iter()
+                        .flat_map(|conj| dnf.iter().map(move |other| [&conj[..], other].concat()))
+                        .collect();
+                }
+                Some(result)
+            }
+            (Filter::Or(filters), false) | (Filter::And(filters), true) => {
+                let mut result = vec![];
+                for filter in filters {
+                    result.extend(filter.to_dnf(negate, max_conjunctions)?);
+                    if result.len() > max_conjunctions {
+                        return None;
+                    }
+                }
+                Some(result)
+            }
+        }
+    }
 }
 
 pub type QueryFilterDimensions = Vec<i8>;
@@ -44,6 +365,9 @@ fn query_filter_encoding(value_id: u16, size: usize, operator: &Operator) -> Que
                     1
                 }
             }
+            // Other operators are expanded into `Equal` and
+            // `NotEqual` predicates before encoding
+            _ => 0,
         })
         .collect::<Vec<i8>>()
 }
@@ -68,7 +392,16 @@ fn and_predicates_to_dimensions(
     for field in &schema.fields {
         match pred_index.get(&field.name.as_ref()) {
             Some(pred) => {
-                let value_id = field.value_id(&pred.field_value)?;
+                let value = match (&pred.operator, &pred.field_value) {
+                    (Operator::Equal | Operator::NotEqual, PredicateValue::Single(value)) => value,
+                    _ => {
+                        return Err(Error::UnsupportedFilter(format!(
+                            "operator {:?} can't be encoded",
+                            pred.operator
+                        )))
+                    }
+                };
+                let value_id = field.value_id(value)?;
                 let mut dims =
                     query_filter_encoding(value_id, field.num_dims as usize, &pred.operator);
                 result.append(&mut dims);
@@ -82,34 +415,96 @@ fn and_predicates_to_dimensions(
     Ok(result)
 }
 
+/// Returns the predicates of a conjunction after checking that they
+/// can be encoded together as a single `QueryFilterDimensions`
+///
+/// That's the case if every predicate refers to a distinct field of
+/// the schema, and the fields are either a single field or covered by
+/// an `And` condition of the schema, as only then the index has
+/// replica nodes with the dimensions of all the fields set.
+fn encodable_conjunction<'a>(
+    schema: &MetadataSchema,
+    preds: &'a [Predicate],
+) -> Result<Vec<&'a Predicate>, Error> {
+    let mut result: Vec<&Predicate> = Vec::with_capacity(preds.len());
+    for pred in preds {
+        if result.contains(&pred) {
+            continue;
+        }
+        if !schema
+            .fields
+            .iter()
+            .any(|field| field.name == pred.field_name)
+        {
+            return Err(Error::InvalidField(format!(
+                "field '{}' is not part of the metadata schema",
+                pred.field_name
+            )));
+        }
+        if result.iter().any(|p| p.field_name == pred.field_name) {
+            return Err(Error::UnsupportedFilter(format!(
+                "multiple predicates on field '{}' in a conjunction",
+                pred.field_name
+            )));
+        }
+        result.push(pred);
+    }
+    match result.len() {
+        0 => Err(Error::UnsupportedFilter(
+            "filter matches all vectors".to_string(),
+        )),
+        1 => Ok(result),
+        _ => {
+            let covered = schema.conditions.iter().any(|cond| match cond {
+                SupportedCondition::And(fields) => {
+                    result.iter().all(|pred| fields.contains(&pred.field_name))
+                }
+                SupportedCondition::Or(_) => false,
+            });
+            if covered {
+                Ok(result)
+            } else {
+                Err(Error::UnsupportedFilter(
+                    "no `And` condition in the metadata schema covers the fields of a conjunction"
+                        .to_string(),
+                ))
+            }
+        }
+    }
+}
+
 /// Returns vector of dimensions encoding query filter
 ///
-/// @NOTE(vineet): Here we're assuming that the `Filter` is valid, for
-/// the schema. Not sure if the check should happen here or in the
-/// calling code
+/// The predicates are expanded to `Equal` and `NotEqual` predicates
+/// and the filter is converted to disjunctive normal form. Every
+/// conjunction is encoded as a separate `QueryFilterDimensions`. An
+/// `Error::UnsupportedFilter` is returned for filters that can't be
+/// encoded that way due to their shape (e.g. expanding to too many
+/// conjunctions), which the caller may handle by filtering the search
+/// results using `Filter::matches` instead. Predicates on fields that
+/// aren't part of the schema result in `Error::InvalidField`.
 pub fn filter_encoded_dimensions(
     schema: &MetadataSchema,
     filter: &Filter,
 ) -> Result<Vec<QueryFilterDimensions>, Error> {
-    match filter {
-        Filter::Is(pred) => {
-            let dims = and_predicates_to_dimensions(schema, vec![pred])?;
-            Ok(vec![dims])
-        }
-        Filter::And(preds) => {
-            let pred_refs = preds.iter().collect();
-            let dims = and_predicates_to_dimensions(schema, pred_refs)?;
-            Ok(vec![dims])
-        }
-        Filter::Or(preds) => {
-            let mut result: Vec<QueryFilterDimensions> = vec![];
-            for pred in preds {
-                let dims = and_predicates_to_dimensions(schema, vec![pred])?;
-                result.push(dims);
-            }
-            Ok(result)
-        }
+    let conjunctions = filter
+        .expand(schema)?
+        .to_dnf(false, MAX_CONJUNCTIONS)
+        .ok_or_else(|| {
+            Error::UnsupportedFilter(format!(
+                "filter expands to more than {} conjunctions",
+                MAX_CONJUNCTIONS
+            ))
+        })?;
+    if conjunctions.is_empty() {
+        return Err(Error::UnsupportedFilter(
+            "filter matches no vectors".to_string(),
+        ));
     }
+    conjunctions
+        .iter()
+        .map(|preds| and_predicates_to_dimensions(schema, encodable_conjunction(schema, preds)?))
+        .collect()
 }
 
 #[cfg(test)]
@@ -117,7 +512,7 @@ mod tests {
 
     use std::collections::HashSet;
 
-    use super::super::schema::{MetadataField, SupportedCondition};
+    use super::super::schema::MetadataField;
     use super::*;
 
     #[test]
@@ -148,7 +543,7 @@ mod tests {
         // Test for `Is` filter
         let filter = Filter::Is(Predicate {
             field_name: "age".to_string(),
-            field_value: FieldValue::Int(6),
+            field_value: FieldValue::Int(6).into(),
             operator: Operator::Equal,
         });
         let qfed = filter_encoded_dimensions(&schema, &filter).unwrap();
@@ -162,16 +557,16 @@ mod tests {
 
         // Test for `And` filter
         let filter = Filter::And(vec![
-            Predicate {
+            Filter::Is(Predicate {
                 field_name: "age".to_string(),
-                field_value: FieldValue::Int(2),
+                field_value: FieldValue::Int(2).into(),
                 operator: Operator::Equal,
-            },
-            Predicate {
+            }),
+            Filter::Is(Predicate {
                 field_name: "group".to_string(),
-                field_value: FieldValue::String("b".to_owned()),
+                field_value: FieldValue::String("b".to_owned()).into(),
                 operator: Operator::NotEqual,
-            },
+            }),
         ]);
         let qfed = filter_encoded_dimensions(&schema, &filter).unwrap();
         assert_eq!(
@@ -184,16 +579,16 @@ mod tests {
 
         // Test for `Or` filter
         let filter = Filter::Or(vec![
-            Predicate {
+            Filter::Is(Predicate {
                 field_name: "age".to_string(),
-                field_value: FieldValue::Int(2),
+                field_value: FieldValue::Int(2).into(),
                 operator: Operator::Equal,
-            },
-            Predicate {
+            }),
+            Filter::Is(Predicate {
                 field_name: "group".to_string(),
-                field_value: FieldValue::String("b".to_owned()),
+                field_value: FieldValue::String("b".to_owned()).into(),
                 operator: Operator::NotEqual,
-            },
+            }),
         ]);
         let qfed = filter_encoded_dimensions(&schema, &filter).unwrap();
         assert_eq!(
@@ -210,4 +605,347 @@ mod tests {
             qfed
         );
     }
+
+    #[test]
+    fn test_filter_matches() {
+        let fields: MetadataFields = HashMap::from([
+            ("age".to_string(), FieldValue::Int(2)),
+            ("group".to_string(), FieldValue::String("b".to_owned())),
+        ]);
+        let age_is_2 = Predicate {
+            field_name: "age".to_string(),
+            field_value: FieldValue::Int(2).into(),
+            operator: Operator::Equal,
+        };
+        let group_is_not_b = Predicate {
+            field_name: "group".to_string(),
+            field_value: FieldValue::String("b".to_owned()).into(),
+            operator: Operator::NotEqual,
+        };
+
+        assert!(Filter::Is(age_is_2.clone()).matches(Some(&fields)));
+        assert!(!Filter::Is(age_is_2.clone()).matches(None));
+        assert!(!Filter::Is(group_is_not_b.clone()).matches(Some(&fields)));
+        assert!(Filter::Is(group_is_not_b.clone()).matches(None));
+        let age_is_2 = Filter::Is(age_is_2);
+        let group_is_not_b = Filter::Is(group_is_not_b);
+        assert!(!Filter::And(vec![age_is_2.clone(), group_is_not_b.clone()]).matches(Some(&fields)));
+        assert!(Filter::Or(vec![age_is_2.clone(), group_is_not_b.clone()]).matches(Some(&fields)));
+
+        // Nested filters
+        let not_group_is_not_b = Filter::Not(Box::new(group_is_not_b.clone()));
+        assert!(not_group_is_not_b.matches(Some(&fields)));
+        assert!(!not_group_is_not_b.matches(None));
+        assert!(Filter::And(vec![
+            age_is_2.clone(),
+            Filter::Or(vec![group_is_not_b.clone(), not_group_is_not_b]),
+        ])
+        .matches(Some(&fields)));
+        assert!(
+            !Filter::Not(Box::new(Filter::Or(vec![age_is_2, group_is_not_b])))
+                .matches(Some(&fields))
+        );
+        assert!(Filter::And(vec![]).matches(None));
+        assert!(!Filter::Or(vec![]).matches(None));
+    }
+
+    fn pred(field_name: &str, field_value: FieldValue, operator: Operator) -> Predicate {
+        Predicate {
+            field_name: field_name.to_string(),
+            field_value: field_value.into(),
+            operator,
+        }
+    }
+
+    #[test]
+    fn test_filter_serde() {
+        let input = r#"{"And": [
+            {"field_name": "tenant", "field_value": "a", "operator": "Equal"},
+            {"Or": [
+                {"Is": {"field_name": "lang", "field_value": "en", "operator": "Equal"}},
+                {"field_name": "lang", "field_value": "de", "operator": "Equal"}
+            ]},
+            {"Not": {"field_name": "year", "field_value": 2020, "operator": "Equal"}}
+        ]}"#;
+        let filter: Filter = serde_json::from_str(input).unwrap();
+        let str_value = |s: &str| FieldValue::String(s.to_string());
+        assert_eq!(
+            Filter::And(vec![
+                Filter::Is(pred("tenant", str_value("a"), Operator::Equal)),
+                Filter::Or(vec![
+                    Filter::Is(pred("lang", str_value("en"), Operator::Equal)),
+                    Filter::Is(pred("lang", str_value("de"), Operator::Equal)),
+                ]),
+                Filter::Not(Box::new(Filter::Is(pred(
+                    "year",
+                    FieldValue::Int(2020),
+                    Operator::Equal
+                )))),
+            ]),
+            filter
+        );
+    }
+
+    #[test]
+    fn test_to_dnf() {
+        let a = pred("a", FieldValue::Int(1), Operator::Equal);
+        let b = pred("b", FieldValue::Int(2), Operator::Equal);
+        let c = pred("c", FieldValue::Int(3), Operator::Equal);
+
+        // a AND (b OR c) AND NOT c
+        let filter = Filter::And(vec![
+            Filter::Is(a.clone()),
+            Filter::Or(vec![Filter::Is(b.clone()), Filter::Is(c.clone())]),
+            Filter::Not(Box::new(Filter::Is(c.clone()))),
+        ]);
+        assert_eq!(
+            Some(vec![
+                vec![a.clone(), b.clone(), c.negated().unwrap()],
+                vec![a.clone(), c.clone(), c.negated().unwrap()],
+            ]),
+            filter.to_dnf(false, MAX_CONJUNCTIONS)
+        );
+
+        // NOT (a OR (b AND NOT c))
+        let filter = Filter::Not(Box::new(Filter::Or(vec![
+            Filter::Is(a.clone()),
+            Filter::And(vec![
+                Filter::Is(b.clone()),
+                Filter::Not(Box::new(Filter::Is(c.clone()))),
+            ]),
+        ])));
+        assert_eq!(
+            Some(vec![
+                vec![a.negated().unwrap(), b.negated().unwrap()],
+                vec![a.negated().unwrap(), c.clone()]
+            ]),
+            filter.to_dnf(false, MAX_CONJUNCTIONS)
+        );
+
+        // (a OR b) AND (a OR c) AND (b OR c) expands to 8 conjunctions
+        let or = |x: &Predicate, y: &Predicate| {
+            Filter::Or(vec![Filter::Is(x.clone()), Filter::Is(y.clone())])
+        };
+        let filter = Filter::And(vec![or(&a, &b), or(&a, &c), or(&b, &c)]);
+        assert_eq!(8, filter.to_dnf(false, 8).unwrap().len());
+        assert!(filter.to_dnf(false, 7).is_none());
+    }
+
+    #[test]
+    fn test_nested_filter_encoded_dimensions() {
+        let age_values: HashSet<FieldValue> = (1..=10).map(FieldValue::Int).collect();
+        let age = MetadataField::new("age".to_owned(), age_values).unwrap();
+        let group_values: HashSet<FieldValue> = vec!["a", "b", "c"]
+            .into_iter()
+            .map(|x| FieldValue::String(String::from(x)))
+            .collect();
+        let group = MetadataField::new("group".to_owned(), group_values.clone()).unwrap();
+        let lang = MetadataField::new("lang".to_owned(), group_values).unwrap();
+        let conditions = vec![SupportedCondition::And(
+            vec!["age", "group"].into_iter().map(String::from).collect(),
+        )];
+        let schema = MetadataSchema::new(vec![age, group, lang], conditions).unwrap();
+        let group_value = |s: &str| FieldValue::String(s.to_string());
+
+        // age = 2 AND NOT (group = a OR group = b)
+        let filter = Filter::And(vec![
+            Filter::Is(pred("age", FieldValue::Int(2), Operator::Equal)),
+            Filter::Not(Box::new(Filter::Or(vec![
+                Filter::Is(pred("group", group_value("a"), Operator::Equal)),
+                Filter::Is(pred("group", group_value("b"), Operator::Equal)),
+            ]))),
+        ]);
+        // Two predicates on `group` in the same conjunction
+        assert!(matches!(
+            filter_encoded_dimensions(&schema, &filter),
+            Err(Error::UnsupportedFilter(_))
+        ));
+
+        // age = 2 AND (group = a OR NOT group = b)
+        let filter = Filter::And(vec![
+            Filter::Is(pred("age", FieldValue::Int(2), Operator::Equal)),
+            Filter::Or(vec![
+                Filter::Is(pred("group", group_value("a"), Operator::Equal)),
+                Filter::Not(Box::new(Filter::Is(pred(
+                    "group",
+                    group_value("b"),
+                    Operator::Equal,
+                )))),
+            ]),
+        ]);
+        let qfed = filter_encoded_dimensions(&schema, &filter).unwrap();
+        assert_eq!(
+            vec![vec![0, 0, 1, 0, 0, 1, 0, 0], vec![0, 0, 1, 0, -1, 1, 0, 0],],
+            qfed
+        );
+
+        // No `And` condition for age and lang
+        let filter = Filter::And(vec![
+            Filter::Is(pred("age", FieldValue::Int(2), Operator::Equal)),
+            Filter::Is(pred("lang", group_value("a"), Operator::Equal)),
+        ]);
+        assert!(matches!(
+            filter_encoded_dimensions(&schema, &filter),
+            Err(Error::UnsupportedFilter(_))
+        ));
+
+        // Unknown field
+        let filter = Filter::Is(pred("foo", FieldValue::Int(2), Operator::Equal));
+        assert!(matches!(
+            filter_encoded_dimensions(&schema, &filter),
+            Err(Error::InvalidField(_))
+        ));
+
+        // Filters matching all or no vectors
+        for filter in [Filter::And(vec![]), Filter::Or(vec![])] {
+            assert!(matches!(
+                filter_encoded_dimensions(&schema, &filter),
+                Err(Error::UnsupportedFilter(_))
+            ));
+        }
+
+        // Invalid values are still reported as such
+        let filter = Filter::Is(pred("age", FieldValue::Int(42), Operator::Equal));
+        assert!(matches!(
+            filter_encoded_dimensions(&schema, &filter),
+            Err(Error::InvalidFieldValue(_))
+        ));
+    }
+
+    #[test]
+    fn test_comparison_predicates() {
+        let fields: MetadataFields = HashMap::from([
+            ("year".to_string(), FieldValue::Int(2020)),
+            ("lang".to_string(), FieldValue::String("en".to_owned())),
+        ]);
+        let year = |operator, value| pred("year", FieldValue::Int(value), operator);
+
+        assert!(year(Operator::Gt, 2019).matches(Some(&fields)));
+        assert!(!year(Operator::Gt, 2020).matches(Some(&fields)));
+        assert!(year(Operator::Gte, 2020).matches(Some(&fields)));
+        assert!(year(Operator::Lt, 2021).matches(Some(&fields)));
+        assert!(!year(Operator::Lt, 2020).matches(Some(&fields)));
+        assert!(year(Operator::Lte, 2020).matches(Some(&fields)));
+        // Missing fields and fields of other types fail comparisons
+        assert!(!year(Operator::Lte, 2020).matches(None));
+        assert!(!pred("lang", FieldValue::Int(0), Operator::Gt).matches(Some(&fields)));
+
+        let years = |operator, values: &[i32]| Predicate {
+            field_name: "year".to_string(),
+            field_value: PredicateValue::Multiple(
+                values.iter().copied().map(FieldValue::Int).collect(),
+            ),
+            operator,
+        };
+        assert!(years(Operator::In, &[2019, 2020]).matches(Some(&fields)));
+        assert!(!years(Operator::In, &[2019, 2021]).matches(Some(&fields)));
+        assert!(!years(Operator::In, &[2020]).matches(None));
+        assert!(!years(Operator::NotIn, &[2019, 2020]).matches(Some(&fields)));
+        assert!(years(Operator::NotIn, &[2019, 2021]).matches(Some(&fields)));
+        assert!(years(Operator::NotIn, &[2020]).matches(None));
+
+        // Comparisons can't be negated, unlike `In` and `NotIn`
+        assert!(year(Operator::Gt, 2020).negated().is_none());
+        assert_eq!(
+            Operator::NotIn,
+            years(Operator::In, &[2020]).negated().unwrap().operator
+        );
+    }
+
+    #[test]
+    fn test_predicate_validation() {
+        let parse = |s: &str| serde_json::from_str::<Predicate>(s);
+        assert!(parse(r#"{"field_name": "year", "field_value": 2020, "operator": "Gte"}"#).is_ok());
+        assert!(
+            parse(r#"{"field_name": "year", "field_value": [2020, 2021], "operator": "In"}"#)
+                .is_ok()
+        );
+        assert!(
+            parse(r#"{"field_name": "lang", "field_value": ["en"], "operator": "NotIn"}"#).is_ok()
+        );
+        // Comparisons are only supported for int values
+        assert!(parse(r#"{"field_name": "lang", "field_value": "en", "operator": "Gt"}"#).is_err());
+        // `In` and `NotIn` require a list of values and the other
+        // operators a single value
+        assert!(parse(r#"{"field_name": "year", "field_value": 2020, "operator": "In"}"#).is_err());
+        assert!(
+            parse(r#"{"field_name": "year", "field_value": [2020], "operator": "Equal"}"#).is_err()
+        );
+        assert!(
+            parse(r#"{"field_name": "year", "field_value": [2020], "operator": "Lt"}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_comparison_filter_encoded_dimensions() {
+        let year_values: HashSet<FieldValue> = (2018..=2022).map(FieldValue::Int).collect();
+        let year = MetadataField::new("year".to_owned(), year_values).unwrap();
+        let lang_values: HashSet<FieldValue> = vec!["de", "en"]
+            .into_iter()
+            .map(|x| FieldValue::String(String::from(x)))
+            .collect();
+        let lang = MetadataField::new("lang".to_owned(), lang_values).unwrap();
+        let schema = MetadataSchema::new(vec![year, lang], vec![]).unwrap();
+
+        // Value ids of 2018..=2022 are 1..=5, encoded in 3 dims
+        let filter = Filter::Is(pred("year", FieldValue::Int(2020), Operator::Gte));
+        assert_eq!(
+            vec![
+                vec![0, 1, 1, 0, 0], // 2020
+                vec![1, 0, 0, 0, 0], // 2021
+                vec![1, 0, 1, 0, 0], // 2022
+            ],
+            filter_encoded_dimensions(&schema, &filter).unwrap()
+        );
+
+        let filter = Filter::Is(pred("year", FieldValue::Int(2018), Operator::Lt));
+        assert!(matches!(
+            filter_encoded_dimensions(&schema, &filter),
+            Err(Error::UnsupportedFilter(_))
+        ));
+
+        let filter = Filter::Is(Predicate {
+            field_name: "lang".to_string(),
+            field_value: PredicateValue::Multiple(vec![
+                FieldValue::String("de".to_string()),
+                FieldValue::String("en".to_string()),
+            ]),
+            operator: Operator::In,
+        });
+        assert_eq!(
+            vec![vec![0, 0, 0, 0, 1], vec![0, 0, 0, 1, 0]],
+            filter_encoded_dimensions(&schema, &filter).unwrap()
+        );
+
+        // `NotIn` with multiple values and negated comparisons are
+        // applied to the search results instead
+        let filter = Filter::Is(Predicate {
+            field_name: "lang".to_string(),
+            field_value: PredicateValue::Multiple(vec![
+                FieldValue::String("de".to_string()),
+                FieldValue::String("en".to_string()),
+            ]),
+            operator: Operator::NotIn,
+        });
+        assert!(matches!(
+            filter_encoded_dimensions(&schema, &filter),
+            Err(Error::UnsupportedFilter(_))
+        ));
+        let filter = Filter::Not(Box::new(Filter::Is(pred(
+            "year",
+            FieldValue::Int(2020),
+            Operator::Gte,
+        ))));
+        assert!(matches!(
+            filter_encoded_dimensions(&schema, &filter),
+            Err(Error::UnsupportedFilter(_))
+        ));
+
+        // Comparisons on string fields
+        let filter = Filter::Is(pred("lang", FieldValue::Int(1), Operator::Gt));
+        assert!(matches!(
+            filter_encoded_dimensions(&schema, &filter),
+            Err(Error::InvalidFieldValue(_))
+        ));
+    }
 }
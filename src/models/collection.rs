@@ -1,8 +1,13 @@
 use super::collection_transaction::CollectionTransaction;
 use super::common::WaCustomError;
+use super::external_ids::{ExternalId, ExternalIds};
+use super::multi_vectors::MultiVectors;
 use super::paths::get_data_path;
-use super::types::MetaDb;
-use super::versioning::{Hash, VersionControl};
+use super::tombstones::Tombstones;
+use super::types::{MetaDb, VectorId};
+use super::vector_ids::VectorIds;
+use super::vector_metadata::VectorMetadata;
+use super::versioning::{Hash, VersionControl, VersionRef, VersionSnapshot};
 use crate::indexes::hnsw::HNSWIndex;
 use crate::indexes::inverted::InvertedIndex;
 use crate::indexes::tf_idf::TFIDFIndex;
@@ -11,6 +16,7 @@ use lmdb::{Database, Environment, Transaction, WriteFlags};
 use serde::{Deserialize, Serialize};
 use serde_cbor::to_vec;
 use siphasher::sip::SipHasher24;
+use std::collections::BTreeMap;
 use std::fs::create_dir_all;
 use std::sync::RwLock;
 use std::{fs, hash::Hasher, path::Path, sync::Arc};
@@ -19,6 +25,58 @@ use std::{fs, hash::Hasher, path::Path, sync::Arc};
 pub struct DenseVectorOptions {
     pub enabled: bool,
     pub dimension: usize,
+    /// Additional dense vector spaces, each indexed by its own named
+    /// dense index
+    #[serde(default)]
+    pub named_vectors: Vec<NamedDenseVectorOptions>,
+}
+
+impl DenseVectorOptions {
+    pub fn get_named_vector(&self, name: &str) -> Option<&NamedDenseVectorOptions> {
+        self.named_vectors
+            .iter()
+            .find(|options| options.name == name)
+    }
+
+    pub fn is_multi_vector(&self, name: &str) -> bool {
+        self.get_named_vector(name)
+            .is_some_and(|options| options.multi_vector)
+    }
+
+    /// Returns the dimension of the default vector space (`name` =
+    /// `None`) or of the named one, if it exists
+    pub fn get_dimension(&self, name: Option<&str>) -> Option<usize> {
+        match name {
+            Some(name) => self.get_named_vector(name).map(|options| options.dimension),
+            None => Some(self.dimension),
+        }
+    }
+
+    /// Checks that the named vector spaces have unique, non-empty
+    /// names which are safe to use as directory names
+    fn validate(&self) -> bool {
+        self.named_vectors.iter().enumerate().all(|(i, options)| {
+            !options.name.is_empty()
+                && options
+                    .name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+                && options.dimension > 0
+                && self.named_vectors[..i]
+                    .iter()
+                    .all(|other| other.name != options.name)
+        })
+    }
+}
+
+#[derive(Deserialize, Clone, Serialize, Debug)]
+pub struct NamedDenseVectorOptions {
+    pub name: String,
+    pub dimension: usize,
+    /// Whether every vector id holds multiple vectors (e.g. token or
+    /// chunk embeddings of a document) in this vector space
+    #[serde(default)]
+    pub multi_vector: bool,
 }
 
 #[derive(Deserialize, Clone, Serialize, Debug)]
@@ -55,8 +113,13 @@ pub struct Collection {
     pub current_open_transaction: RwLock<Option<CollectionTransaction>>,
     pub vcs: VersionControl,
     pub hnsw_index: RwLock<Option<Arc<HNSWIndex>>>,
+    pub named_hnsw_indexes: RwLock<BTreeMap<String, Arc<HNSWIndex>>>,
     pub inverted_index: RwLock<Option<Arc<InvertedIndex>>>,
     pub tf_idf_index: RwLock<Option<Arc<TFIDFIndex>>>,
+    pub tombstones: Tombstones,
+    pub external_ids: ExternalIds,
+    pub multi_vectors: MultiVectors,
+    pub vector_metadata: VectorMetadata,
 }
 
 impl Collection {
@@ -73,10 +136,11 @@ impl Collection {
         current_version: Hash,
         vcs: VersionControl,
     ) -> Result<Self, WaCustomError> {
-        if name.is_empty() {
+        if name.is_empty() || !dense_vector_options.validate() {
             return Err(WaCustomError::InvalidParams);
         }
 
+        let vector_metadata = VectorMetadata::new(lmdb.clone());
         let collection = Collection {
             meta: CollectionMetadata {
                 name,
@@ -92,12 +156,19 @@ impl Collection {
             current_open_transaction: RwLock::new(None),
             vcs,
             hnsw_index: RwLock::new(None),
+            named_hnsw_indexes: RwLock::new(BTreeMap::new()),
             inverted_index: RwLock::new(None),
             tf_idf_index: RwLock::new(None),
+            tombstones: Tombstones::new(),
+            external_ids: ExternalIds::new(),
+            multi_vectors: MultiVectors::new(),
+            vector_metadata,
         };
 
         let collection_path = collection.get_path();
         fs::create_dir_all(&collection_path).map_err(|e| WaCustomError::FsError(e.to_string()))?;
+        VectorIds::mark_recorded(&collection.lmdb)?;
+        VectorMetadata::mark_recorded(&collection.lmdb)?;
 
         Ok(collection)
     }
@@ -165,6 +236,53 @@ impl Collection {
         self.hnsw_index.read().unwrap().clone()
     }
 
+    pub fn get_named_hnsw_index(&self, vector_name: &str) -> Option<Arc<HNSWIndex>> {
+        self.named_hnsw_indexes
+            .read()
+            .unwrap()
+            .get(vector_name)
+            .cloned()
+    }
+
+    /// Returns the default dense index (`vector_name` = `None`) or the
+    /// named dense index
+    pub fn get_dense_index(&self, vector_name: Option<&str>) -> Option<Arc<HNSWIndex>> {
+        match vector_name {
+            Some(vector_name) => self.get_named_hnsw_index(vector_name),
+            None => self.get_hnsw_index(),
+        }
+    }
+
+    /// Returns the dense indexes of the collection in which the
+    /// vectors are indexed by their ids, starting with the default one
+    ///
+    /// The indexes of the multi-vector spaces are not included, see
+    /// [`Collection::get_multi_vector_indexes`].
+    pub fn get_dense_indexes(&self) -> Vec<Arc<HNSWIndex>> {
+        let mut indexes: Vec<_> = self.get_hnsw_index().into_iter().collect();
+        indexes.extend(
+            self.named_hnsw_indexes
+                .read()
+                .unwrap()
+                .iter()
+                .filter(|(name, _)| !self.meta.dense_vector.is_multi_vector(name))
+                .map(|(_, index)| index.clone()),
+        );
+        indexes
+    }
+
+    /// Returns the dense indexes of the multi-vector spaces of the
+    /// collection, along with the names of the vector spaces
+    pub fn get_multi_vector_indexes(&self) -> Vec<(String, Arc<HNSWIndex>)> {
+        self.named_hnsw_indexes
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(name, _)| self.meta.dense_vector.is_multi_vector(name))
+            .map(|(name, index)| (name.clone(), index.clone()))
+            .collect()
+    }
+
     pub fn get_inverted_index(&self) -> Option<Arc<InvertedIndex>> {
         self.inverted_index.read().unwrap().clone()
     }
@@ -172,4 +290,89 @@ impl Collection {
     pub fn get_tf_idf_index(&self) -> Option<Arc<TFIDFIndex>> {
         self.tf_idf_index.read().unwrap().clone()
     }
+
+    /// Returns the internal id of the vector with the user provided
+    /// id, if any
+    pub fn get_internal_id(&self, id: &ExternalId) -> Result<Option<VectorId>, WaCustomError> {
+        self.external_ids.get_internal(&self.lmdb, id)
+    }
+
+    /// Returns the internal ids to be used for inserting the vectors
+    /// with the user provided ids
+    pub fn get_or_assign_internal_ids<'a>(
+        &self,
+        ids: impl IntoIterator<Item = &'a ExternalId> + Clone,
+    ) -> Result<Vec<VectorId>, WaCustomError> {
+        self.external_ids.get_or_assign_internal(&self.lmdb, ids)
+    }
+
+    /// Returns the user provided id of the vector with the internal id
+    pub fn get_external_id(&self, id: &VectorId) -> Result<ExternalId, WaCustomError> {
+        self.external_ids.get_external(&self.lmdb, id)
+    }
+
+    /// Returns the snapshot of the collection at the `target` version
+    /// of the main branch, for reading the data as it was when the
+    /// version was committed
+    ///
+    /// Returns None if there's no such committed version.
+    pub fn version_snapshot(
+        &self,
+        target: VersionRef,
+    ) -> Result<Option<VersionSnapshot>, WaCustomError> {
+        let current_version = *self.current_version.read().unwrap();
+        let branch_versions = self
+            .vcs
+            .get_branch_versions("main")
+            .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
+        Ok(VersionSnapshot::new(
+            &branch_versions,
+            current_version,
+            target,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dense_vector_options() {
+        // Named vectors are optional, for compatibility with the
+        // metadata of existing collections
+        let options: DenseVectorOptions =
+            serde_json::from_str(r#"{"enabled": true, "dimension": 4}"#).unwrap();
+        assert!(options.named_vectors.is_empty());
+        assert_eq!(options.get_dimension(None), Some(4));
+        assert_eq!(options.get_dimension(Some("title")), None);
+        assert!(options.validate());
+
+        let options: DenseVectorOptions = serde_json::from_str(
+            r#"{"enabled": true, "dimension": 4, "named_vectors": [
+                {"name": "title", "dimension": 2},
+                {"name": "body_v2", "dimension": 8, "multi_vector": true}
+            ]}"#,
+        )
+        .unwrap();
+        assert_eq!(options.get_dimension(Some("title")), Some(2));
+        assert_eq!(options.get_dimension(Some("body_v2")), Some(8));
+        assert!(!options.is_multi_vector("title"));
+        assert!(options.is_multi_vector("body_v2"));
+        assert!(options.validate());
+
+        for named_vectors in [
+            r#"[{"name": "title", "dimension": 2}, {"name": "title", "dimension": 3}]"#,
+            r#"[{"name": "", "dimension": 2}]"#,
+            r#"[{"name": "../title", "dimension": 2}]"#,
+            r#"[{"name": "title", "dimension": 0}]"#,
+        ] {
+            let options: DenseVectorOptions = serde_json::from_str(&format!(
+                r#"{{"enabled": true, "dimension": 4, "named_vectors": {}}}"#,
+                named_vectors
+            ))
+            .unwrap();
+            assert!(!options.validate());
+        }
+    }
 }
@@ -1,69 +1,106 @@
 use std::{
+    collections::{BTreeMap, HashMap, HashSet},
     sync::{
-        atomic::{AtomicU32, Ordering},
-        mpsc, Arc,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        mpsc, Arc, Mutex,
     },
     thread,
 };
 
+use dashmap::DashMap;
 use lmdb::{Transaction, WriteFlags};
 
 use crate::{
     config_loader::Config,
-    indexes::{hnsw::types::RawDenseVectorEmbedding, IndexOps},
-    macros::key,
+    indexes::{
+        hnsw::{dense_embedding_key, types::RawDenseVectorEmbedding, HNSWIndex},
+        IndexOps,
+    },
+    metadata::MetadataFields,
 };
 
 use super::{
     collection::Collection,
     common::{TSHashTable, WaCustomError},
     embedding_persist::{write_dense_embedding, EmbeddingOffset},
+    multi_vectors::MultiVectors,
     prob_node::{ProbNode, SharedNode},
+    tombstones::{Tombstone, Tombstones},
     types::VectorId,
+    vector_counts::VectorCounts,
+    vector_ids::{PresenceChange, VectorIds},
     versioning::{Hash, Version},
 };
 
 pub struct CollectionTransaction {
     pub id: Hash,
     pub version_number: u16,
+    dense_index_transaction: DenseIndexTransaction,
+    named_dense_index_transactions: BTreeMap<String, DenseIndexTransaction>,
+    tombstones: Mutex<Vec<(VectorId, Tombstone)>>,
+    // (vector name, document id, vector ids) of the documents inserted
+    // in the multi-vector spaces
+    multi_vector_documents: Mutex<Vec<(String, VectorId, Vec<VectorId>)>>,
+    vector_metadata: Mutex<Vec<(VectorId, MetadataFields)>>,
+    upserted_ids: Mutex<HashSet<VectorId>>,
+    presence_changes: Mutex<HashMap<VectorId, PresenceChange>>,
+}
+
+/// State of a transaction that's specific to one dense index of the
+/// collection i.e. the node offsets in the index files of the version
+/// and the serializer of the raw embeddings
+pub struct DenseIndexTransaction {
     pub lazy_item_versions_table: Arc<TSHashTable<(VectorId, u16, u8), SharedNode>>,
     raw_dense_embedding_serializer_thread_handle:
         Option<thread::JoinHandle<Result<(), WaCustomError>>>,
     raw_dense_embedding_channel: Option<mpsc::Sender<RawDenseVectorEmbedding>>,
+    // Offsets of the level 0 nodes of the vectors indexed in the
+    // transaction, persisted along with the offsets of their raw
+    // embeddings
+    level_0_node_offsets: Arc<DashMap<VectorId, u32>>,
+    // Set when the transaction is aborted, for the offsets of the raw
+    // embeddings to not be persisted
+    discarded: Arc<AtomicBool>,
     level_0_node_offset_counter: AtomicU32,
     node_offset_counter: AtomicU32,
     node_size: u32,
     level_0_node_size: u32,
 }
 
-impl CollectionTransaction {
-    pub fn new(collection: Arc<Collection>) -> Result<Self, WaCustomError> {
-        let branch_info = collection.vcs.get_branch_info("main")?.unwrap();
-        let version_number = *branch_info.get_current_version() + 1;
-        let id = collection
-            .vcs
-            .generate_hash("main", Version::from(version_number))?;
-
-        let level_0_node_offset_counter = AtomicU32::new(0);
-        let node_offset_counter = AtomicU32::new(0);
-
+impl DenseIndexTransaction {
+    fn new(
+        collection: &Arc<Collection>,
+        hnsw_index: Option<&HNSWIndex>,
+        id: Hash,
+        version_number: u16,
+    ) -> Result<Self, WaCustomError> {
+        let level_0_node_offsets = Arc::new(DashMap::new());
+        let discarded = Arc::new(AtomicBool::new(false));
         let (
             node_size,
             level_0_node_size,
             raw_dense_embedding_serializer_thread_handle,
             raw_dense_embedding_channel,
-        ) = if let Some(hnsw_index) = &*collection.hnsw_index.read().unwrap() {
-            let (raw_embedding_channel, rx) = mpsc::channel();
+        ) = if let Some(hnsw_index) = hnsw_index {
+            let (raw_embedding_channel, rx) = mpsc::channel::<RawDenseVectorEmbedding>();
             let raw_embedding_serializer_thread_handle = {
                 let bufman = hnsw_index.vec_raw_manager.get(id)?;
                 let collection = collection.clone();
+                let vector_name = hnsw_index.vector_name.clone();
+                let level_0_node_offsets = level_0_node_offsets.clone();
+                let discarded = discarded.clone();
 
                 thread::spawn(move || {
                     let mut offsets = Vec::new();
                     for raw_emb in rx {
                         let offset = write_dense_embedding(&bufman, &raw_emb)?;
-                        let embedding_key = key!(e:raw_emb.hash_vec);
-                        offsets.push((embedding_key, offset));
+                        let embedding_key =
+                            dense_embedding_key(vector_name.as_deref(), &raw_emb.hash_vec);
+                        offsets.push((raw_emb.hash_vec, embedding_key, offset));
+                    }
+
+                    if discarded.load(Ordering::Acquire) {
+                        return Ok(());
                     }
 
                     let env = collection.lmdb.env.clone();
@@ -72,10 +109,13 @@ impl CollectionTransaction {
                     let mut txn = env.begin_rw_txn().map_err(|e| {
                         WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
                     })?;
-                    for (key, offset) in offsets {
+                    for (vector_id, key, offset) in offsets {
                         let offset = EmbeddingOffset {
                             version: id,
                             offset,
+                            level_0_node: level_0_node_offsets
+                                .get(&vector_id)
+                                .map(|node_offset| (version_number, *node_offset)),
                         };
                         let offset_serialized = offset.serialize();
 
@@ -105,15 +145,15 @@ impl CollectionTransaction {
         };
 
         Ok(Self {
-            id,
-            version_number,
-            level_0_node_offset_counter,
-            node_offset_counter,
-            node_size,
-            level_0_node_size,
             lazy_item_versions_table: Arc::new(TSHashTable::new(16)),
             raw_dense_embedding_serializer_thread_handle,
             raw_dense_embedding_channel,
+            level_0_node_offsets,
+            discarded,
+            level_0_node_offset_counter: AtomicU32::new(0),
+            node_offset_counter: AtomicU32::new(0),
+            node_size,
+            level_0_node_size,
         })
     }
 
@@ -123,31 +163,254 @@ impl CollectionTransaction {
         }
     }
 
-    pub fn pre_commit(self, collection: &Collection, config: &Config) -> Result<(), WaCustomError> {
-        if let Some(hnsw_index) = &*collection.hnsw_index.read().unwrap() {
-            hnsw_index.pre_commit_transaction(collection, &self, config)?;
-        }
-        if let Some(inverted_index) = &*collection.inverted_index.read().unwrap() {
-            inverted_index.pre_commit_transaction(collection, &self, config)?;
-        }
-        if let Some(tf_idf_index) = &*collection.tf_idf_index.read().unwrap() {
-            tf_idf_index.pre_commit_transaction(collection, &self, config)?;
-        }
+    pub fn get_new_node_offset(&self) -> u32 {
+        self.node_offset_counter
+            .fetch_add(self.node_size, Ordering::Relaxed)
+    }
+
+    pub fn get_new_level_0_node_offset(&self) -> u32 {
+        self.level_0_node_offset_counter
+            .fetch_add(self.level_0_node_size, Ordering::Relaxed)
+    }
+
+    /// Records the offset of the level 0 node created for the vector in
+    /// the transaction, so that the node can be located by the vector
+    /// id. Only the first node is recorded for vectors with several
+    /// (metadata replica) nodes.
+    pub fn record_level_0_node_offset(&self, vector_id: VectorId, offset: u32) {
+        self.level_0_node_offsets.entry(vector_id).or_insert(offset);
+    }
+
+    /// Waits for the raw embeddings posted in the transaction to be
+    /// written, without persisting their offsets
+    fn discard(self) -> Result<(), WaCustomError> {
+        self.discarded.store(true, Ordering::Release);
+        self.finish()
+    }
+
+    /// Waits for all the raw embeddings posted in the transaction to be
+    /// written
+    fn finish(self) -> Result<(), WaCustomError> {
         drop(self.raw_dense_embedding_channel);
         if let Some(handle) = self.raw_dense_embedding_serializer_thread_handle {
             handle.join().unwrap()?;
         }
+        Ok(())
+    }
+}
+
+impl CollectionTransaction {
+    pub fn new(collection: Arc<Collection>) -> Result<Self, WaCustomError> {
+        let branch_info = collection.vcs.get_branch_info("main")?.unwrap();
+        let version_number = *branch_info.get_current_version() + 1;
+        let id = collection
+            .vcs
+            .generate_hash("main", Version::from(version_number))?;
+
+        collection.tombstones.begin_version(&collection.lmdb, id)?;
+
+        let dense_index_transaction = DenseIndexTransaction::new(
+            &collection,
+            collection.get_hnsw_index().as_deref(),
+            id,
+            version_number,
+        )?;
+        let named_dense_index_transactions = collection
+            .named_hnsw_indexes
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(vector_name, hnsw_index)| {
+                Ok((
+                    vector_name.clone(),
+                    DenseIndexTransaction::new(&collection, Some(hnsw_index), id, version_number)?,
+                ))
+            })
+            .collect::<Result<_, WaCustomError>>()?;
+
+        Ok(Self {
+            id,
+            version_number,
+            dense_index_transaction,
+            named_dense_index_transactions,
+            tombstones: Mutex::new(Vec::new()),
+            multi_vector_documents: Mutex::new(Vec::new()),
+            vector_metadata: Mutex::new(Vec::new()),
+            upserted_ids: Mutex::new(HashSet::new()),
+            presence_changes: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Returns the state of the transaction for the default dense index
+    /// (`vector_name` = `None`) or for the named dense index
+    ///
+    /// Returns `None` for named dense indexes created after the
+    /// transaction was started.
+    pub fn get_dense_index_transaction(
+        &self,
+        vector_name: Option<&str>,
+    ) -> Option<&DenseIndexTransaction> {
+        match vector_name {
+            Some(vector_name) => self.named_dense_index_transactions.get(vector_name),
+            None => Some(&self.dense_index_transaction),
+        }
+    }
+
+    /// Records a tombstone for the vector `id` in this transaction
+    ///
+    /// It only takes effect once the transaction is committed.
+    pub fn add_tombstone(&self, id: VectorId, tombstone: Tombstone) {
+        self.tombstones.lock().unwrap().push((id, tombstone));
+    }
+
+    /// Records the metadata fields of the vector `id` in this
+    /// transaction
+    ///
+    /// Same as tombstones, it only takes effect once the transaction is
+    /// committed.
+    pub fn add_vector_metadata(&self, id: VectorId, fields: MetadataFields) {
+        self.vector_metadata.lock().unwrap().push((id, fields));
+    }
+
+    /// Returns the metadata fields of the vector `id` recorded last in
+    /// this transaction, if any
+    pub fn get_vector_metadata(&self, id: &VectorId) -> Option<MetadataFields> {
+        self.vector_metadata
+            .lock()
+            .unwrap()
+            .iter()
+            .rev()
+            .find(|(vector_id, _)| vector_id == id)
+            .map(|(_, fields)| fields.clone())
+    }
+
+    /// Records the ids of the vectors inserted in this transaction
+    ///
+    /// If any of them has been inserted in it before, its id is
+    /// returned and none of the ids are recorded.
+    pub fn record_upserted_ids(&self, ids: &[VectorId]) -> Option<VectorId> {
+        let mut upserted_ids = self.upserted_ids.lock().unwrap();
+        if let Some(id) = ids.iter().find(|id| upserted_ids.contains(id)) {
+            return Some(id.clone());
+        }
+        upserted_ids.extend(ids.iter().cloned());
+        None
+    }
+
+    /// Records a change to the indexes in which the vector `id` has
+    /// live data, from which the vector counts of the version are
+    /// derived when the transaction is committed
+    pub fn record_presence_change(&self, id: VectorId, change: PresenceChange) {
+        let mut presence_changes = self.presence_changes.lock().unwrap();
+        let change = match presence_changes.get(&id) {
+            Some(earlier) => earlier.then(change),
+            None => change,
+        };
+        presence_changes.insert(id, change);
+    }
+
+    /// Returns the change to the presence of the vector `id` recorded
+    /// in this transaction so far, if any
+    pub fn get_presence_change(&self, id: &VectorId) -> Option<PresenceChange> {
+        self.presence_changes.lock().unwrap().get(id).copied()
+    }
+
+    /// Records the vectors of a document inserted in a multi-vector
+    /// space in this transaction
+    ///
+    /// The document replaces the earlier version of it (if any) when the
+    /// transaction is committed.
+    pub fn add_multi_vector_document(
+        &self,
+        vector_name: String,
+        document_id: VectorId,
+        vector_ids: Vec<VectorId>,
+    ) {
+        self.multi_vector_documents
+            .lock()
+            .unwrap()
+            .push((vector_name, document_id, vector_ids));
+    }
+
+    /// Writes all the data of the transaction, which is based on the
+    /// version `base_version`, for its version to be made current
+    pub fn pre_commit(
+        self,
+        collection: &Collection,
+        base_version: Hash,
+        config: &Config,
+    ) -> Result<(), WaCustomError> {
+        self.flush_indexes(collection, config)?;
+        let tombstones = self.tombstones.into_inner().unwrap();
+        let vector_metadata = self.vector_metadata.into_inner().unwrap();
+        Tombstones::persist(&collection.lmdb, self.id, self.version_number, &tombstones)?;
+        self.dense_index_transaction.finish()?;
+        for dense_index_transaction in self.named_dense_index_transactions.into_values() {
+            dense_index_transaction.finish()?;
+        }
+        // Written once the embeddings of the documents' vectors are
+        // available
+        MultiVectors::persist(
+            &collection.lmdb,
+            self.id,
+            &self.multi_vector_documents.into_inner().unwrap(),
+        )?;
+
+        collection
+            .vector_metadata
+            .persist(self.id, self.version_number, &vector_metadata)?;
+        collection
+            .tombstones
+            .commit_version(&collection.lmdb, self.id, &tombstones)?;
+        let base_counts = VectorCounts::load(&collection.lmdb, base_version)?.unwrap_or_default();
+        VectorIds::persist(
+            &collection.lmdb,
+            self.presence_changes.into_inner().unwrap(),
+            base_counts,
+        )?
+        .persist(&collection.lmdb, self.id)?;
 
         Ok(())
     }
 
-    pub fn get_new_node_offset(&self) -> u32 {
-        self.node_offset_counter
-            .fetch_add(self.node_size, Ordering::Relaxed)
+    /// Aborts the transaction
+    ///
+    /// The data indexed in it stays in the index files, but as its
+    /// version is recorded as aborted and never committed, none of it
+    /// is live. The tombstones,
+    /// metadata and multi-vector documents recorded in the transaction
+    /// are discarded, and so are the offsets of the raw dense
+    /// embeddings, so that the ones of the earlier versions are kept.
+    pub fn abort(self, collection: &Collection, config: &Config) -> Result<(), WaCustomError> {
+        self.flush_indexes(collection, config)?;
+        collection
+            .tombstones
+            .abort_version(&collection.lmdb, self.id)?;
+        self.dense_index_transaction.discard()?;
+        for dense_index_transaction in self.named_dense_index_transactions.into_values() {
+            dense_index_transaction.discard()?;
+        }
+        Ok(())
     }
 
-    pub fn get_new_level_0_node_offset(&self) -> u32 {
-        self.level_0_node_offset_counter
-            .fetch_add(self.level_0_node_size, Ordering::Relaxed)
+    /// Indexes the data still being sampled and flushes the indexes
+    fn flush_indexes(&self, collection: &Collection, config: &Config) -> Result<(), WaCustomError> {
+        if let Some(hnsw_index) = &*collection.hnsw_index.read().unwrap() {
+            hnsw_index.pre_commit_transaction(collection, self, config)?;
+        }
+        if let Some(inverted_index) = &*collection.inverted_index.read().unwrap() {
+            inverted_index.pre_commit_transaction(collection, self, config)?;
+        }
+        if let Some(tf_idf_index) = &*collection.tf_idf_index.read().unwrap() {
+            tf_idf_index.pre_commit_transaction(collection, self, config)?;
+        }
+        // Named dense indexes created after the transaction was started
+        // can't have any data indexed in it
+        for vector_name in self.named_dense_index_transactions.keys() {
+            if let Some(hnsw_index) = collection.get_named_hnsw_index(vector_name) {
+                hnsw_index.pre_commit_transaction(collection, self, config)?;
+            }
+        }
+        Ok(())
     }
 }
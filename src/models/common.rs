@@ -384,7 +384,7 @@ pub fn get_max_insert_level(x: f64, levels: &[(f64, u8)]) -> u8 {
 
 pub fn remove_duplicates_and_filter(
     vec: Vec<(SharedNode, MetricResult)>,
-    k: Option<usize>,
+    max_results: Option<usize>,
     cache: &HNSWIndexCache,
 ) -> Vec<(VectorId, VectorId, MetricResult)> {
     let mut seen = HashSet::new();
@@ -404,8 +404,8 @@ pub fn remove_duplicates_and_filter(
         .collect::<Vec<_>>();
 
     collected.sort_unstable_by(|(_, _, a), (_, _, b)| b.cmp(a));
-    if let Some(k) = k {
-        collected.truncate(5 * k);
+    if let Some(max_results) = max_results {
+        collected.truncate(max_results);
     }
     collected
 }
@@ -0,0 +1,97 @@
+use super::types::DistanceMetric;
+
+/// Selects `k` of the candidates using Maximal Marginal Relevance
+/// (MMR), returning their positions in the order of selection
+///
+/// Candidates are picked one at a time, maximizing
+/// `lambda * relevance - (1 - lambda) * redundancy`, where redundancy
+/// is the max. cosine similarity of the candidate's vector to the
+/// vectors of the candidates picked before it. Hence `lambda` = 1
+/// selects purely by relevance, while lower values favor candidates
+/// that are dissimilar to the ones already selected.
+///
+/// `relevance` holds the relevance of every candidate, which is to be
+/// on a scale comparable to cosine similarity (e.g. the min-max
+/// normalized scores of the candidates).
+pub fn mmr_select(relevance: &[f32], vectors: &[&[f32]], lambda: f32, k: usize) -> Vec<usize> {
+    debug_assert_eq!(relevance.len(), vectors.len());
+    let k = k.min(relevance.len());
+    let mut selected = Vec::with_capacity(k);
+    // Max. similarity of every candidate to the selected ones, None
+    // once the candidate is selected
+    let mut redundancy: Vec<Option<f32>> = vec![Some(f32::NEG_INFINITY); relevance.len()];
+
+    while selected.len() < k {
+        let mut best: Option<(usize, f32)> = None;
+        for (position, max_similarity) in redundancy.iter().enumerate() {
+            let Some(max_similarity) = max_similarity else {
+                continue;
+            };
+            let penalty = if selected.is_empty() {
+                0.0
+            } else {
+                *max_similarity
+            };
+            let score = lambda * relevance[position] - (1.0 - lambda) * penalty;
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((position, score));
+            }
+        }
+        let Some((picked, _)) = best else {
+            break;
+        };
+        selected.push(picked);
+        redundancy[picked] = None;
+        for (position, max_similarity) in redundancy.iter_mut().enumerate() {
+            if let Some(max_similarity) = max_similarity {
+                *max_similarity =
+                    max_similarity.max(cosine_similarity(vectors[position], vectors[picked]));
+            }
+        }
+    }
+    selected
+}
+
+/// Cosine similarity of the vectors, or 0 if it's undefined (e.g. for
+/// zero vectors or vectors of different lengths)
+pub fn cosine_similarity(x: &[f32], y: &[f32]) -> f32 {
+    if x.len() != y.len() {
+        return 0.0;
+    }
+    DistanceMetric::Cosine
+        .calculate_raw(x, y)
+        .map(|result| result.get_value())
+        .filter(|similarity| similarity.is_finite())
+        .unwrap_or(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mmr_select() {
+        let query = [1.0, 0.0];
+        let vectors: Vec<&[f32]> = vec![&[1.0, 0.1], &[1.0, 0.11], &[0.7, -0.7], &[0.0, 1.0]];
+        let relevance: Vec<f32> = vectors
+            .iter()
+            .map(|vector| cosine_similarity(&query, vector))
+            .collect();
+
+        // Selects by relevance alone
+        assert_eq!(vec![0, 1, 2], mmr_select(&relevance, &vectors, 1.0, 3));
+
+        // The near-duplicate of the first pick is skipped
+        assert_eq!(vec![0, 2], mmr_select(&relevance, &vectors, 0.5, 2));
+
+        assert_eq!(4, mmr_select(&relevance, &vectors, 0.5, 10).len());
+        assert!(mmr_select(&[], &[], 0.5, 3).is_empty());
+    }
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert_eq!(0.0, cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]));
+        assert_eq!(0.0, cosine_similarity(&[], &[1.0, 0.0]));
+    }
+}
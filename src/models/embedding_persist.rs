@@ -7,29 +7,43 @@ use super::{buffered_io::BufferManager, common::WaCustomError, versioning::Hash}
 pub struct EmbeddingOffset {
     pub version: Hash,
     pub offset: u32,
+    /// Version number and offset of the vector's level 0 node in the
+    /// index files of `version`. Not recorded by older releases.
+    pub level_0_node: Option<(u16, u32)>,
 }
 
 impl EmbeddingOffset {
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result = Vec::with_capacity(8);
+        let mut result = Vec::with_capacity(14);
 
         result.extend_from_slice(&self.version.to_le_bytes());
         result.extend_from_slice(&self.offset.to_le_bytes());
+        if let Some((version_number, node_offset)) = self.level_0_node {
+            result.extend_from_slice(&version_number.to_le_bytes());
+            result.extend_from_slice(&node_offset.to_le_bytes());
+        }
 
         result
     }
 
     pub fn deserialize(bytes: &[u8]) -> Result<Self, &'static str> {
-        if bytes.len() != 8 {
-            return Err("Input must be exactly 8 bytes");
+        if bytes.len() != 8 && bytes.len() != 14 {
+            return Err("Input must be exactly 8 or 14 bytes");
         }
 
         let version = u32::from_le_bytes(bytes[0..4].try_into().unwrap());
         let offset = u32::from_le_bytes(bytes[4..8].try_into().unwrap());
+        let level_0_node = (bytes.len() == 14).then(|| {
+            (
+                u16::from_le_bytes(bytes[8..10].try_into().unwrap()),
+                u32::from_le_bytes(bytes[10..14].try_into().unwrap()),
+            )
+        });
 
         Ok(Self {
             version: Hash::from(version),
             offset,
+            level_0_node,
         })
     }
 }
@@ -91,10 +105,10 @@ pub fn read_embedding(
 
 #[cfg(test)]
 mod tests {
-    use super::{read_embedding, write_dense_embedding, RawDenseVectorEmbedding};
+    use super::{read_embedding, write_dense_embedding, EmbeddingOffset, RawDenseVectorEmbedding};
     use crate::{
         metadata,
-        models::{buffered_io::BufferManager, types::VectorId},
+        models::{buffered_io::BufferManager, types::VectorId, versioning::Hash},
     };
     use rand::{distributions::Uniform, rngs::ThreadRng, thread_rng, Rng};
     use std::{collections::HashMap, sync::Arc};
@@ -169,4 +183,20 @@ mod tests {
         let (deserialized, _) = read_embedding(bufman.clone(), offset).unwrap();
         assert_eq!(embedding, deserialized);
     }
+
+    #[test]
+    fn test_embedding_offset_serialization() {
+        for level_0_node in [None, Some((3, 4096))] {
+            let offset = EmbeddingOffset {
+                version: Hash::from(42),
+                offset: 1024,
+                level_0_node,
+            };
+            let deserialized = EmbeddingOffset::deserialize(&offset.serialize()).unwrap();
+            assert_eq!(offset.version, deserialized.version);
+            assert_eq!(offset.offset, deserialized.offset);
+            assert_eq!(offset.level_0_node, deserialized.level_0_node);
+        }
+        assert!(EmbeddingOffset::deserialize(&[0; 10]).is_err());
+    }
 }
@@ -0,0 +1,429 @@
+use std::{collections::HashSet, fmt, sync::Mutex};
+
+use lmdb::{Cursor, RoCursor, Transaction, WriteFlags};
+use lmdb_sys::MDB_SET_RANGE;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::macros::key;
+
+use super::{
+    common::WaCustomError,
+    types::{MetaDb, VectorId},
+};
+
+/// Upper bound (exclusive) of the internal ids
+///
+/// Internal ids are limited to 56 bits as the upper 8 bits of the
+/// node id are used for the metadata replicas (see
+/// `ProbNode::get_id`).
+pub const INTERNAL_IDS_END: u64 = 1 << 56;
+
+/// Upper bound (exclusive) of the ids that can be stored in the
+/// sparse index, which stores them as `u32` (see
+/// `InvertedIndex::insert`)
+const SPARSE_IDS_END: u64 = 1 << 32;
+
+/// Id of a vector as provided by the user
+///
+/// Numeric ids are used as is for the internal [`VectorId`], while
+/// string ids (e.g. UUIDs) are mapped to internal ids which are
+/// assigned on first use, see [`ExternalIds`]. Strings holding a
+/// number are treated as numeric ids, so that `"42"` and `42` refer
+/// to the same vector.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(untagged)]
+pub enum ExternalId {
+    Int(u64),
+    Str(String),
+}
+
+impl ExternalId {
+    /// Returns true if the numeric id can't be used as an internal id,
+    /// see [`INTERNAL_IDS_END`]
+    pub fn is_out_of_range(&self) -> bool {
+        matches!(self, Self::Int(id) if *id >= INTERNAL_IDS_END)
+    }
+}
+
+impl From<String> for ExternalId {
+    fn from(id: String) -> Self {
+        // Only the canonical representation of a number (i.e. without
+        // leading zeros or sign) is treated as a numeric id, so that
+        // converting the id back to string yields the original one
+        match id.parse::<u64>() {
+            Ok(num) if num.to_string() == id => Self::Int(num),
+            _ => Self::Str(id),
+        }
+    }
+}
+
+impl From<u64> for ExternalId {
+    fn from(id: u64) -> Self {
+        Self::Int(id)
+    }
+}
+
+impl fmt::Display for ExternalId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Int(id) => write!(f, "{}", id),
+            Self::Str(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ExternalId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum RawId {
+            Int(u64),
+            Str(String),
+        }
+
+        match RawId::deserialize(deserializer)? {
+            RawId::Int(id) => Ok(Self::Int(id)),
+            RawId::Str(id) if id.is_empty() => {
+                Err(serde::de::Error::custom("vector id must not be empty"))
+            }
+            RawId::Str(id) => Ok(Self::from(id)),
+        }
+    }
+}
+
+/// Persistent bidirectional mapping between string ids and the
+/// internal ids assigned to them
+///
+/// Both directions are stored in the collection's lmdb. Mappings are
+/// written as soon as an id is assigned (i.e. irrespective of whether
+/// the transaction inserting the vector gets committed), as an unused
+/// mapping is harmless.
+///
+/// Internal ids are assigned counting down from the top of the `u32`
+/// range, so that they can be stored in the sparse index, and then
+/// counting down from the top of the internal ids, see
+/// [`string_id_at`]. Ids already used by numeric ids are skipped, and
+/// numeric ids that haven't been used before the internal id is
+/// assigned to a string id can only be referred to by the string id
+/// from then on.
+pub struct ExternalIds {
+    // Position of the next internal id to be assigned, in the order
+    // they are assigned in. The lock also serializes the assignment of
+    // ids, so that the same string id doesn't end up being assigned
+    // two internal ids.
+    next_position: Mutex<u64>,
+}
+
+impl Default for ExternalIds {
+    fn default() -> Self {
+        Self {
+            next_position: Mutex::new(0),
+        }
+    }
+}
+
+impl ExternalIds {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the internal id for the external id, if one exists
+    pub fn get_internal(
+        &self,
+        lmdb: &MetaDb,
+        id: &ExternalId,
+    ) -> Result<Option<VectorId>, WaCustomError> {
+        match id {
+            // Numeric ids assigned to string ids can only be referred
+            // to by the string ids
+            ExternalId::Int(id) if self.is_string_id(lmdb, &VectorId(*id))? => Ok(None),
+            ExternalId::Int(id) => Ok(Some(VectorId(*id))),
+            ExternalId::Str(id) => read(lmdb, &key!(x:id)).map(|bytes| {
+                bytes.map(|bytes| VectorId(u64::from_le_bytes(bytes.try_into().unwrap())))
+            }),
+        }
+    }
+
+    /// Returns the internal ids for the external ids, assigning new
+    /// ones to the string ids that are seen for the first time
+    ///
+    /// The new mappings are written in a single lmdb transaction.
+    /// Callers are expected to reject the numeric ids that are assigned
+    /// to string ids (see [`Self::get_internal`]) before inserting
+    /// vectors with them.
+    pub fn get_or_assign_internal<'a>(
+        &self,
+        lmdb: &MetaDb,
+        ids: impl IntoIterator<Item = &'a ExternalId> + Clone,
+    ) -> Result<Vec<VectorId>, WaCustomError> {
+        let mut next_position = self.next_position.lock().unwrap();
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        let mut position = *next_position;
+        // Numeric ids of the batch, which are yet to be recorded
+        let numeric_ids: HashSet<_> = ids
+            .clone()
+            .into_iter()
+            .filter_map(|id| match id {
+                ExternalId::Int(id) => Some(*id),
+                ExternalId::Str(_) => None,
+            })
+            .collect();
+
+        let mut internal_ids = Vec::new();
+        for id in ids {
+            let str_id = match id {
+                ExternalId::Int(id) => {
+                    internal_ids.push(VectorId(*id));
+                    continue;
+                }
+                ExternalId::Str(id) => id,
+            };
+            // Looked up in the transaction, so that an id repeated
+            // in the batch maps to the id assigned to it earlier
+            match txn.get(*db, &key!(x:str_id)) {
+                Ok(bytes) => {
+                    internal_ids.push(VectorId(u64::from_le_bytes(bytes.try_into().unwrap())));
+                    continue;
+                }
+                Err(lmdb::Error::NotFound) => {}
+                Err(e) => {
+                    return Err(WaCustomError::DatabaseError(format!(
+                        "Failed to get data: {}",
+                        e
+                    )))
+                }
+            }
+            let internal_id = loop {
+                if position >= INTERNAL_IDS_END {
+                    return Err(WaCustomError::DatabaseError(
+                        "No more internal ids available for string ids".to_string(),
+                    ));
+                }
+                let internal_id = string_id_at(position);
+                position += 1;
+                if numeric_ids.contains(&internal_id.0) || is_reserved(&internal_id) {
+                    continue;
+                }
+                // Ids of the vectors inserted so far are recorded, see
+                // `VectorIds`
+                match txn.get(*db, &key!(l:internal_id)) {
+                    Ok(_) => continue,
+                    Err(lmdb::Error::NotFound) => break internal_id,
+                    Err(e) => {
+                        return Err(WaCustomError::DatabaseError(format!(
+                            "Failed to get data: {}",
+                            e
+                        )))
+                    }
+                }
+            };
+            txn.put(
+                *db,
+                &key!(x:str_id),
+                &internal_id.0.to_le_bytes(),
+                WriteFlags::empty(),
+            )
+            .and_then(|_| {
+                txn.put(
+                    *db,
+                    &key!(r:internal_id),
+                    &str_id.as_bytes(),
+                    WriteFlags::empty(),
+                )
+            })
+            .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+            internal_ids.push(internal_id);
+        }
+
+        if position == *next_position {
+            txn.abort();
+            return Ok(internal_ids);
+        }
+        txn.put(
+            *db,
+            &key!(m:next_string_id_position),
+            &position.to_le_bytes(),
+            WriteFlags::empty(),
+        )
+        .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        *next_position = position;
+        Ok(internal_ids)
+    }
+
+    /// Returns the external id for the internal id i.e. the original
+    /// string id if one was mapped to it, otherwise the numeric id
+    pub fn get_external(&self, lmdb: &MetaDb, id: &VectorId) -> Result<ExternalId, WaCustomError> {
+        // Fast path, avoiding the lmdb lookup for the ids that haven't
+        // been reached by the assignment of ids yet
+        if position_of(id) >= *self.next_position.lock().unwrap() {
+            return Ok(ExternalId::Int(id.0));
+        }
+        match read(lmdb, &key!(r:id))? {
+            Some(bytes) => String::from_utf8(bytes)
+                .map(ExternalId::Str)
+                .map_err(|e| WaCustomError::DeserializationError(e.to_string())),
+            None => Ok(ExternalId::Int(id.0)),
+        }
+    }
+
+    /// Returns true if the internal id has been assigned to a string id
+    fn is_string_id(&self, lmdb: &MetaDb, id: &VectorId) -> Result<bool, WaCustomError> {
+        if position_of(id) >= *self.next_position.lock().unwrap() {
+            return Ok(false);
+        }
+        Ok(read(lmdb, &key!(r:id))?.is_some())
+    }
+
+    /// Loads the state of the mapping from lmdb
+    ///
+    /// Only the counter needs to be loaded as the mappings are
+    /// looked up from lmdb directly.
+    pub fn load(lmdb: &MetaDb) -> Result<Self, WaCustomError> {
+        let next_position = match read(lmdb, &key!(m:next_string_id_position))? {
+            Some(bytes) => u64::from_le_bytes(bytes.try_into().map_err(|_| {
+                WaCustomError::DeserializationError(
+                    "Failed to deserialize next string id position: length mismatch".to_string(),
+                )
+            })?),
+            None => 0,
+        };
+        Ok(Self {
+            next_position: Mutex::new(next_position),
+        })
+    }
+}
+
+/// Returns the internal id at `position` in the order the internal ids
+/// are assigned to string ids in i.e. counting down from the top of the
+/// ids that can be stored in the sparse index, and then from the top of
+/// the remaining internal ids
+fn string_id_at(position: u64) -> VectorId {
+    if position < SPARSE_IDS_END {
+        VectorId(SPARSE_IDS_END - 1 - position)
+    } else {
+        VectorId(INTERNAL_IDS_END - 1 - (position - SPARSE_IDS_END))
+    }
+}
+
+/// Returns true if the internal id isn't assigned to string ids as,
+/// truncated to `u32`, it's the same as the id of the root node
+/// (`u64::MAX`) or of the query (`u64::MAX - 1`), which are skipped
+/// when traversing the HNSW index (see `traverse_find_nearest`)
+fn is_reserved(id: &VectorId) -> bool {
+    id.0 as u32 >= u32::MAX - 1
+}
+
+/// Inverse of [`string_id_at`]
+fn position_of(id: &VectorId) -> u64 {
+    if id.0 < SPARSE_IDS_END {
+        SPARSE_IDS_END - 1 - id.0
+    } else {
+        SPARSE_IDS_END + (INTERNAL_IDS_END - 1 - id.0)
+    }
+}
+
+pub(super) fn read(lmdb: &MetaDb, key: &[u8]) -> Result<Option<Vec<u8>>, WaCustomError> {
+    let env = lmdb.env.clone();
+    let db = lmdb.db.clone();
+    let txn = env
+        .begin_ro_txn()
+        .map_err(|e| WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e)))?;
+    let value = match txn.get(*db, &key) {
+        Ok(bytes) => Some(bytes.to_vec()),
+        Err(lmdb::Error::NotFound) => None,
+        Err(e) => {
+            return Err(WaCustomError::DatabaseError(format!(
+                "Failed to get data: {}",
+                e
+            )))
+        }
+    };
+    txn.abort();
+    Ok(value)
+}
+
+/// Returns true if there's any key following `key` (inclusive), which
+/// `Cursor::iter_from` panics without
+pub(super) fn has_keys_from(cursor: &RoCursor, key: &[u8]) -> Result<bool, WaCustomError> {
+    match cursor.get(Some(key), None, MDB_SET_RANGE) {
+        Ok(_) => Ok(true),
+        Err(lmdb::Error::NotFound) => Ok(false),
+        Err(e) => Err(WaCustomError::DatabaseError(format!(
+            "Failed to get data: {}",
+            e
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_external_id_serde() {
+        let ids: Vec<ExternalId> =
+            serde_json::from_str(r#"[42, "42", "042", "doc-1", "e3b0c442-98fc"]"#).unwrap();
+        assert_eq!(
+            ids,
+            vec![
+                ExternalId::Int(42),
+                ExternalId::Int(42),
+                ExternalId::Str("042".to_string()),
+                ExternalId::Str("doc-1".to_string()),
+                ExternalId::Str("e3b0c442-98fc".to_string()),
+            ]
+        );
+        assert_eq!(
+            serde_json::to_string(&ids).unwrap(),
+            r#"[42,42,"042","doc-1","e3b0c442-98fc"]"#
+        );
+        assert!(serde_json::from_str::<ExternalId>(r#""""#).is_err());
+    }
+
+    #[test]
+    fn test_string_id_positions() {
+        assert_eq!(VectorId(u32::MAX as u64), string_id_at(0));
+        assert_eq!(VectorId(0), string_id_at(SPARSE_IDS_END - 1));
+        assert_eq!(VectorId(INTERNAL_IDS_END - 1), string_id_at(SPARSE_IDS_END));
+        assert_eq!(VectorId(SPARSE_IDS_END), string_id_at(INTERNAL_IDS_END - 1));
+        for position in [
+            0,
+            7,
+            SPARSE_IDS_END - 1,
+            SPARSE_IDS_END,
+            INTERNAL_IDS_END - 1,
+        ] {
+            assert_eq!(position, position_of(&string_id_at(position)));
+        }
+    }
+
+    #[test]
+    fn test_is_reserved() {
+        assert!(is_reserved(&string_id_at(0)));
+        assert!(is_reserved(&string_id_at(1)));
+        assert!(!is_reserved(&string_id_at(2)));
+        assert!(is_reserved(&string_id_at(SPARSE_IDS_END)));
+        assert!(is_reserved(&string_id_at(SPARSE_IDS_END + 1)));
+        assert!(!is_reserved(&string_id_at(SPARSE_IDS_END + 2)));
+    }
+
+    #[test]
+    fn test_is_out_of_range() {
+        assert!(!ExternalId::Int(SPARSE_IDS_END).is_out_of_range());
+        assert!(!ExternalId::Int(INTERNAL_IDS_END - 1).is_out_of_range());
+        assert!(ExternalId::Int(INTERNAL_IDS_END).is_out_of_range());
+        assert!(ExternalId::Int(u64::MAX).is_out_of_range());
+        assert!(!ExternalId::Str("a".to_string()).is_out_of_range());
+    }
+}
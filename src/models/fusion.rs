@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use serde::Deserialize;
+
+/// Method used to fuse the result lists of the components of a hybrid
+/// search into a single list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FusionMethod {
+    /// Reciprocal rank fusion, scoring the results by their ranks in
+    /// the lists i.e. `weight / (rank + constant_k)`, summed over the
+    /// lists
+    ReciprocalRank { constant_k: f32 },
+    /// Weighted sum of the normalized scores of the results
+    Weighted(ScoreNormalization),
+}
+
+/// Normalization of the scores of a result list, so that scores of
+/// lists on different scales can be combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoreNormalization {
+    /// Maps the scores to [0, 1]
+    #[default]
+    MinMax,
+    /// Maps the scores to their no. of standard deviations from the
+    /// mean
+    ZScore,
+}
+
+/// Normalizes the scores using the given normalization
+///
+/// If all the scores are equal, min-max normalization maps them to 1
+/// and z-score normalization maps them to 0.
+pub fn normalize_scores(scores: &[f32], normalization: ScoreNormalization) -> Vec<f32> {
+    if scores.is_empty() {
+        return Vec::new();
+    }
+    match normalization {
+        ScoreNormalization::MinMax => {
+            let min = scores.iter().copied().fold(f32::INFINITY, f32::min);
+            let max = scores.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+            let range = max - min;
+            scores
+                .iter()
+                .map(|score| {
+                    if range > 0.0 {
+                        (score - min) / range
+                    } else {
+                        1.0
+                    }
+                })
+                .collect()
+        }
+        ScoreNormalization::ZScore => {
+            let count = scores.len() as f32;
+            let mean = scores.iter().sum::<f32>() / count;
+            let variance = scores
+                .iter()
+                .map(|score| (score - mean).powi(2))
+                .sum::<f32>()
+                / count;
+            let std_dev = variance.sqrt();
+            scores
+                .iter()
+                .map(|score| {
+                    if std_dev > 0.0 {
+                        (score - mean) / std_dev
+                    } else {
+                        0.0
+                    }
+                })
+                .collect()
+        }
+    }
+}
+
+/// Fuses the result lists, each along with its weight, into a single
+/// list sorted in descending order of the fused scores
+///
+/// Every list is expected to be sorted by its scores, in descending
+/// order i.e. higher scores are better. With weighted fusion, results
+/// missing from a list are given the lowest normalized score of the
+/// list, as they scored no better than that. With reciprocal rank
+/// fusion, they get no score from the list.
+pub fn fuse<K: Hash + Eq + Clone>(
+    lists: &[(&[(K, f32)], f32)],
+    method: FusionMethod,
+) -> Vec<(K, f32)> {
+    let mut fused: Vec<(K, f32)> = Vec::new();
+    let mut positions: HashMap<K, usize> = HashMap::new();
+    for (list, _) in lists {
+        for (id, _) in list.iter() {
+            positions.entry(id.clone()).or_insert_with(|| {
+                fused.push((id.clone(), 0.0));
+                fused.len() - 1
+            });
+        }
+    }
+
+    for (list, weight) in lists {
+        match method {
+            FusionMethod::ReciprocalRank { constant_k } => {
+                for (rank, (id, _)) in list.iter().enumerate() {
+                    fused[positions[id]].1 += weight / (rank as f32 + constant_k + f32::EPSILON);
+                }
+            }
+            FusionMethod::Weighted(normalization) => {
+                let scores: Vec<f32> = list.iter().map(|(_, score)| *score).collect();
+                let normalized = normalize_scores(&scores, normalization);
+                let Some(lowest) = normalized.iter().copied().reduce(f32::min) else {
+                    continue;
+                };
+                let mut list_scores = vec![lowest; fused.len()];
+                for ((id, _), score) in list.iter().zip(normalized) {
+                    list_scores[positions[id]] = score;
+                }
+                for ((_, fused_score), score) in fused.iter_mut().zip(list_scores) {
+                    *fused_score += weight * score;
+                }
+            }
+        }
+    }
+
+    fused.sort_by(|a, b| b.1.total_cmp(&a.1));
+    fused
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(expected: &[f32], actual: &[f32]) {
+        assert_eq!(expected.len(), actual.len());
+        for (expected, actual) in expected.iter().zip(actual) {
+            assert!(
+                (expected - actual).abs() < 1e-5,
+                "expected {:?}, got {:?}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn test_normalize_scores() {
+        let scores = [4.0, 2.0, 0.0];
+        assert_close(
+            &[1.0, 0.5, 0.0],
+            &normalize_scores(&scores, ScoreNormalization::MinMax),
+        );
+        let sqrt_1_5 = 1.5f32.sqrt();
+        assert_close(
+            &[sqrt_1_5, 0.0, -sqrt_1_5],
+            &normalize_scores(&scores, ScoreNormalization::ZScore),
+        );
+
+        // Equal scores
+        assert_close(
+            &[1.0, 1.0],
+            &normalize_scores(&[3.0, 3.0], ScoreNormalization::MinMax),
+        );
+        assert_close(
+            &[0.0],
+            &normalize_scores(&[3.0], ScoreNormalization::ZScore),
+        );
+        assert!(normalize_scores(&[], ScoreNormalization::MinMax).is_empty());
+    }
+
+    #[test]
+    fn test_fuse_reciprocal_rank() {
+        let dense = [(1, 0.9), (2, 0.8)];
+        let sparse = [(2, 12.0), (3, 7.0)];
+        let method = FusionMethod::ReciprocalRank { constant_k: 1.0 };
+
+        let fused = fuse(&[(&dense, 1.0), (&sparse, 1.0)], method);
+        let ids: Vec<_> = fused.iter().map(|(id, _)| *id).collect();
+        assert_eq!(vec![2, 1, 3], ids);
+        assert_close(
+            &[1.5, 1.0, 0.5],
+            &fused.iter().map(|(_, score)| *score).collect::<Vec<_>>(),
+        );
+
+        // Weights scale the contributions of the lists
+        let fused = fuse(&[(&dense, 1.0), (&sparse, 3.0)], method);
+        let ids: Vec<_> = fused.iter().map(|(id, _)| *id).collect();
+        assert_eq!(vec![2, 3, 1], ids);
+    }
+
+    #[test]
+    fn test_fuse_weighted() {
+        let dense = [(1, 1.0), (2, 0.5), (3, 0.0)];
+        let text = [(3, 10.0), (4, 5.0), (2, 0.0)];
+        let method = FusionMethod::Weighted(ScoreNormalization::MinMax);
+
+        let fused = fuse(&[(&dense, 1.0), (&text, 1.0)], method);
+        // 1: 1.0 + 0.0 (missing), 2: 0.5 + 0.0, 3: 0.0 + 1.0,
+        // 4: 0.0 (missing) + 0.5
+        let ids: Vec<_> = fused.iter().map(|(id, _)| *id).collect();
+        assert_eq!(vec![1, 3, 2, 4], ids);
+        assert_close(
+            &[1.0, 1.0, 0.5, 0.5],
+            &fused.iter().map(|(_, score)| *score).collect::<Vec<_>>(),
+        );
+
+        let fused = fuse(&[(&dense, 0.5), (&text, 1.0)], method);
+        assert_eq!(3, fused[0].0);
+        assert_close(&[1.0], &[fused[0].1]);
+
+        assert!(fuse::<u32>(&[(&[], 1.0)], method).is_empty());
+    }
+}
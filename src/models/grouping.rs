@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use crate::metadata::FieldValue;
+
+use super::types::VectorId;
+
+/// Groups the results by the value of a metadata field, keeping the
+/// best `group_size` results of each of the first `groups` values
+///
+/// Results are expected in descending order of relevance. Groups are
+/// returned in the order of their best results, and results for which
+/// `group_key` returns None (e.g. vectors without a value for the
+/// field) are skipped.
+pub fn group_results<T>(
+    results: impl IntoIterator<Item = (VectorId, T)>,
+    group_key: impl Fn(&VectorId) -> Option<FieldValue>,
+    groups: usize,
+    group_size: usize,
+) -> Vec<(FieldValue, Vec<(VectorId, T)>)> {
+    let mut grouped: Vec<(FieldValue, Vec<(VectorId, T)>)> = Vec::new();
+    let mut positions: HashMap<FieldValue, usize> = HashMap::new();
+    for (id, score) in results {
+        let Some(key) = group_key(&id) else {
+            continue;
+        };
+        match positions.get(&key) {
+            Some(&position) => {
+                let hits = &mut grouped[position].1;
+                if hits.len() < group_size {
+                    hits.push((id, score));
+                }
+            }
+            None if grouped.len() < groups => {
+                positions.insert(key.clone(), grouped.len());
+                grouped.push((key, vec![(id, score)]));
+            }
+            None => {}
+        }
+    }
+    grouped
+}
+
+/// Returns true if there are `groups` groups with `group_size`
+/// results each
+pub fn are_groups_filled<T>(
+    grouped: &[(FieldValue, Vec<(VectorId, T)>)],
+    groups: usize,
+    group_size: usize,
+) -> bool {
+    grouped.len() >= groups && grouped.iter().all(|(_, hits)| hits.len() >= group_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_results() {
+        let sources = HashMap::from([
+            (1, FieldValue::String("a".to_string())),
+            (2, FieldValue::String("a".to_string())),
+            (3, FieldValue::String("b".to_string())),
+            (4, FieldValue::String("a".to_string())),
+            (6, FieldValue::Int(7)),
+            (7, FieldValue::String("b".to_string())),
+        ]);
+        // Vector 5 has no value for the field
+        let results = (1..=7).map(|id| (VectorId(id), id as f32));
+        let group_key = |id: &VectorId| sources.get(&id.0).cloned();
+
+        let grouped = group_results(results.clone(), group_key, 2, 2);
+        assert_eq!(
+            vec![
+                (
+                    FieldValue::String("a".to_string()),
+                    vec![(VectorId(1), 1.0), (VectorId(2), 2.0)]
+                ),
+                (
+                    FieldValue::String("b".to_string()),
+                    vec![(VectorId(3), 3.0), (VectorId(7), 7.0)]
+                ),
+            ],
+            grouped
+        );
+        assert!(are_groups_filled(&grouped, 2, 2));
+
+        let grouped = group_results(results, group_key, 5, 1);
+        let keys: Vec<_> = grouped.iter().map(|(key, _)| key.clone()).collect();
+        assert_eq!(
+            vec![
+                FieldValue::String("a".to_string()),
+                FieldValue::String("b".to_string()),
+                FieldValue::Int(7),
+            ],
+            keys
+        );
+        assert!(!are_groups_filled(&grouped, 5, 1));
+    }
+}
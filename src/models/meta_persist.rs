@@ -29,12 +29,27 @@ pub fn update_current_version(lmdb: &MetaDb, version_hash: Hash) -> Result<(), W
     Ok(())
 }
 
-pub fn store_values_range(lmdb: &MetaDb, range: (f32, f32)) -> lmdb::Result<()> {
+/// Key of the values range of the default dense index, or of the
+/// named dense index with the given name
+fn values_range_key(vector_name: Option<&str>) -> Vec<u8> {
+    let mut key = key!(m:values_range);
+    if let Some(vector_name) = vector_name {
+        key.push(b':');
+        key.extend_from_slice(vector_name.as_bytes());
+    }
+    key
+}
+
+pub fn store_values_range(
+    lmdb: &MetaDb,
+    vector_name: Option<&str>,
+    range: (f32, f32),
+) -> lmdb::Result<()> {
     let env = lmdb.env.clone();
     let db = lmdb.db.clone();
 
     let mut txn = env.begin_rw_txn()?;
-    let key = key!(m:values_range);
+    let key = values_range_key(vector_name);
     let mut bytes = Vec::with_capacity(8);
     bytes.extend(range.0.to_le_bytes());
     bytes.extend(range.1.to_le_bytes());
@@ -109,13 +124,16 @@ pub fn retrieve_current_version(lmdb: &MetaDb) -> Result<Hash, WaCustomError> {
     Ok(hash)
 }
 
-pub fn retrieve_values_range(lmdb: &MetaDb) -> Result<Option<(f32, f32)>, WaCustomError> {
+pub fn retrieve_values_range(
+    lmdb: &MetaDb,
+    vector_name: Option<&str>,
+) -> Result<Option<(f32, f32)>, WaCustomError> {
     let env = lmdb.env.clone();
     let db = lmdb.db.clone();
     let txn = env
         .begin_ro_txn()
         .map_err(|e| WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e)))?;
-    let key = key!(m:values_range);
+    let key = values_range_key(vector_name);
 
     let serialized_hash = match txn.get(*db, &key) {
         Ok(bytes) => bytes,
@@ -6,25 +6,35 @@ pub mod collection_cache;
 pub mod collection_transaction;
 pub mod common;
 pub mod crypto;
+pub mod diversity;
 pub mod dot_product;
 pub mod embedding_persist;
 pub mod encoding_format;
+pub mod external_ids;
 pub mod file_persist;
 pub mod fixedset;
+pub mod fusion;
+pub mod grouping;
 pub mod inverted_index;
 pub mod kmeans;
 pub mod lru_cache;
 pub mod meta_persist;
+pub mod multi_vectors;
 pub mod page;
 pub mod paths;
 pub mod prob_lazy_load;
 pub mod prob_node;
+pub mod recommend;
 pub mod rpc;
 pub mod serializer;
 pub mod sparse_ann_query;
 pub mod tf_idf_index;
+pub mod tombstones;
 pub mod tree_map;
 pub mod types;
 pub mod user;
 pub mod utils;
+pub mod vector_counts;
+pub mod vector_ids;
+pub mod vector_metadata;
 pub mod versioning;
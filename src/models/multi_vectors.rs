@@ -0,0 +1,298 @@
+use std::sync::{Arc, Mutex};
+
+use lmdb::{Cursor, Transaction, WriteFlags};
+
+use crate::{
+    indexes::{hnsw::HNSWIndex, IndexOps},
+    macros::key,
+};
+
+use super::{
+    common::WaCustomError,
+    external_ids::read,
+    types::{DistanceMetric, MetaDb, VectorId},
+    versioning::Hash,
+};
+
+/// Vectors of a document in a multi-vector space, along with the
+/// version in which they were inserted
+///
+/// Every vector of the document is indexed as a separate node in the
+/// HNSW index of the vector space, with an id of its own that's mapped
+/// back to the document id.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MultiVectorDocument {
+    pub version: Hash,
+    pub vector_ids: Vec<VectorId>,
+}
+
+impl MultiVectorDocument {
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(4 + self.vector_ids.len() * 8);
+        bytes.extend_from_slice(&self.version.to_le_bytes());
+        for id in &self.vector_ids {
+            bytes.extend_from_slice(&id.0.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, WaCustomError> {
+        if bytes.len() < 4 || (bytes.len() - 4) % 8 != 0 {
+            return Err(WaCustomError::DeserializationError(
+                "Failed to deserialize multi-vector document: length mismatch".to_string(),
+            ));
+        }
+        let version = Hash::from(u32::from_le_bytes(bytes[..4].try_into().unwrap()));
+        let vector_ids = bytes[4..]
+            .chunks_exact(8)
+            .map(|chunk| VectorId(u64::from_le_bytes(chunk.try_into().unwrap())))
+            .collect();
+        Ok(Self {
+            version,
+            vector_ids,
+        })
+    }
+}
+
+/// Mapping between the documents of the multi-vector spaces of a
+/// collection and the ids of the vectors indexed for them
+///
+/// The ids of the vectors are assigned from a counter that's shared by
+/// all the multi-vector spaces of the collection. They only ever appear
+/// in the HNSW indexes of those spaces, hence can't be confused with the
+/// ids of the documents.
+#[derive(Default)]
+pub struct MultiVectors {
+    // Next vector id to be assigned
+    next_id: Mutex<u64>,
+}
+
+impl MultiVectors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Assigns ids to `count` vectors of the document
+    ///
+    /// The mapping from the assigned ids to the document id is written
+    /// right away, as an unused mapping is harmless. The document
+    /// itself is only written when the transaction inserting it is
+    /// committed, see [`MultiVectors::persist`].
+    pub fn assign_vector_ids(
+        &self,
+        lmdb: &MetaDb,
+        document_id: &VectorId,
+        count: usize,
+    ) -> Result<Vec<VectorId>, WaCustomError> {
+        let mut next_id = self.next_id.lock().unwrap();
+        let vector_ids: Vec<_> = (*next_id..*next_id + count as u64).map(VectorId).collect();
+
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        for vector_id in &vector_ids {
+            txn.put(
+                *db,
+                &key!(y:vector_id),
+                &document_id.0.to_le_bytes(),
+                WriteFlags::empty(),
+            )
+            .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+        }
+        txn.put(
+            *db,
+            &key!(m:next_multi_vector_id),
+            &(*next_id + count as u64).to_le_bytes(),
+            WriteFlags::empty(),
+        )
+        .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        *next_id += count as u64;
+        Ok(vector_ids)
+    }
+
+    /// Returns the id of the document the vector belongs to
+    pub fn get_document_id(
+        &self,
+        lmdb: &MetaDb,
+        vector_id: &VectorId,
+    ) -> Result<Option<VectorId>, WaCustomError> {
+        read(lmdb, &key!(y:vector_id))
+            .map(|bytes| bytes.map(|bytes| VectorId(u64::from_le_bytes(bytes.try_into().unwrap()))))
+    }
+
+    /// Returns the latest committed vectors of the document in the
+    /// multi-vector space
+    ///
+    /// Note that the vectors may not be live anymore, which is to be
+    /// checked against the tombstones using the document's version.
+    pub fn get_document(
+        &self,
+        lmdb: &MetaDb,
+        vector_name: &str,
+        document_id: &VectorId,
+    ) -> Result<Option<MultiVectorDocument>, WaCustomError> {
+        let vector_key = HNSWIndex::get_key_for_name(vector_name);
+        read(lmdb, &key!(z:vector_key, document_id))?
+            .map(|bytes| MultiVectorDocument::deserialize(&bytes))
+            .transpose()
+    }
+
+    /// Returns all the documents of the multi-vector space, in
+    /// ascending order of the ids
+    pub fn get_documents(
+        &self,
+        lmdb: &MetaDb,
+        vector_name: &str,
+    ) -> Result<Vec<(VectorId, MultiVectorDocument)>, WaCustomError> {
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+        let txn = env.begin_ro_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        let mut cursor = txn
+            .open_ro_cursor(*db)
+            .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
+
+        let vector_key = HNSWIndex::get_key_for_name(vector_name);
+        let start_key = key!(z:vector_key, VectorId(0));
+        let prefix = &start_key[..9];
+
+        let mut documents = Vec::new();
+        for (k, v) in cursor.iter_from(&start_key) {
+            if k.len() != start_key.len() || !k.starts_with(prefix) {
+                break;
+            }
+            documents.push((
+                VectorId(u64::from_le_bytes(k[9..].try_into().unwrap())),
+                MultiVectorDocument::deserialize(v)?,
+            ));
+        }
+        // Keys are ordered by the little endian bytes of the id
+        documents.sort_unstable_by_key(|(id, _)| id.0);
+
+        Ok(documents)
+    }
+
+    /// Persists the documents inserted in the transaction with id
+    /// `version`, as (vector name, document id, vector ids)
+    pub fn persist(
+        lmdb: &MetaDb,
+        version: Hash,
+        documents: &[(String, VectorId, Vec<VectorId>)],
+    ) -> Result<(), WaCustomError> {
+        if documents.is_empty() {
+            return Ok(());
+        }
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+
+        for (vector_name, document_id, vector_ids) in documents {
+            let vector_key = HNSWIndex::get_key_for_name(vector_name);
+            let document = MultiVectorDocument {
+                version,
+                vector_ids: vector_ids.clone(),
+            };
+            txn.put(
+                *db,
+                &key!(z:vector_key, document_id),
+                &document.serialize(),
+                WriteFlags::empty(),
+            )
+            .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+        }
+
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        Ok(())
+    }
+
+    /// Loads the state of the mapping from lmdb
+    pub fn load(lmdb: &MetaDb) -> Result<Self, WaCustomError> {
+        let next_id = match read(lmdb, &key!(m:next_multi_vector_id))? {
+            Some(bytes) => u64::from_le_bytes(bytes.try_into().map_err(|_| {
+                WaCustomError::DeserializationError(
+                    "Failed to deserialize next multi-vector id: length mismatch".to_string(),
+                )
+            })?),
+            None => 0,
+        };
+        Ok(Self {
+            next_id: Mutex::new(next_id),
+        })
+    }
+}
+
+/// Late interaction (MaxSim) score of a document i.e. the sum over the
+/// query vectors of the similarity to the most similar vector of the
+/// document, as per the distance metric of the index
+///
+/// Returns None if the metric can't be calculated on raw vectors (see
+/// [`DistanceMetric::calculate_raw`]).
+pub fn max_sim_score(
+    metric: DistanceMetric,
+    queries: &[Vec<f32>],
+    vectors: &[Arc<Vec<f32>>],
+) -> Option<f32> {
+    queries.iter().try_fold(0.0, |score, query| {
+        let max_similarity = vectors.iter().try_fold(f32::NEG_INFINITY, |max, vector| {
+            let result = metric.calculate_raw(query, vector)?;
+            Some(max.max(result.similarity()))
+        })?;
+        Some(score + max_similarity)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multi_vector_document_serde() {
+        let document = MultiVectorDocument {
+            version: Hash::from(42),
+            vector_ids: vec![VectorId(1), VectorId(2), VectorId(u64::MAX)],
+        };
+        let bytes = document.serialize();
+        assert_eq!(document, MultiVectorDocument::deserialize(&bytes).unwrap());
+        assert!(MultiVectorDocument::deserialize(&bytes[..5]).is_err());
+        assert!(MultiVectorDocument::deserialize(&[0]).is_err());
+    }
+
+    #[test]
+    fn test_max_sim_score() {
+        let vectors = vec![Arc::new(vec![1.0, 0.0]), Arc::new(vec![0.0, 2.0])];
+        let score = |metric, queries: &[Vec<f32>]| max_sim_score(metric, queries, &vectors);
+
+        // Every query vector is matched with its closest vector
+        let cosine = score(DistanceMetric::Cosine, &[vec![3.0, 0.0], vec![0.0, 1.0]]).unwrap();
+        assert!((cosine - 2.0).abs() < 1e-6);
+
+        let cosine = score(DistanceMetric::Cosine, &[vec![1.0, 1.0]]).unwrap();
+        assert!((cosine - std::f32::consts::FRAC_1_SQRT_2).abs() < 1e-6);
+
+        // Each query vector contributes to the score, even if matched
+        // with the same document vector
+        let cosine = score(DistanceMetric::Cosine, &[vec![1.0, 0.0], vec![1.0, 0.0]]).unwrap();
+        assert!((cosine - 2.0).abs() < 1e-6);
+
+        // Scored as per the metric, with distances negated
+        let dot_product = score(DistanceMetric::DotProduct, &[vec![1.0, 1.0]]).unwrap();
+        assert!((dot_product - 2.0).abs() < 1e-6);
+        let euclidean = score(DistanceMetric::Euclidean, &[vec![0.0, 1.0]]).unwrap();
+        assert!((euclidean + 1.0).abs() < 1e-6);
+
+        assert_eq!(None, score(DistanceMetric::Hamming, &[vec![1.0, 1.0]]));
+    }
+}
@@ -263,6 +263,17 @@ impl ProbLazyItem<ProbNode> {
     pub fn get_latest_version(
         this: *mut Self,
         cache: &HNSWIndexCache,
+    ) -> Result<(*mut Self, u16), BufIoError> {
+        // New versions are added starting from the root version, so the
+        // latest version isn't necessarily reachable from the versions
+        // added after `this`
+        let root = Self::get_root_version(this, cache)?;
+        Self::get_latest_version_from(root, cache)
+    }
+
+    fn get_latest_version_from(
+        this: *mut Self,
+        cache: &HNSWIndexCache,
     ) -> Result<(*mut Self, u16), BufIoError> {
         let data = unsafe { &*this }.try_get_data(cache)?;
         let versions = &data.versions;
@@ -277,7 +288,7 @@ impl ProbLazyItem<ProbNode> {
     ) -> Result<(*mut Self, u16), BufIoError> {
         if let Some(last) = versions.last() {
             let (latest_version, relative_local_version_number) =
-                Self::get_latest_version(last, cache)?;
+                Self::get_latest_version_from(last, cache)?;
             Ok((
                 latest_version,
                 (1u16 << ((versions.len() as u8 - 1) * 2)) + relative_local_version_number,
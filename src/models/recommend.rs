@@ -0,0 +1,164 @@
+use std::cmp::Ordering;
+
+use super::types::DistanceMetric;
+
+/// Builds the query vector for the "average vector" recommendation
+/// strategy from the positive and negative examples
+///
+/// The query is the average of the positive examples, moved away from
+/// the average of the negative examples (if any) by the difference
+/// between the two i.e. `avg(pos) + (avg(pos) - avg(neg))`.
+pub fn average_vector_query(positives: &[Vec<f32>], negatives: &[Vec<f32>]) -> Vec<f32> {
+    let avg_positive = average(positives);
+    if negatives.is_empty() {
+        return avg_positive;
+    }
+    let avg_negative = average(negatives);
+    avg_positive
+        .iter()
+        .zip(&avg_negative)
+        .map(|(pos, neg)| pos + (pos - neg))
+        .collect()
+}
+
+fn average(vectors: &[Vec<f32>]) -> Vec<f32> {
+    let dim = vectors.first().map_or(0, |vector| vector.len());
+    let mut sum = vec![0.0; dim];
+    for vector in vectors {
+        for (acc, value) in sum.iter_mut().zip(vector) {
+            *acc += value;
+        }
+    }
+    let count = vectors.len() as f32;
+    sum.iter_mut().for_each(|acc| *acc /= count);
+    sum
+}
+
+/// Score of a vector for the "best score" recommendation strategy, see
+/// [`best_score`]
+///
+/// Vectors closer to a positive example than to any negative one rank
+/// above all the others, irrespective of their similarities. Within
+/// either bucket, vectors are ranked by the raw similarities i.e. the
+/// scores aren't squashed into a bounded range, which would make close
+/// similarities indistinguishable.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BestScore {
+    /// The vector is more similar to its most similar positive example
+    /// than to its most similar negative example, with the similarity
+    /// to the former
+    Positive(f32),
+    /// The vector is at least as similar to its most similar negative
+    /// example, with the similarity to it
+    Negative(f32),
+}
+
+impl BestScore {
+    /// Orders the scores from worst to best
+    pub fn total_cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Self::Positive(a), Self::Positive(b)) => a.total_cmp(b),
+            (Self::Negative(a), Self::Negative(b)) => b.total_cmp(a),
+            (Self::Positive(_), Self::Negative(_)) => Ordering::Greater,
+            (Self::Negative(_), Self::Positive(_)) => Ordering::Less,
+        }
+    }
+
+    /// Returns the value of the score to be reported, which is the
+    /// similarity for [`Self::Positive`] and the negated similarity for
+    /// [`Self::Negative`], so that higher is better within a bucket
+    pub fn value(&self) -> f32 {
+        match self {
+            Self::Positive(similarity) => *similarity,
+            Self::Negative(similarity) => -similarity,
+        }
+    }
+}
+
+/// Score of the vector for the "best score" recommendation strategy
+///
+/// The vector is scored by the similarity to its most similar positive
+/// example if it's more similar to it than to its most similar negative
+/// example, and by the similarity to the latter otherwise, see
+/// [`BestScore`].
+///
+/// Returns None if the metric can't be calculated on raw vectors (see
+/// [`DistanceMetric::calculate_raw`]).
+pub fn best_score(
+    metric: DistanceMetric,
+    positives: &[Vec<f32>],
+    negatives: &[Vec<f32>],
+    vector: &[f32],
+) -> Option<BestScore> {
+    let best_similarity = |examples: &[Vec<f32>]| {
+        examples
+            .iter()
+            .try_fold(f32::NEG_INFINITY, |best, example| {
+                let result = metric.calculate_raw(example, vector)?;
+                Some(best.max(result.similarity()))
+            })
+    };
+    let best_positive = best_similarity(positives)?;
+    let best_negative = best_similarity(negatives)?;
+    if best_positive > best_negative {
+        Some(BestScore::Positive(best_positive))
+    } else {
+        Some(BestScore::Negative(best_negative))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_average_vector_query() {
+        let positives = vec![vec![1.0, 0.0], vec![3.0, 2.0]];
+        assert_eq!(vec![2.0, 1.0], average_vector_query(&positives, &[]));
+
+        let negatives = vec![vec![0.0, 1.0]];
+        assert_eq!(vec![4.0, 1.0], average_vector_query(&positives, &negatives));
+    }
+
+    #[test]
+    fn test_best_score() {
+        let positives = vec![vec![1.0, 0.0]];
+        let negatives = vec![vec![0.0, 1.0]];
+        let score = |metric, negatives: &[Vec<f32>], vector: &[f32]| {
+            best_score(metric, &positives, negatives, vector).unwrap()
+        };
+
+        // Closer to the positive example
+        let near_positive = score(DistanceMetric::Euclidean, &negatives, &[0.9, 0.1]);
+        assert!(matches!(near_positive, BestScore::Positive(_)));
+
+        // Closer to the negative example
+        let near_negative = score(DistanceMetric::Euclidean, &negatives, &[0.1, 0.9]);
+        assert!(matches!(near_negative, BestScore::Negative(_)));
+        assert_eq!(Ordering::Greater, near_positive.total_cmp(&near_negative));
+
+        // Vectors closer to a positive example rank first, even if far
+        // from it, and the others rank higher the further they are from
+        // the negative example
+        let far_positive = score(DistanceMetric::Euclidean, &negatives, &[30.0, -10.0]);
+        assert_eq!(Ordering::Greater, far_positive.total_cmp(&near_negative));
+        let far_negative = score(DistanceMetric::Euclidean, &negatives, &[-10.0, 30.0]);
+        assert!(matches!(far_negative, BestScore::Negative(_)));
+        assert_eq!(Ordering::Greater, far_negative.total_cmp(&near_negative));
+        assert!(far_negative.value() > near_negative.value());
+
+        // Large similarities stay distinguishable
+        let dot = score(DistanceMetric::DotProduct, &[], &[30.0, 0.0]);
+        let higher_dot = score(DistanceMetric::DotProduct, &[], &[30.5, 0.0]);
+        assert_eq!(Ordering::Greater, higher_dot.total_cmp(&dot));
+        assert_eq!(30.5, higher_dot.value());
+
+        // Without negative examples, all scores are positive
+        let cosine = score(DistanceMetric::Cosine, &[], &[0.0, 1.0]);
+        assert!(matches!(cosine, BestScore::Positive(_)));
+        let closer = score(DistanceMetric::Cosine, &[], &[1.0, 1.0]);
+        assert_eq!(Ordering::Greater, closer.total_cmp(&cosine));
+
+        assert!(best_score(DistanceMetric::Hamming, &positives, &negatives, &[1.0, 0.0]).is_none());
+    }
+}
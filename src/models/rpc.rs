@@ -103,8 +103,7 @@ pub struct DenseVector {
 
 pub type Single = MetadataColumnValue;
 
-// @NOTE: Comparison with multiple fields not support for now
-// pub type Multiple = Vec<MetadataColumnValue>;
+pub type Multiple = Vec<MetadataColumnValue>;
 
 // Define the generic MetadataColumn type
 #[allow(clippy::enum_variant_names)]
@@ -134,39 +133,44 @@ pub enum ComparisonOperator {
 
     #[serde(rename = "$ne")]
     Ne(Single),
-    // @NOTE: Only eq and neq are supported for now
 
-    // #[serde(rename = "$gt")]
-    // Gt(Single),
+    #[serde(rename = "$gt")]
+    Gt(Single),
 
-    // #[serde(rename = "$gte")]
-    // Gte(Single),
+    #[serde(rename = "$gte")]
+    Gte(Single),
 
-    // #[serde(rename = "$lt")]
-    // Lt(Single),
+    #[serde(rename = "$lt")]
+    Lt(Single),
 
-    // #[serde(rename = "$lte")]
-    // Lte(Single),
+    #[serde(rename = "$lte")]
+    Lte(Single),
 
-    // #[serde(rename = "$in")]
-    // In(Multiple),
+    #[serde(rename = "$in")]
+    In(Multiple),
 
-    // #[serde(rename = "$nin")]
-    // Nin(Multiple),
+    #[serde(rename = "$nin")]
+    Nin(Multiple),
 }
 
 impl ComparisonOperator {
     #[allow(dead_code)]
-    fn to_predicate(&self, key: &str) -> metadata::Predicate {
+    fn to_predicate(&self, key: &str) -> Result<metadata::Predicate, WaCustomError> {
+        let single = |v: &Single| metadata::PredicateValue::Single(v.to_fieldvalue());
+        let multiple = |vs: &Multiple| {
+            metadata::PredicateValue::Multiple(vs.iter().map(|v| v.to_fieldvalue()).collect())
+        };
         let (op, v) = match self {
-            Self::Eq(v) => (metadata::Operator::Equal, v),
-            Self::Ne(v) => (metadata::Operator::NotEqual, v),
+            Self::Eq(v) => (metadata::Operator::Equal, single(v)),
+            Self::Ne(v) => (metadata::Operator::NotEqual, single(v)),
+            Self::Gt(v) => (metadata::Operator::Gt, single(v)),
+            Self::Gte(v) => (metadata::Operator::Gte, single(v)),
+            Self::Lt(v) => (metadata::Operator::Lt, single(v)),
+            Self::Lte(v) => (metadata::Operator::Lte, single(v)),
+            Self::In(vs) => (metadata::Operator::In, multiple(vs)),
+            Self::Nin(vs) => (metadata::Operator::NotIn, multiple(vs)),
         };
-        metadata::Predicate {
-            field_name: key.to_owned(),
-            field_value: v.to_fieldvalue(),
-            operator: op,
-        }
+        metadata::Predicate::new(key.to_owned(), v, op).map_err(WaCustomError::MetadataError)
     }
 }
 
@@ -177,6 +181,9 @@ pub enum LogicalOperator {
 
     #[serde(rename = "$or")]
     Or(Vec<Filter>),
+
+    #[serde(rename = "$not")]
+    Not(Box<Filter>),
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
@@ -194,65 +201,34 @@ impl Filter {
     /// representation. Perhaps the two types can be unified later
     #[allow(dead_code)]
     pub fn to_internal(&self) -> Result<metadata::Filter, WaCustomError> {
-        let filter_err = |msg: &str| {
-            WaCustomError::MetadataError(metadata::Error::UnsupportedFilter(msg.to_string()))
+        let to_internal_all = |filters: &[Filter]| {
+            filters
+                .iter()
+                .map(|f| f.to_internal())
+                .collect::<Result<Vec<_>, _>>()
         };
         match self {
             Self::Comparison { column } => {
                 if column.len() == 1 {
                     let (key, cop) = column.iter().next().unwrap();
-                    let pred = cop.to_predicate(key);
+                    let pred = cop.to_predicate(key)?;
                     Ok(metadata::Filter::Is(pred))
                 } else {
-                    let mut preds = vec![];
+                    let mut filters = vec![];
                     for (key, cop) in column.iter() {
-                        preds.push(cop.to_predicate(key));
+                        filters.push(metadata::Filter::Is(cop.to_predicate(key)?));
                     }
-                    Ok(metadata::Filter::And(preds))
+                    Ok(metadata::Filter::And(filters))
                 }
             }
             Self::Logical(LogicalOperator::And(filters)) => {
-                let mut preds = vec![];
-                for f in filters {
-                    match f {
-                        Filter::Comparison { column } => {
-                            for (key, cop) in column.iter() {
-                                preds.push(cop.to_predicate(key));
-                            }
-                        }
-                        // @NOTE: Nested predicates are not
-                        // supported.
-                        Filter::Logical(_) => {
-                            return Err(filter_err("nested predicates not supported"))
-                        }
-                    }
-                }
-                Ok(metadata::Filter::And(preds))
+                Ok(metadata::Filter::And(to_internal_all(filters)?))
             }
             Self::Logical(LogicalOperator::Or(filters)) => {
-                let mut preds = vec![];
-                for f in filters {
-                    match f {
-                        Filter::Comparison { column } => {
-                            if column.len() > 1 {
-                                // @NOTE: Mixing And and Or predicates
-                                // is not supported. Perhaps change
-                                // the error type to add a message
-                                return Err(filter_err("mixing and, or predicates not supported"));
-                            }
-                            for (key, cop) in column.iter() {
-                                preds.push(cop.to_predicate(key));
-                            }
-                        }
-                        // @NOTE: Nested predicates are not
-                        // supported. Perhaps change the error type to
-                        // add a message
-                        Filter::Logical(_) => {
-                            return Err(filter_err("nested predicates not supported"))
-                        }
-                    }
-                }
-                Ok(metadata::Filter::Or(preds))
+                Ok(metadata::Filter::Or(to_internal_all(filters)?))
+            }
+            Self::Logical(LogicalOperator::Not(filter)) => {
+                Ok(metadata::Filter::Not(Box::new(filter.to_internal()?)))
             }
         }
     }
@@ -319,6 +295,16 @@ mod tests {
         }
     }
 
+    fn predicates(filters: &[metadata::Filter]) -> Vec<&metadata::Predicate> {
+        filters
+            .iter()
+            .map(|f| match f {
+                metadata::Filter::Is(pred) => pred,
+                _ => panic!(),
+            })
+            .collect()
+    }
+
     #[test]
     fn test_to_internal() {
         // Filter with a single column
@@ -334,7 +320,9 @@ mod tests {
                 assert_eq!("foo", pred.field_name);
                 assert_eq!(
                     pred.field_value,
-                    metadata::FieldValue::String("hello".to_string()),
+                    metadata::PredicateValue::Single(metadata::FieldValue::String(
+                        "hello".to_string()
+                    )),
                 );
                 assert_eq!(pred.operator, metadata::Operator::Equal);
             }
@@ -354,19 +342,25 @@ mod tests {
         let filter = Filter::Comparison { column };
         let internal = filter.to_internal().unwrap();
         match internal {
-            metadata::Filter::And(preds) => {
+            metadata::Filter::And(filters) => {
+                let preds = predicates(&filters);
                 assert_eq!(2, preds.len());
                 let p1 = preds.iter().find(|p| p.field_name == "a").unwrap();
                 assert_eq!("a", p1.field_name);
                 assert_eq!(
                     p1.field_value,
-                    metadata::FieldValue::String("hello".to_string()),
+                    metadata::PredicateValue::Single(metadata::FieldValue::String(
+                        "hello".to_string()
+                    )),
                 );
                 assert_eq!(p1.operator, metadata::Operator::Equal);
 
                 let p2 = preds.iter().find(|p| p.field_name == "b").unwrap();
                 assert_eq!("b", p2.field_name);
-                assert_eq!(p2.field_value, metadata::FieldValue::Int(2));
+                assert_eq!(
+                    p2.field_value,
+                    metadata::PredicateValue::Single(metadata::FieldValue::Int(2))
+                );
                 assert_eq!(p2.operator, metadata::Operator::NotEqual);
             }
             _ => panic!(),
@@ -388,19 +382,25 @@ mod tests {
         let filter = Filter::Logical(LogicalOperator::And(vec![f1, f2]));
         let internal = filter.to_internal().unwrap();
         match internal {
-            metadata::Filter::And(preds) => {
+            metadata::Filter::And(filters) => {
+                let preds = predicates(&filters);
                 assert_eq!(2, preds.len());
                 let p1 = preds.iter().find(|p| p.field_name == "a").unwrap();
                 assert_eq!("a", p1.field_name);
                 assert_eq!(
-                    metadata::FieldValue::String("hello".to_string()),
+                    metadata::PredicateValue::Single(metadata::FieldValue::String(
+                        "hello".to_string()
+                    )),
                     p1.field_value
                 );
                 assert_eq!(p1.operator, metadata::Operator::Equal);
 
                 let p2 = preds.iter().find(|p| p.field_name == "b").unwrap();
                 assert_eq!("b", p2.field_name);
-                assert_eq!(metadata::FieldValue::Int(2), p2.field_value);
+                assert_eq!(
+                    metadata::PredicateValue::Single(metadata::FieldValue::Int(2)),
+                    p2.field_value
+                );
                 assert_eq!(p2.operator, metadata::Operator::NotEqual);
             }
             _ => panic!(),
@@ -430,7 +430,15 @@ mod tests {
         let filter = Filter::Logical(LogicalOperator::And(vec![f1, f2]));
         let internal = filter.to_internal().unwrap();
         match internal {
-            metadata::Filter::And(preds) => assert_eq!(4, preds.len()),
+            metadata::Filter::And(filters) => {
+                assert_eq!(2, filters.len());
+                for f in filters {
+                    match f {
+                        metadata::Filter::And(filters) => assert_eq!(2, predicates(&filters).len()),
+                        _ => panic!(),
+                    }
+                }
+            }
             _ => panic!(),
         }
 
@@ -450,25 +458,31 @@ mod tests {
         let filter = Filter::Logical(LogicalOperator::Or(vec![f1, f2]));
         let internal = filter.to_internal().unwrap();
         match internal {
-            metadata::Filter::Or(preds) => {
+            metadata::Filter::Or(filters) => {
+                let preds = predicates(&filters);
                 assert_eq!(2, preds.len());
                 let p1 = preds.iter().find(|p| p.field_name == "a").unwrap();
                 assert_eq!("a", p1.field_name);
                 assert_eq!(
-                    metadata::FieldValue::String("hello".to_string()),
+                    metadata::PredicateValue::Single(metadata::FieldValue::String(
+                        "hello".to_string()
+                    )),
                     p1.field_value
                 );
                 assert_eq!(p1.operator, metadata::Operator::Equal);
 
                 let p2 = preds.iter().find(|p| p.field_name == "b").unwrap();
                 assert_eq!("b", p2.field_name);
-                assert_eq!(metadata::FieldValue::Int(2), p2.field_value);
+                assert_eq!(
+                    metadata::PredicateValue::Single(metadata::FieldValue::Int(2)),
+                    p2.field_value
+                );
                 assert_eq!(p2.operator, metadata::Operator::NotEqual);
             }
             _ => panic!(),
         }
 
-        // Filter with Logical::Or + multiple columns filters (must fail)
+        // Filter with Logical::Or + multiple columns filters
         let mut c1 = HashMap::new();
         c1.insert(
             "a".to_string(),
@@ -490,11 +504,76 @@ mod tests {
         );
         let f2 = Filter::Comparison { column: c2 };
         let filter = Filter::Logical(LogicalOperator::Or(vec![f1, f2]));
-        match filter.to_internal().err().unwrap() {
-            WaCustomError::MetadataError(metadata::Error::UnsupportedFilter(msg)) => {
-                assert_eq!("mixing and, or predicates not supported", msg)
+        match filter.to_internal().unwrap() {
+            metadata::Filter::Or(filters) => {
+                assert_eq!(2, filters.len());
+                for f in filters {
+                    match f {
+                        metadata::Filter::And(filters) => assert_eq!(2, predicates(&filters).len()),
+                        _ => panic!(),
+                    }
+                }
+            }
+            _ => panic!(),
+        }
+
+        // Nested filter with Logical::Not
+        let input =
+            r#"{"$and":[{"a":{"$eq":"x"}},{"$not":{"$or":[{"b":{"$eq":1}},{"b":{"$eq":2}}]}}]}"#;
+        let filter: Filter = serde_json::from_str(input).unwrap();
+        let b_is = |v: i32| {
+            metadata::Filter::Is(metadata::Predicate {
+                field_name: "b".to_string(),
+                field_value: metadata::FieldValue::Int(v).into(),
+                operator: metadata::Operator::Equal,
+            })
+        };
+        assert_eq!(
+            metadata::Filter::And(vec![
+                metadata::Filter::Is(metadata::Predicate {
+                    field_name: "a".to_string(),
+                    field_value: metadata::FieldValue::String("x".to_string()).into(),
+                    operator: metadata::Operator::Equal,
+                }),
+                metadata::Filter::Not(Box::new(metadata::Filter::Or(vec![b_is(1), b_is(2)]))),
+            ]),
+            filter.to_internal().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_comparison_operators_to_internal() {
+        let input = r#"{"year":{"$gte":2020},"lang":{"$in":["en","de"]}}"#;
+        let filter: Filter = serde_json::from_str(input).unwrap();
+        match filter.to_internal().unwrap() {
+            metadata::Filter::And(filters) => {
+                let preds = predicates(&filters);
+                let year = preds.iter().find(|p| p.field_name == "year").unwrap();
+                assert_eq!(metadata::Operator::Gte, year.operator);
+                assert_eq!(
+                    metadata::PredicateValue::Single(metadata::FieldValue::Int(2020)),
+                    year.field_value
+                );
+                let lang = preds.iter().find(|p| p.field_name == "lang").unwrap();
+                assert_eq!(metadata::Operator::In, lang.operator);
+                assert_eq!(
+                    metadata::PredicateValue::Multiple(vec![
+                        metadata::FieldValue::String("en".to_string()),
+                        metadata::FieldValue::String("de".to_string()),
+                    ]),
+                    lang.field_value
+                );
             }
             _ => panic!(),
         }
+
+        // Comparisons are only supported for int values
+        let filter: Filter = serde_json::from_str(r#"{"lang":{"$lt":"en"}}"#).unwrap();
+        assert!(matches!(
+            filter.to_internal(),
+            Err(WaCustomError::MetadataError(
+                metadata::Error::InvalidFieldValue(_)
+            ))
+        ));
     }
 }
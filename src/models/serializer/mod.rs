@@ -61,3 +61,23 @@ impl SimpleSerialize for u16 {
         Ok(res)
     }
 }
+
+impl SimpleSerialize for u32 {
+    fn serialize(&self, bufman: &BufferManager, cursor: u64) -> Result<u32, BufIoError> {
+        Ok(bufman.write_to_end_of_file(cursor, &self.to_le_bytes())? as u32)
+    }
+
+    fn deserialize(
+        bufman: &BufferManager,
+        FileOffset(offset): FileOffset,
+    ) -> Result<Self, BufIoError>
+    where
+        Self: Sized,
+    {
+        let cursor = bufman.open_cursor()?;
+        bufman.seek_with_cursor(cursor, offset as u64)?;
+        let res = bufman.read_u32_with_cursor(cursor)?;
+        bufman.close_cursor(cursor)?;
+        Ok(res)
+    }
+}
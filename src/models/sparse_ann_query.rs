@@ -3,7 +3,7 @@ use serde::Serialize;
 
 use crate::models::buffered_io::BufIoError;
 
-use crate::models::types::SparseVector;
+use crate::models::types::{SparseVector, VectorId};
 use std::cell::UnsafeCell;
 use std::cmp::Ordering;
 use std::collections::BinaryHeap;
@@ -11,6 +11,8 @@ use std::iter::Peekable;
 
 use super::inverted_index::InvertedIndexRoot;
 use super::tf_idf_index::{TFIDFIndexRoot, TermInfo, TermQuotient, UnsafeVersionedVecIter};
+use super::tombstones::Tombstones;
+use super::versioning::VersionSnapshot;
 
 #[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct SparseAnnResult {
@@ -24,6 +26,17 @@ pub struct SparseAnnIDFResult {
     pub score: f32,
 }
 
+/// Corpus of a TF-IDF index as of a version, for the BM25 scores of
+/// searches as of that version to be computed from the corpus stats
+/// (i.e. the no. of documents, overall and per term) of that version
+/// rather than the latest ones
+pub struct CorpusSnapshot<'a> {
+    /// No. of documents indexed as of the version
+    pub documents_count: u32,
+    /// Returns true if the document was indexed as of the version
+    pub is_indexed: Box<dyn Fn(u32) -> bool + 'a>,
+}
+
 impl Eq for SparseAnnResult {}
 impl Eq for SparseAnnIDFResult {}
 
@@ -62,6 +75,13 @@ impl SparseAnnQueryBasic {
         SparseAnnQueryBasic { query_vector }
     }
 
+    /// Performs search over the inverted index
+    ///
+    /// Vectors for which `matches_filter` returns false (i.e. that
+    /// don't match the metadata filter of the query) are excluded from
+    /// the results. With `as_of`, only the data visible in the snapshot
+    /// is searched.
+    #[allow(clippy::too_many_arguments)]
     pub fn sequential_search(
         self,
         index: &InvertedIndexRoot,
@@ -71,6 +91,9 @@ impl SparseAnnQueryBasic {
         early_terminate_threshold: f32,
         reranking_factor: usize,
         k: Option<usize>,
+        tombstones: &Tombstones,
+        as_of: Option<&VersionSnapshot>,
+        matches_filter: impl Fn(u32) -> bool,
     ) -> Result<Vec<SparseAnnResult>, BufIoError> {
         let mut dot_products = FxHashMap::default();
         // same as `1` quantized
@@ -93,16 +116,27 @@ impl SparseAnnQueryBasic {
                 // High quantized value
                 // Iterate through the full list of values for this dimension
                 for key in (0..=one_quantized).rev() {
-                    let pagepool = unsafe { &*node.data }
+                    let mut current_versioned_pagepool = unsafe { &*node.data }
                         .try_get_data(&index.cache, node.dim_index)?
                         .map
                         .lookup(&key);
-                    if let Some(pagepool) = pagepool {
-                        for x in pagepool.iter() {
-                            let vec_id = x;
-                            let dot_product = dot_products.entry(vec_id).or_insert(0u32);
-                            *dot_product += quantized_query_value * key as u32;
+                    while let Some(versioned_pagepool) = current_versioned_pagepool {
+                        for x in versioned_pagepool.pagepool.inner.read().unwrap().iter() {
+                            for x in x.iter() {
+                                let vec_id = *x;
+                                if !tombstones.is_live_as_of(
+                                    &VectorId(vec_id as u64),
+                                    versioned_pagepool.current_version,
+                                    as_of,
+                                ) {
+                                    continue;
+                                }
+                                let dot_product = dot_products.entry(vec_id).or_insert(0u32);
+                                *dot_product += quantized_query_value * key as u32;
+                            }
                         }
+                        current_versioned_pagepool =
+                            versioned_pagepool.next.read().unwrap().clone();
                     }
                 }
             } else {
@@ -119,6 +153,13 @@ impl SparseAnnQueryBasic {
                         for x in versioned_pagepool.pagepool.inner.read().unwrap().iter() {
                             for x in x.iter() {
                                 let vec_id = *x;
+                                if !tombstones.is_live_as_of(
+                                    &VectorId(vec_id as u64),
+                                    versioned_pagepool.current_version,
+                                    as_of,
+                                ) {
+                                    continue;
+                                }
 
                                 let dot_product = dot_products.entry(vec_id).or_insert(0u32);
                                 *dot_product += quantized_query_value * key as u32;
@@ -134,6 +175,7 @@ impl SparseAnnQueryBasic {
         // Convert the heap to a vector and reverse it to get descending order
         let mut results: Vec<SparseAnnResult> = dot_products
             .into_iter()
+            .filter(|(vector_id, _)| matches_filter(*vector_id))
             .map(|(vector_id, similarity)| SparseAnnResult {
                 vector_id,
                 similarity,
@@ -152,15 +194,27 @@ impl SparseAnnQueryBasic {
         Ok(results)
     }
 
+    /// Performs BM25 search over the TF-IDF index
+    ///
+    /// Documents for which `is_live` returns false (e.g. deleted
+    /// ones, or ones not matching the metadata filter of the query)
+    /// are excluded from the results. The IDF of the terms is computed
+    /// from the stats of `corpus` if given (i.e. when searching as of a
+    /// version), and from the stats of the whole index otherwise.
     pub fn search_bm25(
         self,
         index: &TFIDFIndexRoot,
         k: Option<usize>,
+        corpus: Option<&CorpusSnapshot>,
+        is_live: impl Fn(u32) -> bool,
     ) -> Result<Vec<SparseAnnIDFResult>, BufIoError> {
         const BUCKETS: usize = 512;
-        let documents_count = index
-            .total_documents_count
-            .load(std::sync::atomic::Ordering::Relaxed);
+        let documents_count = match corpus {
+            Some(corpus) => corpus.documents_count,
+            None => index
+                .total_documents_count
+                .load(std::sync::atomic::Ordering::Relaxed),
+        };
         let mut heads = BinaryHeap::new();
 
         for (term_hash, _) in self.query_vector.entries {
@@ -169,7 +223,18 @@ impl SparseAnnQueryBasic {
             if let Some(node) = index.find_node(dim_index) {
                 let data = unsafe { &*node.data }.try_get_data(&index.cache, node.dim_index)?;
                 if let Some(term) = data.map.lookup(&quotient) {
-                    let idf = get_idf(documents_count, term.documents.len() as u32);
+                    let documents_containing_term = match corpus {
+                        Some(corpus) => term
+                            .documents
+                            .iter()
+                            .filter(|(document_id, _)| (corpus.is_indexed)(*document_id))
+                            .count() as u32,
+                        None => term.documents.len() as u32,
+                    };
+                    if documents_containing_term == 0 {
+                        continue;
+                    }
+                    let idf = get_idf(documents_count, documents_containing_term);
 
                     let head = PostingListHead::new(&term, idf);
                     heads.push(head);
@@ -207,8 +272,10 @@ impl SparseAnnQueryBasic {
                 }
             }
 
+            // Liveness is checked last as it may involve checking the
+            // document's metadata against the filter of the query
             let index = doc_id as usize % BUCKETS;
-            if score > buckets[index].1 {
+            if score > buckets[index].1 && is_live(doc_id) {
                 buckets[index] = (doc_id, score);
             }
         }
@@ -0,0 +1,598 @@
+use std::{
+    collections::HashSet,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        RwLock,
+    },
+};
+
+use lmdb::{Cursor, Database, RwTransaction, Transaction, WriteFlags};
+
+use crate::macros::key;
+
+use super::{
+    common::WaCustomError,
+    external_ids::has_keys_from,
+    tree_map::{TreeMap, UnsafeVersionedItem},
+    types::{MetaDb, VectorId},
+    versioning::{Hash, VersionSnapshot},
+};
+
+/// Length of a persisted tombstone along with the version it was
+/// recorded in
+const RECORD_LEN: usize = 9;
+
+/// States of an uncommitted version, as persisted under its key.
+/// Versions persisted by older releases have no state and are open.
+const OPEN: u8 = 0;
+const ABORTED: u8 = 1;
+
+/// Marker recorded for a vector id whose indexed data is no longer
+/// (fully) live.
+///
+/// None of the indexes support removing data in place, so instead
+/// of deleting, a tombstone is recorded for the vector id and the
+/// search paths use it to hide data that's not live anymore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tombstone {
+    /// The vector has been deleted, none of its indexed data is live
+    Deleted,
+    /// The vector has been (re-)inserted in the given version after
+    /// being deleted or replaced. Only the data indexed in that
+    /// version is live.
+    Replaced(Hash),
+}
+
+impl Tombstone {
+    pub fn serialize(&self) -> [u8; 5] {
+        let mut bytes = [0u8; 5];
+        if let Self::Replaced(version) = self {
+            bytes[0] = 1;
+            bytes[1..].copy_from_slice(&version.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, WaCustomError> {
+        let bytes: [u8; 5] = bytes.try_into().map_err(|_| {
+            WaCustomError::DeserializationError(
+                "Failed to deserialize tombstone: length mismatch".to_string(),
+            )
+        })?;
+        match bytes[0] {
+            0 => Ok(Self::Deleted),
+            1 => Ok(Self::Replaced(Hash::from(u32::from_le_bytes(
+                bytes[1..].try_into().unwrap(),
+            )))),
+            tag => Err(WaCustomError::DeserializationError(format!(
+                "Invalid tombstone tag: {}",
+                tag
+            ))),
+        }
+    }
+}
+
+/// Tombstones of all vectors of a collection, keyed by vector id
+///
+/// The in-memory map is versioned by the transaction that recorded
+/// the tombstone. The tombstones of every id are also persisted in the
+/// collection's lmdb along with their versions when the transaction is
+/// committed, see [`Tombstones::persist`], so that the liveness of the
+/// data can be determined as of any version.
+///
+/// The data indexed by a transaction is written to the indexes before
+/// it's committed, hence the versions of the transactions that haven't
+/// been committed (i.e. the open one and the aborted ones) are also
+/// tracked here, and none of their data is live.
+#[derive(Default)]
+pub struct Tombstones {
+    map: TreeMap<Tombstone>,
+    // No. of tombstones recorded so far. Used to avoid map lookups
+    // from the search paths for the (common) case of a collection
+    // where no vector has ever been deleted or replaced.
+    count: AtomicUsize,
+    uncommitted_versions: RwLock<HashSet<Hash>>,
+}
+
+impl Tombstones {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, version: Hash, id: &VectorId, tombstone: Tombstone) {
+        self.map.insert(version, id.0, tombstone);
+        self.count.fetch_add(1, Ordering::Release);
+    }
+
+    pub fn get(&self, id: &VectorId) -> Option<Tombstone> {
+        if self.is_empty() {
+            return None;
+        }
+        self.map.get_latest(id.0).copied()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count.load(Ordering::Acquire) == 0
+    }
+
+    /// Returns true if all the indexed data is live i.e. no tombstone
+    /// has been recorded and all the transactions have been committed
+    pub fn is_all_live(&self) -> bool {
+        self.is_empty() && self.uncommitted_versions.read().unwrap().is_empty()
+    }
+
+    /// Returns true if the vector with id `id` has been deleted
+    pub fn is_deleted(&self, id: &VectorId) -> bool {
+        matches!(self.get(id), Some(Tombstone::Deleted))
+    }
+
+    /// Returns true if the data for the vector `id` that was indexed
+    /// in `version` is live i.e. the version has been committed and the
+    /// data is neither deleted nor replaced by a later version.
+    pub fn is_live(&self, id: &VectorId, version: Hash) -> bool {
+        self.is_committed(version) && Self::is_live_with(self.get(id), version)
+    }
+
+    /// Returns true unless `version` is the version of a transaction
+    /// that hasn't been committed
+    pub fn is_committed(&self, version: Hash) -> bool {
+        !self.uncommitted_versions.read().unwrap().contains(&version)
+    }
+
+    /// Returns the latest item of the versioned value that's visible as
+    /// of the snapshot `as_of` (if any) and was recorded in a committed
+    /// version
+    pub fn visible_item<'a, T>(
+        &self,
+        versioned: &'a UnsafeVersionedItem<T>,
+        as_of: Option<&VersionSnapshot>,
+    ) -> Option<&'a UnsafeVersionedItem<T>> {
+        versioned.latest_item_where(|version| {
+            as_of.is_none_or(|as_of| as_of.includes(version)) && self.is_committed(version)
+        })
+    }
+
+    /// Records the version of a transaction that's being started, none
+    /// of the data indexed in it is live until it's committed
+    ///
+    /// Versions that have been recorded before (e.g. the one of an
+    /// aborted transaction) are rejected, as committing them would
+    /// make the data indexed in the earlier transaction live.
+    pub fn begin_version(&self, lmdb: &MetaDb, version: Hash) -> Result<(), WaCustomError> {
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        match txn.put(*db, &key!(u:version), &[OPEN], WriteFlags::NO_OVERWRITE) {
+            Ok(()) => {}
+            Err(lmdb::Error::KeyExist) => {
+                return Err(WaCustomError::DatabaseError(format!(
+                    "Version {} has already been used by another transaction",
+                    *version
+                )))
+            }
+            Err(e) => {
+                return Err(WaCustomError::DatabaseError(format!(
+                    "Failed to put data: {}",
+                    e
+                )))
+            }
+        }
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        self.uncommitted_versions.write().unwrap().insert(version);
+        Ok(())
+    }
+
+    /// Applies the tombstones recorded in the transaction with version
+    /// `version` and makes the data indexed in it live
+    ///
+    /// The tombstones are expected to have been persisted already, see
+    /// [`Self::persist`].
+    pub fn commit_version(
+        &self,
+        lmdb: &MetaDb,
+        version: Hash,
+        tombstones: &[(VectorId, Tombstone)],
+    ) -> Result<(), WaCustomError> {
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        let key = key!(u:version);
+        match txn.get(*db, &key) {
+            Ok(state) if state == [ABORTED] => {
+                return Err(WaCustomError::DatabaseError(format!(
+                    "Version {} has been aborted and can't be committed",
+                    *version
+                )))
+            }
+            Ok(_) => txn.del(*db, &key, None).map_err(|e| {
+                WaCustomError::DatabaseError(format!("Failed to delete data: {}", e))
+            })?,
+            Err(lmdb::Error::NotFound) => {}
+            Err(e) => {
+                return Err(WaCustomError::DatabaseError(format!(
+                    "Failed to get data: {}",
+                    e
+                )))
+            }
+        }
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        for (id, tombstone) in tombstones {
+            self.insert(version, id, *tombstone);
+        }
+        self.uncommitted_versions.write().unwrap().remove(&version);
+        Ok(())
+    }
+
+    /// Records the version of a transaction as aborted for good, none
+    /// of the data indexed in it ever becomes live
+    pub fn abort_version(&self, lmdb: &MetaDb, version: Hash) -> Result<(), WaCustomError> {
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        txn.put(*db, &key!(u:version), &[ABORTED], WriteFlags::empty())
+            .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        self.uncommitted_versions.write().unwrap().insert(version);
+        Ok(())
+    }
+
+    /// Returns the tombstone of the vector with id `id` that was the
+    /// latest as of the snapshot, or the latest one if `as_of` is None
+    pub fn get_as_of(&self, id: &VectorId, as_of: Option<&VersionSnapshot>) -> Option<Tombstone> {
+        let Some(as_of) = as_of else {
+            return self.get(id);
+        };
+        if self.is_empty() {
+            return None;
+        }
+        self.map
+            .get_versioned(id.0)?
+            .item_as_of(as_of)
+            .map(|item| item.value)
+    }
+
+    /// Returns true if the data for the vector `id` that was indexed
+    /// in `version` is visible in the snapshot `as_of` i.e. it was
+    /// indexed in one of the versions of the snapshot and it was live
+    /// as of the snapshot's version. Same as [`Self::is_live`] if
+    /// `as_of` is None.
+    pub fn is_live_as_of(
+        &self,
+        id: &VectorId,
+        version: Hash,
+        as_of: Option<&VersionSnapshot>,
+    ) -> bool {
+        match as_of {
+            None => self.is_live(id, version),
+            Some(as_of) => {
+                as_of.includes(version)
+                    && self.is_committed(version)
+                    && Self::is_live_with(self.get_as_of(id, Some(as_of)), version)
+            }
+        }
+    }
+
+    fn is_live_with(tombstone: Option<Tombstone>, version: Hash) -> bool {
+        match tombstone {
+            None => true,
+            Some(Tombstone::Deleted) => false,
+            Some(Tombstone::Replaced(live_version)) => live_version == version,
+        }
+    }
+
+    /// Persists the given tombstones, recorded in `version`, to lmdb
+    ///
+    /// Every tombstone is persisted under a key of its own, along with
+    /// the version it was recorded in, keyed by the vector id and the
+    /// `version_number` so that the tombstones of an id are loaded in
+    /// the order of their versions.
+    pub fn persist(
+        lmdb: &MetaDb,
+        version: Hash,
+        version_number: u16,
+        tombstones: &[(VectorId, Tombstone)],
+    ) -> Result<(), WaCustomError> {
+        if tombstones.is_empty() {
+            return Ok(());
+        }
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        Self::put_records(&mut txn, *db, version, version_number, tombstones)?;
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        Ok(())
+    }
+
+    fn put_records(
+        txn: &mut RwTransaction,
+        db: Database,
+        version: Hash,
+        version_number: u16,
+        tombstones: &[(VectorId, Tombstone)],
+    ) -> Result<(), WaCustomError> {
+        for (id, tombstone) in tombstones {
+            let mut bytes = [0u8; RECORD_LEN];
+            bytes[..4].copy_from_slice(&version.to_le_bytes());
+            bytes[4..].copy_from_slice(&tombstone.serialize());
+            txn.put(db, &key!(t:id, version_number), &bytes, WriteFlags::empty())
+                .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+        }
+        Ok(())
+    }
+
+    /// Loads all the tombstones persisted in lmdb, along with the
+    /// versions of the transactions that weren't committed
+    ///
+    /// Tombstones persisted without their versions (i.e. by older
+    /// releases, which only persisted the latest tombstone of every
+    /// id) are migrated to the `current_version` of the collection when
+    /// they are first loaded. They are persisted again along with it,
+    /// so that they're loaded against the same version from then on.
+    pub fn load(lmdb: &MetaDb, current_version: Hash) -> Result<Self, WaCustomError> {
+        let tombstones = Self::new();
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+
+        let txn = env.begin_ro_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        let mut cursor = txn
+            .open_ro_cursor(*db)
+            .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
+
+        let mut uncommitted_versions = tombstones.uncommitted_versions.write().unwrap();
+        let start_key = key!(u:Hash::from(0));
+        let keys = has_keys_from(&cursor, &start_key)?.then(|| cursor.iter_from(&start_key));
+        for (k, _) in keys.into_iter().flatten() {
+            if k.len() != 5 || k[0] != 12 {
+                break;
+            }
+            uncommitted_versions.insert(Hash::from(u32::from_le_bytes(k[1..].try_into().unwrap())));
+        }
+        drop(uncommitted_versions);
+
+        let mut legacy = Vec::new();
+        let start_key = key!(t:VectorId(0));
+        let keys = has_keys_from(&cursor, &start_key)?.then(|| cursor.iter_from(&start_key));
+        for (k, v) in keys.into_iter().flatten() {
+            if k[0] != 4 {
+                break;
+            }
+            let id = VectorId(u64::from_le_bytes(k[1..9].try_into().unwrap()));
+            match k.len() {
+                9 => legacy.push((id, Tombstone::deserialize(v)?)),
+                11 if v.len() == RECORD_LEN => {
+                    let version = Hash::from(u32::from_le_bytes(v[..4].try_into().unwrap()));
+                    tombstones.insert(version, &id, Tombstone::deserialize(&v[4..])?);
+                }
+                _ => {
+                    return Err(WaCustomError::DeserializationError(
+                        "Failed to deserialize tombstone: length mismatch".to_string(),
+                    ))
+                }
+            }
+        }
+        drop(cursor);
+        txn.abort();
+
+        if !legacy.is_empty() {
+            Self::migrate_legacy(lmdb, current_version, &legacy)?;
+            for (id, tombstone) in &legacy {
+                tombstones.insert(current_version, id, *tombstone);
+            }
+        }
+
+        Ok(tombstones)
+    }
+
+    /// Persists the tombstones persisted without their versions again
+    /// along with `version`, removing the unversioned ones
+    ///
+    /// They are keyed by version number 0 i.e. ahead of the tombstones
+    /// of any later version.
+    fn migrate_legacy(
+        lmdb: &MetaDb,
+        version: Hash,
+        tombstones: &[(VectorId, Tombstone)],
+    ) -> Result<(), WaCustomError> {
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        Self::put_records(&mut txn, *db, version, 0, tombstones)?;
+        for (id, _) in tombstones {
+            txn.del(*db, &key!(t:id), None).map_err(|e| {
+                WaCustomError::DatabaseError(format!("Failed to delete data: {}", e))
+            })?;
+        }
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use lmdb::Environment;
+
+    use crate::models::versioning::{BranchId, Version, VersionHash, VersionRef};
+
+    use super::*;
+
+    #[test]
+    fn test_tombstone_serde() {
+        for tombstone in [Tombstone::Deleted, Tombstone::Replaced(Hash::from(42))] {
+            let bytes = tombstone.serialize();
+            assert_eq!(tombstone, Tombstone::deserialize(&bytes).unwrap());
+        }
+        assert!(Tombstone::deserialize(&[2, 0, 0, 0, 0]).is_err());
+        assert!(Tombstone::deserialize(&[0]).is_err());
+    }
+
+    #[test]
+    fn test_is_live() {
+        let tombstones = Tombstones::new();
+        let v1 = Hash::from(1);
+        let v2 = Hash::from(2);
+        let v3 = Hash::from(3);
+        let id = VectorId(7);
+
+        assert!(tombstones.is_empty());
+        assert!(tombstones.is_live(&id, v1));
+
+        tombstones.insert(v2, &id, Tombstone::Deleted);
+        assert!(tombstones.is_deleted(&id));
+        assert!(!tombstones.is_live(&id, v1));
+        assert!(!tombstones.is_live(&id, v2));
+
+        tombstones.insert(v3, &id, Tombstone::Replaced(v3));
+        assert!(!tombstones.is_deleted(&id));
+        assert!(!tombstones.is_live(&id, v1));
+        assert!(tombstones.is_live(&id, v3));
+
+        // Other ids are unaffected
+        assert!(tombstones.is_live(&VectorId(8), v1));
+    }
+
+    #[test]
+    fn test_is_live_as_of() {
+        let main = BranchId::new("main");
+        let branch_versions: Vec<_> = (1..=3)
+            .map(|version| {
+                (
+                    Hash::from(version as u32),
+                    VersionHash::new(main, Version::from(version)),
+                )
+            })
+            .collect();
+        let [v1, v2, v3] = [1, 2, 3].map(Hash::from);
+        let as_of = |version| {
+            VersionSnapshot::new(&branch_versions, v3, VersionRef::Hash(version)).unwrap()
+        };
+        let (as_of_v1, as_of_v2, as_of_v3) = (as_of(v1), as_of(v2), as_of(v3));
+
+        // Inserted in v1, deleted in v2 and re-inserted in v3
+        let tombstones = Tombstones::new();
+        let id = VectorId(7);
+        tombstones.insert(v2, &id, Tombstone::Deleted);
+        tombstones.insert(v3, &id, Tombstone::Replaced(v3));
+
+        assert!(tombstones.is_live_as_of(&id, v1, Some(&as_of_v1)));
+        assert!(!tombstones.is_live_as_of(&id, v3, Some(&as_of_v1)));
+        assert!(!tombstones.is_live_as_of(&id, v1, Some(&as_of_v2)));
+        assert_eq!(
+            Some(Tombstone::Deleted),
+            tombstones.get_as_of(&id, Some(&as_of_v2))
+        );
+        assert!(!tombstones.is_live_as_of(&id, v1, Some(&as_of_v3)));
+        assert!(tombstones.is_live_as_of(&id, v3, Some(&as_of_v3)));
+        assert!(tombstones.is_live_as_of(&id, v3, None));
+
+        // Inserted in v2
+        let id = VectorId(8);
+        assert!(!tombstones.is_live_as_of(&id, v2, Some(&as_of_v1)));
+        assert!(tombstones.is_live_as_of(&id, v2, Some(&as_of_v2)));
+        assert!(tombstones.is_live_as_of(&id, v2, Some(&as_of_v3)));
+    }
+
+    #[test]
+    fn test_persist_and_load() {
+        let path = tempfile::tempdir().unwrap().into_path();
+        let env = Environment::new().set_max_dbs(1).open(&path).unwrap();
+        let lmdb = MetaDb::from_env(Arc::new(env), "test").unwrap();
+        let [v1, v2, v3, v4] = [1, 2, 3, 4].map(Hash::from);
+        let (id, legacy_id) = (VectorId(7), VectorId(8));
+
+        // Tombstone persisted without its version
+        let env = lmdb.env.clone();
+        let mut txn = env.begin_rw_txn().unwrap();
+        txn.put(
+            *lmdb.db,
+            &key!(t:legacy_id),
+            &Tombstone::Deleted.serialize(),
+            WriteFlags::empty(),
+        )
+        .unwrap();
+        txn.commit().unwrap();
+
+        // Versions are persisted out of the order of their hashes
+        Tombstones::persist(&lmdb, v3, 1, &[(id.clone(), Tombstone::Deleted)]).unwrap();
+        Tombstones::persist(&lmdb, v1, 2, &[(id.clone(), Tombstone::Replaced(v1))]).unwrap();
+
+        let tombstones = Tombstones::load(&lmdb, v2).unwrap();
+        assert_eq!(Some(Tombstone::Replaced(v1)), tombstones.get(&id));
+        let item = tombstones.map.get_versioned(id.0).unwrap();
+        assert_eq!(vec![v3, v1], versions(item));
+        assert_eq!(
+            vec![v2],
+            versions(tombstones.map.get_versioned(legacy_id.0).unwrap())
+        );
+
+        // The legacy tombstone keeps the version it was first loaded
+        // against
+        let tombstones = Tombstones::load(&lmdb, v4).unwrap();
+        assert!(tombstones.is_deleted(&legacy_id));
+        assert_eq!(
+            vec![v2],
+            versions(tombstones.map.get_versioned(legacy_id.0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_aborted_version_is_not_reused() {
+        let path = tempfile::tempdir().unwrap().into_path();
+        let env = Environment::new().set_max_dbs(1).open(&path).unwrap();
+        let lmdb = MetaDb::from_env(Arc::new(env), "test").unwrap();
+        let version = Hash::from(1);
+
+        let tombstones = Tombstones::new();
+        tombstones.begin_version(&lmdb, version).unwrap();
+        tombstones.abort_version(&lmdb, version).unwrap();
+
+        // A transaction with the same hash as the aborted one can
+        // neither be started nor committed
+        assert!(tombstones.begin_version(&lmdb, version).is_err());
+        assert!(tombstones.commit_version(&lmdb, version, &[]).is_err());
+        assert!(!tombstones.is_committed(version));
+
+        let tombstones = Tombstones::load(&lmdb, Hash::from(2)).unwrap();
+        assert!(!tombstones.is_committed(version));
+        assert!(tombstones.commit_version(&lmdb, version, &[]).is_err());
+    }
+
+    fn versions<T>(item: &UnsafeVersionedItem<T>) -> Vec<Hash> {
+        let mut versions = Vec::new();
+        item.latest_item_where(|version| {
+            versions.push(version);
+            false
+        });
+        versions
+    }
+}
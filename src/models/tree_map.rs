@@ -17,7 +17,7 @@ use super::{
     serializer::{PartitionedSerialize, SimpleSerialize},
     types::FileOffset,
     utils::calculate_path,
-    versioning::Hash,
+    versioning::{Hash, VersionSnapshot},
 };
 
 pub struct TreeMap<T> {
@@ -157,11 +157,40 @@ impl<T> UnsafeVersionedItem<T> {
     }
 
     pub fn latest(&self) -> &T {
+        &self.latest_item().value
+    }
+
+    pub fn latest_item(&self) -> &Self {
         if let Some(next) = unsafe { &*self.next.get() } {
-            return next.latest();
+            return next.latest_item();
         }
 
-        &self.value
+        self
+    }
+
+    /// Returns the latest item that's visible in the snapshot i.e. the
+    /// one that was latest as of the snapshot's version
+    ///
+    /// Items are expected to be pushed in the order of their versions.
+    pub fn item_as_of(&self, snapshot: &VersionSnapshot) -> Option<&Self> {
+        self.latest_item_where(|version| snapshot.includes(version))
+    }
+
+    /// Returns the latest item whose version satisfies the predicate
+    ///
+    /// Items are expected to be pushed in the order of their versions.
+    pub fn latest_item_where(&self, mut predicate: impl FnMut(Hash) -> bool) -> Option<&Self> {
+        let mut item = self;
+        let mut visible = None;
+        loop {
+            if predicate(item.version) {
+                visible = Some(item);
+            }
+            match unsafe { &*item.next.get() } {
+                Some(next) => item = next,
+                None => return visible,
+            }
+        }
     }
 }
 
@@ -200,6 +229,17 @@ impl<T> TreeMapNode<T> {
     pub fn get_versioned(&self, quotient: u64) -> Option<&UnsafeVersionedItem<T>> {
         self.quotients.get_versioned(quotient)
     }
+
+    fn collect_keys(&self, keys: &mut Vec<u64>) {
+        self.quotients
+            .map
+            .for_each(|quotient, _| keys.push(*quotient));
+        for i in 0..8 {
+            if let Some(child) = self.children.get(i) {
+                unsafe { &*child }.collect_keys(keys);
+            }
+        }
+    }
 }
 
 impl<T> Default for QuotientsMap<T> {
@@ -300,6 +340,14 @@ impl<T> TreeMap<T> {
         let node = self.root.find_or_create_node(&path);
         node.get_versioned(key)
     }
+
+    /// Returns all the keys present in the map, in ascending order
+    pub fn keys(&self) -> Vec<u64> {
+        let mut keys = Vec::new();
+        self.root.collect_keys(&mut keys);
+        keys.sort_unstable();
+        keys
+    }
 }
 
 impl<T: SimpleSerialize> TreeMap<T> {
@@ -354,4 +402,15 @@ mod tests {
             })
         );
     }
+
+    #[test]
+    fn test_keys() {
+        let map = TreeMap::new();
+        assert!(map.keys().is_empty());
+        for key in [65536, 7, 0, 131073, 3] {
+            map.insert(0.into(), key, key);
+        }
+        map.insert(1.into(), 7, 8);
+        assert_eq!(map.keys(), vec![0, 3, 7, 65536, 131073]);
+    }
 }
@@ -3,16 +3,21 @@ use super::{
     cache_loader::HNSWIndexCache,
     collection::{Collection, CollectionMetadata},
     crypto::{DoubleSHA256Hash, SingleSHA256Hash},
+    external_ids::ExternalIds,
     inverted_index::InvertedIndexRoot,
     meta_persist::{
         lmdb_init_collections_db, lmdb_init_db, load_collections, retrieve_average_document_length,
         retrieve_current_version, retrieve_highest_internal_id, retrieve_values_upper_bound,
     },
+    multi_vectors::MultiVectors,
     paths::get_data_path,
     prob_lazy_load::lazy_item::FileIndex,
     prob_node::ProbNode,
     tf_idf_index::TFIDFIndexRoot,
+    tombstones::Tombstones,
     tree_map::TreeMap,
+    vector_ids::VectorIds,
+    vector_metadata::VectorMetadata,
     versioning::VersionControl,
 };
 use crate::{
@@ -25,10 +30,16 @@ use crate::{
         hamming::HammingDistance,
         DistanceError, DistanceFunction,
     },
-    indexes::{hnsw::HNSWIndex, inverted::InvertedIndex, tf_idf::TFIDFIndex, IndexOps},
+    indexes::{
+        hnsw::{dense_index_data_name, dense_index_path, HNSWIndex},
+        inverted::InvertedIndex,
+        tf_idf::TFIDFIndex,
+        IndexOps,
+    },
     metadata::{schema::MetadataDimensions, QueryFilterDimensions, HIGH_WEIGHT},
     models::{
-        buffered_io::BufIoError, common::*, meta_persist::retrieve_values_range, versioning::*,
+        buffered_io::BufIoError, common::*, dot_product::dot_product_f32,
+        meta_persist::retrieve_values_range, versioning::*,
     },
     quantization::{
         product::ProductQuantization, scalar::ScalarQuantization, Quantization, QuantizationError,
@@ -47,6 +58,7 @@ use std::sync::atomic::{AtomicBool, AtomicUsize};
 use std::sync::{Arc, RwLock};
 use std::time::Instant;
 use std::{
+    collections::BTreeMap,
     fmt,
     fs::{create_dir_all, OpenOptions},
     str::FromStr,
@@ -261,6 +273,31 @@ impl MetricResult {
         }
     }
 
+    /// Returns true if the result is at least as good as the
+    /// threshold i.e. a similarity not less than it, or a distance not
+    /// greater than it
+    pub fn meets_threshold(&self, threshold: f32) -> bool {
+        match self {
+            Self::CosineSimilarity(value) => value.0 >= threshold,
+            Self::DotProductDistance(value) => value.0 >= threshold,
+            Self::CosineDistance(value) => value.0 <= threshold,
+            Self::EuclideanDistance(value) => value.0 <= threshold,
+            Self::HammingDistance(value) => value.0 <= threshold,
+        }
+    }
+
+    /// Returns the value of the result such that higher means more
+    /// similar, irrespective of whether the metric is a distance
+    pub fn similarity(&self) -> f32 {
+        match self {
+            Self::CosineSimilarity(value) => value.0,
+            Self::DotProductDistance(value) => value.0,
+            Self::CosineDistance(value) => -value.0,
+            Self::EuclideanDistance(value) => -value.0,
+            Self::HammingDistance(value) => -value.0,
+        }
+    }
+
     pub fn get_tag_and_value(&self) -> (u8, f32) {
         match self {
             Self::CosineSimilarity(value) => (0, value.0),
@@ -334,6 +371,36 @@ impl DistanceFunction for DistanceMetric {
     }
 }
 
+impl DistanceMetric {
+    /// Calculates the exact score between two raw (i.e. unquantized)
+    /// vectors, used for reranking the results of the index
+    ///
+    /// Returns None for hamming distance, as it's only defined for
+    /// binary quantized vectors.
+    pub fn calculate_raw(&self, x: &[f32], y: &[f32]) -> Option<MetricResult> {
+        match self {
+            Self::Cosine => {
+                let dp = dot_product_f32(x, y);
+                let mag_x = x.iter().map(|v| v * v).sum::<f32>().sqrt();
+                let mag_y = y.iter().map(|v| v * v).sum::<f32>().sqrt();
+                Some(MetricResult::CosineSimilarity(CosineSimilarity(
+                    dp / (mag_x * mag_y),
+                )))
+            }
+            Self::Euclidean => {
+                let sum = x.iter().zip(y).map(|(a, b)| (a - b) * (a - b)).sum::<f32>();
+                Some(MetricResult::EuclideanDistance(EuclideanDistance(
+                    sum.sqrt(),
+                )))
+            }
+            Self::DotProduct => Some(MetricResult::DotProductDistance(DotProductDistance(
+                dot_product_f32(x, y),
+            ))),
+            Self::Hamming => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum QuantizationMetric {
     Scalar,
@@ -429,12 +496,27 @@ impl CollectionsMap {
             // if collection has dense index load it from the lmdb
             let hnsw_index = if collection_meta.dense_vector.enabled {
                 collections_map
-                    .load_hnsw_index(&collection_meta, &lmdb, &vcs, config)?
+                    .load_hnsw_index(&collection_meta, None, &lmdb, &vcs, config)?
                     .map(Arc::new)
             } else {
                 None
             };
 
+            // named dense indexes are loaded for the vector spaces for
+            // which they have been created
+            let mut named_hnsw_indexes = BTreeMap::new();
+            for named_vector in &collection_meta.dense_vector.named_vectors {
+                if let Some(hnsw_index) = collections_map.load_hnsw_index(
+                    &collection_meta,
+                    Some(&named_vector.name),
+                    &lmdb,
+                    &vcs,
+                    config,
+                )? {
+                    named_hnsw_indexes.insert(named_vector.name.clone(), Arc::new(hnsw_index));
+                }
+            }
+
             // if collection has inverted index load it from the lmdb
             let inverted_index = if collection_meta.sparse_vector.enabled {
                 collections_map
@@ -452,6 +534,11 @@ impl CollectionsMap {
                 None
             };
 
+            let tombstones = Tombstones::load(&lmdb, current_version)?;
+            let external_ids = ExternalIds::load(&lmdb)?;
+            let multi_vectors = MultiVectors::load(&lmdb)?;
+            let vector_metadata = VectorMetadata::load(&lmdb)?;
+
             let collection = Collection {
                 meta: collection_meta,
                 lmdb,
@@ -459,10 +546,18 @@ impl CollectionsMap {
                 current_open_transaction: RwLock::new(None),
                 vcs,
                 hnsw_index: RwLock::new(hnsw_index),
+                named_hnsw_indexes: RwLock::new(named_hnsw_indexes),
                 inverted_index: RwLock::new(inverted_index),
                 tf_idf_index: RwLock::new(tf_idf_index),
+                tombstones,
+                external_ids,
+                multi_vectors,
+                vector_metadata,
             };
 
+            VectorIds::backfill(&collection)?;
+            VectorMetadata::backfill(&collection)?;
+
             collections_map
                 .inner_collections
                 .insert(collection.meta.name.clone(), Arc::new(collection));
@@ -474,15 +569,19 @@ impl CollectionsMap {
     ///
     /// In doing so, the root vec for all collections' dense indexes are loaded into
     /// memory, which also ends up warming the cache (NodeRegistry)
+    ///
+    /// `vector_name` selects one of the named dense indexes instead of
+    /// the default one
     fn load_hnsw_index(
         &self,
         collection_meta: &CollectionMetadata,
+        vector_name: Option<&str>,
         lmdb: &MetaDb,
         vcs: &VersionControl,
         config: &Config,
     ) -> Result<Option<HNSWIndex>, WaCustomError> {
         let collection_path: Arc<Path> = get_collections_path().join(&collection_meta.name).into();
-        let index_path = collection_path.join("dense_hnsw");
+        let index_path = dense_index_path(&collection_path, vector_name);
 
         // Check if the path exists before proceeding
         if !index_path.exists() {
@@ -492,7 +591,7 @@ impl CollectionsMap {
         let Some(hnsw_index_data) = HNSWIndex::load_data(
             &self.lmdb_env,
             self.lmdb_hnsw_index_db,
-            &collection_meta.name,
+            &dense_index_data_name(&collection_meta.name, vector_name),
         )?
         else {
             return Ok(None);
@@ -683,7 +782,7 @@ impl CollectionsMap {
         let load_time = load_start.elapsed();
         println!("Loaded regions in: {:?}", load_time);
 
-        let values_range_result = retrieve_values_range(lmdb);
+        let values_range_result = retrieve_values_range(lmdb, vector_name);
         let values_range = match values_range_result {
             Ok(vr) => vr,
             Err(e) => {
@@ -706,6 +805,7 @@ impl CollectionsMap {
             values_range.unwrap_or((-1.0, 1.0)),
             hnsw_index_data.sample_threshold,
             values_range.is_some(),
+            vector_name.map(str::to_string),
         );
 
         Ok(Some(hnsw_index))
@@ -794,13 +894,21 @@ impl CollectionsMap {
 
         let average_document_length = retrieve_average_document_length(lmdb)?;
         let highest_internal_id = retrieve_highest_internal_id(lmdb)?;
+        let vec_raw_map =
+            TreeMap::deserialize(&vec_raw_manager, config.inverted_index_data_file_parts)?;
+        let document_ids_manager = TFIDFIndex::document_ids_manager(&index_path);
+        let document_ids = TFIDFIndex::load_document_ids(
+            &index_path,
+            &document_ids_manager,
+            &vec_raw_map,
+            config.inverted_index_data_file_parts,
+        )?;
         let inverted_index = TFIDFIndex {
             root: TFIDFIndexRoot::deserialize(index_path, config.inverted_index_data_file_parts)?,
-            vec_raw_map: TreeMap::deserialize(
-                &vec_raw_manager,
-                config.inverted_index_data_file_parts,
-            )?,
+            vec_raw_map,
             vec_raw_manager,
+            document_ids_manager,
+            document_ids,
             average_document_length: RwLock::new(average_document_length.unwrap_or(1.0)),
             is_configured: AtomicBool::new(average_document_length.is_some()),
             documents: RwLock::new(Vec::new()),
@@ -822,11 +930,20 @@ impl CollectionsMap {
         hnsw_index: Arc<HNSWIndex>,
     ) -> Result<(), WaCustomError> {
         hnsw_index.persist(
-            &collection.meta.name,
+            &dense_index_data_name(&collection.meta.name, hnsw_index.vector_name.as_deref()),
             &self.lmdb_env,
             self.lmdb_hnsw_index_db,
         )?;
-        *collection.hnsw_index.write().unwrap() = Some(hnsw_index);
+        match &hnsw_index.vector_name {
+            Some(vector_name) => {
+                collection
+                    .named_hnsw_indexes
+                    .write()
+                    .unwrap()
+                    .insert(vector_name.clone(), hnsw_index);
+            }
+            None => *collection.hnsw_index.write().unwrap() = Some(hnsw_index),
+        }
         Ok(())
     }
 
@@ -1174,8 +1291,10 @@ pub fn get_app_env(config: &Config, args: CosdataArgs) -> Result<Arc<AppEnv>, Wa
     // Ensure the database directory exists
     create_dir_all(&db_path).map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
     // Initialize the environment
+    // Every collection uses a named database of its own, along with
+    // the few databases shared by all collections
     let env = Environment::new()
-        .set_max_dbs(10)
+        .set_max_dbs(1024)
         .set_map_size(1048576000) // Set the maximum size of the database to 1GB
         .open(&db_path)
         .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
@@ -1255,7 +1374,47 @@ impl SparseVector {
 mod tests {
     use crate::distance::cosine::CosineSimilarity;
 
-    use super::MetricResult;
+    use super::{DistanceMetric, MetricResult};
+    use crate::distance::euclidean::EuclideanDistance;
+
+    #[test]
+    fn test_calculate_raw() {
+        let x = [1.0, 0.0];
+        let y = [3.0, 4.0];
+
+        let cs = DistanceMetric::Cosine.calculate_raw(&x, &y).unwrap();
+        assert!(matches!(cs, MetricResult::CosineSimilarity(_)));
+        assert!((cs.get_value() - 0.6).abs() < 1e-6);
+
+        let ed = DistanceMetric::Euclidean.calculate_raw(&x, &y).unwrap();
+        assert!(matches!(ed, MetricResult::EuclideanDistance(_)));
+        assert!((ed.get_value() - 20.0f32.sqrt()).abs() < 1e-6);
+
+        let dp = DistanceMetric::DotProduct.calculate_raw(&x, &y).unwrap();
+        assert!(matches!(dp, MetricResult::DotProductDistance(_)));
+        assert!((dp.get_value() - 3.0).abs() < 1e-6);
+
+        assert!(DistanceMetric::Hamming.calculate_raw(&x, &y).is_none());
+
+        // Smaller euclidean distances rank higher
+        let closer = DistanceMetric::Euclidean
+            .calculate_raw(&x, &[1.0, 1.0])
+            .unwrap();
+        assert!(closer > ed);
+    }
+
+    #[test]
+    fn test_meets_threshold() {
+        let cs = MetricResult::CosineSimilarity(CosineSimilarity(0.95));
+        assert!(cs.meets_threshold(0.9));
+        assert!(cs.meets_threshold(0.95));
+        assert!(!cs.meets_threshold(0.96));
+
+        let ed = MetricResult::EuclideanDistance(EuclideanDistance(0.5));
+        assert!(ed.meets_threshold(0.6));
+        assert!(ed.meets_threshold(0.5));
+        assert!(!ed.meets_threshold(0.4));
+    }
 
     #[test]
     fn test_metric_result_ordering() {
@@ -0,0 +1,176 @@
+use lmdb::{Transaction, WriteFlags};
+use serde::Serialize;
+
+use crate::macros::key;
+
+use super::{common::WaCustomError, types::MetaDb, vector_ids::VectorPresence, versioning::Hash};
+
+/// No. of live vectors in a version of a collection
+///
+/// A vector is counted in an index if it has live (i.e. neither
+/// deleted nor replaced) data in it, and in `total` if it has live
+/// data in any of the indexes. `dense` only counts the default dense
+/// index, the named dense indexes only contribute to `total`.
+///
+/// The counts of a version are derived from the ones of the version
+/// it's based on, by applying the changes to the presence of the
+/// vectors made in the transaction, see `VectorIds::persist`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct VectorCounts {
+    pub total: u64,
+    pub dense: u64,
+    pub sparse: u64,
+    pub tf_idf: u64,
+}
+
+impl VectorCounts {
+    pub fn serialize(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (chunk, count) in
+            bytes
+                .chunks_exact_mut(8)
+                .zip([self.total, self.dense, self.sparse, self.tf_idf])
+        {
+            chunk.copy_from_slice(&count.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, WaCustomError> {
+        if bytes.len() != 32 {
+            return Err(WaCustomError::DeserializationError(
+                "Failed to deserialize vector counts: length mismatch".to_string(),
+            ));
+        }
+        let count = |i: usize| u64::from_le_bytes(bytes[i * 8..(i + 1) * 8].try_into().unwrap());
+        Ok(Self {
+            total: count(0),
+            dense: count(1),
+            sparse: count(2),
+            tf_idf: count(3),
+        })
+    }
+
+    /// Updates the counts for a vector whose presence changed from
+    /// `old` to `new`
+    pub fn apply(&mut self, old: VectorPresence, new: VectorPresence) {
+        let counts = [
+            (&mut self.total, !old.is_empty(), !new.is_empty()),
+            (
+                &mut self.dense,
+                old.contains(VectorPresence::DENSE),
+                new.contains(VectorPresence::DENSE),
+            ),
+            (
+                &mut self.sparse,
+                old.contains(VectorPresence::SPARSE),
+                new.contains(VectorPresence::SPARSE),
+            ),
+            (
+                &mut self.tf_idf,
+                old.contains(VectorPresence::TF_IDF),
+                new.contains(VectorPresence::TF_IDF),
+            ),
+        ];
+        for (count, was_counted, is_counted) in counts {
+            match (was_counted, is_counted) {
+                (false, true) => *count += 1,
+                (true, false) => *count = count.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+
+    /// Persists the counts of the `version` to lmdb
+    pub fn persist(&self, lmdb: &MetaDb, version: Hash) -> Result<(), WaCustomError> {
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        txn.put(
+            *db,
+            &key!(c:version),
+            &self.serialize(),
+            WriteFlags::empty(),
+        )
+        .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        Ok(())
+    }
+
+    /// Loads the counts of the `version` from lmdb, if they have been
+    /// persisted
+    pub fn load(lmdb: &MetaDb, version: Hash) -> Result<Option<Self>, WaCustomError> {
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+
+        let txn = env.begin_ro_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        let counts = match txn.get(*db, &key!(c:version)) {
+            Ok(bytes) => Some(Self::deserialize(bytes)?),
+            Err(lmdb::Error::NotFound) => None,
+            Err(e) => {
+                return Err(WaCustomError::DatabaseError(format!(
+                    "Failed to get vector counts: {}",
+                    e
+                )))
+            }
+        };
+        txn.abort();
+
+        Ok(counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vector_counts_serde() {
+        let counts = VectorCounts {
+            total: 10,
+            dense: 7,
+            sparse: 3,
+            tf_idf: u64::MAX,
+        };
+        let bytes = counts.serialize();
+        assert_eq!(counts, VectorCounts::deserialize(&bytes).unwrap());
+        assert!(VectorCounts::deserialize(&bytes[..31]).is_err());
+    }
+
+    #[test]
+    fn test_apply() {
+        let mut counts = VectorCounts::default();
+        let dense_sparse = VectorPresence::DENSE | VectorPresence::SPARSE;
+        counts.apply(VectorPresence::default(), dense_sparse);
+        counts.apply(VectorPresence::default(), VectorPresence::OTHER);
+        assert_eq!(
+            VectorCounts {
+                total: 2,
+                dense: 1,
+                sparse: 1,
+                tf_idf: 0,
+            },
+            counts
+        );
+
+        counts.apply(dense_sparse, VectorPresence::TF_IDF);
+        counts.apply(VectorPresence::OTHER, VectorPresence::default());
+        assert_eq!(
+            VectorCounts {
+                total: 1,
+                dense: 0,
+                sparse: 0,
+                tf_idf: 1,
+            },
+            counts
+        );
+    }
+}
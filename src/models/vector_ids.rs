@@ -0,0 +1,308 @@
+use std::{
+    collections::HashMap,
+    ops::{BitOr, BitOrAssign},
+};
+
+use lmdb::{Cursor, Transaction, WriteFlags};
+
+use crate::{macros::key, vector_store::get_dense_embedding_versions};
+
+use super::{
+    collection::Collection,
+    common::WaCustomError,
+    external_ids::{has_keys_from, read},
+    types::{MetaDb, VectorId},
+    vector_counts::VectorCounts,
+};
+
+/// Indexes in which a vector has live data, as bit flags
+///
+/// `DENSE` is only about the default dense index, the named dense
+/// indexes (including the multi-vector ones) are covered by `OTHER`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VectorPresence(u8);
+
+impl VectorPresence {
+    pub const DENSE: Self = Self(1);
+    pub const SPARSE: Self = Self(1 << 1);
+    pub const TF_IDF: Self = Self(1 << 2);
+    pub const OTHER: Self = Self(1 << 3);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for VectorPresence {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for VectorPresence {
+    fn bitor_assign(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+}
+
+/// Change to the presence of a vector made by a transaction
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PresenceChange {
+    /// Whether the data of the vector in earlier versions has been
+    /// retired (i.e. the vector has been deleted or replaced)
+    pub replaces: bool,
+    /// Indexes in which data has been inserted for the vector
+    pub presence: VectorPresence,
+}
+
+impl PresenceChange {
+    /// Combines the change with a later one in the same transaction
+    pub fn then(self, later: Self) -> Self {
+        if later.replaces {
+            later
+        } else {
+            Self {
+                replaces: self.replaces,
+                presence: self.presence | later.presence,
+            }
+        }
+    }
+
+    fn apply(&self, presence: VectorPresence) -> VectorPresence {
+        if self.replaces {
+            self.presence
+        } else {
+            presence | self.presence
+        }
+    }
+}
+
+/// Ids of the vectors inserted in a collection, in ascending order,
+/// along with their latest [`VectorPresence`]
+///
+/// The keys of the data of the indexes are ordered by the little
+/// endian bytes of the ids (if they are ordered at all), hence the ids
+/// are recorded under keys of their own, for the vectors to be listed
+/// page by page with a range scan. Ids are recorded when the
+/// transaction inserting them is committed and are never removed
+/// (the presence of deleted vectors is empty), i.e. whether the
+/// vector is live as of a version has to be checked separately.
+pub struct VectorIds;
+
+impl VectorIds {
+    /// Records the changes to the presence of the vectors made in a
+    /// transaction, and returns the counts of the transaction's version
+    /// given the ones of the version it's based on
+    pub fn persist(
+        lmdb: &MetaDb,
+        changes: impl IntoIterator<Item = (VectorId, PresenceChange)>,
+        mut counts: VectorCounts,
+    ) -> Result<VectorCounts, WaCustomError> {
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        for (id, change) in changes {
+            let key = key!(l:id);
+            let presence = match txn.get(*db, &key) {
+                Ok(bytes) => VectorPresence(bytes.first().copied().unwrap_or_default()),
+                Err(lmdb::Error::NotFound) => VectorPresence::default(),
+                Err(e) => {
+                    return Err(WaCustomError::DatabaseError(format!(
+                        "Failed to get data: {}",
+                        e
+                    )))
+                }
+            };
+            let new_presence = change.apply(presence);
+            counts.apply(presence, new_presence);
+            txn.put(*db, &key, &[new_presence.0], WriteFlags::empty())
+                .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+        }
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        Ok(counts)
+    }
+
+    /// Returns up to `limit` ids, in ascending order, following `after`
+    /// (if any)
+    pub fn get_range(
+        lmdb: &MetaDb,
+        after: Option<&VectorId>,
+        limit: usize,
+    ) -> Result<Vec<VectorId>, WaCustomError> {
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+        let txn = env.begin_ro_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        let mut cursor = txn
+            .open_ro_cursor(*db)
+            .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
+
+        let start_key = match after {
+            Some(VectorId(u64::MAX)) => return Ok(Vec::new()),
+            Some(id) => key!(l:VectorId(id.0 + 1)),
+            None => key!(l:VectorId(0)),
+        };
+        if !has_keys_from(&cursor, &start_key)? {
+            return Ok(Vec::new());
+        }
+        let mut ids = Vec::with_capacity(limit);
+        for (k, _) in cursor.iter_from(&start_key).take(limit) {
+            if k.len() != start_key.len() || k[0] != start_key[0] {
+                break;
+            }
+            ids.push(VectorId(u64::from_be_bytes(k[1..].try_into().unwrap())));
+        }
+
+        Ok(ids)
+    }
+
+    /// Records the ids and presence of the vectors in the indexes of a
+    /// collection created before they were recorded, along with the
+    /// counts of its current version (if missing)
+    ///
+    /// This is done only once per collection, when it's loaded.
+    pub fn backfill(collection: &Collection) -> Result<(), WaCustomError> {
+        if read(&collection.lmdb, &key!(m:vector_ids_recorded))?.is_some() {
+            return Ok(());
+        }
+
+        let changes = get_live_presence(collection)?
+            .into_iter()
+            .map(|(id, presence)| {
+                let change = PresenceChange {
+                    replaces: true,
+                    presence,
+                };
+                (id, change)
+            });
+        let counts = Self::persist(&collection.lmdb, changes, VectorCounts::default())?;
+        let current_version = *collection.current_version.read().unwrap();
+        if VectorCounts::load(&collection.lmdb, current_version)?.is_none() {
+            counts.persist(&collection.lmdb, current_version)?;
+        }
+        Self::mark_recorded(&collection.lmdb)
+    }
+
+    /// Marks the ids of the vectors of the collection as being
+    /// recorded, which is the case from its creation onwards
+    pub fn mark_recorded(lmdb: &MetaDb) -> Result<(), WaCustomError> {
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        txn.put(*db, &key!(m:vector_ids_recorded), &[], WriteFlags::empty())
+            .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        Ok(())
+    }
+}
+
+/// Returns the presence of the vectors that have live data in any of
+/// the indexes of the collection, by going through all of them
+fn get_live_presence(
+    collection: &Collection,
+) -> Result<HashMap<VectorId, VectorPresence>, WaCustomError> {
+    let tombstones = &collection.tombstones;
+    let mut live_presence: HashMap<_, VectorPresence> = HashMap::new();
+
+    for hnsw_index in collection.get_dense_indexes() {
+        let presence = match hnsw_index.vector_name {
+            Some(_) => VectorPresence::OTHER,
+            None => VectorPresence::DENSE,
+        };
+        for (id, version) in get_dense_embedding_versions(collection, &hnsw_index)? {
+            if tombstones.is_live(&id, version) {
+                *live_presence.entry(id).or_default() |= presence;
+            }
+        }
+    }
+    for (vector_name, _) in collection.get_multi_vector_indexes() {
+        for (id, document) in collection
+            .multi_vectors
+            .get_documents(&collection.lmdb, &vector_name)?
+        {
+            if tombstones.is_live(&id, document.version) {
+                *live_presence.entry(id).or_default() |= VectorPresence::OTHER;
+            }
+        }
+    }
+
+    if let Some(inverted_index) = collection.get_inverted_index() {
+        for key in inverted_index.vec_raw_map.keys() {
+            let Some(item) = inverted_index.vec_raw_map.get_versioned(key) else {
+                continue;
+            };
+            let Some(item) = tombstones.visible_item(item, None) else {
+                continue;
+            };
+            if tombstones.is_live(&VectorId(key), item.version) {
+                *live_presence.entry(VectorId(key)).or_default() |= VectorPresence::SPARSE;
+            }
+        }
+    }
+
+    if let Some(tf_idf_index) = collection.get_tf_idf_index() {
+        for document_id in tf_idf_index.vec_raw_map.keys() {
+            let Some((id, _)) = tf_idf_index.vec_raw_map.get_latest(document_id) else {
+                continue;
+            };
+            if tf_idf_index.is_document_live(document_id as u32, tombstones, None) {
+                *live_presence.entry(id.clone()).or_default() |= VectorPresence::TF_IDF;
+            }
+        }
+    }
+
+    Ok(live_presence)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_presence_change() {
+        let dense = PresenceChange {
+            replaces: false,
+            presence: VectorPresence::DENSE,
+        };
+        let replaced_by_sparse = PresenceChange {
+            replaces: true,
+            presence: VectorPresence::SPARSE,
+        };
+        let deleted = PresenceChange {
+            replaces: true,
+            presence: VectorPresence::default(),
+        };
+        let presence = VectorPresence::TF_IDF;
+
+        assert_eq!(
+            VectorPresence::DENSE | VectorPresence::TF_IDF,
+            dense.apply(presence)
+        );
+        assert_eq!(VectorPresence::SPARSE, replaced_by_sparse.apply(presence));
+        assert!(deleted.apply(presence).is_empty());
+        assert_eq!(
+            VectorPresence::SPARSE | VectorPresence::DENSE,
+            replaced_by_sparse.then(dense).apply(presence)
+        );
+        assert_eq!(VectorPresence::DENSE, deleted.then(dense).apply(presence));
+        assert!(dense.then(deleted).apply(presence).is_empty());
+    }
+}
@@ -0,0 +1,409 @@
+use std::{
+    collections::HashSet,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use lmdb::{Cursor, Database, RwTransaction, Transaction, WriteFlags};
+
+use crate::{
+    macros::key,
+    metadata::{Filter, MetadataFields},
+    vector_store::{get_dense_embedding_by_id, get_dense_embedding_versions},
+};
+
+use super::{
+    collection::Collection,
+    common::WaCustomError,
+    external_ids::{has_keys_from, read},
+    types::{MetaDb, VectorId},
+    versioning::{Hash, VersionSnapshot},
+};
+
+/// Metadata fields of all vectors of a collection, keyed by vector id
+///
+/// The dense indexes encode metadata in the HNSW nodes, but the other
+/// indexes have no notion of it. So the metadata of every vector is
+/// also recorded here, irrespective of the indexes the vector is
+/// inserted in, for metadata filters to be applied to the results of
+/// sparse and TF-IDF searches.
+///
+/// The metadata is persisted in the collection's lmdb along with the
+/// version of the transaction that recorded it, when the transaction is
+/// committed, and it's read from there on demand i.e. it isn't kept in
+/// memory.
+pub struct VectorMetadata {
+    lmdb: MetaDb,
+    // Whether any metadata has been recorded, used to avoid lmdb
+    // lookups in the (common) case of a collection without any metadata
+    is_empty: AtomicBool,
+}
+
+impl VectorMetadata {
+    pub fn new(lmdb: MetaDb) -> Self {
+        Self {
+            lmdb,
+            is_empty: AtomicBool::new(true),
+        }
+    }
+
+    /// Returns the latest metadata fields of the vector with id `id`
+    pub fn get(&self, id: &VectorId) -> Result<Option<MetadataFields>, WaCustomError> {
+        self.get_as_of(id, None)
+    }
+
+    /// Returns true if the metadata of the vector matches the filter
+    pub fn matches(&self, id: &VectorId, filter: &Filter) -> bool {
+        self.matches_as_of(id, filter, None)
+    }
+
+    /// Returns the metadata fields of the vector with id `id` that were
+    /// the latest as of the snapshot, or the latest ones if `as_of` is
+    /// None
+    pub fn get_as_of(
+        &self,
+        id: &VectorId,
+        as_of: Option<&VersionSnapshot>,
+    ) -> Result<Option<MetadataFields>, WaCustomError> {
+        if self.is_empty.load(Ordering::Acquire) {
+            return Ok(None);
+        }
+        let env = self.lmdb.env.clone();
+        let db = self.lmdb.db.clone();
+        let txn = env.begin_ro_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        let mut cursor = txn
+            .open_ro_cursor(*db)
+            .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
+
+        // The keys of the id are ordered by version
+        let start_key = key!(f:id, 0u16);
+        let prefix = &start_key[..9];
+        let keys = has_keys_from(&cursor, &start_key)?.then(|| cursor.iter_from(&start_key));
+        let mut latest = None;
+        for (k, v) in keys.into_iter().flatten() {
+            if k.len() != start_key.len() || !k.starts_with(prefix) {
+                break;
+            }
+            let Some((version, fields)) = v.split_at_checked(4) else {
+                return Err(WaCustomError::DeserializationError(
+                    "Failed to deserialize metadata: length mismatch".to_string(),
+                ));
+            };
+            let version = Hash::from(u32::from_le_bytes(version.try_into().unwrap()));
+            if as_of.is_none_or(|as_of| as_of.includes(version)) {
+                latest = Some(fields);
+            }
+        }
+        latest.map(deserialize_fields).transpose()
+    }
+
+    /// Returns true if the metadata of the vector as of the snapshot
+    /// matches the filter, see [`Self::get_as_of`]
+    ///
+    /// Vectors whose metadata can't be read don't match.
+    pub fn matches_as_of(
+        &self,
+        id: &VectorId,
+        filter: &Filter,
+        as_of: Option<&VersionSnapshot>,
+    ) -> bool {
+        match self.get_as_of(id, as_of) {
+            Ok(fields) => filter.matches(fields.as_ref()),
+            Err(e) => {
+                log::error!("Failed to read the metadata of vector {}: {}", id.0, e);
+                false
+            }
+        }
+    }
+
+    /// Persists the given metadata, recorded in `version`, to lmdb
+    ///
+    /// The metadata of every id is persisted under a key of its own,
+    /// along with the version it was recorded in, keyed by the vector
+    /// id and the `version_number` so that the metadata of an id is
+    /// ordered by version.
+    pub fn persist(
+        &self,
+        version: Hash,
+        version_number: u16,
+        metadata: &[(VectorId, MetadataFields)],
+    ) -> Result<(), WaCustomError> {
+        if metadata.is_empty() {
+            return Ok(());
+        }
+        let env = self.lmdb.env.clone();
+        let db = self.lmdb.db.clone();
+
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        for (id, fields) in metadata {
+            put_record(&mut txn, *db, id, version, version_number, fields)?;
+        }
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        self.is_empty.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    /// Opens the metadata of the vectors persisted in the lmdb
+    pub fn load(lmdb: &MetaDb) -> Result<Self, WaCustomError> {
+        let vector_metadata = Self::new(lmdb.clone());
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+
+        let txn = env.begin_ro_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        let mut cursor = txn
+            .open_ro_cursor(*db)
+            .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
+        let start_key = key!(f:VectorId(0));
+        let keys = has_keys_from(&cursor, &start_key)?.then(|| cursor.iter_from(&start_key));
+        let is_empty = keys
+            .into_iter()
+            .flatten()
+            .next()
+            .is_none_or(|(k, _)| k[0] != 11);
+        vector_metadata.is_empty.store(is_empty, Ordering::Release);
+
+        Ok(vector_metadata)
+    }
+
+    /// Records the metadata of the vectors of a collection created
+    /// before the metadata of all vectors was recorded per version
+    ///
+    /// Metadata persisted without its version (i.e. only the latest
+    /// metadata of every id) is persisted again along with the
+    /// `current_version` of the collection, so that it's read against
+    /// the same version from then on. For the vectors inserted before
+    /// the metadata was recorded separately, the metadata indexed with
+    /// their live dense embeddings is recorded, along with the versions
+    /// of the embeddings. Either is keyed by version number 0 i.e.
+    /// ahead of the metadata of any later version.
+    ///
+    /// This is done only once per collection, when it's loaded.
+    pub fn backfill(collection: &Collection) -> Result<(), WaCustomError> {
+        let lmdb = &collection.lmdb;
+        if read(lmdb, &key!(m:vector_metadata_recorded))?.is_some() {
+            return Ok(());
+        }
+        let current_version = *collection.current_version.read().unwrap();
+        let legacy = get_legacy_metadata(lmdb)?;
+        let legacy_ids: Vec<_> = legacy.iter().map(|(id, _)| id.clone()).collect();
+        let mut recorded: HashSet<_> = legacy_ids.iter().cloned().collect();
+        let mut records: Vec<_> = legacy
+            .into_iter()
+            .map(|(id, fields)| (id, current_version, fields))
+            .collect();
+        for hnsw_index in collection.get_dense_indexes() {
+            for (id, version) in get_dense_embedding_versions(collection, &hnsw_index)? {
+                if recorded.contains(&id)
+                    || !collection.tombstones.is_live(&id, version)
+                    || collection.vector_metadata.get(&id)?.is_some()
+                {
+                    continue;
+                }
+                let embedding = get_dense_embedding_by_id(collection, &hnsw_index, &id)?;
+                if let Some(fields) = embedding.raw_metadata {
+                    recorded.insert(id.clone());
+                    records.push((id, version, fields));
+                }
+            }
+        }
+
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        for (id, version, fields) in &records {
+            put_record(&mut txn, *db, id, *version, 0, fields)?;
+        }
+        for id in &legacy_ids {
+            txn.del(*db, &key!(f:id), None).map_err(|e| {
+                WaCustomError::DatabaseError(format!("Failed to delete data: {}", e))
+            })?;
+        }
+        txn.put(
+            *db,
+            &key!(m:vector_metadata_recorded),
+            &[],
+            WriteFlags::empty(),
+        )
+        .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        if !records.is_empty() {
+            collection
+                .vector_metadata
+                .is_empty
+                .store(false, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    /// Marks the metadata of the vectors of the collection as being
+    /// recorded per version, which is the case from its creation onwards
+    pub fn mark_recorded(lmdb: &MetaDb) -> Result<(), WaCustomError> {
+        let env = lmdb.env.clone();
+        let db = lmdb.db.clone();
+        let mut txn = env.begin_rw_txn().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e))
+        })?;
+        txn.put(
+            *db,
+            &key!(m:vector_metadata_recorded),
+            &[],
+            WriteFlags::empty(),
+        )
+        .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))?;
+        txn.commit().map_err(|e| {
+            WaCustomError::DatabaseError(format!("Failed to commit transaction: {}", e))
+        })?;
+
+        Ok(())
+    }
+}
+
+fn put_record(
+    txn: &mut RwTransaction,
+    db: Database,
+    id: &VectorId,
+    version: Hash,
+    version_number: u16,
+    fields: &MetadataFields,
+) -> Result<(), WaCustomError> {
+    let mut bytes = version.to_le_bytes().to_vec();
+    serde_cbor::to_writer(&mut bytes, fields)
+        .map_err(|e| WaCustomError::SerializationError(e.to_string()))?;
+    txn.put(db, &key!(f:id, version_number), &bytes, WriteFlags::empty())
+        .map_err(|e| WaCustomError::DatabaseError(format!("Failed to put data: {}", e)))
+}
+
+/// Returns the metadata persisted without its version, see
+/// [`VectorMetadata::backfill`]
+fn get_legacy_metadata(lmdb: &MetaDb) -> Result<Vec<(VectorId, MetadataFields)>, WaCustomError> {
+    let env = lmdb.env.clone();
+    let db = lmdb.db.clone();
+    let txn = env
+        .begin_ro_txn()
+        .map_err(|e| WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e)))?;
+    let mut cursor = txn
+        .open_ro_cursor(*db)
+        .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
+
+    let mut legacy = Vec::new();
+    let start_key = key!(f:VectorId(0));
+    let keys = has_keys_from(&cursor, &start_key)?.then(|| cursor.iter_from(&start_key));
+    for (k, v) in keys.into_iter().flatten() {
+        if k[0] != 11 {
+            break;
+        }
+        if k.len() == start_key.len() {
+            let id = VectorId(u64::from_le_bytes(k[1..].try_into().unwrap()));
+            legacy.push((id, deserialize_fields(v)?));
+        }
+    }
+    Ok(legacy)
+}
+
+fn deserialize_fields(bytes: &[u8]) -> Result<MetadataFields, WaCustomError> {
+    serde_cbor::from_slice(bytes).map_err(|e| WaCustomError::DeserializationError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use lmdb::Environment;
+
+    use crate::metadata::{FieldValue, Operator, Predicate};
+
+    use super::*;
+
+    fn test_lmdb() -> MetaDb {
+        let path = tempfile::tempdir().unwrap().into_path();
+        let env = Environment::new().set_max_dbs(1).open(&path).unwrap();
+        MetaDb::from_env(Arc::new(env), "test").unwrap()
+    }
+
+    #[test]
+    fn test_vector_metadata_matches() {
+        let vector_metadata = VectorMetadata::new(test_lmdb());
+        let id = VectorId(7);
+        let filter = Filter::Is(Predicate {
+            field_name: "tenant".to_string(),
+            field_value: FieldValue::String("a".to_string()).into(),
+            operator: Operator::Equal,
+        });
+
+        assert!(vector_metadata.get(&id).unwrap().is_none());
+        assert!(!vector_metadata.matches(&id, &filter));
+
+        let fields = |tenant: &str| {
+            HashMap::from([("tenant".to_string(), FieldValue::String(tenant.to_string()))])
+        };
+        vector_metadata
+            .persist(Hash::from(1), 1, &[(id.clone(), fields("a"))])
+            .unwrap();
+        assert!(vector_metadata.matches(&id, &filter));
+        assert!(!vector_metadata.matches(&VectorId(8), &filter));
+
+        // The latest metadata takes effect
+        vector_metadata
+            .persist(Hash::from(2), 2, &[(id.clone(), fields("b"))])
+            .unwrap();
+        assert!(!vector_metadata.matches(&id, &filter));
+        vector_metadata
+            .persist(Hash::from(3), 3, &[(id.clone(), MetadataFields::new())])
+            .unwrap();
+        assert!(!vector_metadata.matches(&id, &filter));
+    }
+
+    #[test]
+    fn test_persist_and_load() {
+        let lmdb = test_lmdb();
+        let fields = |year| HashMap::from([("year".to_string(), FieldValue::Int(year))]);
+        let [v1, v3] = [1, 3].map(Hash::from);
+        let (id, other_id) = (VectorId(7), VectorId(8));
+
+        assert!(VectorMetadata::load(&lmdb)
+            .unwrap()
+            .is_empty
+            .load(Ordering::Acquire));
+
+        // Versions are persisted out of the order of their hashes
+        let vector_metadata = VectorMetadata::new(lmdb.clone());
+        vector_metadata
+            .persist(v3, 1, &[(id.clone(), fields(2020))])
+            .unwrap();
+        vector_metadata
+            .persist(v1, 2, &[(id.clone(), fields(2021))])
+            .unwrap();
+        vector_metadata
+            .persist(v1, 2, &[(other_id.clone(), fields(2019))])
+            .unwrap();
+
+        let vector_metadata = VectorMetadata::load(&lmdb).unwrap();
+        assert_eq!(Some(fields(2021)), vector_metadata.get(&id).unwrap());
+        assert_eq!(Some(fields(2019)), vector_metadata.get(&other_id).unwrap());
+        assert_eq!(None, vector_metadata.get(&VectorId(9)).unwrap());
+    }
+
+    #[test]
+    fn test_metadata_fields_serde() {
+        let fields: MetadataFields = HashMap::from([
+            ("year".to_string(), FieldValue::Int(-2020)),
+            ("tenant".to_string(), FieldValue::String("a".to_string())),
+        ]);
+        let bytes = serde_cbor::to_vec(&fields).unwrap();
+        let deserialized: MetadataFields = serde_cbor::from_slice(&bytes).unwrap();
+        assert_eq!(fields, deserialized);
+    }
+}
@@ -2,8 +2,10 @@ use crate::macros::key;
 use lmdb::{Cursor, Database, Environment, RoTransaction, Transaction, WriteFlags};
 use serde::{Deserialize, Serialize};
 use siphasher::sip::SipHasher24;
+use std::collections::HashSet;
 use std::hash::Hasher;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -83,30 +85,29 @@ impl BranchId {
     }
 }
 
-impl Timestamp {
-    fn now() -> Self {
-        Timestamp(
-            SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs() as u32,
-        )
-    }
-}
-
 #[derive(Debug, Clone)]
 pub struct VersionHash {
     pub branch: BranchId,
     pub version: Version,
     pub timestamp: Timestamp,
+    // Hashed along with the other fields (but not persisted), so that
+    // the versions created with the same number in the same second
+    // (e.g. the one following an aborted transaction) get different
+    // hashes
+    nonce: u64,
 }
 
 impl VersionHash {
     pub fn new(branch: BranchId, version: Version) -> Self {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let count = COUNTER.fetch_add(1, Ordering::Relaxed);
         Self {
             branch,
             version,
-            timestamp: Timestamp::now(),
+            timestamp: Timestamp(now.as_secs() as u32),
+            nonce: ((count as u64) << 32) | now.subsec_nanos() as u64,
         }
     }
 
@@ -115,6 +116,7 @@ impl VersionHash {
         hasher.write(&self.branch.to_le_bytes());
         hasher.write(&self.version.to_le_bytes());
         hasher.write(&self.timestamp.to_le_bytes());
+        hasher.write(&self.nonce.to_le_bytes());
         let hash = (hasher.finish() & 0xFFFFFFFF) as u32;
         Hash(hash)
     }
@@ -142,6 +144,7 @@ impl VersionHash {
             branch: BranchId(branch),
             version: Version(version),
             timestamp: Timestamp(timestamp),
+            nonce: 0,
         })
     }
 }
@@ -253,15 +256,24 @@ impl VersionControl {
         Self { env, db }
     }
 
+    /// Records a new version of the branch and returns its hash
+    ///
+    /// The hash is never one that's already recorded (including the
+    /// ones of aborted transactions), a new one is generated instead.
     pub fn generate_hash(&self, branch_name: &str, version: Version) -> lmdb::Result<Hash> {
         let branch_id = BranchId::new(branch_name);
-        let version_hash = VersionHash::new(branch_id, version);
-        let hash = version_hash.calculate_hash();
-        let version_key = key!(v:hash);
-        let bytes = version_hash.serialize();
 
         let mut txn = self.env.begin_rw_txn()?;
-        txn.put(*self.db, &version_key, &bytes, WriteFlags::empty())?;
+        let hash = loop {
+            let version_hash = VersionHash::new(branch_id, version);
+            let hash = version_hash.calculate_hash();
+            let bytes = version_hash.serialize();
+            match txn.put(*self.db, &key!(v:hash), &bytes, WriteFlags::NO_OVERWRITE) {
+                Ok(()) => break hash,
+                Err(lmdb::Error::KeyExist) => continue,
+                Err(e) => return Err(e),
+            }
+        };
         txn.commit()?;
 
         Ok(hash)
@@ -493,12 +505,129 @@ impl VersionControl {
     }
 }
 
+/// Reference to a version of a branch, either by its hash or by its
+/// version number
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionRef {
+    Hash(Hash),
+    Number(Version),
+}
+
+impl std::fmt::Display for VersionRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hash(hash) => write!(f, "with hash {}", **hash),
+            Self::Number(version) => write!(f, "number {}", **version),
+        }
+    }
+}
+
+/// A version of the main branch along with all the versions preceding
+/// it, for reading the data of a collection as it was when the version
+/// was committed
+///
+/// Data is versioned by the hash of the transaction that indexed it (or
+/// recorded a tombstone for it), hence data of any of the `versions` is
+/// visible in the snapshot while data of later versions isn't.
+#[derive(Debug, Clone)]
+pub struct VersionSnapshot {
+    pub hash: Hash,
+    pub version: Version,
+    versions: HashSet<Hash>,
+}
+
+impl VersionSnapshot {
+    /// Builds the snapshot of the `target` version from the versions of
+    /// the branch (see [`VersionControl::get_branch_versions`])
+    ///
+    /// Returns None if the branch has no such version, or if it's newer
+    /// than the `current` version i.e. it hasn't been committed (yet).
+    pub fn new(
+        branch_versions: &[(Hash, VersionHash)],
+        current: Hash,
+        target: VersionRef,
+    ) -> Option<Self> {
+        let version_of = |hash: Hash| {
+            branch_versions
+                .iter()
+                .find(|(version_hash, _)| *version_hash == hash)
+                .map(|(_, version_hash)| version_hash.version)
+        };
+        let (hash, version) = match target {
+            VersionRef::Hash(hash) => (hash, version_of(hash)?),
+            VersionRef::Number(version) => branch_versions
+                .iter()
+                .find(|(_, version_hash)| version_hash.version == version)
+                .map(|(hash, _)| (*hash, version))?,
+        };
+        if *version > *version_of(current)? {
+            return None;
+        }
+        let versions = branch_versions
+            .iter()
+            .filter(|(_, version_hash)| *version_hash.version <= *version)
+            .map(|(hash, _)| *hash)
+            .collect();
+        Some(Self {
+            hash,
+            version,
+            versions,
+        })
+    }
+
+    /// Returns true if data of the given version is visible in the
+    /// snapshot
+    pub fn includes(&self, version: Hash) -> bool {
+        self.versions.contains(&version)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
 
+    use lmdb::DatabaseFlags;
+
     use super::*;
 
+    #[test]
+    fn test_version_snapshot() {
+        let main = BranchId::new("main");
+        let branch_versions: Vec<_> = (0..4)
+            .map(|version| {
+                (
+                    Hash(version as u32 + 100),
+                    VersionHash::new(main, Version(version)),
+                )
+            })
+            .collect();
+        let current = Hash(102);
+
+        let snapshot =
+            VersionSnapshot::new(&branch_versions, current, VersionRef::Number(Version(1)))
+                .unwrap();
+        assert_eq!(Hash(101), snapshot.hash);
+        assert!(snapshot.includes(Hash(100)));
+        assert!(snapshot.includes(Hash(101)));
+        assert!(!snapshot.includes(Hash(102)));
+        assert!(!snapshot.includes(Hash(7)));
+
+        let snapshot =
+            VersionSnapshot::new(&branch_versions, current, VersionRef::Hash(Hash(102))).unwrap();
+        assert_eq!(Version(2), snapshot.version);
+        assert!(snapshot.includes(Hash(102)));
+        assert!(!snapshot.includes(Hash(103)));
+
+        // Unknown and uncommitted versions
+        assert!(
+            VersionSnapshot::new(&branch_versions, current, VersionRef::Hash(Hash(7))).is_none()
+        );
+        assert!(
+            VersionSnapshot::new(&branch_versions, current, VersionRef::Number(Version(3)))
+                .is_none()
+        );
+    }
+
     #[test]
     fn test_version_hashing_function_uniqueness() {
         let branch_id = BranchId::new("main");
@@ -513,4 +642,19 @@ mod tests {
 
         assert_eq!(versions.len(), 1000);
     }
+
+    #[test]
+    fn test_generate_hash_is_unique() {
+        let path = tempfile::tempdir().unwrap().into_path();
+        let env = Arc::new(Environment::new().set_max_dbs(1).open(&path).unwrap());
+        let db = Arc::new(env.create_db(Some("test"), DatabaseFlags::empty()).unwrap());
+        let (vcs, _) = VersionControl::new(env, db).unwrap();
+
+        // Versions with the same number created in the same second
+        // (e.g. after a transaction is aborted) get different hashes
+        let hashes: HashSet<_> = (0..1000)
+            .map(|_| vcs.generate_hash("main", Version(1)).unwrap())
+            .collect();
+        assert_eq!(1000, hashes.len());
+    }
 }
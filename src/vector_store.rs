@@ -1,13 +1,13 @@
 use crate::config_loader::Config;
 use crate::config_loader::VectorsIndexingMode;
-use crate::distance::cosine::CosineSimilarity;
 use crate::distance::DistanceFunction;
+use crate::indexes::hnsw::types::DenseSearchOptions;
 use crate::indexes::hnsw::types::HNSWHyperParams;
 use crate::indexes::hnsw::types::QuantizedDenseVectorEmbedding;
 use crate::indexes::hnsw::types::RawDenseVectorEmbedding;
+use crate::indexes::hnsw::types::DEFAULT_RERANK_OVERSAMPLING;
 use crate::indexes::hnsw::DenseInputEmbedding;
 use crate::indexes::hnsw::HNSWIndex;
-use crate::macros::key;
 use crate::metadata;
 use crate::metadata::fields_to_dimensions;
 use crate::metadata::pseudo_level_probs;
@@ -18,24 +18,25 @@ use crate::models::buffered_io::*;
 use crate::models::collection::Collection;
 use crate::models::collection_transaction::CollectionTransaction;
 use crate::models::common::*;
-use crate::models::dot_product::dot_product_f32;
+use crate::models::diversity::mmr_select;
 use crate::models::embedding_persist::*;
 use crate::models::file_persist::*;
 use crate::models::fixedset::PerformantFixedSet;
+use crate::models::fusion::{normalize_scores, ScoreNormalization};
 use crate::models::prob_lazy_load::lazy_item::FileIndex;
 use crate::models::prob_lazy_load::lazy_item::ProbLazyItem;
 use crate::models::prob_lazy_load::lazy_item_array::ProbLazyItemArray;
 use crate::models::prob_node::ProbNode;
 use crate::models::prob_node::SharedNode;
 use crate::models::types::*;
-use crate::models::versioning::Hash;
+use crate::models::versioning::{Hash, VersionSnapshot};
 use crate::quantization::{Quantization, StorageType};
 use crate::storage::Storage;
-use lmdb::Transaction;
+use lmdb::{Cursor, Transaction};
 use rand::Rng;
 use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
 use std::cmp::Reverse;
-use std::collections::BinaryHeap;
+use std::collections::{BinaryHeap, HashMap};
 use std::fs::File;
 use std::ptr;
 use std::sync::atomic::Ordering;
@@ -282,32 +283,387 @@ pub fn ann_search(
     Ok(z)
 }
 
+/// Finds the latest version of the level 0 node of the vector with the
+/// id `vector_id` in the dense index
+///
+/// The node is located by the offset recorded for it along with the
+/// offset of the vector's raw embedding. In case of metadata replicas,
+/// the node of the first replica is returned. Returns `None` if the
+/// vector has no dense embedding in the index.
+pub fn find_node_by_id(
+    config: &Config,
+    collection: &Collection,
+    hnsw_index: Arc<HNSWIndex>,
+    vector_id: &VectorId,
+) -> Result<Option<SharedNode>, WaCustomError> {
+    let Some(embedding_offset) = get_dense_embedding_offset(collection, &hnsw_index, vector_id)?
+    else {
+        return Ok(None);
+    };
+    let Some((version_number, node_offset)) = embedding_offset.level_0_node else {
+        let embedding = get_dense_embedding_by_id(collection, &hnsw_index, vector_id)?;
+        return search_node_by_embedding(config, hnsw_index, &embedding.raw_vec, vector_id);
+    };
+
+    let file_index = FileIndex {
+        offset: FileOffset(node_offset),
+        version_number,
+        version_id: embedding_offset.version,
+    };
+    let lazy_item = hnsw_index.cache.get_object(file_index, true)?;
+    let (latest, _) = ProbLazyItem::get_latest_version(lazy_item, &hnsw_index.cache)?;
+    Ok(Some(latest))
+}
+
+/// Finds the level 0 node of the vector by searching the index with the
+/// vector's own raw embedding
+///
+/// Used for the embeddings indexed by older releases, which didn't
+/// record the offsets of the nodes. This relies on the node being the
+/// nearest neighbor of its own embedding, which the approximate search
+/// doesn't guarantee. In case of metadata replicas, the closest replica
+/// node is returned. Returns `None` if the node couldn't be reached.
+fn search_node_by_embedding(
+    config: &Config,
+    hnsw_index: Arc<HNSWIndex>,
+    raw_vec: &[f32],
+    vector_id: &VectorId,
+) -> Result<Option<SharedNode>, WaCustomError> {
+    let quantized_vec = hnsw_index.quantization_metric.read().unwrap().quantize(
+        raw_vec,
+        *hnsw_index.storage_type.read().unwrap(),
+        *hnsw_index.values_range.read().unwrap(),
+    )?;
+
+    // The id of the search embedding gets added to the skip list
+    // during traversal, hence a placeholder id is used so that the
+    // node being searched for doesn't get skipped.
+    let vec_emb = QuantizedDenseVectorEmbedding {
+        quantized_vec: Arc::new(quantized_vec),
+        hash_vec: VectorId(u64::MAX - 1),
+    };
+
+    let hnsw_params = hnsw_index.hnsw_params.read().unwrap();
+    let results = ann_search(
+        config,
+        hnsw_index.clone(),
+        vec_emb,
+        None,
+        hnsw_index.get_root_vec(),
+        HNSWLevel(hnsw_params.num_layers),
+        &hnsw_params,
+    )?;
+    drop(hnsw_params);
+
+    let mut best: Option<(SharedNode, MetricResult)> = None;
+    for (lazy_item, score) in results {
+        let node = unsafe { &*lazy_item }.try_get_data(&hnsw_index.cache)?;
+        if node.hnsw_level.0 != 0 || &node.prop_value.id != vector_id {
+            continue;
+        }
+        if best.is_none_or(|(_, best_score)| score > best_score) {
+            best = Some((lazy_item, score));
+        }
+    }
+
+    match best {
+        Some((lazy_item, _)) => {
+            let (latest, _) = ProbLazyItem::get_latest_version(lazy_item, &hnsw_index.cache)?;
+            Ok(Some(latest))
+        }
+        None => Ok(None),
+    }
+}
+
+/// Searches the index with `ann_search`, leaving out the nodes that
+/// aren't visible (see `remove_tombstoned`)
+///
+/// Nodes that aren't visible are still traversed, hence they may crowd
+/// out the visible ones (e.g. with `as_of` set to a version long
+/// before the latest one). So while fewer than `k` distinct vectors are
+/// visible, `ef_search` is doubled and the index searched again, until
+/// the search doesn't find any more candidates or `ef_search` reaches
+/// the configured `max_ef_search`.
+#[allow(clippy::too_many_arguments)]
+pub fn visible_ann_search(
+    config: &Config,
+    collection: &Collection,
+    hnsw_index: Arc<HNSWIndex>,
+    vector_emb: QuantizedDenseVectorEmbedding,
+    query_filter_dims: Option<&Vec<metadata::QueryFilterDimensions>>,
+    hnsw_params: &HNSWHyperParams,
+    as_of: Option<&VersionSnapshot>,
+    k: Option<usize>,
+) -> Result<Vec<(SharedNode, MetricResult)>, WaCustomError> {
+    let mut hnsw_params = hnsw_params.clone();
+    let mut num_candidates = 0;
+    loop {
+        let results = ann_search(
+            config,
+            hnsw_index.clone(),
+            vector_emb.clone(),
+            query_filter_dims,
+            hnsw_index.get_root_vec(),
+            HNSWLevel(hnsw_params.num_layers),
+            &hnsw_params,
+        )?;
+        if collection.tombstones.is_all_live() && as_of.is_none() {
+            return Ok(results);
+        }
+        let Some(k) = k else {
+            return remove_tombstoned(collection, &hnsw_index, results, as_of);
+        };
+
+        let prev_num_candidates = num_candidates;
+        num_candidates =
+            remove_duplicates_and_filter(results.clone(), None, &hnsw_index.cache).len();
+        let visible = remove_tombstoned(collection, &hnsw_index, results, as_of)?;
+        let num_visible =
+            remove_duplicates_and_filter(visible.clone(), None, &hnsw_index.cache).len();
+        if num_visible >= k
+            || num_candidates <= prev_num_candidates
+            || hnsw_params.ef_search >= config.search.max_ef_search
+        {
+            return Ok(visible);
+        }
+        hnsw_params.ef_search = hnsw_params
+            .ef_search
+            .saturating_mul(2)
+            .min(config.search.max_ef_search);
+    }
+}
+
+/// Removes the nodes whose vectors have been deleted or replaced
+/// since the nodes were created.
+///
+/// Tombstoned nodes are still traversed during the search so that
+/// the graph stays connected, hence they are only filtered out from
+/// the final results. Similarly with `as_of`, nodes that aren't
+/// visible in the snapshot (e.g. created after its version) are
+/// filtered out here, and so are the nodes created in transactions
+/// that haven't been committed.
+fn remove_tombstoned(
+    collection: &Collection,
+    hnsw_index: &HNSWIndex,
+    results: Vec<(SharedNode, MetricResult)>,
+    as_of: Option<&VersionSnapshot>,
+) -> Result<Vec<(SharedNode, MetricResult)>, WaCustomError> {
+    if collection.tombstones.is_all_live() && as_of.is_none() {
+        return Ok(results);
+    }
+    // The nodes of a multi-vector space are the vectors of its
+    // documents, whose ids aren't the ones tombstoned
+    let multi_vector_name = hnsw_index
+        .vector_name
+        .as_deref()
+        .filter(|name| collection.meta.dense_vector.is_multi_vector(name));
+    let mut live = Vec::with_capacity(results.len());
+    for (lazy_item, score) in results {
+        let node = unsafe { &*lazy_item }.try_get_data(&hnsw_index.cache)?;
+        let root_version = ProbLazyItem::get_root_version(lazy_item, &hnsw_index.cache)?;
+        let version = unsafe { &*root_version }.get_current_version_id();
+        let is_live = match multi_vector_name {
+            Some(vector_name) => {
+                as_of.is_none_or(|as_of| as_of.includes(version))
+                    && collection.tombstones.is_committed(version)
+                    && is_live_document_vector(collection, vector_name, &node.prop_value.id)?
+            }
+            None => collection
+                .tombstones
+                .is_live_as_of(&node.prop_value.id, version, as_of),
+        };
+        if is_live {
+            live.push((lazy_item, score));
+        }
+    }
+    Ok(live)
+}
+
+/// Returns true if the vector of the multi-vector space is one of the
+/// vectors of a document that's live
+fn is_live_document_vector(
+    collection: &Collection,
+    vector_name: &str,
+    vector_id: &VectorId,
+) -> Result<bool, WaCustomError> {
+    let Some(document_id) = collection
+        .multi_vectors
+        .get_document_id(&collection.lmdb, vector_id)?
+    else {
+        return Ok(false);
+    };
+    let Some(document) =
+        collection
+            .multi_vectors
+            .get_document(&collection.lmdb, vector_name, &document_id)?
+    else {
+        return Ok(false);
+    };
+    Ok(document.vector_ids.contains(vector_id)
+        && collection
+            .tombstones
+            .is_live(&document_id, document.version))
+}
+
+/// Converts the results of `visible_ann_search` into (vector id,
+/// score) pairs
+///
+/// Unless `options.skip_rerank` is set, the top candidates (see
+/// `options.rerank_oversampling`) are rescored exactly using the raw
+/// embeddings and the distance metric of the index. Otherwise the
+/// (approximate) scores computed from the quantized vectors are
+/// returned as is.
+///
+/// `post_filter` is the metadata filter to be checked against the raw
+/// metadata of the vectors, for filters that couldn't be applied when
+/// traversing the index. Results that don't meet the score threshold
+/// (if any) are dropped, based on the final scores.
+///
+/// With `options.diversity`, the results are selected from the top
+/// candidates using Maximal Marginal Relevance over the raw embeddings,
+/// with the min-max normalized scores of the candidates as their
+/// relevance (same as for hybrid searches), and returned in the order
+/// of selection.
+///
+/// With `options.as_of`, only the raw embeddings are replaced in place
+/// when vectors are updated, so the ones updated after the snapshot's
+/// version keep the score from the index and are post filtered using
+/// their metadata as of the snapshot. They aren't considered similar
+/// to any other result when diversifying.
 pub fn finalize_ann_results(
     collection: &Collection,
     hnsw_index: &HNSWIndex,
     results: Vec<(SharedNode, MetricResult)>,
     query: &[f32],
+    post_filter: Option<&metadata::Filter>,
+    options: &DenseSearchOptions,
     k: Option<usize>,
 ) -> Result<Vec<(VectorId, MetricResult)>, WaCustomError> {
-    let filtered = remove_duplicates_and_filter(results, k, &hnsw_index.cache);
-    let mut results = Vec::with_capacity(k.unwrap_or(filtered.len()));
-    let mag_query = query.iter().map(|x| x * x).sum::<f32>().sqrt();
-
-    for (orig_id, _, _) in filtered {
-        let raw = get_dense_embedding_by_id(collection, hnsw_index, &orig_id)?;
-        let dp = dot_product_f32(query, &raw.raw_vec);
-        let mag_raw = raw.raw_vec.iter().map(|x| x * x).sum::<f32>().sqrt();
-        let cs = dp / (mag_query * mag_raw);
-        results.push((
-            orig_id,
-            MetricResult::CosineSimilarity(CosineSimilarity(cs)),
-        ));
+    let as_of = options.as_of.as_deref();
+    let rerank = !options.skip_rerank;
+    let oversampling = if rerank {
+        options
+            .rerank_oversampling
+            .unwrap_or(DEFAULT_RERANK_OVERSAMPLING)
+    } else {
+        1
+    };
+    // All candidates are considered when post filtering, as any of
+    // them may get filtered out
+    let max_candidates = match post_filter {
+        Some(_) => None,
+        None => k.map(|k| {
+            let num_candidates = k.saturating_mul(oversampling);
+            let num_candidates = match options.diversity {
+                Some(diversity) => num_candidates.max(diversity.fetch_k(k)),
+                None => num_candidates,
+            };
+            // There can't be more candidates than the results of the
+            // search, which are bounded by `ef_search`
+            num_candidates.min(results.len())
+        }),
+    };
+    let filtered = remove_duplicates_and_filter(results, max_candidates, &hnsw_index.cache);
+    let mut results = Vec::with_capacity(filtered.len());
+    let distance_metric = *hnsw_index.distance_metric.read().unwrap();
+    let diversify = options.diversity.is_some();
+
+    for (orig_id, _, score) in filtered {
+        // Raw embeddings needn't be loaded if neither rescoring, post
+        // filtering nor diversifying
+        let (score, raw_vec) = if rerank || post_filter.is_some() || diversify {
+            match get_dense_embedding_as_of(collection, hnsw_index, &orig_id, as_of)? {
+                Some(raw) => {
+                    // The metadata indexed with the raw embedding is only
+                    // used for vectors inserted before the metadata of all
+                    // vectors was recorded separately, as it's not updated
+                    // along with the recorded metadata
+                    let metadata = collection
+                        .vector_metadata
+                        .get_as_of(&orig_id, as_of)?
+                        .or(raw.raw_metadata);
+                    if post_filter.is_some_and(|filter| !filter.matches(metadata.as_ref())) {
+                        continue;
+                    }
+                    let score = if rerank {
+                        // Metrics that can't be computed on raw embeddings
+                        // keep the score from the index
+                        distance_metric
+                            .calculate_raw(query, &raw.raw_vec)
+                            .unwrap_or(score)
+                    } else {
+                        score
+                    };
+                    (score, diversify.then_some(raw.raw_vec))
+                }
+                None => {
+                    let metadata = collection.vector_metadata.get_as_of(&orig_id, as_of)?;
+                    if post_filter.is_some_and(|filter| !filter.matches(metadata.as_ref())) {
+                        continue;
+                    }
+                    (score, None)
+                }
+            }
+        } else {
+            (score, None)
+        };
+        if options
+            .score_threshold
+            .is_some_and(|threshold| !score.meets_threshold(threshold))
+        {
+            continue;
+        }
+        results.push((orig_id, score, raw_vec));
     }
-    results.sort_unstable_by(|(_, a), (_, b)| b.cmp(a));
+    results.sort_unstable_by(|(_, a, _), (_, b, _)| b.cmp(a));
+
+    if let Some(diversity) = options.diversity {
+        let k = k.unwrap_or(results.len());
+        results.truncate(diversity.fetch_k(k));
+        let vectors: Vec<&[f32]> = results
+            .iter()
+            .map(|(_, _, raw_vec)| raw_vec.as_deref().map_or(&[][..], Vec::as_slice))
+            .collect();
+        let similarities: Vec<f32> = results
+            .iter()
+            .map(|(_, score, _)| score.similarity())
+            .collect();
+        let relevance = normalize_scores(&similarities, ScoreNormalization::MinMax);
+        let selected = mmr_select(&relevance, &vectors, diversity.lambda, k);
+        return Ok(selected
+            .into_iter()
+            .map(|position| (results[position].0.clone(), results[position].1))
+            .collect());
+    }
+
     if let Some(k) = k {
         results.truncate(k);
     }
-    Ok(results)
+    Ok(results
+        .into_iter()
+        .map(|(id, score, _)| (id, score))
+        .collect())
+}
+
+/// Returns the raw embedding of the vector, unless `as_of` is given
+/// and the embedding was indexed after the snapshot's version
+///
+/// Only the latest raw embedding of every vector is retained, hence
+/// the one the vector had as of the snapshot isn't available if it has
+/// been replaced since.
+fn get_dense_embedding_as_of(
+    collection: &Collection,
+    hnsw_index: &HNSWIndex,
+    vector_id: &VectorId,
+    as_of: Option<&VersionSnapshot>,
+) -> Result<Option<RawDenseVectorEmbedding>, WaCustomError> {
+    if let Some(as_of) = as_of {
+        let version = get_dense_embedding_version(collection, hnsw_index, vector_id)?;
+        if !version.is_some_and(|version| as_of.includes(version)) {
+            return Ok(None);
+        }
+    }
+    get_dense_embedding_by_id(collection, hnsw_index, vector_id).map(Some)
 }
 
 /// Retrieves a raw embedding vector from the vector store by its ID.
@@ -382,7 +738,7 @@ pub fn get_dense_embedding_by_id(
         .begin_ro_txn()
         .map_err(|e| WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e)))?;
 
-    let embedding_key = key!(e:vector_id);
+    let embedding_key = hnsw_index.embedding_key(vector_id);
 
     let offset_serialized = txn.get(*db, &embedding_key).map_err(|e| {
         WaCustomError::DatabaseError(format!("Failed to get serialized embedding offset: {}", e))
@@ -401,6 +757,139 @@ pub fn get_dense_embedding_by_id(
     Ok(embedding)
 }
 
+/// Returns the version in which the raw dense embedding for
+/// `vector_id` was stored, if any, by looking up its offset key in
+/// LMDB without reading the embedding itself.
+pub fn get_dense_embedding_version(
+    collection: &Collection,
+    hnsw_index: &HNSWIndex,
+    vector_id: &VectorId,
+) -> Result<Option<Hash>, WaCustomError> {
+    Ok(
+        get_dense_embedding_offset(collection, hnsw_index, vector_id)?
+            .map(|embedding_offset| embedding_offset.version),
+    )
+}
+
+/// Returns the offset record of the raw dense embedding for
+/// `vector_id` in LMDB, if any
+fn get_dense_embedding_offset(
+    collection: &Collection,
+    hnsw_index: &HNSWIndex,
+    vector_id: &VectorId,
+) -> Result<Option<EmbeddingOffset>, WaCustomError> {
+    let env = collection.lmdb.env.clone();
+    let db = collection.lmdb.db.clone();
+
+    let txn = env
+        .begin_ro_txn()
+        .map_err(|e| WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e)))?;
+
+    let embedding_key = hnsw_index.embedding_key(vector_id);
+
+    let offset_serialized = match txn.get(*db, &embedding_key) {
+        Ok(bytes) => bytes,
+        Err(lmdb::Error::NotFound) => return Ok(None),
+        Err(e) => {
+            return Err(WaCustomError::DatabaseError(format!(
+                "Failed to get serialized embedding offset: {}",
+                e
+            )))
+        }
+    };
+
+    let embedding_offset = EmbeddingOffset::deserialize(offset_serialized)
+        .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
+
+    txn.abort();
+
+    Ok(Some(embedding_offset))
+}
+
+/// Returns the ids of all the vectors for which a raw dense
+/// embedding has been stored in the dense index, along with the
+/// version in which it was stored, in ascending order of the ids
+///
+/// The ids are read from the embedding offset keys in LMDB, which are
+/// only written when a transaction is committed.
+pub fn get_dense_embedding_versions(
+    collection: &Collection,
+    hnsw_index: &HNSWIndex,
+) -> Result<Vec<(VectorId, Hash)>, WaCustomError> {
+    let env = collection.lmdb.env.clone();
+    let db = collection.lmdb.db.clone();
+
+    let txn = env
+        .begin_ro_txn()
+        .map_err(|e| WaCustomError::DatabaseError(format!("Failed to begin transaction: {}", e)))?;
+    let mut cursor = txn
+        .open_ro_cursor(*db)
+        .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
+
+    // All keys of the index share the prefix preceding the id
+    let start_key = hnsw_index.embedding_key(&VectorId(0));
+    let prefix = &start_key[..start_key.len() - 8];
+
+    let mut ids = Vec::new();
+    for (k, v) in cursor.iter_from(&start_key) {
+        if k.len() != start_key.len() || !k.starts_with(prefix) {
+            break;
+        }
+        let embedding_offset = EmbeddingOffset::deserialize(v)
+            .map_err(|e| WaCustomError::DatabaseError(e.to_string()))?;
+        ids.push((
+            VectorId(u64::from_le_bytes(k[prefix.len()..].try_into().unwrap())),
+            embedding_offset.version,
+        ));
+    }
+    // Keys are ordered by the little endian bytes of the id
+    ids.sort_unstable_by_key(|(id, _)| id.0);
+
+    Ok(ids)
+}
+
+/// Returns the raw embeddings of the vectors of the document in the
+/// multi-vector space if the document is live, as of the snapshot
+/// `as_of` (if any)
+///
+/// Documents whose embeddings haven't been written yet (i.e. the
+/// transaction inserting them is being committed) are treated as not
+/// found.
+pub fn get_multi_vector_document_embeddings(
+    collection: &Collection,
+    hnsw_index: &HNSWIndex,
+    vector_name: &str,
+    document_id: &VectorId,
+    as_of: Option<&VersionSnapshot>,
+) -> Result<Option<Vec<RawDenseVectorEmbedding>>, WaCustomError> {
+    let Some(document) =
+        collection
+            .multi_vectors
+            .get_document(&collection.lmdb, vector_name, document_id)?
+    else {
+        return Ok(None);
+    };
+    if !collection
+        .tombstones
+        .is_live_as_of(document_id, document.version, as_of)
+    {
+        return Ok(None);
+    }
+    let mut embeddings = Vec::with_capacity(document.vector_ids.len());
+    for vector_id in &document.vector_ids {
+        let Some(version) = get_dense_embedding_version(collection, hnsw_index, vector_id)? else {
+            return Ok(None);
+        };
+        if as_of.is_some_and(|as_of| !as_of.includes(version)) {
+            return Ok(None);
+        }
+        embeddings.push(get_dense_embedding_by_id(
+            collection, hnsw_index, vector_id,
+        )?);
+    }
+    Ok(Some(embeddings))
+}
+
 /// Intermediate representation of the embedding in a form that's
 /// ready for indexing.
 ///
@@ -663,6 +1152,15 @@ pub fn index_embeddings_in_transaction(
     transaction: &CollectionTransaction,
     vecs: Vec<DenseInputEmbedding>,
 ) -> Result<(), WaCustomError> {
+    let vector_name = hnsw_index.vector_name.as_deref();
+    let dense_index_transaction = transaction
+        .get_dense_index_transaction(vector_name)
+        .ok_or_else(|| {
+            WaCustomError::NotFound(format!(
+                "dense index '{}' was created after the transaction was started",
+                vector_name.unwrap_or_default()
+            ))
+        })?;
     let hnsw_params_guard = hnsw_index.hnsw_params.read().unwrap();
     let index = |vecs: Vec<DenseInputEmbedding>| {
         let embeddings = vecs
@@ -675,7 +1173,7 @@ pub fn index_embeddings_in_transaction(
                     raw_metadata: metadata,
                     is_pseudo,
                 };
-                transaction.post_raw_dense_embedding(raw_emb.clone());
+                dense_index_transaction.post_raw_dense_embedding(raw_emb.clone());
                 raw_emb
             })
             .flat_map(|emb| {
@@ -693,6 +1191,10 @@ pub fn index_embeddings_in_transaction(
             // Start from root at highest level
             let root_entry = hnsw_index.get_root_vec();
             let highest_level = HNSWLevel(hnsw_params_guard.num_layers);
+            let vector_id = emb.prop_value.id.clone();
+            // The first level 0 offset is allocated for the embedding's
+            // own node, the later ones for new versions of its neighbors
+            let mut level_0_node_offset = None;
 
             index_embedding(
                 config,
@@ -705,13 +1207,20 @@ pub fn index_embeddings_in_transaction(
                 highest_level,
                 transaction.id,
                 transaction.version_number,
-                transaction.lazy_item_versions_table.clone(),
+                dense_index_transaction.lazy_item_versions_table.clone(),
                 &hnsw_params_guard,
                 max_level, // Pass max_level to let index_embedding control node creation
-                &mut || transaction.get_new_node_offset(),
-                &mut || transaction.get_new_level_0_node_offset(),
+                &mut || dense_index_transaction.get_new_node_offset(),
+                &mut || {
+                    let offset = dense_index_transaction.get_new_level_0_node_offset();
+                    level_0_node_offset.get_or_insert(offset);
+                    offset
+                },
                 *hnsw_index.distance_metric.read().unwrap(),
             )?;
+            if let Some(offset) = level_0_node_offset {
+                dense_index_transaction.record_level_0_node_offset(vector_id, offset);
+            }
         }
         Ok::<_, WaCustomError>(())
     };
@@ -756,7 +1265,6 @@ pub fn index_embedding(
     } else {
         hnsw_params.neighbors_count
     });
-    skipm.insert(vector_emb.hash_vec.0 as u32);
 
     let cur_node = unsafe { &*ProbLazyItem::get_latest_version(cur_entry, &hnsw_index.cache)?.0 }
         .try_get_data(&hnsw_index.cache)?;
@@ -800,6 +1308,18 @@ pub fn index_embedding(
     } else {
         z
     };
+    // The nodes of the vector being indexed (its metadata replicas, or
+    // the nodes of an earlier version of it, if it's being replaced)
+    // are traversed, so the parts of the graph only reachable through
+    // them aren't missed, but are never linked to the new node.
+    let vector_id = vector_emb.hash_vec.0 as u32;
+    let mut neighbors = Vec::with_capacity(z.len());
+    for &(neighbor, dist) in &z {
+        let neighbor_data = unsafe { &*neighbor }.try_get_data(&hnsw_index.cache)?;
+        if neighbor_data.get_id().0 as u32 != vector_id {
+            neighbors.push((neighbor, dist));
+        }
+    }
     if cur_level.0 > max_level {
         // Just traverse down without creating nodes
         if cur_level.0 != 0 {
@@ -892,7 +1412,7 @@ pub fn index_embedding(
             hnsw_index,
             lazy_node,
             node,
-            z,
+            neighbors,
             version,
             version_number,
             lazy_item_versions_table,
@@ -953,7 +1473,10 @@ fn get_or_create_version(
     offset_fn: &mut dyn FnMut() -> u32,
     distance_metric: DistanceMetric,
 ) -> Result<(SharedNode, bool), WaCustomError> {
-    let node = unsafe { &*lazy_item }.try_get_data(&hnsw_index.cache)?;
+    // The new version is a copy of the latest one, which has the
+    // neighbors added since `lazy_item`
+    let (latest_item, _) = ProbLazyItem::get_latest_version(lazy_item, &hnsw_index.cache)?;
+    let node = unsafe { &*latest_item }.try_get_data(&hnsw_index.cache)?;
 
     let new_version = lazy_item_versions_table.get_or_create_with_flag(
         (node.get_id().clone(), version_number, node.hnsw_level.0),
@@ -1173,6 +1696,13 @@ fn traverse_find_nearest(
 
     let start_id = start_data.get_id().0 as u32;
     skipm.insert(start_id);
+    // Locations of the raw vectors of the nodes visited, by id. A
+    // vector that's been replaced has nodes for each time it was
+    // indexed, all with the same id, so the nodes sharing the id of a
+    // visited node are only skipped if they have the same raw vector
+    // (i.e. are the same node or its metadata replicas).
+    let mut visited = HashMap::new();
+    visited.insert(start_id, vec![start_data.prop_value.location.0]);
     candidate_queue.push((start_dist, start_node));
 
     while let Some((dist, current_node)) = candidate_queue.pop() {
@@ -1205,7 +1735,16 @@ fn traverse_find_nearest(
             // replicas. This is because the ids of base node and the
             // pseudo node are > u32::MAX, whereas neighbor_id is of
             // type u32. Hence they get truncated to u32::MAX - 1.
-            if !skipm.is_member(neighbor_id) {
+            let is_skipped = skipm.is_member(neighbor_id)
+                && match visited.get(&neighbor_id) {
+                    Some(locations) => {
+                        let neighbor_data =
+                            unsafe { &*neighbor_node }.try_get_data(&hnsw_index.cache)?;
+                        locations.contains(&neighbor_data.prop_value.location.0)
+                    }
+                    None => true,
+                };
+            if !is_skipped {
                 let neighbor_data = unsafe { &*neighbor_node }.try_get_data(&hnsw_index.cache)?;
                 let neighbor_metadata =
                     neighbor_data.prop_metadata.clone().map(|pm| pm.vec.clone());
@@ -1217,12 +1756,22 @@ fn traverse_find_nearest(
                 let dist =
                     distance_metric.calculate(&fvec_data, &neighbor_vec_data, is_indexing)?;
                 skipm.insert(neighbor_id);
+                visited
+                    .entry(neighbor_id)
+                    .or_insert_with(Vec::new)
+                    .push(neighbor_data.prop_value.location.0);
                 candidate_queue.push((dist, neighbor_node));
             }
         }
     }
 
-    let final_len = if is_indexing { 64 } else { 100 };
+    // Searches widened with a larger `ef_search` (e.g. to fill the
+    // groups of group-by searches) return as many candidates
+    let final_len = if is_indexing {
+        64
+    } else {
+        (ef as usize).max(100)
+    };
 
     if results.len() > final_len {
         results.select_nth_unstable_by(final_len, |(a, _), (b, _)| b.cmp(a));
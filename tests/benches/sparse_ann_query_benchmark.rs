@@ -8,7 +8,8 @@ use std::{
 };
 
 use cosdata::models::{
-    inverted_index::InvertedIndexRoot, sparse_ann_query::SparseAnnQueryBasic, types::SparseVector,
+    inverted_index::InvertedIndexRoot, sparse_ann_query::SparseAnnQueryBasic,
+    tombstones::Tombstones, types::SparseVector,
 };
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 
@@ -162,6 +163,7 @@ fn sparse_ann_query_tshashmap_benchmark(c: &mut Criterion) {
     query_vector.entries = new_entries;
 
     let sparse_ann_query_basic = SparseAnnQueryBasic::new(query_vector);
+    let tombstones = Tombstones::new();
 
     println!(
         "Starting benchmark.. for Vector count {:?} and dimension {:?}",
@@ -183,7 +185,17 @@ fn sparse_ann_query_tshashmap_benchmark(c: &mut Criterion) {
                 let _res = black_box(
                     sparse_ann_query_basic
                         .clone()
-                        .sequential_search(&inverted_index, 6, 5.0, 0.5, 100, Some(10))
+                        .sequential_search(
+                            &inverted_index,
+                            6,
+                            5.0,
+                            0.5,
+                            100,
+                            Some(10),
+                            &tombstones,
+                            None,
+                            |_| true,
+                        )
                         .unwrap(),
                 );
             });